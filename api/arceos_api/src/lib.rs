@@ -145,6 +145,16 @@ pub mod task {
     }
 }
 
+/// Blocking synchronization primitives.
+#[cfg(feature = "multitask")]
+pub mod sync {
+    pub use axsync::{
+        Barrier, BarrierWaitResult, Condvar, LazyLock, Mutex, MutexGuard, Once, RwLock,
+        RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard, Semaphore, SemaphoreGuard,
+        WaitTimeoutResult,
+    };
+}
+
 /// Filesystem manipulation operations.
 pub mod fs {
     use crate::AxResult;
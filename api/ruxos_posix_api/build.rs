@@ -41,9 +41,13 @@ typedef struct {{
         // TODO: generate size and initial content automatically.
         let (mutex_size, mutex_init) = if cfg!(feature = "multitask") {
             if cfg!(feature = "smp") {
-                (6, "{0, 8, 0, 0, 0, 0}") // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(()))
+                // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(())), then the PI futex
+                // word and the protocol.
+                (7, "{0, 8, 0, 0, 0, 0, 0}")
             } else {
-                (5, "{8, 0, 0, 0, 0}") // core::mem::transmute::<_, [usize; 5]>(axsync::Mutex::new(()))
+                // core::mem::transmute::<_, [usize; 5]>(axsync::Mutex::new(())), then the PI futex
+                // word and the protocol.
+                (6, "{8, 0, 0, 0, 0, 0}")
             }
         } else {
             (1, "{0}")
@@ -96,6 +100,7 @@ typedef struct {{
             "msghdr",
             "pthread_cond_t",
             "pthread_condattr_t",
+            "__ptcb",
             "sysinfo",
             "sigaction",
             "k_sigaction",
@@ -106,29 +111,51 @@ typedef struct {{
             "stack_t",
             "ino_t",
             "dirent",
+            "cpu_set_t",
+            "sched_param",
+            "sched_attr",
+            "key_t",
+            "ipc_perm",
+            "shmid_ds",
         ];
         let allow_vars = [
             "O_.*",
             "AF_.*",
             "SOCK_.*",
             "IPPROTO_.*",
+            "PACKET_.*",
             "FD_.*",
             "F_.*",
             "_SC_.*",
+            "PTHREAD_DESTRUCTOR_ITERATIONS",
+            "PTHREAD_KEYS_MAX",
+            "PTHREAD_CANCEL_.*",
+            "PTHREAD_CREATE_.*",
+            "PTHREAD_PRIO_.*",
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
             "EAI_.*",
+            "NI_.*",
             "MAXADDRS",
             "ITIMER_.*",
             "SIG.*",
             "EINVAL",
             "CLONE_.*",
+            "SCHED_.*",
             "AT_.*",
             "MAP_.+",
             "PROT_.+",
             "MS_.+",
             "MREMAP_.+",
+            "MADV_.+",
+            "MCL_.+",
+            "MLOCK_.+",
+            "SWAP_FLAG_.+",
+            "MFD_.+",
+            "IPC_.+",
+            "SHM_.+",
+            "SHMLBA",
         ];
 
         #[derive(Debug)]
@@ -263,6 +263,13 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 }
                 Ok(flags as c_int)
             }
+            #[cfg(feature = "fs")]
+            ctypes::F_ADD_SEALS => {
+                super::fs::File::from_fd(fd)?.add_seals(arg as u32)?;
+                Ok(0)
+            }
+            #[cfg(feature = "fs")]
+            ctypes::F_GET_SEALS => Ok(super::fs::File::from_fd(fd)?.get_seals()? as c_int),
             ctypes::F_SETFD => {
                 if arg == 0 || arg == 1 || arg == 2 {
                     return Ok(0);
@@ -8,9 +8,13 @@
  */
 
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int, c_long, c_void};
+use core::{
+    ffi::{c_char, c_int, c_long, c_uint, c_void},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use axerrno::{LinuxError, LinuxResult};
+use axfs_vfs::VfsNodeRef;
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 use ruxfdtable::{FileLike, RuxStat};
@@ -19,19 +23,132 @@ use ruxfs::{
     fops::{DirEntry, OpenOptions},
 };
 
-use super::fd_ops::get_file_like;
-use crate::{ctypes, utils::char_ptr_to_str};
+use super::{fd_ops::get_file_like, mmap::page_cache};
+use crate::{
+    ctypes,
+    utils::{char_ptr_to_str, write_all},
+};
 use alloc::vec::Vec;
 
 pub struct File {
     pub(crate) inner: Mutex<ruxfs::fops::File>,
+    /// The VFS node, which keys the page cache shared with `MAP_SHARED` mappings.
+    pub(crate) node: VfsNodeRef,
+    /// Seals added by `F_ADD_SEALS`, `None` if the file can't be sealed.
+    seals: Option<AtomicU32>,
 }
 
 impl File {
     pub(crate) fn new(inner: ruxfs::fops::File) -> Self {
         Self {
+            node: inner.get_node().expect("opened file should have a node"),
             inner: Mutex::new(inner),
+            seals: None,
+        }
+    }
+
+    /// Makes the file sealable with the initial `seals`, for `memfd_create`.
+    pub(crate) fn with_seals(mut self, seals: u32) -> Self {
+        self.seals = Some(AtomicU32::new(seals));
+        self
+    }
+
+    /// Returns the seals of the file, 0 if it can't be sealed.
+    pub(crate) fn seals(&self) -> u32 {
+        self.seals
+            .as_ref()
+            .map_or(0, |seals| seals.load(Ordering::Acquire))
+    }
+
+    /// Returns the seals of the file for `F_GET_SEALS`.
+    pub(crate) fn get_seals(&self) -> LinuxResult<u32> {
+        let seals = self.seals.as_ref().ok_or(LinuxError::EINVAL)?;
+        Ok(seals.load(Ordering::Acquire))
+    }
+
+    /// Adds `seals` to the file.
+    ///
+    /// `F_SEAL_WRITE` can't be added while the file has writable `MAP_SHARED`
+    /// mappings.
+    pub(crate) fn add_seals(&self, seals: u32) -> LinuxResult {
+        let current = self.seals.as_ref().ok_or(LinuxError::EINVAL)?;
+        if seals & ctypes::F_SEAL_WRITE != 0 && super::mmap::has_writable_shared_mapping(self) {
+            return Err(LinuxError::EBUSY);
+        }
+        current
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |old| {
+                (old & ctypes::F_SEAL_SEAL == 0).then_some(old | seals)
+            })
+            .map_err(|_| LinuxError::EPERM)?;
+        Ok(())
+    }
+
+    /// Checks the seals before writing `len` bytes at `offset` of `inner`, the
+    /// locked file of `self`.
+    fn check_write(&self, inner: &ruxfs::fops::File, offset: u64, len: usize) -> LinuxResult {
+        let seals = self.seals();
+        if seals & (ctypes::F_SEAL_WRITE | ctypes::F_SEAL_FUTURE_WRITE) != 0 {
+            return Err(LinuxError::EPERM);
+        }
+        if seals & ctypes::F_SEAL_GROW != 0 && offset + len as u64 > inner.get_attr()?.size() {
+            return Err(LinuxError::EPERM);
         }
+        Ok(())
+    }
+
+    /// Reads `inner`, the locked file of `self`, at `offset` like [`File::read_at`].
+    pub(crate) fn read_locked(
+        &self,
+        inner: &ruxfs::fops::File,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> LinuxResult<usize> {
+        let len = inner.read_at(offset, buf)?;
+        page_cache::read_cached(self, offset, &mut buf[..len]);
+        Ok(len)
+    }
+
+    /// Writes `inner`, the locked file of `self`, at `offset` like [`File::write_at`].
+    pub(crate) fn write_locked(
+        &self,
+        inner: &ruxfs::fops::File,
+        offset: u64,
+        buf: &[u8],
+    ) -> LinuxResult<usize> {
+        self.check_write(inner, offset, buf.len())?;
+        let len = inner.write_at(offset, buf)?;
+        page_cache::write_cached(self, offset, &buf[..len]);
+        Ok(len)
+    }
+
+    /// Reads the file at `offset`, seeing the pages written by `MAP_SHARED` mappings.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.read_locked(&self.inner.lock(), offset, buf)
+    }
+
+    /// Writes the file at `offset`, updating the pages of `MAP_SHARED` mappings.
+    pub(crate) fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        self.write_locked(&self.inner.lock(), offset, buf)
+    }
+
+    /// Truncates or extends the file to `size` bytes.
+    ///
+    /// The cached pages past `size` are cut off, so they don't extend the file
+    /// again when written back.
+    pub(crate) fn truncate(&self, size: u64) -> LinuxResult {
+        let seals = self.seals();
+        let inner = self.inner.lock();
+        let old_size = inner.get_attr()?.size();
+        if (size < old_size && seals & ctypes::F_SEAL_SHRINK != 0)
+            || (size > old_size && seals & ctypes::F_SEAL_GROW != 0)
+        {
+            return Err(LinuxError::EPERM);
+        }
+        inner.truncate(size)?;
+        if size < old_size {
+            page_cache::truncate_cached(self, size);
+        }
+        Ok(())
     }
 
     pub(crate) fn add_to_fd_table(self) -> LinuxResult<c_int> {
@@ -48,11 +165,25 @@ impl File {
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().read(buf)?)
+        let mut inner = self.inner.lock();
+        let offset = inner.seek(SeekFrom::Current(0))?;
+        let len = inner.read(buf)?;
+        drop(inner);
+        page_cache::read_cached(self, offset, &mut buf[..len]);
+        Ok(len)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write(buf)?)
+        let mut inner = self.inner.lock();
+        if self.seals.is_some() {
+            let offset = inner.seek(SeekFrom::Current(0))?;
+            self.check_write(&inner, offset, buf.len())?;
+        }
+        let len = inner.write(buf)?;
+        let offset = inner.seek(SeekFrom::Current(0))? - len as u64;
+        drop(inner);
+        page_cache::write_cached(self, offset, &buf[..len]);
+        Ok(len)
     }
 
     fn flush(&self) -> LinuxResult {
@@ -172,7 +303,7 @@ impl FileLike for Directory {
 }
 
 /// Convert open flags to [`OpenOptions`].
-fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
+pub(crate) fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
     let mut options = OpenOptions::new();
     match flags & 0b11 {
@@ -243,6 +374,18 @@ pub fn sys_openat(fd: usize, path: *const c_char, flags: c_int, mode: ctypes::mo
     })
 }
 
+/// Truncate or extend the file indicated by `fd` to `length` bytes.
+pub fn sys_ftruncate(fd: c_int, length: ctypes::off_t) -> c_int {
+    debug!("sys_ftruncate <= fd: {}, length: {}", fd, length);
+    syscall_body!(sys_ftruncate, {
+        if length < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        File::from_fd(fd)?.truncate(length as u64)?;
+        Ok(0)
+    })
+}
+
 /// Set the position of the file indicated by `fd`.
 ///
 /// Read data from a file at a specific offset.
@@ -258,7 +401,7 @@ pub fn sys_pread64(
             return Err(LinuxError::EFAULT);
         }
         let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
-        let size = File::from_fd(fd)?.inner.lock().read_at(pos as u64, dst)?;
+        let size = File::from_fd(fd)?.read_at(pos as u64, dst)?;
         Ok(size as ctypes::ssize_t)
     })
 }
@@ -278,7 +421,7 @@ pub fn sys_pwrite64(
             return Err(LinuxError::EFAULT);
         }
         let src = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
-        let size = File::from_fd(fd)?.inner.lock().write_at(pos as u64, src)?;
+        let size = File::from_fd(fd)?.write_at(pos as u64, src)?;
         Ok(size as ctypes::ssize_t)
     })
 }
@@ -302,18 +445,28 @@ pub fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> ctypes::off
 
 /// Synchronize a file's in-core state with storage device
 ///
-/// TODO
+/// Only the dirty pages of `MAP_SHARED` mappings are written back.
 pub unsafe fn sys_fsync(fd: c_int) -> c_int {
     debug!("sys_fsync <= fd: {}", fd);
-    syscall_body!(sys_fsync, Ok(0))
+    syscall_body!(sys_fsync, {
+        if let Ok(file) = File::from_fd(fd) {
+            page_cache::sync_file(&file);
+        }
+        Ok(0)
+    })
 }
 
 /// Synchronize a file's in-core state with storage device
 ///
-/// TODO
+/// Only the dirty pages of `MAP_SHARED` mappings are written back.
 pub unsafe fn sys_fdatasync(fd: c_int) -> c_int {
     debug!("sys_fdatasync <= fd: {}", fd);
-    syscall_body!(sys_fdatasync, Ok(0))
+    syscall_body!(sys_fdatasync, {
+        if let Ok(file) = File::from_fd(fd) {
+            page_cache::sync_file(&file);
+        }
+        Ok(0)
+    })
 }
 
 /// Get the file metadata by `path` and write into `buf`.
@@ -704,3 +857,227 @@ pub fn sys_chdir(path: *const c_char) -> c_int {
         Ok(0)
     })
 }
+
+/// Max number of bytes moved by one `sendfile` or `copy_file_range`, same as Linux.
+const MAX_RW_COUNT: usize = 0x7fff_f000;
+
+/// Size of the kernel buffer used to copy between files.
+const COPY_BUF_SIZE: usize = 64 * 1024;
+
+/// Transfer data from the file `in_fd` to the file descriptor `out_fd`.
+///
+/// If `offset` is not NULL, the file is read from `*offset`, which is updated
+/// afterwards, and the file position is unchanged. Otherwise, the file is read
+/// from and advances the file position.
+///
+/// The data is copied through a kernel buffer. If `out_fd` is a TCP socket,
+/// only what the socket accepts is consumed from the file.
+///
+/// Return the number of bytes transferred.
+pub unsafe fn sys_sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_sendfile <= out_fd: {}, in_fd: {}, offset: {:#x}, count: {}",
+        out_fd, in_fd, offset as usize, count
+    );
+    syscall_body!(sys_sendfile, {
+        let file = File::from_fd(in_fd)?;
+        let out = get_file_like(out_fd)?;
+        let count = count.min(MAX_RW_COUNT);
+        let len = unsafe {
+            with_file_offset(&file, offset, |inner, pos| {
+                #[cfg(feature = "net")]
+                if let Ok(socket) = out.clone().into_any().downcast::<super::net::Socket>() {
+                    if socket.is_stream() {
+                        return send_file_to_socket(&socket, &file, inner, pos, count);
+                    }
+                }
+                copy_to_file_like(&file, inner, pos, &*out, count)
+            })?
+        };
+        Ok(len as ctypes::ssize_t)
+    })
+}
+
+/// Copy a range of data from the file `fd_in` to the file `fd_out`.
+///
+/// `off_in` and `off_out` are used in the same way as `offset` of
+/// [`sys_sendfile`]. `flags` must be 0.
+///
+/// Return the number of bytes copied.
+pub unsafe fn sys_copy_file_range(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_copy_file_range <= fd_in: {}, off_in: {:#x}, fd_out: {}, off_out: {:#x}, len: {}, flags: {}",
+        fd_in, off_in as usize, fd_out, off_out as usize, len, flags
+    );
+    syscall_body!(sys_copy_file_range, {
+        if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let file_in = File::from_fd(fd_in)?;
+        let file_out = File::from_fd(fd_out)?;
+        let len = len.min(MAX_RW_COUNT);
+
+        if Arc::ptr_eq(&file_in, &file_out) {
+            // Both ends are the same open file, so it can only be locked once.
+            let mut inner = file_in.inner.lock();
+            let cur = inner.seek(SeekFrom::Current(0))?;
+            let pos_in = unsafe { offset_or(off_in, cur)? };
+            let pos_out = unsafe { offset_or(off_out, cur)? };
+            if pos_in < pos_out + len as u64 && pos_out < pos_in + len as u64 {
+                return Err(LinuxError::EINVAL);
+            }
+            let copied =
+                copy_between_files(&file_in, &inner, pos_in, &file_out, &inner, pos_out, len)?;
+            unsafe {
+                advance_offset(&mut inner, off_in, pos_in + copied as u64)?;
+                advance_offset(&mut inner, off_out, pos_out + copied as u64)?;
+            }
+            return Ok(copied as ctypes::ssize_t);
+        }
+
+        let copied = unsafe {
+            with_file_offset(&file_in, off_in, |inner_in, pos_in| {
+                with_file_offset(&file_out, off_out, |inner_out, pos_out| {
+                    copy_between_files(
+                        &file_in, inner_in, pos_in, &file_out, inner_out, pos_out, len,
+                    )
+                })
+            })?
+        };
+        Ok(copied as ctypes::ssize_t)
+    })
+}
+
+/// Returns `*offset`, or `cur` if `offset` is NULL.
+unsafe fn offset_or(offset: *const ctypes::off_t, cur: u64) -> LinuxResult<u64> {
+    if offset.is_null() {
+        return Ok(cur);
+    }
+    match unsafe { *offset } {
+        off if off < 0 => Err(LinuxError::EINVAL),
+        off => Ok(off as u64),
+    }
+}
+
+/// Sets `*offset` to `pos`, or moves the file position to `pos` if `offset`
+/// is NULL.
+unsafe fn advance_offset(
+    inner: &mut ruxfs::fops::File,
+    offset: *mut ctypes::off_t,
+    pos: u64,
+) -> LinuxResult {
+    if offset.is_null() {
+        inner.seek(SeekFrom::Start(pos))?;
+    } else {
+        unsafe { *offset = pos as ctypes::off_t };
+    }
+    Ok(())
+}
+
+/// Does I/O on `file` with `f`, starting at `*offset` if it is not NULL, or
+/// at the file position otherwise. Then the offset is advanced by the number
+/// of bytes returned by `f`.
+pub(crate) unsafe fn with_file_offset<F>(
+    file: &File,
+    offset: *mut ctypes::off_t,
+    f: F,
+) -> LinuxResult<usize>
+where
+    F: FnOnce(&mut ruxfs::fops::File, u64) -> LinuxResult<usize>,
+{
+    let mut inner = file.inner.lock();
+    let cur = if offset.is_null() {
+        inner.seek(SeekFrom::Current(0))?
+    } else {
+        0
+    };
+    let pos = unsafe { offset_or(offset, cur)? };
+    let len = f(&mut inner, pos)?;
+    unsafe { advance_offset(&mut inner, offset, pos + len as u64)? };
+    Ok(len)
+}
+
+/// Runs `op` repeatedly to move up to `count` bytes, until it reaches the end
+/// of file. Errors are only reported if nothing has been moved.
+fn transfer<F>(count: usize, mut op: F) -> LinuxResult<usize>
+where
+    F: FnMut(usize, usize) -> LinuxResult<usize>,
+{
+    let mut done = 0;
+    while done < count {
+        match op(done, count - done) {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(_) if done > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(done)
+}
+
+#[cfg(feature = "net")]
+fn send_file_to_socket(
+    socket: &super::net::Socket,
+    file: &File,
+    inner: &ruxfs::fops::File,
+    pos: u64,
+    count: usize,
+) -> LinuxResult<usize> {
+    // The file is read without holding the socket, which is only locked to
+    // push the data. What is not accepted is read again in the next round.
+    let mut buf = alloc::vec![0; count.min(COPY_BUF_SIZE)];
+    transfer(count, |done, remain| {
+        let len = buf.len().min(remain);
+        match file.read_locked(inner, pos + done as u64, &mut buf[..len])? {
+            0 => Ok(0),
+            len => socket.send(&buf[..len]),
+        }
+    })
+}
+
+fn copy_to_file_like(
+    file: &File,
+    inner: &ruxfs::fops::File,
+    pos: u64,
+    out: &dyn FileLike,
+    count: usize,
+) -> LinuxResult<usize> {
+    let mut buf = alloc::vec![0; count.min(COPY_BUF_SIZE)];
+    transfer(count, |done, remain| {
+        let len = buf.len().min(remain);
+        let len = file.read_locked(inner, pos + done as u64, &mut buf[..len])?;
+        write_all(&buf[..len], |data| out.write(data))
+    })
+}
+
+fn copy_between_files(
+    file_in: &File,
+    inner_in: &ruxfs::fops::File,
+    pos_in: u64,
+    file_out: &File,
+    inner_out: &ruxfs::fops::File,
+    pos_out: u64,
+    len: usize,
+) -> LinuxResult<usize> {
+    let mut buf = alloc::vec![0; len.min(COPY_BUF_SIZE)];
+    transfer(len, |done, remain| {
+        let len = buf.len().min(remain);
+        let len = file_in.read_locked(inner_in, pos_in + done as u64, &mut buf[..len])?;
+        write_all(&buf[..len], |data| {
+            let pos = pos_out + done as u64 + (len - data.len()) as u64;
+            file_out.write_locked(inner_out, pos, data)
+        })
+    })
+}
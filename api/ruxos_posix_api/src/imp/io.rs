@@ -8,7 +8,7 @@
  */
 
 use crate::ctypes;
-use axerrno::LinuxError;
+use axerrno::{LinuxError, LinuxResult};
 use core::ffi::{c_int, c_void};
 
 #[cfg(feature = "fd")]
@@ -26,19 +26,33 @@ pub fn sys_read(fd: c_int, buf: *mut c_void, count: usize) -> ctypes::ssize_t {
             return Err(LinuxError::EFAULT);
         }
         let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
-        #[cfg(feature = "fd")]
-        {
-            Ok(get_file_like(fd)?.read(dst)? as ctypes::ssize_t)
-        }
-        #[cfg(not(feature = "fd"))]
-        match fd {
-            0 => Ok(super::stdio::stdin().read(dst)? as ctypes::ssize_t),
-            1 | 2 => Err(LinuxError::EPERM),
-            _ => Err(LinuxError::EBADF),
-        }
+        // It is a cancellation point, a blocking read is woken up if the
+        // thread is canceled, which is acted upon after reading.
+        #[cfg(feature = "multitask")]
+        let len = {
+            let len = crate::imp::pthread::cancelable(|| read_fd(fd, dst));
+            crate::imp::pthread::test_cancel();
+            len.ok_or(LinuxError::EINTR)??
+        };
+        #[cfg(not(feature = "multitask"))]
+        let len = read_fd(fd, dst)?;
+        Ok(len as ctypes::ssize_t)
     })
 }
 
+fn read_fd(fd: c_int, dst: &mut [u8]) -> LinuxResult<usize> {
+    #[cfg(feature = "fd")]
+    {
+        get_file_like(fd)?.read(dst)
+    }
+    #[cfg(not(feature = "fd"))]
+    match fd {
+        0 => Ok(super::stdio::stdin().read(dst)?),
+        1 | 2 => Err(LinuxError::EPERM),
+        _ => Err(LinuxError::EBADF),
+    }
+}
+
 /// Write data to the file indicated by `fd`.
 ///
 /// Return the written size if success.
@@ -8,29 +8,46 @@
  */
 
 use crate::ctypes;
-use alloc::vec::Vec;
-use axerrno::LinuxError;
+use alloc::{collections::BTreeMap, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
 use core::{
-    ffi::{c_int, c_void},
+    ffi::{c_int, c_uchar, c_void},
     ops::Bound,
+    sync::atomic::{AtomicU32, Ordering},
 };
 use memory_addr::PAGE_SIZE_4K;
-use ruxhal::{mem::VirtAddr, paging::pte_update_page};
+use ruxhal::{
+    mem::VirtAddr,
+    paging::{pte_query, pte_update_page},
+};
 
-use super::utils::{
-    find_free_region, get_mflags_from_usize, get_overlap, release_pages_mapped, shift_mapped_page,
-    snatch_fixed_region, Vma, MEM_MAP, VMA_END, VMA_MAP,
+use super::{
+    trap::fault_in_page,
+    utils::{
+        find_free_region, get_mflags_from_usize, get_overlap, is_mapped, release_pages_mapped,
+        shift_mapped_page, snatch_fixed_region, split_huge_page_across, split_vma_at,
+        vmas_overlapped, Vma, HUGE_PAGE_SIZE, MEM_MAP, READ_AHEAD_MAX_PAGES, READ_AHEAD_PAGES,
+        VMA_END, VMA_MAP,
+    },
 };
 
 #[cfg(feature = "fs")]
 use {
-    super::utils::{release_pages_swaped, write_into},
+    super::{
+        page_cache, reclaim,
+        utils::{lazyfree_pages, release_pages_swaped, shrink_mapped_pages, VMA_START},
+    },
+    crate::imp::fs::File,
     alloc::sync::Arc,
 };
 
+/// Flags of `mlockall` applied to the future mappings, i.e. `MCL_FUTURE` and `MCL_ONFAULT`.
+static MLOCK_FUTURE: AtomicU32 = AtomicU32::new(0);
+
 /// Creates a new mapping in the virtual address space of the calling process.
 ///
-/// Note: support flags `MAP_PRIVATE`, `MAP_SHARED`, `MAP_ANONYMOUS`, `MAP_FILE`, `MAP_FIXED`.
+/// Note: support flags `MAP_PRIVATE`, `MAP_SHARED`, `MAP_ANONYMOUS`, `MAP_FILE`, `MAP_FIXED`,
+/// and `MAP_HUGETLB` with 2M huge pages for anonymous mappings.
 pub fn sys_mmap(
     start: *mut c_void,
     len: ctypes::size_t,
@@ -75,26 +92,105 @@ pub fn sys_mmap(
             fid
         };
 
-        let mut new = Vma::new(fid, offset, prot, flags);
-        let mut vma_map = VMA_MAP.lock();
-        let addr_condition = if start == 0 { None } else { Some(start) };
-
-        let try_addr = if flags & ctypes::MAP_FIXED != 0 {
-            snatch_fixed_region(&mut vma_map, start, len)
+        // `MAP_HUGETLB` mapping is made of whole huge pages, only the size of 2M is supported.
+        let len = if flags & ctypes::MAP_HUGETLB != 0 {
+            let huge_shift = (flags >> ctypes::MAP_HUGE_SHIFT) & ctypes::MAP_HUGE_MASK;
+            if fid >= 0
+                || (huge_shift != 0 && 1 << huge_shift != HUGE_PAGE_SIZE)
+                || !VirtAddr::from(start).is_aligned(HUGE_PAGE_SIZE)
+            {
+                error!("mmap failed because of unsupported `MAP_HUGETLB` mapping");
+                return Err(LinuxError::EINVAL);
+            }
+            memory_addr::align_up(len, HUGE_PAGE_SIZE)
         } else {
-            find_free_region(&vma_map, addr_condition, len)
+            len
         };
 
-        match try_addr {
-            Some(vaddr) => {
-                new.start_addr = vaddr;
-                new.end_addr = vaddr + len;
-                vma_map.insert(vaddr, new);
-                Ok(vaddr as *mut c_void)
+        let new = Vma::new(fid, offset, prot, flags);
+        insert_vma(new, start, len).map(|vaddr| vaddr as *mut c_void)
+    })
+}
+
+/// Map `file` at `start` like `sys_mmap`, the file is not opened in the fd
+/// table, e.g. a System V shared memory segment.
+#[cfg(feature = "fs")]
+pub(crate) fn mmap_file(
+    start: usize,
+    len: usize,
+    prot: u32,
+    flags: u32,
+    file: Arc<File>,
+) -> LinuxResult<usize> {
+    insert_vma(Vma::from_file(file, 0, prot, flags), start, len)
+}
+
+/// Whether `file` is mapped by a writable `MAP_SHARED` mapping.
+#[cfg(feature = "fs")]
+pub(crate) fn has_writable_shared_mapping(file: &File) -> bool {
+    VMA_MAP.lock().values().any(|vma| {
+        vma.flags & ctypes::MAP_SHARED != 0
+            && vma.prot & ctypes::PROT_WRITE != 0
+            && vma
+                .file
+                .as_ref()
+                .is_some_and(|mapped| Arc::ptr_eq(&mapped.node, &file.node))
+    })
+}
+
+/// Find the region for `new` and record it in `VMA_MAP`, return its start address.
+fn insert_vma(mut new: Vma, start: usize, len: usize) -> LinuxResult<usize> {
+    // The sealed file can't be written by a new `MAP_SHARED` mapping.
+    #[cfg(feature = "fs")]
+    if let Some(file) = &new.file {
+        if new.flags & ctypes::MAP_SHARED != 0
+            && new.prot & ctypes::PROT_WRITE != 0
+            && file.seals() & (ctypes::F_SEAL_WRITE | ctypes::F_SEAL_FUTURE_WRITE) != 0
+        {
+            return Err(LinuxError::EPERM);
+        }
+    }
+
+    // The new mapping is locked after `mlockall` with `MCL_FUTURE`.
+    let lock_future = MLOCK_FUTURE.load(Ordering::Relaxed);
+    new.locked = lock_future & ctypes::MCL_FUTURE != 0;
+    let populate = new.locked && lock_future & ctypes::MCL_ONFAULT == 0;
+
+    // The mapped pages are reclaimed for the heap when it runs out, which
+    // has no effect if the shrinker has been registered.
+    #[cfg(feature = "fs")]
+    axalloc::register_shrinker(shrink_mapped_pages);
+    #[cfg(all(feature = "fs", feature = "multitask"))]
+    super::utils::start_reclaim_task();
+
+    let mut vma_map = VMA_MAP.lock();
+    let addr_condition = if start == 0 { None } else { Some(start) };
+
+    let try_addr = if new.flags & ctypes::MAP_FIXED != 0 {
+        snatch_fixed_region(&mut vma_map, start, len)
+    } else if new.huge && addr_condition.is_none() && len >= HUGE_PAGE_SIZE {
+        // Align the large anonymous mapping to huge pages, so that it can be backed by them.
+        find_free_region(&vma_map, None, len + HUGE_PAGE_SIZE - PAGE_SIZE_4K)
+            .map(|vaddr| memory_addr::align_up(vaddr, HUGE_PAGE_SIZE))
+            .or_else(|| find_free_region(&vma_map, None, len))
+    } else {
+        find_free_region(&vma_map, addr_condition, len)
+    };
+
+    match try_addr {
+        Some(vaddr) => {
+            new.start_addr = vaddr;
+            new.end_addr = vaddr + len;
+            vma_map.insert(vaddr, new);
+            // The population is best-effort as in Linux, the mapping has
+            // been made and the pages left are faulted in on access.
+            if populate && populate_range(&vma_map, vaddr, vaddr + len).is_err() {
+                warn!("failed to populate the locked mapping at {:#x}", vaddr);
             }
-            _ => Err(LinuxError::ENOMEM),
+            Ok(vaddr)
         }
-    })
+        _ => Err(LinuxError::ENOMEM),
+    }
 }
 
 /// Deletes the mappings for the specified address range
@@ -213,6 +309,17 @@ pub fn sys_mprotect(start: *mut c_void, len: ctypes::size_t, prot: c_int) -> c_i
             if let Some((overlapped_start, overlapped_end)) =
                 get_overlap(syncing_interval, (vma.start_addr, vma.end_addr))
             {
+                // The sealed file can't be made writable by a `MAP_SHARED` mapping.
+                #[cfg(feature = "fs")]
+                if let Some(file) = &vma.file {
+                    if vma.flags & ctypes::MAP_SHARED != 0
+                        && prot as u32 & ctypes::PROT_WRITE != 0
+                        && file.seals() & (ctypes::F_SEAL_WRITE | ctypes::F_SEAL_FUTURE_WRITE) != 0
+                    {
+                        return Err(LinuxError::EACCES);
+                    }
+                }
+
                 // Accumulate the size of the mapping area to be released
                 counter += overlapped_end - overlapped_start;
 
@@ -247,8 +354,16 @@ pub fn sys_mprotect(start: *mut c_void, len: ctypes::size_t, prot: c_int) -> c_i
             return Err(LinuxError::EFAULT);
         }
 
-        // upate PTEs if mprotect is successful.
-        for (&vaddr, _) in MEM_MAP.lock().range(start..end) {
+        // upate PTEs if mprotect is successful, huge pages inside the range are updated as a whole.
+        let mut memory_map = MEM_MAP.lock();
+        split_huge_page_across(&mut memory_map, start);
+        split_huge_page_across(&mut memory_map, end);
+        for (&vaddr, _page_info) in memory_map.range(start..end) {
+            // The dirty state may be lost with the write permission.
+            #[cfg(feature = "fs")]
+            if let Some((file, offset, _)) = _page_info {
+                page_cache::take_dirty(file, *offset, vaddr);
+            }
             if pte_update_page(
                 VirtAddr::from(vaddr),
                 None,
@@ -297,10 +412,9 @@ pub fn sys_msync(start: *mut c_void, len: ctypes::size_t, flags: c_int) -> c_int
             if !VirtAddr::from(start).is_aligned(PAGE_SIZE_4K) || len == 0 {
                 return Err(LinuxError::EINVAL);
             }
-            for (&vaddr, page_info) in MEM_MAP.lock().range(start..end) {
-                if let Some((file, offset, size)) = page_info {
-                    let src = vaddr as *mut u8;
-                    write_into(file, src, *offset as u64, *size);
+            for (_, page_info) in MEM_MAP.lock().range(start..end) {
+                if let Some((file, offset, _)) = page_info {
+                    page_cache::sync_cached(file, *offset);
                 }
             }
         }
@@ -405,13 +519,20 @@ pub fn sys_mremap(
                     return Err(LinuxError::ENOMEM);
                 }
 
-                // copy the dirty page.
-                shift_mapped_page(
+                // copy the dirty page, drop the pages copied so far if it fails.
+                if let Err(e) = shift_mapped_page(
+                    &vma_map,
                     old_vma.start_addr,
                     old_vma.end_addr,
                     new_start - old_vma.start_addr,
                     true,
-                );
+                ) {
+                    let new_end = new_start + (old_vma.end_addr - old_vma.start_addr);
+                    release_pages_mapped(new_start, new_end);
+                    #[cfg(feature = "fs")]
+                    release_pages_swaped(new_start, new_end);
+                    return Err(e.into());
+                }
 
                 // copy the old to the new.
                 vma_map.insert(
@@ -491,11 +612,13 @@ pub fn sys_mremap(
 
                     // Shift mapped memory in both `MEM_MAP` and `SWAP_MAP`.
                     shift_mapped_page(
+                        &vma_map,
                         old_vma.start_addr,
                         old_vma.end_addr,
                         vaddr - old_vma.start_addr,
                         false,
-                    );
+                    )
+                    .unwrap();
 
                     // Insert the new vma.
                     vma_map.insert(vaddr, Vma::clone_from(&old_vma, vaddr, vaddr + new_size));
@@ -532,11 +655,244 @@ pub fn sys_mremap(
 /// give advice about use of memory
 /// if success return 0, if error return -1
 ///
-/// TODO: implement this to improve performance.
+/// Note: support the advice below, and `MADV_DONTFORK`/`MADV_DOFORK` are
+/// ignored as there is no fork.
+/// - `MADV_NORMAL`, `MADV_RANDOM` and `MADV_SEQUENTIAL` set the read-ahead
+///   window of file mappings.
+/// - `MADV_WILLNEED` faults in the pages, from the file or swap devices.
+/// - `MADV_DONTNEED` drops the pages, the anonymous ones are zero-filled on
+///   the next access.
+/// - `MADV_FREE` frees the anonymous pages lazily, they are dropped when
+///   reclaimed, unless written again before that.
+/// - `MADV_HUGEPAGE` and `MADV_NOHUGEPAGE` for anonymous mappings.
 pub fn sys_madvise(addr: *mut c_void, len: ctypes::size_t, advice: c_int) -> c_int {
     debug!(
         "sys_madvise <= addr: {:p}, len: {}, advice: {}",
         addr, len, advice
     );
-    syscall_body!(sys_madvise, Ok(0))
+    syscall_body!(sys_madvise, {
+        let start = addr as usize;
+        let end = VirtAddr::from(start + len).align_up_4k().as_usize();
+        if !VirtAddr::from(start).is_aligned(PAGE_SIZE_4K) {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut vma_map = VMA_MAP.lock();
+        if !is_mapped(&vma_map, start, end) {
+            return Err(LinuxError::ENOMEM);
+        }
+
+        match advice as u32 {
+            ctypes::MADV_NORMAL | ctypes::MADV_RANDOM | ctypes::MADV_SEQUENTIAL => {
+                let read_ahead = match advice as u32 {
+                    ctypes::MADV_NORMAL => READ_AHEAD_PAGES,
+                    ctypes::MADV_SEQUENTIAL => READ_AHEAD_MAX_PAGES,
+                    _ => 0,
+                };
+                split_vma_at(&mut vma_map, start);
+                split_vma_at(&mut vma_map, end);
+                for (_, vma) in vma_map.range_mut(start..end) {
+                    vma.read_ahead = read_ahead;
+                }
+            }
+            // Only the faults after the advice are affected.
+            ctypes::MADV_HUGEPAGE | ctypes::MADV_NOHUGEPAGE => {
+                split_vma_at(&mut vma_map, start);
+                split_vma_at(&mut vma_map, end);
+                for (_, vma) in vma_map.range_mut(start..end) {
+                    if vma.is_anonymous() {
+                        vma.huge = advice as u32 == ctypes::MADV_HUGEPAGE;
+                    }
+                }
+            }
+            ctypes::MADV_WILLNEED => populate_range(&vma_map, start, end)?,
+            ctypes::MADV_DONTNEED => {
+                if vmas_overlapped(&vma_map, start, end).any(|vma| vma.locked) {
+                    return Err(LinuxError::EINVAL);
+                }
+                release_pages_mapped(start, end);
+                #[cfg(feature = "fs")]
+                release_pages_swaped(start, end);
+            }
+            ctypes::MADV_FREE => {
+                if vmas_overlapped(&vma_map, start, end)
+                    .any(|vma| vma.locked || !vma.is_anonymous())
+                {
+                    return Err(LinuxError::EINVAL);
+                }
+                #[cfg(feature = "fs")]
+                lazyfree_pages(start, end);
+                // The pages are never reclaimed without swap, so they are freed at once.
+                #[cfg(not(feature = "fs"))]
+                release_pages_mapped(start, end);
+            }
+            ctypes::MADV_DONTFORK | ctypes::MADV_DOFORK => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
+/// Fault in the unmapped pages in [start, end), which is mapped by the vmas.
+fn populate_range(vma_map: &BTreeMap<usize, Vma>, start: usize, end: usize) -> LinuxResult {
+    for vaddr in (start..end).step_by(PAGE_SIZE_4K) {
+        let vma = vma_map
+            .upper_bound(Bound::Included(&vaddr))
+            .value()
+            .ok_or(LinuxError::ENOMEM)?;
+        if pte_query(VirtAddr::from(vaddr)).is_err() && !fault_in_page(vma_map, vma, vaddr) {
+            return Err(LinuxError::ENOMEM);
+        }
+    }
+    Ok(())
+}
+
+/// Take the pages in [start, end) off the LRU lists if they are locked, as
+/// they are never reclaimed, or put them back if they are unlocked.
+#[cfg(feature = "fs")]
+fn update_lru(start: usize, end: usize, locked: bool) {
+    if locked {
+        reclaim::lru_remove_range(start, end);
+    } else {
+        for (&vaddr, _) in MEM_MAP.lock().range(start..end) {
+            reclaim::lru_add(vaddr);
+        }
+    }
+}
+
+/// Lock or unlock the pages in [start, start + len), the locked pages are
+/// faulted in at once unless `onfault`.
+fn mlock_range(start: usize, len: usize, locked: bool, onfault: bool) -> LinuxResult<c_int> {
+    let end = VirtAddr::from(start + len).align_up_4k().as_usize();
+    let start = VirtAddr::from(start).align_down_4k().as_usize();
+    let mut vma_map = VMA_MAP.lock();
+    if !is_mapped(&vma_map, start, end) {
+        return Err(LinuxError::ENOMEM);
+    }
+    split_vma_at(&mut vma_map, start);
+    split_vma_at(&mut vma_map, end);
+    for (_, vma) in vma_map.range_mut(start..end) {
+        vma.locked = locked;
+    }
+    if locked && !onfault {
+        populate_range(&vma_map, start, end)?;
+    }
+    #[cfg(feature = "fs")]
+    update_lru(start, end, locked);
+    Ok(0)
+}
+
+/// Lock the pages in [addr, addr + len) into memory, they are never swapped out.
+pub fn sys_mlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_mlock <= addr: {:p}, len: 0x{:x}", addr, len);
+    syscall_body!(sys_mlock, mlock_range(addr as usize, len, true, false))
+}
+
+/// Lock the pages in [addr, addr + len) into memory, they are faulted in
+/// on access with `MLOCK_ONFAULT`.
+pub fn sys_mlock2(addr: *const c_void, len: ctypes::size_t, flags: c_int) -> c_int {
+    debug!(
+        "sys_mlock2 <= addr: {:p}, len: 0x{:x}, flags: {}",
+        addr, len, flags
+    );
+    syscall_body!(sys_mlock2, {
+        let flags = flags as u32;
+        if flags & !ctypes::MLOCK_ONFAULT != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        mlock_range(addr as usize, len, true, flags != 0)
+    })
+}
+
+/// Unlock the pages in [addr, addr + len).
+pub fn sys_munlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_munlock <= addr: {:p}, len: 0x{:x}", addr, len);
+    syscall_body!(sys_munlock, mlock_range(addr as usize, len, false, false))
+}
+
+/// Lock all the current mappings with `MCL_CURRENT`, and the future ones
+/// with `MCL_FUTURE`.
+pub fn sys_mlockall(flags: c_int) -> c_int {
+    debug!("sys_mlockall <= flags: {}", flags);
+    syscall_body!(sys_mlockall, {
+        let flags = flags as u32;
+        let valid = ctypes::MCL_CURRENT | ctypes::MCL_FUTURE | ctypes::MCL_ONFAULT;
+        if flags & !valid != 0 || flags & !ctypes::MCL_ONFAULT == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let future = if flags & ctypes::MCL_FUTURE != 0 {
+            flags & (ctypes::MCL_FUTURE | ctypes::MCL_ONFAULT)
+        } else {
+            0
+        };
+        MLOCK_FUTURE.store(future, Ordering::Relaxed);
+
+        if flags & ctypes::MCL_CURRENT != 0 {
+            let mut vma_map = VMA_MAP.lock();
+            for vma in vma_map.values_mut() {
+                vma.locked = true;
+            }
+            if flags & ctypes::MCL_ONFAULT == 0 {
+                let ranges: Vec<(usize, usize)> = vma_map
+                    .values()
+                    .map(|vma| (vma.start_addr, vma.end_addr))
+                    .collect();
+                for (start, end) in ranges {
+                    populate_range(&vma_map, start, end)?;
+                }
+            }
+            #[cfg(feature = "fs")]
+            update_lru(VMA_START, VMA_END, true);
+        }
+        Ok(0)
+    })
+}
+
+/// Unlock all the mappings, and the future mappings are not locked.
+pub fn sys_munlockall() -> c_int {
+    debug!("sys_munlockall");
+    syscall_body!(sys_munlockall, {
+        MLOCK_FUTURE.store(0, Ordering::Relaxed);
+        let mut vma_map = VMA_MAP.lock();
+        for vma in vma_map.values_mut() {
+            vma.locked = false;
+        }
+        #[cfg(feature = "fs")]
+        update_lru(VMA_START, VMA_END, false);
+        Ok(0)
+    })
+}
+
+/// Report whether the pages in [addr, addr + len) are resident in memory,
+/// the pages swapped out or not faulted in yet are not.
+pub fn sys_mincore(addr: *mut c_void, len: ctypes::size_t, vec: *mut c_uchar) -> c_int {
+    debug!(
+        "sys_mincore <= addr: {:p}, len: 0x{:x}, vec: {:p}",
+        addr, len, vec
+    );
+    syscall_body!(sys_mincore, {
+        let start = addr as usize;
+        let end = VirtAddr::from(start + len).align_up_4k().as_usize();
+        if !VirtAddr::from(start).is_aligned(PAGE_SIZE_4K) {
+            return Err(LinuxError::EINVAL);
+        }
+        if vec.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let vma_map = VMA_MAP.lock();
+        if !is_mapped(&vma_map, start, end) {
+            return Err(LinuxError::ENOMEM);
+        }
+
+        let memory_map = MEM_MAP.lock();
+        for (i, vaddr) in (start..end).step_by(PAGE_SIZE_4K).enumerate() {
+            // The page may be a part of a huge page, which is recorded by its first page.
+            let huge_vaddr = vaddr & !(HUGE_PAGE_SIZE - 1);
+            let resident = memory_map.contains_key(&vaddr)
+                || (memory_map.contains_key(&huge_vaddr)
+                    && pte_query(VirtAddr::from(huge_vaddr))
+                        .is_ok_and(|(_, _, size)| size.is_huge()));
+            unsafe { *vec.add(i) = resident as c_uchar };
+        }
+        Ok(0)
+    })
 }
@@ -11,8 +11,9 @@ use crate::ctypes;
 use alloc::alloc::{alloc, dealloc};
 use core::{
     alloc::Layout,
-    ffi::{c_int, c_void},
+    ffi::{c_int, c_uchar, c_void},
 };
+use memory_addr::PAGE_SIZE_4K;
 
 use axerrno::LinuxError;
 
@@ -62,6 +63,34 @@ pub fn sys_mmap(
     })
 }
 
+/// Map `file` at `start` like `sys_mmap`, the file is not opened in the fd
+/// table, e.g. a System V shared memory segment.
+///
+/// The file is mapped by copy, so the mapping is not shared without paging.
+#[cfg(feature = "fs")]
+pub(crate) fn mmap_file(
+    start: usize,
+    len: usize,
+    _prot: u32,
+    _flags: u32,
+    file: alloc::sync::Arc<crate::imp::fs::File>,
+) -> axerrno::LinuxResult<usize> {
+    let ptr = if start != 0 {
+        start as *mut u8
+    } else {
+        let layout = Layout::from_size_align(len, 8).unwrap();
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            return Err(LinuxError::ENOMEM);
+        }
+        ptr
+    };
+    let dst = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+    dst.fill(0);
+    file.read_at(0, dst)?;
+    Ok(ptr as usize)
+}
+
 /// Deletes the mappings for the specified address range
 pub fn sys_munmap(start: *mut c_void, len: ctypes::size_t) -> c_int {
     debug!("sys_munmap <= start: {:p}, len: {}", start, len);
@@ -127,3 +156,57 @@ pub fn sys_madvise(addr: *mut c_void, len: ctypes::size_t, advice: c_int) -> c_i
     );
     syscall_body!(sys_madvise, Ok(0))
 }
+
+/// Lock the pages in [addr, addr + len) into memory.
+///
+/// Memory is never swapped out without paging, so nothing to do.
+pub fn sys_mlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_mlock <= addr: {:p}, len: {}", addr, len);
+    syscall_body!(sys_mlock, Ok(0))
+}
+
+/// Lock the pages in [addr, addr + len) into memory, see `sys_mlock`.
+pub fn sys_mlock2(addr: *const c_void, len: ctypes::size_t, flags: c_int) -> c_int {
+    debug!(
+        "sys_mlock2 <= addr: {:p}, len: {}, flags: {}",
+        addr, len, flags
+    );
+    syscall_body!(sys_mlock2, Ok(0))
+}
+
+/// Unlock the pages in [addr, addr + len), see `sys_mlock`.
+pub fn sys_munlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_munlock <= addr: {:p}, len: {}", addr, len);
+    syscall_body!(sys_munlock, Ok(0))
+}
+
+/// Lock all the mappings, see `sys_mlock`.
+pub fn sys_mlockall(flags: c_int) -> c_int {
+    debug!("sys_mlockall <= flags: {}", flags);
+    syscall_body!(sys_mlockall, Ok(0))
+}
+
+/// Unlock all the mappings, see `sys_mlock`.
+pub fn sys_munlockall() -> c_int {
+    debug!("sys_munlockall");
+    syscall_body!(sys_munlockall, Ok(0))
+}
+
+/// Report whether the pages in [addr, addr + len) are resident in memory,
+/// which they always are without paging.
+pub fn sys_mincore(addr: *mut c_void, len: ctypes::size_t, vec: *mut c_uchar) -> c_int {
+    debug!(
+        "sys_mincore <= addr: {:p}, len: {}, vec: {:p}",
+        addr, len, vec
+    );
+    syscall_body!(sys_mincore, {
+        if addr as usize % PAGE_SIZE_4K != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if vec.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        unsafe { vec.write_bytes(1, len.div_ceil(PAGE_SIZE_4K)) };
+        Ok(0)
+    })
+}
@@ -13,10 +13,57 @@ cfg_if::cfg_if! {
         #[macro_use]
         mod utils;
         mod api;
+        #[cfg(feature = "fs")]
+        pub(crate) mod page_cache;
+        #[cfg(feature = "fs")]
+        mod reclaim;
+        #[cfg(feature = "fs")]
+        pub mod swap;
         mod trap;
-        pub use self::api::{sys_madvise, sys_mmap, sys_mprotect, sys_mremap, sys_msync, sys_munmap};
+        pub use self::api::{
+            sys_madvise, sys_mincore, sys_mlock, sys_mlock2, sys_mlockall, sys_mmap, sys_mprotect,
+            sys_mremap, sys_msync, sys_munlock, sys_munlockall, sys_munmap,
+        };
+        #[cfg(feature = "fs")]
+        pub(crate) use self::api::{has_writable_shared_mapping, mmap_file};
+        #[cfg(feature = "fs")]
+        use self::utils::swap_in_device;
     }else {
         mod legacy;
-        pub use self::legacy::{sys_madvise, sys_mmap, sys_mprotect, sys_mremap, sys_msync, sys_munmap};
+        pub use self::legacy::{
+            sys_madvise, sys_mincore, sys_mlock, sys_mlock2, sys_mlockall, sys_mmap, sys_mprotect,
+            sys_mremap, sys_msync, sys_munlock, sys_munlockall, sys_munmap,
+        };
+        #[cfg(feature = "fs")]
+        pub(crate) use self::legacy::mmap_file;
+
+        /// Files are mapped by copy without paging, so no mapping writes to them.
+        #[cfg(feature = "fs")]
+        pub(crate) fn has_writable_shared_mapping(_file: &crate::imp::fs::File) -> bool {
+            false
+        }
+
+        /// Files are mapped by copy without paging, no page is shared with the regular read/write.
+        #[cfg(feature = "fs")]
+        pub(crate) mod page_cache {
+            use crate::imp::fs::File;
+
+            pub(crate) fn read_cached(_file: &File, _offset: u64, _buf: &mut [u8]) {}
+            pub(crate) fn write_cached(_file: &File, _offset: u64, _buf: &[u8]) {}
+            pub(crate) fn sync_file(_file: &File) {}
+        }
+
+        /// Pages are never swapped out without paging, only the swap devices are managed.
+        #[cfg(feature = "fs")]
+        #[allow(dead_code)]
+        pub mod swap;
+
+        #[cfg(feature = "fs")]
+        fn swap_in_device(_dev: usize) -> bool {
+            true
+        }
     }
 }
+
+#[cfg(feature = "fs")]
+pub use self::swap::{sys_swapoff, sys_swapon};
@@ -0,0 +1,415 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Page cache of the files mapped with `MAP_SHARED`.
+//!
+//! Pages are cached by VFS node and page offset. All `MAP_SHARED` mappings of
+//! a page map the same physical page, and the regular read/write of the file
+//! go through the cached pages, so they see each other's changes. Whether a
+//! page is dirty is taken from the PTEs mapping it, it is written back on
+//! `msync`/`fsync`, and when its last mapping is unmapped or evicted.
+//!
+//! The cached pages are accounted as [`MemTag::FsCache`] instead of
+//! [`MemTag::MmapPage`], until they are dropped from the cache.
+
+use crate::imp::fs::File;
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use axalloc::MemTag;
+use axfs_vfs::{VfsNodeOps, VfsNodeRef};
+use axsync::Mutex;
+use core::cmp::{max, min};
+use memory_addr::PAGE_SIZE_4K;
+use page_table::{MappingFlags, PagingResult};
+use ruxhal::{
+    mem::VirtAddr,
+    paging::{do_pte_map, pte_clear_dirty, pte_unmap_shared_page},
+};
+
+/// A page of file shared by the `MAP_SHARED` mappings.
+struct CachedPage {
+    /// The page on the linear mapping region.
+    frame: VirtAddr,
+    /// Length to write back, the last page of a mapping may pass the end of file.
+    len: usize,
+    /// Virtual addresses mapping the page.
+    mappers: Vec<usize>,
+    /// Dirty state taken from the PTEs, or set by the regular write.
+    dirty: bool,
+}
+
+/// Cached pages of a VFS node.
+struct CachedNode {
+    node: VfsNodeRef,
+    pages: BTreeMap<usize, CachedPage>, // page offset => page
+}
+
+static PAGE_CACHE: Mutex<BTreeMap<usize, CachedNode>> = Mutex::new(BTreeMap::new()); // node address => pages
+
+fn node_key(file: &File) -> usize {
+    Arc::as_ptr(&file.node) as *const u8 as usize
+}
+
+/// Map the page and clear its dirty state, as a new writable PTE counts as
+/// dirty on the architectures without hardware dirty state management.
+fn map_frame(vaddr: usize, frame: VirtAddr, flags: MappingFlags) -> PagingResult {
+    let vaddr = VirtAddr::from(vaddr);
+    do_pte_map(vaddr, frame, flags)?;
+    pte_clear_dirty(vaddr)?;
+    Ok(())
+}
+
+/// Write back the page at `offset`, never past the end of file, which may
+/// have been truncated since the page is mapped.
+fn write_back(node: &VfsNodeRef, offset: usize, page: &CachedPage) {
+    let size = match node.get_attr() {
+        Ok(attr) => attr.size() as usize,
+        Err(e) => {
+            warn!("write back page 0x{offset:x} failed: {e:?}");
+            return;
+        }
+    };
+    let len = min(page.len, size.saturating_sub(offset));
+    if len == 0 {
+        return;
+    }
+    let src = unsafe { core::slice::from_raw_parts(page.frame.as_ptr(), len) };
+    match node.write_at(offset as u64, src) {
+        Ok(actual_len) if actual_len == len => {}
+        Ok(actual_len) => {
+            warn!("write back page 0x{offset:x} len=0x{len:x} but actual_len=0x{actual_len:x}")
+        }
+        Err(e) => warn!("write back page 0x{offset:x} failed: {e:?}"),
+    }
+}
+
+/// Collect the dirty state from the PTEs of the page, write it back if dirty.
+fn sync_page(node: &VfsNodeRef, offset: usize, page: &mut CachedPage) {
+    for &vaddr in page.mappers.iter() {
+        if let Ok(true) = pte_clear_dirty(VirtAddr::from(vaddr)) {
+            page.dirty = true;
+        }
+    }
+    if page.dirty {
+        write_back(node, offset, page);
+        page.dirty = false;
+    }
+}
+
+/// Map the cached page of `file` at `offset` on `vaddr`.
+///
+/// Return `None` if the page is not cached.
+pub(crate) fn map_cached(
+    file: &File,
+    offset: usize,
+    vaddr: usize,
+    len: usize,
+    flags: MappingFlags,
+) -> Option<PagingResult> {
+    let mut cache = PAGE_CACHE.lock();
+    let page = cache.get_mut(&node_key(file))?.pages.get_mut(&offset)?;
+    if let Err(e) = map_frame(vaddr, page.frame, flags) {
+        return Some(Err(e));
+    }
+    page.mappers.push(vaddr);
+    page.len = max(page.len, len);
+    Some(Ok(()))
+}
+
+/// Cache `frame` filled with the page of `file` at `offset`, and map it on `vaddr`.
+pub(crate) fn map_new(
+    file: &File,
+    offset: usize,
+    vaddr: usize,
+    len: usize,
+    flags: MappingFlags,
+    frame: VirtAddr,
+) -> PagingResult {
+    map_frame(vaddr, frame, flags)?;
+    let mut cache = PAGE_CACHE.lock();
+    let cached = cache.entry(node_key(file)).or_insert_with(|| CachedNode {
+        node: file.node.clone(),
+        pages: BTreeMap::new(),
+    });
+    let page = CachedPage {
+        frame,
+        len,
+        mappers: vec![vaddr],
+        dirty: false,
+    };
+    if cached.pages.insert(offset, page).is_some() {
+        panic!("page 0x{offset:x} has been cached");
+    }
+    axalloc::uncharge(MemTag::MmapPage, PAGE_SIZE_4K);
+    axalloc::charge(MemTag::FsCache, PAGE_SIZE_4K);
+    Ok(())
+}
+
+/// Unmap the cached page of `file` at `offset` from `vaddr`.
+///
+/// After its last mapping is unmapped, the page is written back if dirty and
+/// dropped from the cache, then it's returned to be reused or freed.
+pub(crate) fn unmap_cached(file: &File, offset: usize, vaddr: usize) -> Option<VirtAddr> {
+    let (_, dirty) =
+        pte_unmap_shared_page(VirtAddr::from(vaddr)).expect("cached page should be mapped");
+    let key = node_key(file);
+    let mut cache = PAGE_CACHE.lock();
+    let cached = cache.get_mut(&key).expect("page should be cached");
+    let page = cached
+        .pages
+        .get_mut(&offset)
+        .expect("page should be cached");
+    page.dirty |= dirty;
+    page.mappers.retain(|&mapper| mapper != vaddr);
+    if !page.mappers.is_empty() {
+        return None;
+    }
+
+    let page = cached.pages.remove(&offset).unwrap();
+    if page.dirty {
+        write_back(&cached.node, offset, &page);
+    }
+    if cached.pages.is_empty() {
+        cache.remove(&key);
+    }
+    axalloc::uncharge(MemTag::FsCache, PAGE_SIZE_4K);
+    axalloc::charge(MemTag::MmapPage, PAGE_SIZE_4K);
+    Some(page.frame)
+}
+
+/// Unmap the cached page of `file` at `offset` from `vaddr` like
+/// [`unmap_cached`] if it's clean, for the shrinker which must not block or
+/// write back.
+///
+/// Return `None` if the cache is locked or the page is dirty, and it's left
+/// mapped. If it's written right before it's unmapped, it's left in the cache
+/// for [`release_unmapped`] to write back.
+pub(crate) fn try_unmap_clean(
+    file: &File,
+    offset: usize,
+    vaddr: usize,
+) -> Option<Option<VirtAddr>> {
+    let mut cache = PAGE_CACHE.try_lock()?;
+    let key = node_key(file);
+    let cached = cache.get_mut(&key)?;
+    let page = cached.pages.get_mut(&offset)?;
+    for &mapper in page.mappers.iter() {
+        if let Ok(true) = pte_clear_dirty(VirtAddr::from(mapper)) {
+            page.dirty = true;
+        }
+    }
+    if page.dirty {
+        return None;
+    }
+    let (_, dirty) =
+        pte_unmap_shared_page(VirtAddr::from(vaddr)).expect("cached page should be mapped");
+    page.dirty = dirty;
+    page.mappers.retain(|&mapper| mapper != vaddr);
+    if !page.mappers.is_empty() || page.dirty {
+        return Some(None);
+    }
+
+    let page = cached.pages.remove(&offset).unwrap();
+    if cached.pages.is_empty() {
+        cache.remove(&key);
+    }
+    axalloc::uncharge(MemTag::FsCache, PAGE_SIZE_4K);
+    axalloc::charge(MemTag::MmapPage, PAGE_SIZE_4K);
+    Some(Some(page.frame))
+}
+
+/// Write back and drop the cached pages without mappings, which are left by
+/// [`try_unmap_clean`], and return them to be freed.
+pub(crate) fn release_unmapped() -> Vec<VirtAddr> {
+    let mut frames = Vec::new();
+    let mut cache = PAGE_CACHE.lock();
+    cache.retain(|_, cached| {
+        let node = &cached.node;
+        cached.pages.retain(|&offset, page| {
+            if !page.mappers.is_empty() {
+                return true;
+            }
+            if page.dirty {
+                write_back(node, offset, page);
+            }
+            axalloc::uncharge(MemTag::FsCache, PAGE_SIZE_4K);
+            axalloc::charge(MemTag::MmapPage, PAGE_SIZE_4K);
+            frames.push(page.frame);
+            false
+        });
+        !cached.pages.is_empty()
+    });
+    frames
+}
+
+/// Move the mapping of the cached page of `file` at `offset` from `old` to `new`.
+pub(crate) fn remap_cached(
+    file: &File,
+    offset: usize,
+    old: usize,
+    new: usize,
+    flags: MappingFlags,
+) -> PagingResult {
+    let (frame, dirty) = pte_unmap_shared_page(VirtAddr::from(old))?;
+    let mut cache = PAGE_CACHE.lock();
+    let page = cache
+        .get_mut(&node_key(file))
+        .and_then(|cached| cached.pages.get_mut(&offset))
+        .expect("page should be cached");
+    page.dirty |= dirty;
+    for mapper in page.mappers.iter_mut().filter(|mapper| **mapper == old) {
+        *mapper = new;
+    }
+    map_frame(new, frame, flags)
+}
+
+/// Take the dirty state from the PTE of `vaddr`, before its flags are changed.
+pub(crate) fn take_dirty(file: &File, offset: usize, vaddr: usize) {
+    if let Ok(true) = pte_clear_dirty(VirtAddr::from(vaddr)) {
+        let mut cache = PAGE_CACHE.lock();
+        if let Some(page) = cache
+            .get_mut(&node_key(file))
+            .and_then(|cached| cached.pages.get_mut(&offset))
+        {
+            page.dirty = true;
+        }
+    }
+}
+
+/// Whether the cached page of `file` at `offset` needs to be written back
+/// before it's reclaimed, the dirty state is taken from its PTEs.
+pub(crate) fn is_dirty(file: &File, offset: usize) -> bool {
+    let mut cache = PAGE_CACHE.lock();
+    let Some(page) = cache
+        .get_mut(&node_key(file))
+        .and_then(|cached| cached.pages.get_mut(&offset))
+    else {
+        return false;
+    };
+    for &vaddr in page.mappers.iter() {
+        if let Ok(true) = pte_clear_dirty(VirtAddr::from(vaddr)) {
+            page.dirty = true;
+        }
+    }
+    page.dirty
+}
+
+/// Write back the cached page of `file` at `offset` if dirty, for `msync`.
+pub(crate) fn sync_cached(file: &File, offset: usize) {
+    let mut cache = PAGE_CACHE.lock();
+    if let Some(cached) = cache.get_mut(&node_key(file)) {
+        if let Some(page) = cached.pages.get_mut(&offset) {
+            sync_page(&cached.node, offset, page);
+        }
+    }
+}
+
+/// Write back all dirty cached pages of `file`, for `fsync`.
+pub(crate) fn sync_file(file: &File) {
+    let mut cache = PAGE_CACHE.lock();
+    if let Some(cached) = cache.get_mut(&node_key(file)) {
+        for (&offset, page) in cached.pages.iter_mut() {
+            sync_page(&cached.node, offset, page);
+        }
+    }
+}
+
+/// Cut off the cached pages of `file` past `size`, after it's truncated.
+///
+/// The pages stay mapped, but the part past `size` is zeroed like a hole and
+/// is no longer written back, so the file isn't extended again with stale data.
+pub(crate) fn truncate_cached(file: &File, size: u64) {
+    let size = size as usize;
+    let mut cache = PAGE_CACHE.lock();
+    let Some(cached) = cache.get_mut(&node_key(file)) else {
+        return;
+    };
+    for (&offset, page) in cached.pages.range_mut(size & !(PAGE_SIZE_4K - 1)..) {
+        let keep = size.saturating_sub(offset);
+        let tail = unsafe {
+            core::slice::from_raw_parts_mut(page.frame.as_mut_ptr().add(keep), PAGE_SIZE_4K - keep)
+        };
+        tail.fill(0);
+        page.len = min(page.len, keep);
+    }
+}
+
+/// Overlay the cached pages of `file` on `buf`, which is just read from `offset`.
+///
+/// The data is copied out before `buf` is accessed, as `buf` may be mapped
+/// and fault into the page cache.
+pub(crate) fn read_cached(file: &File, offset: u64, buf: &mut [u8]) {
+    let start = offset as usize;
+    let end = start + buf.len();
+    let mut copied = Vec::new();
+    {
+        let cache = PAGE_CACHE.lock();
+        let Some(cached) = cache.get(&node_key(file)) else {
+            return;
+        };
+        for (&page_offset, page) in cached.pages.range(start & !(PAGE_SIZE_4K - 1)..end) {
+            let (from, to) = (
+                max(start, page_offset),
+                min(end, page_offset + PAGE_SIZE_4K),
+            );
+            let src = unsafe {
+                core::slice::from_raw_parts(page.frame.as_ptr().add(from - page_offset), to - from)
+            };
+            copied.push((from - start, src.to_vec()));
+        }
+    }
+    for (pos, data) in copied {
+        buf[pos..pos + data.len()].copy_from_slice(&data);
+    }
+}
+
+/// Update the cached pages of `file` with `buf`, which is just written at `offset`.
+///
+/// The pages are marked dirty, in case they are written back with the old
+/// data in the meantime.
+pub(crate) fn write_cached(file: &File, offset: u64, buf: &[u8]) {
+    let start = offset as usize;
+    let end = start + buf.len();
+    let key = node_key(file);
+    let ranges: Vec<(usize, usize)> = match PAGE_CACHE.lock().get(&key) {
+        Some(cached) => cached
+            .pages
+            .range(start & !(PAGE_SIZE_4K - 1)..end)
+            .map(|(&page_offset, _)| {
+                (
+                    max(start, page_offset),
+                    min(end, page_offset + PAGE_SIZE_4K),
+                )
+            })
+            .collect(),
+        None => return,
+    };
+    // Copy the data before locking, as `buf` may fault into the page cache.
+    let copied: Vec<(usize, Vec<u8>)> = ranges
+        .into_iter()
+        .map(|(from, to)| (from, buf[from - start..to - start].to_vec()))
+        .collect();
+
+    let mut cache = PAGE_CACHE.lock();
+    let Some(cached) = cache.get_mut(&key) else {
+        return;
+    };
+    for (from, data) in copied {
+        let page_offset = from & !(PAGE_SIZE_4K - 1);
+        if let Some(page) = cached.pages.get_mut(&page_offset) {
+            let dst = unsafe {
+                core::slice::from_raw_parts_mut(
+                    page.frame.as_mut_ptr().add(from - page_offset),
+                    data.len(),
+                )
+            };
+            dst.copy_from_slice(&data);
+            page.dirty = true;
+        }
+    }
+}
@@ -0,0 +1,305 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Reclaim policy of the mapped pages.
+//!
+//! The pages recorded in `MEM_MAP` are kept on two LRU lists. A page faulted
+//! in starts on the inactive list, and it's promoted to the active list, i.e.
+//! the working set, if it's accessed again before it's reclaimed. The active
+//! list is aged into the inactive list to keep them balanced. The accesses are
+//! sampled from the accessed state of the PTEs, in the way of the clock
+//! algorithm.
+//!
+//! Among the unaccessed pages on the inactive list, clean file pages are
+//! reclaimed first, as they are dropped without IO, then dirty file pages,
+//! and anonymous pages which are written to the swap devices at last. The
+//! pages freed by `MADV_FREE` are reclaimed before all of them.
+//!
+//! The pages of the vmas locked by `mlock` are unevictable, they are taken
+//! off the lists, and put back after they are unlocked.
+
+use crate::imp::mmap::{
+    page_cache,
+    utils::{is_locked, PageInfo, Vma},
+};
+use alloc::collections::{BTreeMap, BTreeSet};
+use axalloc::global_allocator;
+use axsync::Mutex;
+use core::cmp::min;
+use ruxhal::{
+    mem::VirtAddr,
+    paging::{pte_clear_accessed, pte_clear_dirty},
+};
+
+/// Pages scanned at most on a list for a victim.
+const SCAN_BATCH: usize = 32;
+
+/// Pages reclaimed at most in advance at a time, to bound the latency of
+/// the page fault.
+const RECLAIM_BATCH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LruList {
+    Active,
+    Inactive,
+}
+
+/// The LRU lists of the mapped pages.
+struct PageLru {
+    /// Age of the next page added to a list, the older pages have smaller ages.
+    next_age: u64,
+    active: BTreeMap<u64, usize>,           // age => vaddr
+    inactive: BTreeMap<u64, usize>,         // age => vaddr
+    pages: BTreeMap<usize, (LruList, u64)>, // vaddr => (list, age)
+    /// Anonymous pages freed by `MADV_FREE`, which are also on the lists.
+    lazyfree: BTreeSet<usize>,
+}
+
+impl PageLru {
+    const fn new() -> Self {
+        Self {
+            next_age: 0,
+            active: BTreeMap::new(),
+            inactive: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            lazyfree: BTreeSet::new(),
+        }
+    }
+
+    fn list(&self, list: LruList) -> &BTreeMap<u64, usize> {
+        match list {
+            LruList::Active => &self.active,
+            LruList::Inactive => &self.inactive,
+        }
+    }
+
+    fn list_mut(&mut self, list: LruList) -> &mut BTreeMap<u64, usize> {
+        match list {
+            LruList::Active => &mut self.active,
+            LruList::Inactive => &mut self.inactive,
+        }
+    }
+
+    /// Add `vaddr` to the tail of `list`, it's moved if it's on a list.
+    fn add(&mut self, vaddr: usize, list: LruList) {
+        self.remove(vaddr);
+        let age = self.next_age;
+        self.next_age += 1;
+        self.list_mut(list).insert(age, vaddr);
+        self.pages.insert(vaddr, (list, age));
+    }
+
+    fn remove(&mut self, vaddr: usize) {
+        if let Some((list, age)) = self.pages.remove(&vaddr) {
+            self.list_mut(list).remove(&age);
+        }
+    }
+
+    /// The oldest pages on `list`, at most `SCAN_BATCH`. They are copied to
+    /// a buffer on the stack, as nothing is allocated under the lock.
+    fn oldest(&self, list: LruList) -> ([usize; SCAN_BATCH], usize) {
+        let mut batch = [0; SCAN_BATCH];
+        let mut len = 0;
+        for (slot, &vaddr) in batch.iter_mut().zip(self.list(list).values()) {
+            *slot = vaddr;
+            len += 1;
+        }
+        (batch, len)
+    }
+
+    /// Age the oldest pages of the active list, the unaccessed ones are moved
+    /// to the inactive list.
+    fn age_active(
+        &mut self,
+        vma_map: &BTreeMap<usize, Vma>,
+        memory_map: &BTreeMap<usize, PageInfo>,
+    ) {
+        let (batch, len) = self.oldest(LruList::Active);
+        for &vaddr in &batch[..len] {
+            if self.active.len() <= self.inactive.len() {
+                break;
+            }
+            if !memory_map.contains_key(&vaddr) || is_locked(vma_map, vaddr) {
+                self.remove(vaddr);
+            } else if test_and_clear_accessed(vaddr) {
+                self.add(vaddr, LruList::Active);
+            } else {
+                self.add(vaddr, LruList::Inactive);
+            }
+        }
+    }
+
+    /// Scan the oldest pages of the inactive list for the cheapest one to
+    /// reclaim, the accessed ones are promoted to the active list.
+    fn scan_inactive(
+        &mut self,
+        vma_map: &BTreeMap<usize, Vma>,
+        memory_map: &BTreeMap<usize, PageInfo>,
+    ) -> Option<usize> {
+        let mut victim: Option<(usize, usize)> = None; // (cost, vaddr)
+        let (batch, len) = self.oldest(LruList::Inactive);
+        for &vaddr in &batch[..len] {
+            let page_info = match memory_map.get(&vaddr) {
+                Some(page_info) if !is_locked(vma_map, vaddr) => page_info,
+                _ => {
+                    self.remove(vaddr);
+                    continue;
+                }
+            };
+            if test_and_clear_accessed(vaddr) {
+                self.add(vaddr, LruList::Active);
+                continue;
+            }
+            let cost = reclaim_cost(page_info);
+            if victim.map_or(true, |(min_cost, _)| cost < min_cost) {
+                victim = Some((cost, vaddr));
+            }
+            if cost == 0 {
+                break;
+            }
+        }
+        victim.map(|(_, vaddr)| vaddr)
+    }
+}
+
+static PAGE_LRU: Mutex<PageLru> = Mutex::new(PageLru::new());
+
+fn test_and_clear_accessed(vaddr: usize) -> bool {
+    pte_clear_accessed(VirtAddr::from(vaddr)).unwrap_or(false)
+}
+
+/// Clean file pages cost nothing to reclaim, dirty file pages are written
+/// back, and anonymous pages are written to the swap devices.
+fn reclaim_cost(page_info: &PageInfo) -> usize {
+    match page_info {
+        Some((file, offset, _)) if !page_cache::is_dirty(file, *offset) => 0,
+        Some(_) => 1,
+        None => 2,
+    }
+}
+
+/// Add the page just mapped at `vaddr` to the inactive list.
+pub(crate) fn lru_add(vaddr: usize) {
+    let mut lru = PAGE_LRU.lock();
+    lru.lazyfree.remove(&vaddr);
+    lru.add(vaddr, LruList::Inactive);
+}
+
+/// Remove the pages in [start, end) from the lists.
+pub(crate) fn lru_remove_range(start: usize, end: usize) {
+    let mut lru = PAGE_LRU.lock();
+    while let Some(&vaddr) = lru.pages.range(start..end).next().map(|(k, _)| k) {
+        lru.remove(vaddr);
+    }
+    while let Some(&vaddr) = lru.lazyfree.range(start..end).next() {
+        lru.lazyfree.remove(&vaddr);
+    }
+}
+
+/// Mark the anonymous page at `vaddr` freed by `MADV_FREE`.
+pub(crate) fn lru_lazyfree(vaddr: usize) {
+    let mut lru = PAGE_LRU.lock();
+    lru.lazyfree.insert(vaddr);
+    if !lru.pages.contains_key(&vaddr) {
+        lru.add(vaddr, LruList::Inactive);
+    }
+}
+
+/// Whether the page at `vaddr` to be reclaimed is freed by `MADV_FREE`,
+/// the mark is cleared.
+pub(crate) fn take_lazyfree(vaddr: usize) -> bool {
+    PAGE_LRU.lock().lazyfree.remove(&vaddr)
+}
+
+/// Choose a page recorded in `memory_map` to be reclaimed, and remove it
+/// from the lists. The pages of the locked vmas in `vma_map` are skipped.
+///
+/// If all of the scanned pages have been accessed, the oldest page is chosen
+/// after they are promoted, so that a page is always found if there is any.
+///
+/// The lists are scanned in place without any buffer allocated, as it's
+/// called when the memory runs short.
+pub(crate) fn pick_victim(
+    vma_map: &BTreeMap<usize, Vma>,
+    memory_map: &BTreeMap<usize, PageInfo>,
+) -> Option<usize> {
+    let mut lru = PAGE_LRU.lock();
+    let evictable = |vaddr: &usize| memory_map.contains_key(vaddr) && !is_locked(vma_map, *vaddr);
+    if let Some(vaddr) = lru.lazyfree.iter().copied().find(evictable) {
+        lru.remove(vaddr);
+        return Some(vaddr);
+    }
+    for _ in 0..2 {
+        lru.age_active(vma_map, memory_map);
+        if let Some(vaddr) = lru.scan_inactive(vma_map, memory_map) {
+            lru.remove(vaddr);
+            return Some(vaddr);
+        }
+    }
+    // The pages not on the lists, or all the scanned pages are accessed.
+    let vaddr = memory_map
+        .keys()
+        .copied()
+        .find(|vaddr| !lru.pages.contains_key(vaddr) && !is_locked(vma_map, *vaddr))
+        .or_else(|| {
+            let oldest = lru.inactive.values().chain(lru.active.values());
+            oldest.copied().find(evictable)
+        })?;
+    lru.remove(vaddr);
+    Some(vaddr)
+}
+
+/// Choose a page recorded in `memory_map` which is dropped without IO, for
+/// the shrinker, and remove it from the lists. The pages of the locked vmas
+/// in `vma_map` are skipped.
+///
+/// A clean page freed by `MADV_FREE` is chosen first, which is still mapped.
+/// Otherwise the file pages among the oldest ones of the inactive list are
+/// passed to `unmap_clean`, until one of them is unmapped as it's clean.
+///
+/// Unlike [`pick_victim`], the lists are not aged and nothing is allocated,
+/// and it gives up if the lists are locked.
+pub(crate) fn pick_clean(
+    vma_map: &BTreeMap<usize, Vma>,
+    memory_map: &BTreeMap<usize, PageInfo>,
+    mut unmap_clean: impl FnMut(usize) -> bool,
+) -> Option<usize> {
+    let mut lru = PAGE_LRU.try_lock()?;
+    let evictable = |vaddr: &usize| memory_map.contains_key(vaddr) && !is_locked(vma_map, *vaddr);
+    while let Some(vaddr) = lru.lazyfree.iter().copied().find(evictable) {
+        // It's not freed anymore if it's written since `MADV_FREE`.
+        lru.lazyfree.remove(&vaddr);
+        if !pte_clear_dirty(VirtAddr::from(vaddr)).unwrap_or(true) {
+            lru.remove(vaddr);
+            return Some(vaddr);
+        }
+    }
+    let vaddr = lru
+        .inactive
+        .values()
+        .copied()
+        .take(SCAN_BATCH)
+        .filter(|vaddr| evictable(vaddr) && matches!(memory_map.get(vaddr), Some(Some(_))))
+        .find(|&vaddr| unmap_clean(vaddr))?;
+    lru.remove(vaddr);
+    Some(vaddr)
+}
+
+/// Number of pages to be reclaimed in advance, if the free pages are below
+/// the low watermark.
+pub(crate) fn pages_to_reclaim() -> usize {
+    let available = global_allocator().available_pages();
+    if available >= ruxconfig::RECLAIM_LOW_WATERMARK {
+        return 0;
+    }
+    min(
+        ruxconfig::RECLAIM_HIGH_WATERMARK.saturating_sub(available),
+        RECLAIM_BATCH,
+    )
+}
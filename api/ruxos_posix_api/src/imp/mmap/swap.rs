@@ -0,0 +1,573 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Swap space for the anonymous pages evicted from memory.
+//!
+//! Pages are swapped out to the enabled swap devices, each of which stores
+//! the pages in page-sized slots of a [`SwapBackend`]. The devices of higher
+//! priority are used first. If no device has been enabled at the first
+//! eviction, the swap file `ruxconfig::SWAP_FILE_PATH` of
+//! `ruxconfig::SWAP_FILE_SIZE` bytes is enabled.
+
+use crate::{ctypes, utils::char_ptr_to_str};
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axsync::Mutex;
+use core::ffi::{c_char, c_int};
+use driver_block::BlockDriverOps;
+use memory_addr::PAGE_SIZE_4K;
+use ruxfs::fops::{File, OpenOptions};
+
+#[cfg(feature = "zram")]
+use alloc::collections::BTreeMap;
+
+/// Storage of the swapped pages, addressed by page-sized slots.
+pub trait SwapBackend: Send + Sync {
+    /// Type of the storage, shown in the `Type` column of `/proc/swaps`.
+    fn kind(&self) -> &'static str;
+
+    /// Number of the slots.
+    fn num_slots(&self) -> usize;
+
+    /// Reads the page in `slot` into `buf`.
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult;
+
+    /// Writes `buf` as the page in `slot`.
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult;
+
+    /// Notifies that the page in `slot` is no longer used.
+    fn free_slot(&self, _slot: usize) {}
+}
+
+/// A swap file on the file system.
+pub struct SwapFile {
+    file: File,
+    num_slots: usize,
+}
+
+impl SwapFile {
+    /// Opens the existing file at `path` as swap space, the size of the file
+    /// decides the number of slots.
+    pub fn open(path: &str) -> AxResult<Self> {
+        let mut opt = OpenOptions::new();
+        opt.read(true);
+        opt.write(true);
+        let file = File::open(path, &opt)?;
+        let num_slots = file.get_attr()?.size() as usize / PAGE_SIZE_4K;
+        Ok(Self { file, num_slots })
+    }
+
+    /// Creates the file at `path` as swap space of `size` bytes, or uses it
+    /// if it exists.
+    pub fn create(path: &str, size: usize) -> AxResult<Self> {
+        let mut opt = OpenOptions::new();
+        opt.read(true);
+        opt.write(true);
+        opt.create(true);
+        let file = File::open(path, &opt)?;
+        Ok(Self {
+            file,
+            num_slots: size / PAGE_SIZE_4K,
+        })
+    }
+}
+
+impl SwapBackend for SwapFile {
+    fn kind(&self) -> &'static str {
+        "file"
+    }
+
+    fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult {
+        let len = self.file.read_at((slot * PAGE_SIZE_4K) as u64, buf)?;
+        // The slot is never written if the file is shorter.
+        buf[len..].fill(0);
+        Ok(())
+    }
+
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult {
+        match self.file.write_at((slot * PAGE_SIZE_4K) as u64, buf)? {
+            len if len == buf.len() => Ok(()),
+            _ => ax_err!(StorageFull),
+        }
+    }
+}
+
+/// A range of blocks on a block device, such as a disk partition.
+pub struct SwapPartition<D> {
+    dev: Mutex<D>,
+    start_block: u64,
+    blocks_per_slot: u64,
+    num_slots: usize,
+}
+
+impl<D: BlockDriverOps> SwapPartition<D> {
+    /// Uses `num_blocks` blocks of `dev` starting from `start_block` as swap
+    /// space, the data on them is overwritten.
+    pub fn new(dev: D, start_block: u64, num_blocks: u64) -> AxResult<Self> {
+        let block_size = dev.block_size();
+        if block_size == 0
+            || PAGE_SIZE_4K % block_size != 0
+            || start_block + num_blocks > dev.num_blocks()
+        {
+            return ax_err!(InvalidInput);
+        }
+        let blocks_per_slot = (PAGE_SIZE_4K / block_size) as u64;
+        Ok(Self {
+            dev: Mutex::new(dev),
+            start_block,
+            blocks_per_slot,
+            num_slots: (num_blocks / blocks_per_slot) as usize,
+        })
+    }
+
+    fn block_of(&self, slot: usize) -> u64 {
+        self.start_block + slot as u64 * self.blocks_per_slot
+    }
+}
+
+impl<D: BlockDriverOps> SwapBackend for SwapPartition<D> {
+    fn kind(&self) -> &'static str {
+        "partition"
+    }
+
+    fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult {
+        self.dev
+            .lock()
+            .read_block(self.block_of(slot), buf)
+            .map_err(|_| AxError::Io)
+    }
+
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult {
+        self.dev
+            .lock()
+            .write_block(self.block_of(slot), buf)
+            .map_err(|_| AxError::Io)
+    }
+}
+
+/// A page stored in [`ZramSwap`].
+#[cfg(feature = "zram")]
+enum ZramPage {
+    /// The page is filled with zeros, nothing is stored.
+    Zero,
+    /// The page compressed by LZ4.
+    Compressed(Box<[u8]>),
+    /// The page can't be compressed, it's stored as is.
+    Raw(Box<[u8]>),
+}
+
+/// Compressed swap space in memory, like the zram device of Linux.
+///
+/// It trades CPU time for the memory of the swapped pages, instead of the
+/// slow IO of the storage.
+#[cfg(feature = "zram")]
+pub struct ZramSwap {
+    pages: Mutex<BTreeMap<usize, ZramPage>>,
+    num_slots: usize,
+}
+
+#[cfg(feature = "zram")]
+impl ZramSwap {
+    /// Creates a compressed swap space for `size` bytes of pages before
+    /// compression.
+    pub fn new(size: usize) -> Self {
+        Self {
+            pages: Mutex::new(BTreeMap::new()),
+            num_slots: size / PAGE_SIZE_4K,
+        }
+    }
+
+    /// Returns the total size of the stored pages, before and after
+    /// compression.
+    pub fn data_size(&self) -> (usize, usize) {
+        let pages = self.pages.lock();
+        let compressed = pages
+            .values()
+            .map(|page| match page {
+                ZramPage::Zero => 0,
+                ZramPage::Compressed(data) | ZramPage::Raw(data) => data.len(),
+            })
+            .sum();
+        (pages.len() * PAGE_SIZE_4K, compressed)
+    }
+}
+
+#[cfg(feature = "zram")]
+impl SwapBackend for ZramSwap {
+    fn kind(&self) -> &'static str {
+        "zram"
+    }
+
+    fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult {
+        match self.pages.lock().get(&slot) {
+            None | Some(ZramPage::Zero) => buf.fill(0),
+            Some(ZramPage::Raw(data)) => buf.copy_from_slice(data),
+            Some(ZramPage::Compressed(data)) => match lz4_flex::block::decompress_into(data, buf) {
+                Ok(len) if len == buf.len() => {}
+                _ => return ax_err!(InvalidData),
+            },
+        }
+        Ok(())
+    }
+
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult {
+        let page = if buf.iter().all(|&b| b == 0) {
+            ZramPage::Zero
+        } else {
+            let data = lz4_flex::block::compress(buf);
+            if data.len() < buf.len() {
+                ZramPage::Compressed(data.into_boxed_slice())
+            } else {
+                ZramPage::Raw(buf.into())
+            }
+        };
+        self.pages.lock().insert(slot, page);
+        Ok(())
+    }
+
+    fn free_slot(&self, slot: usize) {
+        self.pages.lock().remove(&slot);
+    }
+}
+
+/// A slot on a swap device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SwapSlot {
+    /// ID of the swap device.
+    dev: usize,
+    /// Index of the slot on the device.
+    index: usize,
+}
+
+impl SwapSlot {
+    /// Whether the slot is on the swap device `dev`.
+    pub(crate) fn on(&self, dev: usize) -> bool {
+        self.dev == dev
+    }
+}
+
+/// Bitmap of the used slots.
+struct SlotBitmap {
+    words: Vec<u64>,
+    len: usize,
+    used: usize,
+    /// Word to start searching from.
+    hint: usize,
+}
+
+impl SlotBitmap {
+    fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+            used: 0,
+            hint: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let num_words = self.words.len();
+        for i in (self.hint..num_words).chain(0..self.hint) {
+            let word = self.words[i];
+            if word == u64::MAX {
+                continue;
+            }
+            let index = i * 64 + word.trailing_ones() as usize;
+            if index >= self.len {
+                continue;
+            }
+            self.words[i] |= 1 << (index % 64);
+            self.used += 1;
+            self.hint = i;
+            return Some(index);
+        }
+        None
+    }
+
+    fn free(&mut self, index: usize) {
+        let mask = 1 << (index % 64);
+        assert!(self.words[index / 64] & mask != 0, "free unused swap slot");
+        self.words[index / 64] &= !mask;
+        self.used -= 1;
+    }
+}
+
+/// An enabled swap device.
+struct SwapDevice {
+    id: usize,
+    name: String,
+    backend: Box<dyn SwapBackend>,
+    slots: SlotBitmap,
+    priority: isize,
+    /// No more page is swapped out to the device after `swapoff` begins.
+    active: bool,
+}
+
+struct SwapDevices {
+    /// Sorted by priority from high to low.
+    devices: Vec<SwapDevice>,
+    next_id: usize,
+    /// Whether the default swap file has been tried.
+    default_tried: bool,
+}
+
+impl SwapDevices {
+    fn get(&self, dev: usize) -> &SwapDevice {
+        self.devices
+            .iter()
+            .find(|device| device.id == dev)
+            .expect("swap device of the slot should be enabled")
+    }
+
+    fn get_mut(&mut self, dev: usize) -> &mut SwapDevice {
+        self.devices
+            .iter_mut()
+            .find(|device| device.id == dev)
+            .expect("swap device of the slot should be enabled")
+    }
+
+    fn insert(&mut self, name: &str, backend: Box<dyn SwapBackend>, priority: isize) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let device = SwapDevice {
+            id,
+            name: name.into(),
+            slots: SlotBitmap::new(backend.num_slots()),
+            backend,
+            priority,
+            active: true,
+        };
+        // The devices of the same priority are used in the order of enabling.
+        let pos = self
+            .devices
+            .iter()
+            .position(|d| d.priority < priority)
+            .unwrap_or(self.devices.len());
+        self.devices.insert(pos, device);
+        id
+    }
+
+    fn enable_default(&mut self) {
+        self.default_tried = true;
+        if ruxconfig::SWAP_FILE_SIZE == 0 {
+            return;
+        }
+        match SwapFile::create(ruxconfig::SWAP_FILE_PATH, ruxconfig::SWAP_FILE_SIZE) {
+            Ok(file) => {
+                self.insert(ruxconfig::SWAP_FILE_PATH, Box::new(file), -1);
+            }
+            Err(e) => warn!(
+                "failed to create swap file {}: {:?}",
+                ruxconfig::SWAP_FILE_PATH,
+                e
+            ),
+        }
+    }
+}
+
+static SWAP_DEVICES: Mutex<SwapDevices> = Mutex::new(SwapDevices {
+    devices: Vec::new(),
+    next_id: 0,
+    default_tried: false,
+});
+
+/// Statistics of a swap device, as a line of `/proc/swaps`.
+#[derive(Debug, Clone)]
+pub struct SwapStat {
+    /// Name of the device, the path for swap files.
+    pub name: String,
+    /// Type of the storage.
+    pub kind: &'static str,
+    /// Size of the swap space in bytes.
+    pub size: usize,
+    /// Size of the used swap space in bytes.
+    pub used: usize,
+    /// Priority of the device.
+    pub priority: isize,
+}
+
+/// Enables the swap device `backend` named `name`, the devices of higher
+/// `priority` are used first.
+pub fn swapon(name: &str, backend: Box<dyn SwapBackend>, priority: isize) -> AxResult {
+    if backend.num_slots() == 0 {
+        return ax_err!(InvalidInput);
+    }
+    let mut swap = SWAP_DEVICES.lock();
+    if swap.devices.iter().any(|device| device.name == name) {
+        return ax_err!(ResourceBusy);
+    }
+    // The default swap file is not needed any more.
+    swap.default_tried = true;
+    swap.insert(name, backend, priority);
+    Ok(())
+}
+
+/// Disables the swap device named `name`, after the pages on it are swapped
+/// back into memory.
+pub fn swapoff(name: &str) -> AxResult {
+    let dev = {
+        let mut swap = SWAP_DEVICES.lock();
+        let device = swap
+            .devices
+            .iter_mut()
+            .find(|device| device.name == name && device.active)
+            .ok_or(AxError::InvalidInput)?;
+        device.active = false;
+        device.id
+    };
+    if let Err(e) = super::swap_in_device(dev) {
+        SWAP_DEVICES.lock().get_mut(dev).active = true;
+        return Err(e);
+    }
+    SWAP_DEVICES
+        .lock()
+        .devices
+        .retain(|device| device.id != dev);
+    Ok(())
+}
+
+/// Returns the statistics of the enabled swap devices.
+pub fn swap_stats() -> Vec<SwapStat> {
+    SWAP_DEVICES
+        .lock()
+        .devices
+        .iter()
+        .map(|device| SwapStat {
+            name: device.name.clone(),
+            kind: device.backend.kind(),
+            size: device.slots.len * PAGE_SIZE_4K,
+            used: device.slots.used * PAGE_SIZE_4K,
+            priority: device.priority,
+        })
+        .collect()
+}
+
+/// Returns the statistics of the enabled swap devices in the format of
+/// `/proc/swaps`.
+pub fn proc_swaps() -> String {
+    let mut content = String::from("Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n");
+    for stat in swap_stats() {
+        content += &format!(
+            "{:<40}{:<16}{:<16}{:<16}{}\n",
+            stat.name,
+            stat.kind,
+            stat.size / 1024,
+            stat.used / 1024,
+            stat.priority
+        );
+    }
+    content
+}
+
+/// Swaps out the page in `buf`, return the slot storing it.
+///
+/// Return `None` if there is no free slot on the swap devices.
+pub(crate) fn swap_out(buf: &[u8]) -> Option<SwapSlot> {
+    let mut swap = SWAP_DEVICES.lock();
+    if !swap.default_tried {
+        swap.enable_default();
+    }
+    for device in swap.devices.iter_mut().filter(|device| device.active) {
+        while let Some(index) = device.slots.alloc() {
+            match device.backend.write_slot(index, buf) {
+                Ok(()) => {
+                    return Some(SwapSlot {
+                        dev: device.id,
+                        index,
+                    })
+                }
+                // Leave the bad slot used, try the next one.
+                Err(e) => warn!(
+                    "failed to write swap slot {} of {}: {:?}",
+                    index, device.name, e
+                ),
+            }
+        }
+    }
+    None
+}
+
+/// Reads the page in `slot` into `buf`, the slot is still used.
+pub(crate) fn read_slot(slot: SwapSlot, buf: &mut [u8]) -> AxResult {
+    let swap = SWAP_DEVICES.lock();
+    let device = swap.get(slot.dev);
+    device.backend.read_slot(slot.index, buf).map_err(|e| {
+        warn!(
+            "failed to read swap slot {} of {}: {:?}",
+            slot.index, device.name, e
+        );
+        e
+    })
+}
+
+/// Frees `slot` whose page is no longer used.
+pub(crate) fn free_slot(slot: SwapSlot) {
+    let mut swap = SWAP_DEVICES.lock();
+    let device = swap.get_mut(slot.dev);
+    device.backend.free_slot(slot.index);
+    device.slots.free(slot.index);
+}
+
+/// Swaps in the page in `slot` into `buf`, and frees the slot.
+///
+/// The slot is kept if it can't be read, so that the page is not lost.
+pub(crate) fn swap_in(slot: SwapSlot, buf: &mut [u8]) -> AxResult {
+    read_slot(slot, buf)?;
+    free_slot(slot);
+    Ok(())
+}
+
+/// Copies the page in `slot` to a new slot.
+pub(crate) fn duplicate(slot: SwapSlot) -> AxResult<SwapSlot> {
+    let mut buf = [0u8; PAGE_SIZE_4K];
+    read_slot(slot, &mut buf)?;
+    swap_out(&buf).ok_or(AxError::NoMemory)
+}
+
+/// Enables the swap file at `path`.
+///
+/// Note: `SWAP_FLAG_DISCARD` is ignored.
+pub fn sys_swapon(path: *const c_char, flags: c_int) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_swapon <= path: {:?}, flags: 0x{:x}", path, flags);
+    syscall_body!(sys_swapon, {
+        let path = path?;
+        let flags = flags as u32;
+        let priority = if flags & ctypes::SWAP_FLAG_PREFER != 0 {
+            ((flags & ctypes::SWAP_FLAG_PRIO_MASK) >> ctypes::SWAP_FLAG_PRIO_SHIFT) as isize
+        } else {
+            -1
+        };
+        let file = SwapFile::open(path)?;
+        swapon(path, Box::new(file), priority)?;
+        Ok(0)
+    })
+}
+
+/// Disables the swap device at `path`.
+pub fn sys_swapoff(path: *const c_char) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_swapoff <= path: {:?}", path);
+    syscall_body!(sys_swapoff, {
+        swapoff(path?)?;
+        Ok(0)
+    })
+}
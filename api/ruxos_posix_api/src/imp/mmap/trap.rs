@@ -10,18 +10,25 @@
 #[cfg(feature = "fs")]
 use crate::{
     ctypes,
-    imp::mmap::utils::{preload_page_with_swap, read_from, BITMAP_FREE, SWAPED_MAP, SWAP_FILE},
+    imp::mmap::{
+        page_cache, reclaim, swap,
+        utils::{preload_page_with_swap, read_from, SWAPED_MAP},
+    },
 };
 #[cfg(not(feature = "fs"))]
 use ruxhal::paging::alloc_page_preload;
 
-use crate::imp::mmap::utils::{get_mflags_from_usize, MEM_MAP, VMA_MAP};
+use crate::imp::mmap::utils::{get_mflags_from_usize, Vma, HUGE_PAGE_SIZE, MEM_MAP, VMA_MAP};
+use alloc::collections::BTreeMap;
 use core::{cmp::min, ops::Bound};
 use memory_addr::PAGE_SIZE_4K;
 use page_table::MappingFlags;
 use ruxhal::{
     mem::VirtAddr,
-    paging::{do_pte_map, pte_query},
+    paging::{
+        alloc_huge_page_preload, do_pte_map, do_pte_map_huge, free_page_preload, pte_query,
+        pte_set_accessed, pte_set_dirty,
+    },
     trap::PageFaultCause,
 };
 
@@ -38,7 +45,6 @@ impl ruxhal::trap::TrapHandler for TrapHandlerImpl {
             }
 
             let vaddr = VirtAddr::from(vaddr).align_down_4k().as_usize();
-            let size = min(PAGE_SIZE_4K, vma.end_addr - vaddr);
             let map_flag = get_mflags_from_usize(vma.prot);
 
             trace!(
@@ -62,90 +68,205 @@ impl ruxhal::trap::TrapHandler for TrapHandlerImpl {
             // simultaneously trigger a page miss interrupt on the same page,
             // resulting in the page being actually mapped and causing an `AlreadyMap`
             // error
+            //
+            // It's also the write to a clean page of `MAP_SHARED` file mapping,
+            // which is write-protected to track the dirty state if the hardware
+            // doesn't, see `page_table_entry::GenericPTE::set_dirty`, and the
+            // access to a page whose accessed state is cleared for reclaim.
             if pte_query(VirtAddr::from(vaddr)).is_ok() {
+                let _ = pte_set_accessed(VirtAddr::from(vaddr));
+                if matches!(cause, PageFaultCause::WRITE) {
+                    let _ = pte_set_dirty(VirtAddr::from(vaddr));
+                }
                 return true;
             }
 
-            let mut memory_map = MEM_MAP.lock();
-            used_fs! {
-                let mut swaped_map = SWAPED_MAP.lock();
-                let mut off_pool = BITMAP_FREE.lock();
+            if !fault_in_page(&vma_map, vma, vaddr) {
+                return false;
             }
-
-            // Due to the existence of only one page table in ruxos, in
-            // order to prevent data competition in multi-threaded environ-
-            // -ments caused by adding the current virtual address to the
-            // page table, it is necessary to first map the physical address
-            // that needs to be mapped to another virtual address, and then
-            // perform operations such as filling the corresponding memory
-            // data. After completing all operations involving memory read
-            // and write, map the actual virtual addresses that need to be mapped.
-            //
-            // fake_vaddr = preload() => do_pte_map(vaddr... fake_vaddr ...)
-            #[cfg(not(feature = "fs"))]
-            let fake_vaddr = alloc_page_preload().expect("alloc memory for new page failed");
             #[cfg(feature = "fs")]
-            let fake_vaddr =
-                preload_page_with_swap(&mut memory_map, &mut swaped_map, &mut off_pool);
+            read_ahead(&vma_map, vma, vaddr);
+            true
+        } else {
+            warn!("vaddr=0x{:x?},cause=0x{:x?}", vaddr, cause);
+            false
+        }
+    }
+}
 
-            // Fill target data to assigned physical addresses, from file or zero according to mapping type
-            let dst: *mut u8 = fake_vaddr.as_mut_ptr();
-            #[cfg(feature = "fs")]
-            {
-                if let Some(off) = swaped_map.remove(&vaddr) {
-                    off_pool.push(off);
-                    read_from(&SWAP_FILE, dst, off as u64, size);
-                } else if let Some(file) = &vma.file {
-                    let off = (vma.offset + (vaddr - vma.start_addr)) as u64;
-                    read_from(file, dst, off, size);
-                } else {
-                    // Set page to 0 for anonymous mapping
-                    //
-                    // Safe because the page memory is allocated here
-                    // and the page fault exception has not exited.
-                    unsafe {
-                        dst.write_bytes(0, size);
-                    }
+/// Map the page at `vaddr` in `vma`, which is not mapped yet.
+///
+/// The page is mapped from the page cache, swapped in, read from the file or
+/// zero-filled, according to the mapping type. Return false if it fails.
+/// take care of AA-deadlock, this function should not be used after `MEM_MAP` is used.
+pub(crate) fn fault_in_page(_vma_map: &BTreeMap<usize, Vma>, vma: &Vma, vaddr: usize) -> bool {
+    let size = min(PAGE_SIZE_4K, vma.end_addr - vaddr);
+    let map_flag = get_mflags_from_usize(vma.prot);
+
+    let mut memory_map = MEM_MAP.lock();
+    used_fs! {
+        let mut swaped_map = SWAPED_MAP.lock();
+    }
+
+    // Due to the existence of only one page table in ruxos, in
+    // order to prevent data competition in multi-threaded environ-
+    // -ments caused by adding the current virtual address to the
+    // page table, it is necessary to first map the physical address
+    // that needs to be mapped to another virtual address, and then
+    // perform operations such as filling the corresponding memory
+    // data. After completing all operations involving memory read
+    // and write, map the actual virtual addresses that need to be mapped.
+    //
+    // fake_vaddr = preload() => do_pte_map(vaddr... fake_vaddr ...)
+    //
+    // Pages of `MAP_SHARED` file mapping are mapped from the page cache,
+    // and only loaded from the file if they are not cached yet.
+    #[cfg(feature = "fs")]
+    if let (Some(file), 0) = (&vma.file, vma.flags & ctypes::MAP_PRIVATE) {
+        let offset = vma.offset + (vaddr - vma.start_addr);
+        let ret = match page_cache::map_cached(file, offset, vaddr, size, map_flag) {
+            Some(ret) => ret,
+            None => {
+                let Some(fake_vaddr) =
+                    preload_page_with_swap(_vma_map, &mut memory_map, &mut swaped_map)
+                else {
+                    return false;
+                };
+                let dst: *mut u8 = fake_vaddr.as_mut_ptr();
+                // Safe because the page memory is allocated here.
+                unsafe {
+                    dst.write_bytes(0, PAGE_SIZE_4K);
+                }
+                read_from(file, dst, offset as u64, size);
+                page_cache::map_new(file, offset, vaddr, size, map_flag, fake_vaddr)
+            }
+        };
+        if ret.is_ok() {
+            memory_map.insert(vaddr, Some((file.clone(), offset, size)));
+            reclaim::lru_add(vaddr);
+        }
+        return ret.is_ok();
+    }
+
+    // Back the anonymous mapping with a huge page, if the whole huge page
+    // is inside the vma and none of its pages is mapped or swapped. Fall
+    // back to 4K pages if no contiguous memory is available.
+    let huge_vaddr = vaddr & !(HUGE_PAGE_SIZE - 1);
+    let huge_range = huge_vaddr..huge_vaddr + HUGE_PAGE_SIZE;
+    #[cfg(feature = "fs")]
+    let swapped = swaped_map.range(huge_range.clone()).next().is_some();
+    #[cfg(not(feature = "fs"))]
+    let swapped = false;
+    if vma.huge
+        && vma.start_addr <= huge_range.start
+        && huge_range.end <= vma.end_addr
+        && !swapped
+        && memory_map.range(huge_range).next().is_none()
+    {
+        if let Ok(fake_vaddr) = alloc_huge_page_preload() {
+            // Safe because the page memory is allocated here.
+            unsafe {
+                fake_vaddr.as_mut_ptr().write_bytes(0, HUGE_PAGE_SIZE);
+            }
+            if do_pte_map_huge(VirtAddr::from(huge_vaddr), fake_vaddr, map_flag).is_ok() {
+                memory_map.insert(huge_vaddr, None);
+                used_fs! {
+                    reclaim::lru_add(huge_vaddr);
                 }
+                return true;
             }
+            // The range has been mapped with 4K pages before.
+            free_page_preload(fake_vaddr, HUGE_PAGE_SIZE / PAGE_SIZE_4K);
+        }
+    }
+
+    #[cfg(not(feature = "fs"))]
+    let fake_vaddr = loop {
+        match alloc_page_preload() {
+            Ok(vaddr) => break vaddr,
+            Err(_) if axalloc::out_of_memory(PAGE_SIZE_4K) => continue,
+            Err(_) => return false,
+        }
+    };
+    #[cfg(feature = "fs")]
+    let Some(fake_vaddr) = preload_page_with_swap(_vma_map, &mut memory_map, &mut swaped_map) else {
+        return false;
+    };
 
+    // Fill target data to assigned physical addresses, from file or zero according to mapping type
+    let dst: *mut u8 = fake_vaddr.as_mut_ptr();
+    #[cfg(feature = "fs")]
+    {
+        if let Some(&slot) = swaped_map.get(&vaddr) {
+            // Fail the fault if the page can't be read back, the slot is kept.
+            if swap::swap_in(slot, unsafe {
+                core::slice::from_raw_parts_mut(dst, PAGE_SIZE_4K)
+            })
+            .is_err()
+            {
+                free_page_preload(fake_vaddr, 1);
+                return false;
+            }
+            swaped_map.remove(&vaddr);
+        } else if let Some(file) = &vma.file {
+            let off = (vma.offset + (vaddr - vma.start_addr)) as u64;
+            read_from(file, dst, off, size);
+        } else {
             // Set page to 0 for anonymous mapping
             //
             // Safe because the page memory is allocated here
             // and the page fault exception has not exited.
-            #[cfg(not(feature = "fs"))]
             unsafe {
                 dst.write_bytes(0, size);
             }
+        }
+    }
 
-            // Insert the record into `MEM_MAP` with write-back information(`None` if no need to write-back).
-            #[cfg(feature = "fs")]
-            if (vma.prot & ctypes::PROT_WRITE != 0)
-                && (vma.flags & ctypes::MAP_PRIVATE == 0)
-                && (vma.file.is_some())
-            {
-                let map_length = min(PAGE_SIZE_4K, vma.end_addr - vaddr);
-                let offset = vma.offset + (vaddr - vma.start_addr);
-                memory_map.insert(
-                    vaddr,
-                    Some((vma.file.as_ref().unwrap().clone(), offset, map_length)),
-                );
-            } else {
-                memory_map.insert(vaddr, None);
-            }
-            #[cfg(not(feature = "fs"))]
-            memory_map.insert(vaddr, None);
+    // Set page to 0 for anonymous mapping
+    //
+    // Safe because the page memory is allocated here
+    // and the page fault exception has not exited.
+    #[cfg(not(feature = "fs"))]
+    unsafe {
+        dst.write_bytes(0, size);
+    }
 
-            // Do actual mmapping for target vaddr
-            //
-            // Note: other threads can access this page of memory after this code.
-            match do_pte_map(VirtAddr::from(vaddr), fake_vaddr, map_flag) {
-                Ok(()) => true,
-                Err(_) => false,
-            }
-        } else {
-            warn!("vaddr=0x{:x?},cause=0x{:x?}", vaddr, cause);
-            false
+    // Insert the record into `MEM_MAP`, the page is private so that
+    // there is no need to write-back.
+    memory_map.insert(vaddr, None);
+    used_fs! {
+        reclaim::lru_add(vaddr);
+    }
+
+    // Do actual mmapping for target vaddr
+    //
+    // Note: other threads can access this page of memory after this code.
+    do_pte_map(VirtAddr::from(vaddr), fake_vaddr, map_flag).is_ok()
+}
+
+/// Read ahead the pages following `vaddr` of the file mapping, up to the
+/// read-ahead window of `vma` and the end of file.
+#[cfg(feature = "fs")]
+fn read_ahead(vma_map: &BTreeMap<usize, Vma>, vma: &Vma, vaddr: usize) {
+    let Some(file) = &vma.file else {
+        return;
+    };
+    let Ok(file_size) = file
+        .inner
+        .lock()
+        .get_attr()
+        .map(|attr| attr.size() as usize)
+    else {
+        return;
+    };
+    let file_end = vma.start_addr + file_size.saturating_sub(vma.offset);
+    let end = min(
+        min(vma.end_addr, file_end),
+        vaddr + (vma.read_ahead + 1) * PAGE_SIZE_4K,
+    );
+    for page in (vaddr + PAGE_SIZE_4K..end).step_by(PAGE_SIZE_4K) {
+        if pte_query(VirtAddr::from(page)).is_err() && !fault_in_page(vma_map, vma, page) {
+            break;
         }
     }
 }
@@ -10,19 +10,38 @@
 use crate::ctypes;
 
 #[cfg(feature = "fs")]
-use {crate::imp::fs::File, alloc::sync::Arc, page_table::PagingError, ruxfs::fops::OpenOptions};
+use {
+    crate::imp::{
+        fs::File,
+        mmap::{
+            page_cache, reclaim,
+            swap::{self, SwapSlot},
+        },
+    },
+    alloc::sync::Arc,
+    axerrno::ax_err,
+    page_table::PagingError,
+};
 
 use alloc::{collections::BTreeMap, vec::Vec};
+use axerrno::{AxError, AxResult};
 use axsync::Mutex;
+#[cfg(all(feature = "fs", feature = "multitask"))]
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::{
     cmp::{max, min},
     ops::Bound,
 };
 use memory_addr::PAGE_SIZE_4K;
-use page_table::MappingFlags;
+use page_table::{MappingFlags, PageSize};
+#[cfg(feature = "fs")]
+use ruxhal::paging::{free_page_preload, pte_clear_dirty};
 use ruxhal::{
     mem::VirtAddr,
-    paging::{alloc_page_preload, do_pte_map, pte_query, pte_swap_preload, pte_unmap_page},
+    paging::{
+        alloc_page_preload, do_pte_map, pte_query, pte_split_huge_page, pte_swap_preload,
+        pte_unmap_page,
+    },
 };
 
 // use `used_fs` instead of `#[cfg(feature = "fs")]{}` to cancel the scope of code.
@@ -39,24 +58,24 @@ macro_rules! used_fs {
 pub(crate) const VMA_START: usize = ruxconfig::MMAP_START_VADDR;
 pub(crate) const VMA_END: usize = ruxconfig::MMAP_END_VADDR;
 
-// TODO: move defination of `SWAP_MAX` and `SWAP_PATH` from const numbers to `ruxconfig`.
-used_fs! {
-    pub(crate) const SWAP_MAX: usize = 1024 * 1024 * 1024;
-    pub(crate) const SWAP_PATH: &str = "swap.raw\0";
-    pub(crate) static SWAPED_MAP: Mutex<BTreeMap<usize, Offset>> = Mutex::new(BTreeMap::new()); // Vaddr => (page_size, offset_at_swaped)
-    lazy_static::lazy_static! {
-        pub(crate) static ref SWAP_FILE: Arc<File> = open_swap_file(SWAP_PATH);
-        pub(crate) static ref BITMAP_FREE: Mutex<Vec<usize>> = Mutex::new((0..SWAP_MAX).step_by(PAGE_SIZE_4K).collect());
-    }
-}
+/// Size of the huge pages backing the anonymous mappings.
+pub(crate) const HUGE_PAGE_SIZE: usize = PageSize::Size2M as usize;
+
+/// Pages read ahead on the fault of file mapping, by default and with
+/// `MADV_SEQUENTIAL`.
+pub(crate) const READ_AHEAD_PAGES: usize = 4;
+pub(crate) const READ_AHEAD_MAX_PAGES: usize = 32;
+
+#[cfg(feature = "fs")]
+pub(crate) static SWAPED_MAP: Mutex<BTreeMap<usize, SwapSlot>> = Mutex::new(BTreeMap::new()); // Vaddr => slot on the swap device
 
 pub(crate) static VMA_MAP: Mutex<BTreeMap<usize, Vma>> = Mutex::new(BTreeMap::new()); // start_addr
 pub(crate) static MEM_MAP: Mutex<BTreeMap<usize, PageInfo>> = Mutex::new(BTreeMap::new()); // Vaddr => (fid, offset, page_size)
 
 #[cfg(feature = "fs")]
-type PageInfo = Option<(Arc<File>, Offset, Len)>; // (fid, offset, page_size)
+pub(crate) type PageInfo = Option<(Arc<File>, Offset, Len)>; // (fid, offset, page_size)
 #[cfg(not(feature = "fs"))]
-type PageInfo = Option<Len>; // (fid, offset, page_size)
+pub(crate) type PageInfo = Option<Len>; // (fid, offset, page_size)
 #[cfg(feature = "fs")]
 type Offset = usize;
 type Len = usize;
@@ -70,16 +89,22 @@ pub(crate) struct Vma {
     pub offset: usize,
     pub prot: u32,
     pub flags: u32,
+    /// Whether the page faults are served with huge pages if possible.
+    pub huge: bool,
+    /// Locked by `mlock`, the pages are never reclaimed.
+    pub locked: bool,
+    /// Pages following the faulting page to be read ahead from the file.
+    pub read_ahead: usize,
 }
 
 /// Impl for Vma.
 impl Vma {
-    pub(crate) fn new(_fid: i32, offset: usize, prot: u32, flags: u32) -> Self {
+    pub(crate) fn new(fid: i32, offset: usize, prot: u32, flags: u32) -> Self {
         #[cfg(feature = "fs")]
-        let file = if _fid < 0 {
+        let file = if fid < 0 {
             None
         } else {
-            Some(File::from_fd(_fid).expect("should be effective fid"))
+            Some(File::from_fd(fid).expect("should be effective fid"))
         };
         Vma {
             start_addr: 0,
@@ -89,35 +114,60 @@ impl Vma {
             offset,
             flags,
             prot,
+            // Transparent huge pages are used for anonymous mappings by default.
+            huge: fid < 0,
+            locked: false,
+            read_ahead: READ_AHEAD_PAGES,
+        }
+    }
+
+    /// Create a vma mapping `file`, which is not opened in the fd table.
+    #[cfg(feature = "fs")]
+    pub(crate) fn from_file(file: Arc<File>, offset: usize, prot: u32, flags: u32) -> Self {
+        Vma {
+            start_addr: 0,
+            end_addr: 0,
+            file: Some(file),
+            offset,
+            flags,
+            prot,
+            huge: false,
+            locked: false,
+            read_ahead: READ_AHEAD_PAGES,
         }
     }
 
+    /// Whether the vma maps no file.
+    pub(crate) fn is_anonymous(&self) -> bool {
+        #[cfg(feature = "fs")]
+        let anonymous = self.file.is_none();
+        #[cfg(not(feature = "fs"))]
+        let anonymous = true;
+        anonymous
+    }
+
     pub(crate) fn clone_from(vma: &Vma, start_addr: usize, end_addr: usize) -> Self {
+        // The file offset moves along with `start_addr` if `vma` is split.
+        let offset = if (vma.start_addr..vma.end_addr).contains(&start_addr) {
+            vma.offset + (start_addr - vma.start_addr)
+        } else {
+            vma.offset
+        };
         Vma {
             start_addr,
             end_addr,
             #[cfg(feature = "fs")]
             file: vma.file.clone(),
-            offset: vma.offset,
+            offset,
             prot: vma.prot,
-            flags: vma.prot,
+            flags: vma.flags,
+            huge: vma.huge,
+            locked: vma.locked,
+            read_ahead: vma.read_ahead,
         }
     }
 }
 
-/// open target file
-#[cfg(feature = "fs")]
-fn open_swap_file(filename: &str) -> Arc<File> {
-    let mut opt = OpenOptions::new();
-    opt.read(true);
-    opt.write(true);
-    opt.append(true);
-    opt.create(true);
-
-    let file = ruxfs::fops::File::open(filename, &opt).expect("create swap file failed");
-    Arc::new(File::new(file))
-}
-
 /// read from target file
 #[cfg(feature = "fs")]
 pub(crate) fn read_from(file: &Arc<File>, buf: *mut u8, offset: u64, len: usize) {
@@ -132,20 +182,6 @@ pub(crate) fn read_from(file: &Arc<File>, buf: *mut u8, offset: u64, len: usize)
     }
 }
 
-/// write into target file
-#[cfg(feature = "fs")]
-pub(crate) fn write_into(file: &Arc<File>, buf: *mut u8, offset: u64, len: usize) {
-    let src = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-    let actual_len = file
-        .inner
-        .lock()
-        .write_at(offset, src)
-        .expect("write_into failed");
-    if len != actual_len {
-        warn!("write_into len=0x{len:x} but actual_len=0x{actual_len:x}");
-    }
-}
-
 /// transform usize-like mmap flags to MappingFlags
 pub(crate) fn get_mflags_from_usize(prot: u32) -> MappingFlags {
     let mut mmap_prot = MappingFlags::empty();
@@ -281,51 +317,223 @@ pub(crate) fn snatch_fixed_region(
     Some(start)
 }
 
+/// Whether [start, end) is fully covered by the vmas.
+pub(crate) fn is_mapped(vma_map: &BTreeMap<usize, Vma>, start: usize, end: usize) -> bool {
+    let mut addr = start;
+    let mut node = vma_map.upper_bound(Bound::Included(&start));
+    while addr < end {
+        match node.value() {
+            Some(vma) if vma.start_addr <= addr && addr < vma.end_addr => addr = vma.end_addr,
+            _ => return false,
+        }
+        node.move_next();
+    }
+    true
+}
+
+/// The vmas overlapping [start, end).
+pub(crate) fn vmas_overlapped(
+    vma_map: &BTreeMap<usize, Vma>,
+    start: usize,
+    end: usize,
+) -> impl Iterator<Item = &Vma> {
+    vma_map
+        .range(..end)
+        .rev()
+        .map(|(_, vma)| vma)
+        .take_while(move |vma| vma.end_addr > start)
+}
+
+/// Whether `vaddr` is in a vma locked by `mlock`.
+#[cfg(feature = "fs")]
+pub(crate) fn is_locked(vma_map: &BTreeMap<usize, Vma>, vaddr: usize) -> bool {
+    vma_map
+        .upper_bound(Bound::Included(&vaddr))
+        .value()
+        .is_some_and(|vma| vma.locked && vaddr < vma.end_addr)
+}
+
+/// Split the vma containing `addr` into two at `addr`, so that the both sides
+/// can be changed separately.
+/// take care of AA-deadlock, this function should not be used after `MEM_MAP` is used.
+pub(crate) fn split_vma_at(vma_map: &mut BTreeMap<usize, Vma>, addr: usize) {
+    let right_vma = match vma_map.upper_bound_mut(Bound::Excluded(&addr)).value_mut() {
+        Some(vma) if vma.end_addr > addr => {
+            let right_vma = Vma::clone_from(vma, addr, vma.end_addr);
+            vma.end_addr = addr;
+            right_vma
+        }
+        _ => return,
+    };
+    vma_map.insert(addr, right_vma);
+}
+
+/// Split the huge page mapped at `vaddr` into 4K pages, and record each of
+/// them in `MEM_MAP`. Return false if `vaddr` is not mapped by a huge page.
+pub(crate) fn split_huge_page(memory_map: &mut BTreeMap<usize, PageInfo>, vaddr: usize) -> bool {
+    match pte_query(VirtAddr::from(vaddr)) {
+        Ok((_, _, size)) if size.is_huge() => {}
+        _ => return false,
+    }
+    pte_split_huge_page(VirtAddr::from(vaddr)).expect("split huge page failed");
+    let page_info = memory_map
+        .remove(&vaddr)
+        .expect("huge page should be recorded");
+    for page in (vaddr..vaddr + HUGE_PAGE_SIZE).step_by(PAGE_SIZE_4K) {
+        memory_map.insert(page, page_info.clone());
+        // The first page stays where the huge page is on the LRU lists.
+        #[cfg(feature = "fs")]
+        if page != vaddr {
+            reclaim::lru_add(page);
+        }
+    }
+    true
+}
+
+/// Split the huge page across `addr`, so that the pages on both sides of `addr`
+/// can be handled separately.
+pub(crate) fn split_huge_page_across(memory_map: &mut BTreeMap<usize, PageInfo>, addr: usize) {
+    let huge_vaddr = addr & !(HUGE_PAGE_SIZE - 1);
+    if huge_vaddr != addr && memory_map.contains_key(&huge_vaddr) {
+        split_huge_page(memory_map, huge_vaddr);
+    }
+}
+
 /// release the range of [start, end) in mem_map
 /// take care of AA-deadlock, this function should not be used after `MEM_MAP` is used.
 pub(crate) fn release_pages_mapped(start: usize, end: usize) {
     let mut memory_map = MEM_MAP.lock();
+    // Huge pages inside the range are released as a whole.
+    split_huge_page_across(&mut memory_map, start);
+    split_huge_page_across(&mut memory_map, end);
+    used_fs! {
+        reclaim::lru_remove_range(start, end);
+    }
     let mut removing_vaddr = Vec::new();
     for (&vaddr, _page_info) in memory_map.range(start..end) {
+        removing_vaddr.push(vaddr);
+        // The cached page is written back and freed after its last mapping is unmapped.
         #[cfg(feature = "fs")]
-        if let Some((file, offset, size)) = _page_info {
-            let src = vaddr as *mut u8;
-            write_into(file, src, *offset as u64, *size);
+        if let Some((file, offset, _)) = _page_info {
+            if let Some(frame) = page_cache::unmap_cached(file, *offset, vaddr) {
+                free_page_preload(frame, 1);
+            }
+            continue;
         }
         if pte_unmap_page(VirtAddr::from(vaddr)).is_err() {
             panic!("Release page failed when munmapping!");
         }
-        removing_vaddr.push(vaddr);
     }
     for vaddr in removing_vaddr {
         memory_map.remove(&vaddr);
     }
 }
 
-/// release the range of [start, end) in swaped-file, swaped-file should not contain file-mapping.
-/// take care of AA-deadlock, this function should not be used after `SWAPED_MAP` and `BITMAP_FREE` is used.
+/// Free the anonymous pages in [start, end) lazily for `MADV_FREE`.
+///
+/// The mapped pages are reclaimed first without being swapped out, unless
+/// they are written again before that. The swapped pages are freed at once.
+/// take care of AA-deadlock, this function should not be used after `MEM_MAP` is used.
+#[cfg(feature = "fs")]
+pub(crate) fn lazyfree_pages(start: usize, end: usize) {
+    let mut memory_map = MEM_MAP.lock();
+    let huge_pages: Vec<usize> = memory_map.range(start..end).map(|(&k, _)| k).collect();
+    split_huge_page_across(&mut memory_map, start);
+    for vaddr in huge_pages {
+        split_huge_page(&mut memory_map, vaddr);
+    }
+    for (&vaddr, _) in memory_map.range(start..end) {
+        let _ = pte_clear_dirty(VirtAddr::from(vaddr));
+        reclaim::lru_lazyfree(vaddr);
+    }
+    drop(memory_map);
+    release_pages_swaped(start, end);
+}
+
+/// release the range of [start, end) in swap devices, swap devices should not contain file-mapping.
+/// take care of AA-deadlock, this function should not be used after `SWAPED_MAP` is used.
 #[cfg(feature = "fs")]
 pub(crate) fn release_pages_swaped(start: usize, end: usize) {
     let mut swap_map = SWAPED_MAP.lock();
-    let mut off_pool = BITMAP_FREE.lock();
 
     let mut removing_vaddr = Vec::new();
-    for (&vaddr, &off) in swap_map.range(start..end) {
+    for (&vaddr, &slot) in swap_map.range(start..end) {
         removing_vaddr.push(vaddr);
-        off_pool.push(off);
+        swap::free_slot(slot);
     }
     for vaddr in removing_vaddr {
         swap_map.remove(&vaddr);
     }
 }
 
+/// Swap in the pages on the swap device `dev` and map them, for `swapoff`.
+/// Return `Err` if there is no enough memory for them, or the device fails to read.
+/// take care of AA-deadlock, this function should not be used after `VMA_MAP`, `MEM_MAP` and `SWAPED_MAP` is used.
+#[cfg(feature = "fs")]
+pub(crate) fn swap_in_device(dev: usize) -> AxResult {
+    let vma_map = VMA_MAP.lock();
+    let mut memory_map = MEM_MAP.lock();
+    let mut swaped_map = SWAPED_MAP.lock();
+
+    let swapped: Vec<(usize, SwapSlot)> = swaped_map
+        .iter()
+        .filter(|(_, slot)| slot.on(dev))
+        .map(|(&vaddr, &slot)| (vaddr, slot))
+        .collect();
+    for (vaddr, slot) in swapped {
+        // Don't swap out other pages, which may go to the same device.
+        let Ok(fake_vaddr) = alloc_page_preload() else {
+            return ax_err!(NoMemory);
+        };
+        let dst = unsafe { core::slice::from_raw_parts_mut(fake_vaddr.as_mut_ptr(), PAGE_SIZE_4K) };
+        if let Err(e) = swap::swap_in(slot, dst) {
+            free_page_preload(fake_vaddr, 1);
+            return Err(e);
+        }
+        let vma = vma_map
+            .upper_bound(Bound::Included(&vaddr))
+            .value()
+            .expect("swapped page should be in a vma");
+        do_pte_map(
+            VirtAddr::from(vaddr),
+            fake_vaddr,
+            get_mflags_from_usize(vma.prot),
+        )
+        .unwrap();
+        swaped_map.remove(&vaddr);
+        memory_map.insert(vaddr, None);
+        reclaim::lru_add(vaddr);
+    }
+    Ok(())
+}
+
 /// shift mapped the page in both MEM_MAP and SWAPED_MAP.
 /// No page fault here should be guaranteed
-pub(crate) fn shift_mapped_page(start: usize, end: usize, vma_offset: usize, copy: bool) {
+///
+/// Return `Err` if a page can't be copied because there is no free memory or swap
+/// space left, or the swap device fails to read, the pages copied so far stay
+/// mapped at the new addresses.
+pub(crate) fn shift_mapped_page(
+    _vma_map: &BTreeMap<usize, Vma>,
+    start: usize,
+    end: usize,
+    vma_offset: usize,
+    copy: bool,
+) -> AxResult {
     let mut memory_map = MEM_MAP.lock();
     used_fs! {
         let mut swaped_map = SWAPED_MAP.lock();
-        let mut off_pool = BITMAP_FREE.lock();
+    }
+
+    // The pages are moved one by one, as the new addresses may be not aligned to huge pages.
+    let huge_pages: Vec<usize> = memory_map.range(start..end).map(|(&k, _)| k).collect();
+    for vaddr in huge_pages {
+        split_huge_page(&mut memory_map, vaddr);
+    }
+    used_fs! {
+        if !copy {
+            reclaim::lru_remove_range(start, end);
+        }
     }
 
     let mut opt_buffer = Vec::new();
@@ -333,6 +541,22 @@ pub(crate) fn shift_mapped_page(start: usize, end: usize, vma_offset: usize, cop
         opt_buffer.push((start, page_info.clone()));
     }
     for (start, page_info) in opt_buffer {
+        // Pages of `MAP_SHARED` file mapping stay in the page cache, only the mapping moves.
+        #[cfg(feature = "fs")]
+        if let Some((file, offset, size)) = &page_info {
+            let (_, flags, _) = pte_query(VirtAddr::from(start)).unwrap();
+            if !copy {
+                memory_map.remove(&start);
+                page_cache::remap_cached(file, *offset, start, start + vma_offset, flags).unwrap();
+            } else {
+                page_cache::map_cached(file, *offset, start + vma_offset, *size, flags)
+                    .expect("page should be cached")
+                    .unwrap();
+            }
+            memory_map.insert(start + vma_offset, page_info.clone());
+            reclaim::lru_add(start + vma_offset);
+            continue;
+        }
         // opt for the PTE.
         let (fake_vaddr, flags) = if !copy {
             memory_map.remove(&start);
@@ -344,10 +568,10 @@ pub(crate) fn shift_mapped_page(start: usize, end: usize, vma_offset: usize, cop
             let (_, flags, _) = pte_query(VirtAddr::from(start)).unwrap();
 
             #[cfg(not(feature = "fs"))]
-            let fake_vaddr = alloc_page_preload().expect("alloc memory for new page failed");
+            let fake_vaddr = alloc_page_preload().map_err(|_| AxError::NoMemory)?;
             #[cfg(feature = "fs")]
-            let fake_vaddr =
-                preload_page_with_swap(&mut memory_map, &mut swaped_map, &mut off_pool);
+            let fake_vaddr = preload_page_with_swap(_vma_map, &mut memory_map, &mut swaped_map)
+                .ok_or(AxError::NoMemory)?;
 
             let dst = unsafe {
                 core::slice::from_raw_parts_mut(fake_vaddr.as_usize() as *mut u8, PAGE_SIZE_4K)
@@ -361,34 +585,181 @@ pub(crate) fn shift_mapped_page(start: usize, end: usize, vma_offset: usize, cop
             /* has been swapped from memory */
             {
                 used_fs! {
-                    let offset = swaped_map.get(&start).unwrap();
-                    read_from(&SWAP_FILE, start as *mut u8, *offset as u64, PAGE_SIZE_4K);
+                    if let Err(e) = swap::read_slot(*swaped_map.get(&start).unwrap(), dst) {
+                        free_page_preload(fake_vaddr, 1);
+                        return Err(e);
+                    }
                 }
             }
             (fake_vaddr, flags)
         };
         do_pte_map(VirtAddr::from(start + vma_offset), fake_vaddr, flags).unwrap();
         memory_map.insert(start + vma_offset, page_info.clone());
+        used_fs! {
+            reclaim::lru_add(start + vma_offset);
+        }
     }
 
     used_fs! {
         let mut opt_buffer = Vec::new();
-        for (&start, &off_in_swap) in swaped_map.range(start..end) {
-            opt_buffer.push((start, off_in_swap));
+        for (&start, &slot) in swaped_map.range(start..end) {
+            opt_buffer.push((start, slot));
         }
-        for (start, off_in_swap) in opt_buffer {
-            // opt for the swapped file, should copy swaped page for the new page.
-            if !copy {
+        for (start, slot) in opt_buffer {
+            // opt for the swapped page, should copy swaped page for the new page.
+            let slot = if !copy {
                 swaped_map.remove(&start);
+                slot
             } else {
-                let off_ptr = off_pool
-                    .pop()
-                    .expect("There are no free space in swap-file!");
-                let mut rw_buffer: [u8; PAGE_SIZE_4K] = [0_u8; PAGE_SIZE_4K];
-                read_from(&SWAP_FILE, rw_buffer.as_mut_ptr(), off_in_swap as u64, PAGE_SIZE_4K);
-                write_into(&SWAP_FILE, rw_buffer.as_mut_ptr(), off_ptr as u64, PAGE_SIZE_4K);
+                swap::duplicate(slot)?
+            };
+            swaped_map.insert(start + vma_offset, slot);
+        }
+    }
+    Ok(())
+}
+
+/// Evict a page chosen by the reclaim policy, huge pages are split before eviction.
+/// The pages of the vmas locked by `mlock` are never chosen.
+///
+/// Return the frame of the page if it can be reused, or `None` if it's still
+/// mapped by other `MAP_SHARED` mappings. Return `Err` if there is no page
+/// to evict, or no free space in the swap devices.
+#[cfg(feature = "fs")]
+fn evict_page(
+    vma_map: &BTreeMap<usize, Vma>,
+    memory_map: &mut BTreeMap<usize, PageInfo>,
+    swaped_map: &mut BTreeMap<usize, SwapSlot>,
+) -> AxResult<Option<VirtAddr>> {
+    let Some(vaddr) = reclaim::pick_victim(vma_map, memory_map) else {
+        return ax_err!(NoMemory);
+    };
+    split_huge_page(memory_map, vaddr);
+    match memory_map.remove(&vaddr).unwrap() {
+        // For file mapping, the mapped content will be written directly to the original file,
+        // and the page can be used only after all of its mappings are evicted.
+        Some((file, offset, _)) => Ok(page_cache::unmap_cached(&file, offset, vaddr)),
+        // For anonymous mapping, you need to save the mapped memory to the swap devices,
+        //  and record the memory address and its slot on the swap devices.
+        None => {
+            // The page freed by `MADV_FREE` is dropped, if it's not written since then.
+            if reclaim::take_lazyfree(vaddr) && !pte_clear_dirty(VirtAddr::from(vaddr)).unwrap() {
+                return Ok(Some(pte_swap_preload(VirtAddr::from(vaddr)).unwrap()));
+            }
+            let src = unsafe { core::slice::from_raw_parts(vaddr as *const u8, PAGE_SIZE_4K) };
+            let Some(slot) = swap::swap_out(src) else {
+                memory_map.insert(vaddr, None);
+                reclaim::lru_add(vaddr);
+                return ax_err!(NoMemory);
+            };
+            swaped_map.insert(vaddr, slot);
+            Ok(Some(pte_swap_preload(VirtAddr::from(vaddr)).unwrap()))
+        }
+    }
+}
+
+/// Reclaim at most `pages` mapped pages for the heap, registered by
+/// `axalloc::register_shrinker`.
+///
+/// It's called in the allocator, so it only drops the clean pages without
+/// allocating, and gives up if the maps are locked, e.g., by the current task
+/// handling a page fault. The rest of the pages are swapped out or written
+/// back by the reclaim task woken up on the next timer tick, as waking it up
+/// here may take the run queue locked by a task waiting for the shrinker, see
+/// [`start_reclaim_task`].
+#[cfg(feature = "fs")]
+pub(crate) fn shrink_mapped_pages(pages: usize) -> usize {
+    let freed = drop_clean_pages(pages);
+    #[cfg(feature = "multitask")]
+    if freed < pages {
+        RECLAIM_PENDING.store(true, Ordering::Release);
+        ruxtask::notify_on_tick(&RECLAIM_WQ);
+    }
+    freed
+}
+
+/// Drop at most `pages` clean mapped pages, which are freed at once without
+/// IO, and return the number of pages freed.
+#[cfg(feature = "fs")]
+fn drop_clean_pages(pages: usize) -> usize {
+    let Some(vma_map) = VMA_MAP.try_lock() else {
+        return 0;
+    };
+    let Some(mut memory_map) = MEM_MAP.try_lock() else {
+        return 0;
+    };
+    let mut freed = 0;
+    while freed < pages {
+        let mut unmapped = None;
+        let picked = reclaim::pick_clean(&vma_map, &memory_map, |vaddr| {
+            let Some(Some((file, offset, _))) = memory_map.get(&vaddr) else {
+                return false;
+            };
+            page_cache::try_unmap_clean(file, *offset, vaddr)
+                .map(|frame| unmapped = frame)
+                .is_some()
+        });
+        let Some(vaddr) = picked else {
+            break;
+        };
+        let frame = match memory_map.remove(&vaddr).unwrap() {
+            Some(_) => unmapped,
+            // The page freed by `MADV_FREE` is dropped.
+            None => Some(pte_swap_preload(VirtAddr::from(vaddr)).unwrap()),
+        };
+        if let Some(frame) = frame {
+            free_page_preload(frame, 1);
+            freed += 1;
+        }
+    }
+    freed
+}
+
+/// The reclaim task waits here to be woken up by the shrinker.
+#[cfg(all(feature = "fs", feature = "multitask"))]
+static RECLAIM_WQ: ruxtask::WaitQueue = ruxtask::WaitQueue::new();
+#[cfg(all(feature = "fs", feature = "multitask"))]
+static RECLAIM_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Start the task reclaiming the mapped pages for the shrinker, which swaps
+/// out the pages and writes back the dirty ones. Starting it again has no
+/// effect.
+#[cfg(all(feature = "fs", feature = "multitask"))]
+pub(crate) fn start_reclaim_task() {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let task = ruxtask::spawn_raw(
+        || loop {
+            RECLAIM_WQ.wait_until(|| RECLAIM_PENDING.swap(false, Ordering::AcqRel));
+            for frame in page_cache::release_unmapped() {
+                free_page_preload(frame, 1);
             }
-            swaped_map.insert(start + vma_offset, off_in_swap);
+            let vma_map = VMA_MAP.lock();
+            let mut memory_map = MEM_MAP.lock();
+            let mut swaped_map = SWAPED_MAP.lock();
+            reclaim_in_advance(&vma_map, &mut memory_map, &mut swaped_map);
+        },
+        "reclaim".into(),
+        ruxconfig::TASK_STACK_SIZE,
+    );
+    task.set_kernel();
+}
+
+/// Reclaim some pages if the free memory is below the low watermark, so that
+/// the pages are not reclaimed in a hurry when it runs out.
+#[cfg(feature = "fs")]
+fn reclaim_in_advance(
+    vma_map: &BTreeMap<usize, Vma>,
+    memory_map: &mut BTreeMap<usize, PageInfo>,
+    swaped_map: &mut BTreeMap<usize, SwapSlot>,
+) {
+    for _ in 0..reclaim::pages_to_reclaim() {
+        match evict_page(vma_map, memory_map, swaped_map) {
+            Ok(Some(frame)) => free_page_preload(frame, 1),
+            Ok(None) => {}
+            Err(_) => break,
         }
     }
 }
@@ -396,44 +767,33 @@ pub(crate) fn shift_mapped_page(start: usize, end: usize, vma_offset: usize, cop
 /// Allocate a section of physical memory for faulty pages
 /// Since there is only one page table in RuxOS, the return value is the starting value
 /// of a virtual address that is also mapped to the allocated physical address.
+///
+/// If the free memory is below the low watermark, some pages are reclaimed in
+/// advance, so that the pages are not reclaimed in a hurry when it runs out.
+///
+/// Return `None` if no page can be reclaimed either, after the OOM policy is
+/// applied by `axalloc::out_of_memory`.
 #[cfg(feature = "fs")]
 pub(crate) fn preload_page_with_swap(
+    vma_map: &BTreeMap<usize, Vma>,
     memory_map: &mut BTreeMap<usize, PageInfo>,
-    swaped_map: &mut BTreeMap<usize, Offset>,
-    off_pool: &mut Vec<usize>,
-) -> VirtAddr {
-    match alloc_page_preload() {
-        Ok(vaddr) => vaddr,
-        // Try to swap the mapped memory into Disk and use this segment of physical memory
-        #[cfg(feature = "fs")]
-        Err(PagingError::NoMemory) => match memory_map.pop_first() {
-            // For file mapping, the mapped content will be written directly to the original file.
-            Some((vaddr_swapped, Some((file, offset, size)))) => {
-                let offset = offset.try_into().unwrap();
-                write_into(&file, vaddr_swapped as *mut u8, offset, size);
-                pte_swap_preload(VirtAddr::from(vaddr_swapped)).unwrap()
-            }
-            // For anonymous mapping, you need to save the mapped memory to the prepared swap file,
-            //  and record the memory address and its offset in the swap file.
-            Some((vaddr_swapped, None)) => {
-                let offset_get = off_pool.pop();
-                let offset = offset_get.unwrap();
-                swaped_map.insert(vaddr_swapped, offset);
-
-                write_into(
-                    &SWAP_FILE,
-                    vaddr_swapped as *mut u8,
-                    offset as u64,
-                    PAGE_SIZE_4K,
-                );
-                pte_swap_preload(VirtAddr::from(vaddr_swapped)).unwrap()
-            }
-            _ => panic!("No memory for mmap, check if huge memory leaky exists"),
-        },
-
-        Err(ecode) => panic!(
-            "Unexpected error 0x{:x?} happening when page fault occurs!",
-            ecode
-        ),
+    swaped_map: &mut BTreeMap<usize, SwapSlot>,
+) -> Option<VirtAddr> {
+    reclaim_in_advance(vma_map, memory_map, swaped_map);
+    loop {
+        return match alloc_page_preload() {
+            Ok(vaddr) => Some(vaddr),
+            // Try to swap the mapped memory into Disk and use this segment of physical memory
+            Err(PagingError::NoMemory) => match evict_page(vma_map, memory_map, swaped_map) {
+                Ok(Some(frame)) => Some(frame),
+                Ok(None) => continue,
+                Err(_) if axalloc::out_of_memory(PAGE_SIZE_4K) => continue,
+                Err(_) => None,
+            },
+            Err(ecode) => panic!(
+                "Unexpected error 0x{:x?} happening when page fault occurs!",
+                ecode
+            ),
+        };
     }
 }
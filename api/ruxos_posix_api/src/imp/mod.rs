@@ -38,6 +38,8 @@ pub mod net;
 pub mod pipe;
 #[cfg(feature = "multitask")]
 pub mod pthread;
+#[cfg(feature = "fs")]
+pub mod shm;
 #[cfg(feature = "signal")]
 pub mod signal;
 
@@ -10,20 +10,29 @@
 use alloc::{sync::Arc, vec, vec::Vec};
 use core::ffi::{c_char, c_int, c_void};
 use core::mem::size_of;
-use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
-use axerrno::{LinuxError, LinuxResult};
+use axerrno::{AxError, LinuxError, LinuxResult};
 use axio::PollState;
-use axnet::{TcpSocket, UdpSocket};
+use axnet::{
+    AddrFamily, IcmpSocket, PacketAddr, PacketKind, PacketSocket, PacketType, RawSocket, TcpSocket,
+    UdpSocket,
+};
 use axsync::Mutex;
 use ruxfdtable::{FileLike, RuxStat};
 
 use crate::ctypes;
 use crate::utils::char_ptr_to_str;
 
+/// The hardware type of ethernet in `sockaddr_ll`.
+const ARPHRD_ETHER: u16 = 1;
+
 pub enum Socket {
     Udp(Mutex<UdpSocket>),
     Tcp(Mutex<TcpSocket>),
+    Raw(Mutex<RawSocket>),
+    Icmp(Mutex<IcmpSocket>),
+    Packet(Mutex<PacketSocket>),
 }
 
 impl Socket {
@@ -38,17 +47,30 @@ impl Socket {
             .map_err(|_| LinuxError::EINVAL)
     }
 
-    fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
+    pub(crate) fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().send(buf)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().send(buf)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().send(buf)?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().send(buf)?),
+            Socket::Packet(packetsocket) => Ok(packetsocket.lock().send_to(buf, None)?),
         }
     }
 
+    /// Whether the socket is a stream socket, so that data can be sent in
+    /// any chunks.
+    #[cfg(feature = "fs")]
+    pub(crate) fn is_stream(&self) -> bool {
+        matches!(self, Socket::Tcp(_))
+    }
+
     fn recv(&self, buf: &mut [u8], flags: i32) -> LinuxResult<usize> {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().recv_from(buf).map(|e| e.0)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf, flags)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().recv(buf)?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().recv(buf)?),
+            Socket::Packet(packetsocket) => Ok(packetsocket.lock().recv_from(buf).map(|e| e.0)?),
         }
     }
 
@@ -56,6 +78,9 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().poll()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().poll()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().poll()?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().poll()?),
+            Socket::Packet(packetsocket) => Ok(packetsocket.lock().poll()?),
         }
     }
 
@@ -63,6 +88,10 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().local_addr()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().local_addr()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().local_addr()?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().local_addr()?),
+            // link layer addresses are handled by the callers
+            Socket::Packet(_) => Err(LinuxError::EINVAL),
         }
     }
 
@@ -70,28 +99,44 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().peer_addr()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().peer_addr()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().peer_addr()?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().peer_addr()?),
+            Socket::Packet(_) => Err(LinuxError::ENOTCONN),
         }
     }
 
     fn bind(&self, addr: SocketAddr) -> LinuxResult {
         match self {
+            // TCP and UDP sockets are IPv4 only
+            Socket::Udp(_) | Socket::Tcp(_) if addr.is_ipv6() => Err(LinuxError::EAFNOSUPPORT),
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().bind(addr)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().bind(addr)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().bind(addr)?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().bind(addr)?),
+            Socket::Packet(_) => Err(LinuxError::EINVAL),
         }
     }
 
     fn connect(&self, addr: SocketAddr) -> LinuxResult {
         match self {
+            Socket::Udp(_) | Socket::Tcp(_) if addr.is_ipv6() => Err(LinuxError::EAFNOSUPPORT),
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().connect(addr)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().connect(addr)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().connect(addr)?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().connect(addr)?),
+            Socket::Packet(_) => Err(LinuxError::EOPNOTSUPP),
         }
     }
 
     fn sendto(&self, buf: &[u8], addr: SocketAddr) -> LinuxResult<usize> {
         match self {
+            Socket::Udp(_) if addr.is_ipv6() => Err(LinuxError::EAFNOSUPPORT),
             // diff: must bind before sendto
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().send_to(buf, addr)?),
             Socket::Tcp(_) => Err(LinuxError::EISCONN),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().send_to(buf, addr)?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().send_to(buf, addr)?),
+            Socket::Packet(_) => Err(LinuxError::EINVAL),
         }
     }
 
@@ -103,19 +148,35 @@ impl Socket {
                 .recv_from(buf)
                 .map(|res| (res.0, Some(res.1)))?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf, 0).map(|res| (res, None))?),
+            Socket::Raw(rawsocket) => Ok(rawsocket
+                .lock()
+                .recv_from(buf)
+                .map(|res| (res.0, Some(res.1)))?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket
+                .lock()
+                .recv_from(buf)
+                .map(|res| (res.0, Some(res.1)))?),
+            Socket::Packet(packetsocket) => Ok(packetsocket
+                .lock()
+                .recv_from(buf)
+                .map(|res| (res.0, None))?),
         }
     }
 
     fn listen(&self) -> LinuxResult {
         match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
+            Socket::Udp(_) | Socket::Raw(_) | Socket::Icmp(_) | Socket::Packet(_) => {
+                Err(LinuxError::EOPNOTSUPP)
+            }
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
         }
     }
 
     fn accept(&self) -> LinuxResult<TcpSocket> {
         match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
+            Socket::Udp(_) | Socket::Raw(_) | Socket::Icmp(_) | Socket::Packet(_) => {
+                Err(LinuxError::EOPNOTSUPP)
+            }
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().accept()?),
         }
     }
@@ -135,6 +196,22 @@ impl Socket {
                 tcpsocket.shutdown()?;
                 Ok(())
             }
+
+            Socket::Raw(rawsocket) => {
+                let rawsocket = rawsocket.lock();
+                rawsocket.peer_addr()?;
+                rawsocket.shutdown()?;
+                Ok(())
+            }
+
+            Socket::Icmp(icmpsocket) => {
+                let icmpsocket = icmpsocket.lock();
+                icmpsocket.peer_addr()?;
+                icmpsocket.shutdown()?;
+                Ok(())
+            }
+
+            Socket::Packet(_) => Err(LinuxError::EOPNOTSUPP),
         }
     }
 }
@@ -179,6 +256,9 @@ impl FileLike for Socket {
         match self {
             Socket::Udp(udpsocket) => udpsocket.lock().set_nonblocking(nonblock),
             Socket::Tcp(tcpsocket) => tcpsocket.lock().set_nonblocking(nonblock),
+            Socket::Raw(rawsocket) => rawsocket.lock().set_nonblocking(nonblock),
+            Socket::Icmp(icmpsocket) => icmpsocket.lock().set_nonblocking(nonblock),
+            Socket::Packet(packetsocket) => packetsocket.lock().set_nonblocking(nonblock),
         }
         Ok(())
     }
@@ -208,17 +288,75 @@ impl From<ctypes::sockaddr_in> for SocketAddrV4 {
     }
 }
 
-fn into_sockaddr(addr: SocketAddr) -> (ctypes::sockaddr, ctypes::socklen_t) {
-    debug!("    Sockaddr: {}", addr);
-    match addr {
-        SocketAddr::V4(addr) => (
-            unsafe { *(&ctypes::sockaddr_in::from(addr) as *const _ as *const ctypes::sockaddr) },
-            size_of::<ctypes::sockaddr>() as _,
-        ),
-        SocketAddr::V6(_) => panic!("IPv6 is not supported"),
+impl From<SocketAddrV6> for ctypes::sockaddr_in6 {
+    fn from(addr: SocketAddrV6) -> ctypes::sockaddr_in6 {
+        ctypes::sockaddr_in6 {
+            sin6_family: ctypes::AF_INET6 as u16,
+            sin6_port: addr.port().to_be(),
+            sin6_flowinfo: addr.flowinfo().to_be(),
+            sin6_addr: ctypes::in6_addr {
+                __in6_union: ctypes::in6_addr__bindgen_ty_1 {
+                    __s6_addr: addr.ip().octets(),
+                },
+            },
+            sin6_scope_id: addr.scope_id(),
+        }
+    }
+}
+
+impl From<ctypes::sockaddr_in6> for SocketAddrV6 {
+    fn from(addr: ctypes::sockaddr_in6) -> SocketAddrV6 {
+        SocketAddrV6::new(
+            // SAFETY: all variants of the union are plain integers.
+            unsafe { addr.sin6_addr.__in6_union.__s6_addr }.into(),
+            u16::from_be(addr.sin6_port),
+            u32::from_be(addr.sin6_flowinfo),
+            addr.sin6_scope_id,
+        )
     }
 }
 
+impl From<PacketAddr> for ctypes::sockaddr_ll {
+    fn from(addr: PacketAddr) -> ctypes::sockaddr_ll {
+        let mut sll_addr = [0; 8];
+        sll_addr[..6].copy_from_slice(&addr.addr);
+        ctypes::sockaddr_ll {
+            sll_family: ctypes::AF_PACKET as u16,
+            sll_protocol: addr.protocol.to_be(),
+            sll_ifindex: addr.ifindex,
+            sll_hatype: ARPHRD_ETHER,
+            sll_pkttype: addr.pkttype as u8,
+            sll_halen: 6,
+            sll_addr,
+        }
+    }
+}
+
+/// Writes an IPv4 or IPv6 address to `dst`, truncated to `*addrlen` bytes.
+unsafe fn write_sockaddr(
+    addr: SocketAddr,
+    dst: *mut ctypes::sockaddr,
+    addrlen: *mut ctypes::socklen_t,
+) {
+    debug!("    Sockaddr: {}", addr);
+    let (src, len) = match addr {
+        SocketAddr::V4(addr) => {
+            // `sockaddr_in` is padded to the size of `sockaddr`
+            let sin = ctypes::sockaddr_in::from(addr);
+            (&sin as *const _ as *const u8, size_of::<ctypes::sockaddr>())
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = ctypes::sockaddr_in6::from(addr);
+            (
+                &sin6 as *const _ as *const u8,
+                size_of::<ctypes::sockaddr_in6>(),
+            )
+        }
+    };
+    core::ptr::copy_nonoverlapping(src, dst as *mut u8, (*addrlen as usize).min(len));
+    *addrlen = len as _;
+}
+
 fn from_sockaddr(
     addr: *const ctypes::sockaddr,
     addrlen: ctypes::socklen_t,
@@ -226,18 +364,54 @@ fn from_sockaddr(
     if addr.is_null() {
         return Err(LinuxError::EFAULT);
     }
-    if addrlen != size_of::<ctypes::sockaddr>() as _ {
+    let res = match unsafe { (*addr).sa_family } as u32 {
+        ctypes::AF_INET if addrlen == size_of::<ctypes::sockaddr>() as _ => {
+            SocketAddr::V4(unsafe { *(addr as *const ctypes::sockaddr_in) }.into())
+        }
+        ctypes::AF_INET6 if addrlen as usize >= size_of::<ctypes::sockaddr_in6>() => {
+            SocketAddr::V6(unsafe { *(addr as *const ctypes::sockaddr_in6) }.into())
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    debug!("    load sockaddr:{:#x} => {:?}", addr as usize, res);
+    Ok(res)
+}
+
+/// Writes a link layer address to `addr`, truncated to `*addrlen` bytes.
+unsafe fn write_sockaddr_ll(
+    packet_addr: PacketAddr,
+    addr: *mut ctypes::sockaddr,
+    addrlen: *mut ctypes::socklen_t,
+) {
+    let sll = ctypes::sockaddr_ll::from(packet_addr);
+    let len = (*addrlen as usize).min(size_of::<ctypes::sockaddr_ll>());
+    core::ptr::copy_nonoverlapping(&sll as *const _ as *const u8, addr as *mut u8, len);
+    *addrlen = size_of::<ctypes::sockaddr_ll>() as _;
+}
+
+fn from_sockaddr_ll(
+    addr: *const ctypes::sockaddr,
+    addrlen: ctypes::socklen_t,
+) -> LinuxResult<PacketAddr> {
+    if addr.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if (addrlen as usize) < size_of::<ctypes::sockaddr_ll>() {
         return Err(LinuxError::EINVAL);
     }
 
-    let mid = unsafe { *(addr as *const ctypes::sockaddr_in) };
-    if mid.sin_family != ctypes::AF_INET as u16 {
+    let sll = unsafe { *(addr as *const ctypes::sockaddr_ll) };
+    if sll.sll_family != ctypes::AF_PACKET as u16 {
         return Err(LinuxError::EINVAL);
     }
-
-    let res = SocketAddr::V4(mid.into());
-    debug!("    load sockaddr:{:#x} => {:?}", addr as usize, res);
-    Ok(res)
+    let mut hw_addr = [0; 6];
+    hw_addr.copy_from_slice(&sll.sll_addr[..6]);
+    Ok(PacketAddr {
+        protocol: u16::from_be(sll.sll_protocol),
+        ifindex: sll.sll_ifindex,
+        pkttype: PacketType::Host,
+        addr: hw_addr,
+    })
 }
 
 /// Create an socket for communication.
@@ -262,6 +436,39 @@ pub fn sys_socket(domain: c_int, socktype: c_int, protocol: c_int) -> c_int {
                 tcp_socket.set_nonblocking(true);
                 Socket::Tcp(Mutex::new(tcp_socket)).add_to_fd_table()
             }
+            // "ping sockets", which send ICMP echo requests without privileges
+            (ctypes::AF_INET, ctypes::SOCK_DGRAM, ctypes::IPPROTO_ICMP) => {
+                Socket::Icmp(Mutex::new(IcmpSocket::new())).add_to_fd_table()
+            }
+            (ctypes::AF_INET6, ctypes::SOCK_DGRAM, ctypes::IPPROTO_ICMPV6) => {
+                Socket::Icmp(Mutex::new(IcmpSocket::new_v6())).add_to_fd_table()
+            }
+            // TCP and UDP segments are handled by the network stack, which
+            // does not pass them to raw sockets
+            (ctypes::AF_INET | ctypes::AF_INET6, ctypes::SOCK_RAW, 0)
+            | (ctypes::AF_INET | ctypes::AF_INET6, ctypes::SOCK_RAW, ctypes::IPPROTO_TCP)
+            | (ctypes::AF_INET | ctypes::AF_INET6, ctypes::SOCK_RAW, ctypes::IPPROTO_UDP) => {
+                Err(LinuxError::EPROTONOSUPPORT)
+            }
+            (ctypes::AF_INET, ctypes::SOCK_RAW, protocol) if protocol <= ctypes::IPPROTO_RAW => {
+                Socket::Raw(Mutex::new(RawSocket::new(protocol as u8))).add_to_fd_table()
+            }
+            (ctypes::AF_INET6, ctypes::SOCK_RAW, protocol) if protocol <= ctypes::IPPROTO_RAW => {
+                Socket::Raw(Mutex::new(RawSocket::new_v6(protocol as u8))).add_to_fd_table()
+            }
+            (ctypes::AF_PACKET, ctypes::SOCK_RAW, protocol) => {
+                // the protocol is in network byte order
+                let protocol = u16::from_be(protocol as u16);
+                let socket = PacketSocket::new(PacketKind::Raw, protocol);
+                Socket::Packet(Mutex::new(socket)).add_to_fd_table()
+            }
+            (ctypes::AF_PACKET, ctypes::SOCK_DGRAM, protocol) => {
+                let protocol = u16::from_be(protocol as u16);
+                let socket = PacketSocket::new(PacketKind::Dgram, protocol);
+                Socket::Packet(Mutex::new(socket)).add_to_fd_table()
+            }
+            // only raw and ping sockets support IPv6
+            (ctypes::AF_INET6, _, _) => Err(LinuxError::EAFNOSUPPORT),
             _ => Err(LinuxError::EINVAL),
         }
     })
@@ -297,8 +504,15 @@ pub fn sys_bind(
         socket_fd, socket_addr as usize, addrlen
     );
     syscall_body!(sys_bind, {
+        let socket = Socket::from_fd(socket_fd)?;
+        if let Socket::Packet(packetsocket) = &*socket {
+            packetsocket
+                .lock()
+                .bind(from_sockaddr_ll(socket_addr, addrlen)?)?;
+            return Ok(0);
+        }
         let addr = from_sockaddr(socket_addr, addrlen)?;
-        Socket::from_fd(socket_fd)?.bind(addr)?;
+        socket.bind(addr)?;
         Ok(0)
     })
 }
@@ -345,9 +559,14 @@ pub fn sys_sendto(
         if buf_ptr.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        let addr = from_sockaddr(socket_addr, addrlen)?;
         let buf = unsafe { core::slice::from_raw_parts(buf_ptr as *const u8, len) };
-        Socket::from_fd(socket_fd)?.sendto(buf, addr)
+        let socket = Socket::from_fd(socket_fd)?;
+        if let Socket::Packet(packetsocket) = &*socket {
+            let addr = from_sockaddr_ll(socket_addr, addrlen)?;
+            return Ok(packetsocket.lock().send_to(buf, Some(addr))?);
+        }
+        let addr = from_sockaddr(socket_addr, addrlen)?;
+        socket.sendto(buf, addr)
     })
 }
 
@@ -399,11 +618,14 @@ pub unsafe fn sys_recvfrom(
         let socket = Socket::from_fd(socket_fd)?;
         let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr as *mut u8, len) };
 
+        if let Socket::Packet(packetsocket) = &*socket {
+            let (len, addr) = packetsocket.lock().recv_from(buf)?;
+            unsafe { write_sockaddr_ll(addr, socket_addr, addrlen) };
+            return Ok(len);
+        }
         let res = socket.recvfrom(buf)?;
         if let Some(addr) = res.1 {
-            unsafe {
-                (*socket_addr, *addrlen) = into_sockaddr(addr);
-            }
+            unsafe { write_sockaddr(addr, socket_addr, addrlen) };
         }
         Ok(res.0)
     })
@@ -465,9 +687,7 @@ pub unsafe fn sys_accept(
         let new_socket = socket.accept()?;
         let addr = new_socket.peer_addr()?;
         let new_fd = Socket::add_to_fd_table(Socket::Tcp(Mutex::new(new_socket)))?;
-        unsafe {
-            (*socket_addr, *socket_len) = into_sockaddr(addr);
-        }
+        unsafe { write_sockaddr(addr, socket_addr, socket_len) };
         Ok(new_fd)
     })
 }
@@ -488,14 +708,14 @@ pub fn sys_shutdown(
 
 /// Query addresses for a domain name.
 ///
-/// Only IPv4. Ports are always 0. Ignore servname and hint.
-/// Results' ai_flags and ai_canonname are 0 or NULL.
+/// Only `ai_family` of hints is honored. Ports are always parsed from servname
+/// as numbers. Results' ai_flags and ai_canonname are 0 or NULL.
 ///
 /// Return address number if success.
 pub unsafe fn sys_getaddrinfo(
     nodename: *const c_char,
     servname: *const c_char,
-    _hints: *const ctypes::addrinfo,
+    hints: *const ctypes::addrinfo,
     res: *mut *mut ctypes::addrinfo,
 ) -> c_int {
     let name = char_ptr_to_str(nodename);
@@ -509,12 +729,19 @@ pub unsafe fn sys_getaddrinfo(
             return Err(LinuxError::EFAULT);
         }
 
+        let family = match unsafe { hints.as_ref() }.map_or(ctypes::AF_UNSPEC as _, |h| h.ai_family)
+        {
+            f if f == ctypes::AF_UNSPEC as c_int => AddrFamily::Any,
+            f if f == ctypes::AF_INET as c_int => AddrFamily::Ipv4,
+            f if f == ctypes::AF_INET6 as c_int => AddrFamily::Ipv6,
+            _ => return Err(LinuxError::EAFNOSUPPORT),
+        };
         let port = port.map_or(0, |p| p.parse::<u16>().unwrap_or(0));
         let ip_addrs = if let Ok(domain) = name {
-            if let Ok(a) = domain.parse::<IpAddr>() {
-                vec![a]
-            } else {
-                axnet::dns_query(domain)?
+            match axnet::lookup_host(domain, family) {
+                Ok(info) => info.addrs,
+                Err(AxError::NotFound) => vec![],
+                Err(e) => return Err(e.into()),
             }
         } else {
             vec![Ipv4Addr::LOCALHOST.into()]
@@ -527,31 +754,41 @@ pub unsafe fn sys_getaddrinfo(
 
         let mut out: Vec<ctypes::aibuf> = Vec::with_capacity(len);
         for (i, &ip) in ip_addrs.iter().enumerate().take(len) {
-            let buf = match ip {
-                IpAddr::V4(ip) => ctypes::aibuf {
-                    ai: ctypes::addrinfo {
-                        ai_family: ctypes::AF_INET as _,
-                        // TODO: This is a hard-code part, only return TCP parameters
-                        ai_socktype: ctypes::SOCK_STREAM as _,
-                        ai_protocol: ctypes::IPPROTO_TCP as _,
-                        ai_addrlen: size_of::<ctypes::sockaddr_in>() as _,
-                        ai_addr: core::ptr::null_mut(),
-                        ai_canonname: core::ptr::null_mut(),
-                        ai_next: core::ptr::null_mut(),
-                        ai_flags: 0,
-                    },
-                    sa: ctypes::aibuf_sa {
+            let (ai_family, ai_addrlen, sa) = match ip {
+                IpAddr::V4(ip) => (
+                    ctypes::AF_INET,
+                    size_of::<ctypes::sockaddr_in>(),
+                    ctypes::aibuf_sa {
                         sin: SocketAddrV4::new(ip, port).into(),
                     },
-                    slot: i as i16,
-                    lock: [0],
-                    ref_: 0,
-                },
-                _ => panic!("IPv6 is not supported"),
+                ),
+                IpAddr::V6(ip) => (
+                    ctypes::AF_INET6,
+                    size_of::<ctypes::sockaddr_in6>(),
+                    ctypes::aibuf_sa {
+                        sin6: SocketAddrV6::new(ip, port, 0, 0).into(),
+                    },
+                ),
             };
-            out.push(buf);
+            out.push(ctypes::aibuf {
+                ai: ctypes::addrinfo {
+                    ai_family: ai_family as _,
+                    // TODO: This is a hard-code part, only return TCP parameters
+                    ai_socktype: ctypes::SOCK_STREAM as _,
+                    ai_protocol: ctypes::IPPROTO_TCP as _,
+                    ai_addrlen: ai_addrlen as _,
+                    ai_addr: core::ptr::null_mut(),
+                    ai_canonname: core::ptr::null_mut(),
+                    ai_next: core::ptr::null_mut(),
+                    ai_flags: 0,
+                },
+                sa,
+                slot: i as i16,
+                lock: [0],
+                ref_: 0,
+            });
             out[i].ai.ai_addr =
-                unsafe { core::ptr::addr_of_mut!(out[i].sa.sin) as *mut ctypes::sockaddr };
+                unsafe { core::ptr::addr_of_mut!(out[i].sa) as *mut ctypes::sockaddr };
             if i > 0 {
                 out[i - 1].ai.ai_next = core::ptr::addr_of_mut!(out[i].ai);
             }
@@ -577,6 +814,70 @@ pub unsafe fn sys_freeaddrinfo(res: *mut ctypes::addrinfo) {
     drop(vec);
 }
 
+/// Convert a socket address to the host and service names.
+///
+/// The host is looked up in `/etc/hosts` and by reverse DNS query, unless
+/// `NI_NUMERICHOST` is set. The service is always the numeric port.
+pub unsafe fn sys_getnameinfo(
+    sa: *const ctypes::sockaddr,
+    salen: ctypes::socklen_t,
+    host: *mut c_char,
+    hostlen: ctypes::socklen_t,
+    serv: *mut c_char,
+    servlen: ctypes::socklen_t,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_getnameinfo <= {:#x} {} {:#x}",
+        sa as usize, salen, flags
+    );
+    syscall_body!(sys_getnameinfo, {
+        if sa.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let addr = match unsafe { (*sa).sa_family } as u32 {
+            ctypes::AF_INET => from_sockaddr(sa, salen)?,
+            ctypes::AF_INET6 if salen as usize >= size_of::<ctypes::sockaddr_in6>() => {
+                SocketAddr::V6(unsafe { *(sa as *const ctypes::sockaddr_in6) }.into())
+            }
+            ctypes::AF_INET6 => return Err(LinuxError::EINVAL),
+            _ => return Err(LinuxError::EAFNOSUPPORT),
+        };
+        if !host.is_null() && hostlen > 0 {
+            let numeric = || alloc::format!("{}", addr.ip());
+            let name = if flags & ctypes::NI_NUMERICHOST as c_int != 0 {
+                numeric()
+            } else {
+                match axnet::lookup_addr(addr.ip()) {
+                    Ok(name) => name,
+                    Err(AxError::NotFound) if flags & ctypes::NI_NAMEREQD as c_int != 0 => {
+                        return Err(LinuxError::ENOENT)
+                    }
+                    Err(AxError::NotFound) => numeric(),
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            unsafe { write_c_str(&name, host, hostlen)? };
+        }
+        if !serv.is_null() && servlen > 0 {
+            unsafe { write_c_str(&alloc::format!("{}", addr.port()), serv, servlen)? };
+        }
+        Ok(0)
+    })
+}
+
+/// Copies `s` to the C string buffer `buf` of `len` bytes, with the trailing
+/// NUL byte.
+unsafe fn write_c_str(s: &str, buf: *mut c_char, len: ctypes::socklen_t) -> LinuxResult {
+    if s.len() >= len as usize {
+        return Err(LinuxError::ENOSPC);
+    }
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, s.len() + 1) };
+    buf[..s.len()].copy_from_slice(s.as_bytes());
+    buf[s.len()] = 0;
+    Ok(())
+}
+
 /// Get current address to which the socket sockfd is bound.
 pub unsafe fn sys_getsockname(
     sock_fd: c_int,
@@ -591,12 +892,15 @@ pub unsafe fn sys_getsockname(
         if addr.is_null() || addrlen.is_null() {
             return Err(LinuxError::EFAULT);
         }
+        let socket = Socket::from_fd(sock_fd)?;
+        if let Socket::Packet(packetsocket) = &*socket {
+            unsafe { write_sockaddr_ll(packetsocket.lock().local_addr()?, addr, addrlen) };
+            return Ok(0);
+        }
         if unsafe { *addrlen } < size_of::<ctypes::sockaddr>() as u32 {
             return Err(LinuxError::EINVAL);
         }
-        unsafe {
-            (*addr, *addrlen) = into_sockaddr(Socket::from_fd(sock_fd)?.local_addr()?);
-        }
+        unsafe { write_sockaddr(socket.local_addr()?, addr, addrlen) };
         Ok(0)
     })
 }
@@ -618,9 +922,7 @@ pub unsafe fn sys_getpeername(
         if unsafe { *addrlen } < size_of::<ctypes::sockaddr>() as u32 {
             return Err(LinuxError::EINVAL);
         }
-        unsafe {
-            (*addr, *addrlen) = into_sockaddr(Socket::from_fd(sock_fd)?.peer_addr()?);
-        }
+        unsafe { write_sockaddr(Socket::from_fd(sock_fd)?.peer_addr()?, addr, addrlen) };
         Ok(0)
     })
 }
@@ -653,11 +955,22 @@ pub unsafe fn sys_sendmsg(
             }
             let buf = core::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len);
             ret += match &socket as &Socket {
-                Socket::Udp(udpsocket) => udpsocket.lock().send_to(
+                Socket::Udp(_) | Socket::Raw(_) | Socket::Icmp(_) => socket.sendto(
                     buf,
                     from_sockaddr(msg.msg_name as *const ctypes::sockaddr, msg.msg_namelen)?,
                 )?,
                 Socket::Tcp(tcpsocket) => tcpsocket.lock().send(buf)?,
+                Socket::Packet(packetsocket) => {
+                    let addr = if msg.msg_name.is_null() {
+                        None
+                    } else {
+                        Some(from_sockaddr_ll(
+                            msg.msg_name as *const ctypes::sockaddr,
+                            msg.msg_namelen,
+                        )?)
+                    };
+                    packetsocket.lock().send_to(buf, addr)?
+                }
             };
         }
         Ok(ret)
@@ -8,15 +8,15 @@
  */
 
 use alloc::sync::Arc;
-use core::ffi::c_int;
+use core::ffi::{c_int, c_uint};
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
 use ruxfdtable::{FileLike, RuxStat};
 
-use super::fd_ops::{add_file_like, close_file_like};
-use crate::{ctypes, sys_fcntl};
+use super::fd_ops::{add_file_like, close_file_like, get_file_like};
+use crate::{ctypes, sys_fcntl, utils::write_all};
 
 #[derive(Copy, Clone, PartialEq)]
 enum RingBufferStatus {
@@ -137,6 +137,9 @@ impl FileLike for Pipe {
                 } else {
                     // write end is open
                     drop(ring_buffer);
+                    // A blocking read is a cancellation point.
+                    #[cfg(feature = "multitask")]
+                    super::pthread::test_cancel();
                     // Data not ready, wait for write end
                     crate::sys_sched_yield(); // TODO: use synconize primitive
                     ring_buffer = self.buffer.lock();
@@ -269,3 +272,87 @@ pub fn sys_pipe2(fds: &mut [c_int], flag: c_int) -> c_int {
         Ok(0)
     })
 }
+
+/// Move data between a pipe and another file descriptor.
+///
+/// One of `fd_in` and `fd_out` must be a pipe, and the offset of the pipe end
+/// must be NULL. If the other end is a file, its offset is used in the same
+/// way as that of `sendfile`. At most the capacity of the pipe is moved at a
+/// time, and `flags` are ignored.
+///
+/// Return the number of bytes moved, or 0 if the write end of the input pipe
+/// is closed.
+pub unsafe fn sys_splice(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_splice <= fd_in: {}, off_in: {:#x}, fd_out: {}, off_out: {:#x}, len: {}, flags: {:#x}",
+        fd_in, off_in as usize, fd_out, off_out as usize, len, flags
+    );
+    syscall_body!(sys_splice, {
+        let file_in = get_file_like(fd_in)?;
+        let file_out = get_file_like(fd_out)?;
+        let in_is_pipe = file_in.clone().into_any().downcast::<Pipe>().is_ok();
+        let out_is_pipe = file_out.clone().into_any().downcast::<Pipe>().is_ok();
+        if !in_is_pipe && !out_is_pipe {
+            return Err(LinuxError::EINVAL);
+        }
+        if (in_is_pipe && !off_in.is_null()) || (out_is_pipe && !off_out.is_null()) {
+            return Err(LinuxError::ESPIPE);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut buf = [0; RING_BUFFER_SIZE];
+        let buf = &mut buf[..len.min(RING_BUFFER_SIZE)];
+        let len = unsafe { splice_read(&file_in, off_in, buf)? };
+        let len = unsafe { splice_write(&file_out, off_out, &buf[..len])? };
+        Ok(len as ctypes::ssize_t)
+    })
+}
+
+unsafe fn splice_read(
+    file: &Arc<dyn FileLike>,
+    offset: *mut ctypes::off_t,
+    buf: &mut [u8],
+) -> LinuxResult<usize> {
+    if offset.is_null() {
+        return file.read(buf);
+    }
+    #[cfg(feature = "fs")]
+    if let Ok(file) = file.clone().into_any().downcast::<super::fs::File>() {
+        return unsafe {
+            super::fs::with_file_offset(&file, offset, |inner, pos| {
+                file.read_locked(inner, pos, buf)
+            })
+        };
+    }
+    Err(LinuxError::ESPIPE)
+}
+
+unsafe fn splice_write(
+    file: &Arc<dyn FileLike>,
+    offset: *mut ctypes::off_t,
+    buf: &[u8],
+) -> LinuxResult<usize> {
+    if offset.is_null() {
+        return write_all(buf, |data| file.write(data));
+    }
+    #[cfg(feature = "fs")]
+    if let Ok(file) = file.clone().into_any().downcast::<super::fs::File>() {
+        return unsafe {
+            super::fs::with_file_offset(&file, offset, |inner, pos| {
+                write_all(buf, |data| {
+                    file.write_locked(inner, pos + (buf.len() - data.len()) as u64, data)
+                })
+            })
+        };
+    }
+    Err(LinuxError::ESPIPE)
+}
@@ -10,6 +10,7 @@
 use core::ffi::c_int;
 use core::mem::size_of;
 
+use super::{cancelable, test_cancel};
 use crate::{ctypes, sys_pthread_mutex_lock, sys_pthread_mutex_unlock};
 use axerrno::LinuxResult;
 use ruxtask::WaitQueue;
@@ -28,16 +29,20 @@ impl Condvar {
         }
     }
 
+    /// It is a cancellation point, the thread exits with the mutex locked
+    /// if it is canceled.
     fn wait(&self, mutex: *mut ctypes::pthread_mutex_t) -> LinuxResult {
+        test_cancel();
         let ret = sys_pthread_mutex_unlock(mutex);
         if ret < 0 {
             return Err(axerrno::LinuxError::try_from(ret).unwrap());
         }
-        self.wq.wait();
+        cancelable(|| self.wq.wait());
         let ret = sys_pthread_mutex_lock(mutex);
         if ret < 0 {
             return Err(axerrno::LinuxError::try_from(ret).unwrap());
         }
+        test_cancel();
         Ok(())
     }
 
@@ -46,17 +51,21 @@ impl Condvar {
         mutex: *mut ctypes::pthread_mutex_t,
         abstime: *const ctypes::timespec,
     ) -> LinuxResult {
+        test_cancel();
         let ret = sys_pthread_mutex_unlock(mutex);
         if ret < 0 {
             return Err(axerrno::LinuxError::try_from(ret).unwrap());
         }
-        self.wq
-            .wait_timeout_absolutely(core::time::Duration::from(unsafe { *abstime }));
+        cancelable(|| {
+            self.wq
+                .wait_timeout_absolutely(core::time::Duration::from(unsafe { *abstime }))
+        });
 
         let ret = sys_pthread_mutex_lock(mutex);
         if ret < 0 {
             return Err(axerrno::LinuxError::try_from(ret).unwrap());
         }
+        test_cancel();
         Ok(())
     }
 
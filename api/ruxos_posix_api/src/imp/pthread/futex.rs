@@ -14,7 +14,10 @@ use core::{
 
 use axerrno::{ax_err, ax_err_type, AxResult, LinuxError};
 use bitflags::bitflags;
-use ruxfutex::{futex_wait, futex_wait_bitset, futex_wake, futex_wake_bitset};
+use ruxfutex::{
+    exit_pi_state_list, futex_lock_pi, futex_owner_died, futex_requeue, futex_trylock_pi,
+    futex_unlock_pi, futex_wait, futex_wait_bitset, futex_wake, futex_wake_bitset, futex_wake_op,
+};
 
 use crate::ctypes;
 
@@ -88,24 +91,29 @@ pub fn sys_futex(
     uaddr: usize,
     op: c_uint,
     val: c_int,
-    // timeout value, should be struct timespec pointer
+    // timeout value, should be struct timespec pointer,
+    // or the max number of waiters to requeue or wake on `uaddr2`
     to: usize,
-    // used by Requeue, unused for now
-    #[allow(unused_variables)] uaddr2: c_int,
-    // bitset
+    // used by Requeue and WakeOp
+    uaddr2: usize,
+    // bitset, the expected value for CmpRequeue, or the operation for WakeOp
     val3: c_int,
 ) -> c_int {
     let futex_addr = uaddr as *const i32;
+    let futex_addr2 = uaddr2 as *const i32;
     let bitset = val3 as _;
     let max_count = val as _;
     let futex_val = val as _;
+    let max_count2 = to;
 
     syscall_body!(sys_futex, {
         let (op, _flag) = futex_op_and_flags_from_u32(op).map_err(LinuxError::from)?;
         let timeout = to as *const ctypes::timespec;
         let timeout = if !timeout.is_null()
-            && matches!(op, FutexOp::FUTEX_WAIT | FutexOp::FUTEX_WAIT_BITSET)
-        {
+            && matches!(
+                op,
+                FutexOp::FUTEX_WAIT | FutexOp::FUTEX_WAIT_BITSET | FutexOp::FUTEX_LOCK_PI
+            ) {
             let dur = unsafe { Duration::from(*timeout) };
             Some(dur)
         } else {
@@ -123,8 +131,105 @@ pub fn sys_futex(
             }
             FutexOp::FUTEX_WAKE => futex_wake(futex_addr, max_count),
             FutexOp::FUTEX_WAKE_BITSET => futex_wake_bitset(futex_addr, max_count, bitset),
+            FutexOp::FUTEX_REQUEUE => {
+                futex_requeue(futex_addr, max_count, futex_addr2, max_count2, None)
+            }
+            FutexOp::FUTEX_CMP_REQUEUE => {
+                futex_requeue(futex_addr, max_count, futex_addr2, max_count2, Some(val3))
+            }
+            FutexOp::FUTEX_WAKE_OP => {
+                futex_wake_op(futex_addr, max_count, futex_addr2, max_count2, val3 as _)
+            }
+            FutexOp::FUTEX_LOCK_PI => {
+                return futex_lock_pi(futex_addr, timeout, |tid| super::task_by_tid(tid as _).ok())
+                    .map(|_| 0);
+            }
+            FutexOp::FUTEX_TRYLOCK_PI => return futex_trylock_pi(futex_addr).map(|_| 0),
+            FutexOp::FUTEX_UNLOCK_PI => return futex_unlock_pi(futex_addr).map(|_| 0),
             _ => ax_err!(Unsupported, "unsupported futex option: {:?}", op),
         };
         ret.map_err(LinuxError::from)
     })
 }
+
+/// Max number of entries walked in a robust futex list, as Linux does.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// `struct robust_list` of Linux, the `next` pointer has its lowest bit set
+/// if the futex of the entry is a PI futex.
+#[repr(C)]
+struct RobustList {
+    next: usize,
+}
+
+/// `struct robust_list_head` of Linux.
+#[repr(C)]
+struct RobustListHead {
+    list: RobustList,
+    futex_offset: isize,
+    list_op_pending: usize,
+}
+
+/// Sets the head of the robust futex list of the current thread.
+pub fn sys_set_robust_list(head: usize, len: usize) -> c_int {
+    debug!("sys_set_robust_list <= head: {:#x}, len: {}", head, len);
+    syscall_body!(sys_set_robust_list, {
+        if len != core::mem::size_of::<RobustListHead>() {
+            return Err(LinuxError::EINVAL);
+        }
+        ruxtask::current().set_robust_list(head);
+        Ok(0)
+    })
+}
+
+/// Gets the head of the robust futex list of the thread `pid`, or the current
+/// thread if `pid` is 0.
+pub unsafe fn sys_get_robust_list(pid: c_int, head_ptr: *mut usize, len_ptr: *mut usize) -> c_int {
+    debug!("sys_get_robust_list <= pid: {}", pid);
+    syscall_body!(sys_get_robust_list, {
+        if head_ptr.is_null() || len_ptr.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let task = super::task_by_tid(pid)?;
+        unsafe {
+            *head_ptr = task.robust_list();
+            *len_ptr = core::mem::size_of::<RobustListHead>();
+        }
+        Ok(0)
+    })
+}
+
+/// Releases the robust futexes and the PI futexes still held by the exiting
+/// current thread, so that their waiters get `EOWNERDEAD`.
+pub(crate) fn exit_futexes() {
+    let curr = ruxtask::current();
+    let head_addr = curr.robust_list();
+    if head_addr != 0 {
+        curr.set_robust_list(0);
+        exit_robust_list(head_addr);
+    }
+    exit_pi_state_list();
+}
+
+fn exit_robust_list(head_addr: usize) {
+    // Safety: the list is set by the thread itself with `set_robust_list`.
+    let head = unsafe { &*(head_addr as *const RobustListHead) };
+    let futex_of = |entry: usize| (entry as isize + head.futex_offset) as *const i32;
+    let pending = head.list_op_pending;
+
+    let mut entry = head.list.next;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry & !1 != head_addr && entry != 0 && limit > 0 {
+        let (addr, pi) = (entry & !1, entry & 1 != 0);
+        // Read the next entry before the futex is released, which may free
+        // the entry by other threads.
+        entry = unsafe { (*(addr as *const RobustList)).next };
+        if addr != pending & !1 {
+            futex_owner_died(futex_of(addr), pi);
+        }
+        limit -= 1;
+    }
+    if pending & !1 != 0 {
+        futex_owner_died(futex_of(pending & !1), pending & 1 != 0);
+    }
+}
@@ -10,9 +10,10 @@
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::cell::UnsafeCell;
 use core::ffi::{c_int, c_void};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering};
 
 use axerrno::{LinuxError, LinuxResult};
-use ruxtask::AxTaskRef;
+use ruxtask::{AxTaskRef, StackAttr};
 use spin::RwLock;
 
 use crate::ctypes;
@@ -42,12 +43,12 @@ lazy_static::lazy_static! {
         let mut map = BTreeMap::new();
         let main_task = ruxtask::current();
         let main_tid = main_task.id().as_u64();
-        let main_thread = Pthread {
-            inner: main_task.as_task_ref().clone(),
-            retval: Arc::new(Packet {
+        let main_thread = Pthread::new(
+            main_task.as_task_ref().clone(),
+            Arc::new(Packet {
                 result: UnsafeCell::new(core::ptr::null_mut()),
             }),
-        };
+        );
         let ptr = Box::into_raw(Box::new(main_thread)) as *mut c_void;
         map.insert(main_tid, ForceSendSync(ptr));
         RwLock::new(map)
@@ -61,17 +62,61 @@ struct Packet<T> {
 unsafe impl<T> Send for Packet<T> {}
 unsafe impl<T> Sync for Packet<T> {}
 
+/// The value returned to the joiner of a canceled thread.
+const PTHREAD_CANCELED: *mut c_void = usize::MAX as *mut c_void;
+
+/// The thread can be joined.
+const JOINABLE: u8 = 0;
+/// The thread is detached, it releases its resources when it exits.
+const DETACHED: u8 = 1;
+/// The thread has exited but has not been joined or detached yet.
+const EXITED: u8 = 2;
+
+/// Whether any thread has ever been canceled, so that cancellation points
+/// need not look up the current thread before that.
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The cancelability state and type, and the pending cancellation request of
+/// a thread.
+#[derive(Default)]
+struct CancelState {
+    disabled: AtomicBool,
+    asynchronous: AtomicBool,
+    pending: AtomicBool,
+    /// Whether the thread is exiting by a cancellation or a kill, which is
+    /// not acted upon again.
+    exiting: AtomicBool,
+}
+
 pub struct Pthread {
     inner: AxTaskRef,
     retval: Arc<Packet<*mut c_void>>,
+    /// `JOINABLE`, `DETACHED` or `EXITED`.
+    detach_state: AtomicU8,
+    cancel: CancelState,
+    /// The cleanup handler pushed last, which is on the stack of the thread.
+    #[cfg(not(feature = "musl"))]
+    cleanup: AtomicPtr<ctypes::__ptcb>,
 }
 
 impl Pthread {
+    fn new(inner: AxTaskRef, retval: Arc<Packet<*mut c_void>>) -> Self {
+        Self {
+            inner,
+            retval,
+            detach_state: AtomicU8::new(JOINABLE),
+            cancel: CancelState::default(),
+            #[cfg(not(feature = "musl"))]
+            cleanup: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
     fn create(
-        _attr: *const ctypes::pthread_attr_t,
+        attr: *const ctypes::pthread_attr_t,
         start_routine: extern "C" fn(arg: *mut c_void) -> *mut c_void,
         arg: *mut c_void,
     ) -> LinuxResult<ctypes::pthread_t> {
+        let (stack, detached) = unsafe { parse_attr(attr)? };
         let arg_wrapper = ForceSendSync(arg);
 
         let my_packet: Arc<Packet<*mut c_void>> = Arc::new(Packet {
@@ -84,16 +129,22 @@ impl Pthread {
             let ret = start_routine(arg.0);
             unsafe { *their_packet.result.get() = ret };
             drop(their_packet);
+            Self::release_current();
         };
 
-        let task_inner = ruxtask::spawn(main);
+        // It runs after it is recorded in `TID_TO_PTHREAD`, where it looks
+        // itself up. It fails with `EAGAIN` if the stack can't be allocated.
+        let task_inner =
+            ruxtask::new_task_with_stack(main, "".into(), stack).map_err(|_| LinuxError::EAGAIN)?;
         let tid = task_inner.id().as_u64();
-        let thread = Pthread {
-            inner: task_inner,
-            retval: my_packet,
-        };
+        let thread = Pthread::new(task_inner.clone(), my_packet);
+        if detached {
+            thread.detach_state.store(DETACHED, Ordering::Release);
+            task_inner.detach();
+        }
         let ptr = Box::into_raw(Box::new(thread)) as *mut c_void;
         TID_TO_PTHREAD.write().insert(tid, ForceSendSync(ptr));
+        ruxtask::put_task(task_inner);
         Ok(ptr)
     }
 
@@ -118,13 +169,11 @@ impl Pthread {
             start_routine(arg.0);
         };
 
-        let task_inner = ruxtask::pspawn(main, tls as usize, set_tid, tl);
+        let task_inner =
+            ruxtask::pspawn(main, tls as usize, set_tid, tl).map_err(|_| LinuxError::EAGAIN)?;
 
         let tid = task_inner.id().as_u64();
-        let thread = Pthread {
-            inner: task_inner.clone(),
-            retval: my_packet,
-        };
+        let thread = Pthread::new(task_inner.clone(), my_packet);
         let ptr = Box::into_raw(Box::new(thread)) as *mut c_void;
         TID_TO_PTHREAD.write().insert(tid, ForceSendSync(ptr));
         Ok((tid, task_inner))
@@ -160,23 +209,201 @@ impl Pthread {
     #[cfg(not(feature = "musl"))]
     fn exit_current(retval: *mut c_void) -> ! {
         let thread = Self::current().expect("fail to get current thread");
+        thread.run_cleanup_handlers();
         unsafe { *thread.retval.result.get() = retval };
+        Self::release_current();
         ruxtask::exit(0);
     }
 
+    /// Pops and runs all cleanup handlers of the current thread, the last
+    /// pushed first.
+    #[cfg(not(feature = "musl"))]
+    fn run_cleanup_handlers(&self) {
+        loop {
+            let cb = self.cleanup.load(Ordering::Acquire);
+            if cb.is_null() {
+                break;
+            }
+            let cb = unsafe { &*cb };
+            self.cleanup.store(cb.__next, Ordering::Release);
+            if let Some(routine) = cb.__f {
+                unsafe { routine(cb.__x) };
+            }
+        }
+    }
+
+    /// Marks the current thread as exited, and frees its `Pthread` if it is
+    /// detached, as nobody is going to join it.
+    fn release_current() {
+        let Some(thread) = Self::current() else {
+            return;
+        };
+        // The destructors of keys may still use the thread.
+        #[cfg(not(feature = "musl"))]
+        ruxtask::current().destroy_keys();
+        if thread.detach_state.swap(EXITED, Ordering::AcqRel) == DETACHED {
+            Self::release(thread as *const Pthread as _);
+        }
+    }
+
+    /// Removes the thread from `TID_TO_PTHREAD` and frees it.
+    fn release(ptr: ctypes::pthread_t) {
+        let thread = unsafe { Box::from_raw(ptr as *mut Pthread) };
+        TID_TO_PTHREAD.write().remove(&thread.inner.id().as_u64());
+    }
+
     fn join(ptr: ctypes::pthread_t) -> LinuxResult<*mut c_void> {
         if core::ptr::eq(ptr, Self::current_ptr() as _) {
             return Err(LinuxError::EDEADLK);
         }
 
-        let thread = unsafe { Box::from_raw(ptr as *mut Pthread) };
+        let thread = unsafe { &*(ptr as *const Pthread) };
+        if thread.detach_state.load(Ordering::Acquire) == DETACHED {
+            return Err(LinuxError::EINVAL);
+        }
+        test_cancel();
         thread.inner.join();
-        let tid = thread.inner.id().as_u64();
         let retval = unsafe { *thread.retval.result.get() };
-        TID_TO_PTHREAD.write().remove(&tid);
-        drop(thread);
+        Self::release(ptr);
         Ok(retval)
     }
+
+    fn detach(ptr: ctypes::pthread_t) -> LinuxResult {
+        let thread = unsafe { &*(ptr as *const Pthread) };
+        match thread.detach_state.compare_exchange(
+            JOINABLE,
+            DETACHED,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                // Set before it exits, it is dropped at once when it exits.
+                thread.inner.detach();
+                Ok(())
+            }
+            Err(EXITED) => {
+                Self::release(ptr);
+                Ok(())
+            }
+            Err(_) => Err(LinuxError::EINVAL),
+        }
+    }
+
+    fn cancel(ptr: ctypes::pthread_t) -> LinuxResult {
+        let thread = unsafe { &*(ptr as *const Pthread) };
+        CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+        thread.cancel.pending.store(true, Ordering::SeqCst);
+        if core::ptr::eq(ptr, Self::current_ptr() as _) {
+            if thread.cancel.asynchronous.load(Ordering::Acquire) {
+                test_cancel();
+            }
+        } else if !thread.cancel.disabled.load(Ordering::Acquire) {
+            // Wakes it up if it is blocked in a cancellation point.
+            ruxtask::interrupt(&thread.inner);
+        }
+        Ok(())
+    }
+}
+
+/// Gets the stack and whether the thread is created detached from the
+/// attributes `attr`, which may be null for the defaults.
+unsafe fn parse_attr(attr: *const ctypes::pthread_attr_t) -> LinuxResult<(StackAttr, bool)> {
+    // The layout of `pthread_attr_t` follows musl, see `pthread.h`.
+    const DETACH_INDEX: usize = 3 * core::mem::size_of::<usize>() / core::mem::size_of::<c_int>();
+    if attr.is_null() {
+        return Ok((StackAttr::with_size(ruxconfig::TASK_STACK_SIZE), false));
+    }
+    let attr = unsafe { &(*attr).__u };
+    let (size, guard_size, stack_addr) = unsafe {
+        (
+            attr.__s[0] as usize,
+            attr.__s[1] as usize,
+            attr.__s[2] as usize,
+        )
+    };
+    let detached = unsafe { attr.__i[DETACH_INDEX] } as u32 == ctypes::PTHREAD_CREATE_DETACHED;
+    let stack = if stack_addr != 0 {
+        // `stack_addr` is the top of the stack, set by `pthread_attr_setstack`.
+        if size == 0 || size > stack_addr {
+            return Err(LinuxError::EINVAL);
+        }
+        StackAttr::User {
+            bottom: stack_addr - size,
+            size,
+        }
+    } else if size == 0 {
+        StackAttr::with_size(ruxconfig::TASK_STACK_SIZE)
+    } else {
+        StackAttr::Alloc { size, guard_size }
+    };
+    Ok((stack, detached))
+}
+
+/// Whether the current thread has a pending cancellation request, and
+/// cancellation is enabled.
+///
+/// A thread killed by the OOM killer is treated as canceled, even if
+/// cancellation is disabled, like `SIGKILL`. So is a killed task which is not
+/// a pthread, e.g., spawned by `ruxtask` directly.
+fn cancel_pending() -> bool {
+    let killed = ruxtask::current().is_killed();
+    if !killed && !CANCEL_REQUESTED.load(Ordering::SeqCst) {
+        return false;
+    }
+    match Pthread::current() {
+        Some(thread) => {
+            !thread.cancel.exiting.load(Ordering::Acquire)
+                && (killed
+                    || thread.cancel.pending.load(Ordering::SeqCst)
+                        && !thread.cancel.disabled.load(Ordering::Acquire))
+        }
+        None => killed,
+    }
+}
+
+/// Acts upon the pending cancellation request of the current thread, if
+/// cancellation is enabled, i.e., a cancellation point.
+pub(crate) fn test_cancel() {
+    if cancel_pending() {
+        let Some(thread) = Pthread::current() else {
+            debug!("task {} killed", ruxtask::current().id().as_u64());
+            ruxtask::exit(0);
+        };
+        debug!("thread {} canceled", thread.inner.id().as_u64());
+        // Not acted upon again, e.g., by the destructors of keys.
+        thread.cancel.disabled.store(true, Ordering::Release);
+        thread.cancel.exiting.store(true, Ordering::Release);
+        sys_pthread_exit(PTHREAD_CANCELED);
+    }
+}
+
+/// Runs the blocking operation `f` of a cancellation point, which is woken
+/// up early if the current thread is canceled, see
+/// [`ruxtask::interruptible`].
+///
+/// Returns `None` without running `f` if the thread has been canceled. The
+/// caller should call [`test_cancel`] afterwards, once it is ready to exit.
+pub(crate) fn cancelable<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce() -> R,
+{
+    // Checked after the thread becomes interruptible, so that a request
+    // coming later wakes it up.
+    ruxtask::interruptible(|| (!cancel_pending()).then(f))
+}
+
+/// Gets the task of the thread with the given ID, or the current thread if
+/// `tid` is 0.
+pub(crate) fn task_by_tid(tid: ctypes::pid_t) -> LinuxResult<AxTaskRef> {
+    let curr = ruxtask::current();
+    if tid == 0 || tid as u64 == curr.id().as_u64() {
+        return Ok(curr.as_task_ref().clone());
+    }
+    TID_TO_PTHREAD
+        .read()
+        .get(&(tid as u64))
+        .map(|ptr| unsafe { &*(ptr.0 as *const Pthread) }.inner.clone())
+        .ok_or(LinuxError::ESRCH)
 }
 
 /// Returns the `pthread` struct of current thread.
@@ -208,6 +435,7 @@ pub unsafe fn sys_pthread_create(
 /// Exits the current thread. The value `retval` will be returned to the joiner.
 pub fn sys_pthread_exit(retval: *mut c_void) -> ! {
     debug!("sys_pthread_exit <= {:#x}", retval as usize);
+    futex::exit_futexes();
     #[cfg(feature = "musl")]
     {
         use core::sync::atomic::Ordering;
@@ -227,6 +455,126 @@ pub fn sys_pthread_exit(retval: *mut c_void) -> ! {
     Pthread::exit_current(retval);
 }
 
+/// Marks the given thread as detached, its resources are released when it
+/// exits, without being joined.
+pub unsafe fn sys_pthread_detach(thread: ctypes::pthread_t) -> c_int {
+    debug!("sys_pthread_detach <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_detach, {
+        Pthread::detach(thread)?;
+        Ok(0)
+    })
+}
+
+/// Sends a cancellation request to the given thread.
+///
+/// It is acted upon when the thread reaches a cancellation point with
+/// cancellation enabled. Asynchronous cancellation is only acted upon at
+/// cancellation points too, or when the thread cancels itself.
+pub unsafe fn sys_pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    debug!("sys_pthread_cancel <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_cancel, {
+        Pthread::cancel(thread)?;
+        Ok(0)
+    })
+}
+
+/// Sets the cancelability state of the current thread, and stores the old
+/// one in `oldstate` if it is not null.
+pub unsafe fn sys_pthread_setcancelstate(state: c_int, oldstate: *mut c_int) -> c_int {
+    debug!("sys_pthread_setcancelstate <= {}", state);
+    syscall_body!(sys_pthread_setcancelstate, {
+        let disabled = match state as u32 {
+            ctypes::PTHREAD_CANCEL_ENABLE => false,
+            ctypes::PTHREAD_CANCEL_DISABLE => true,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let cancel = &Pthread::current().ok_or(LinuxError::ESRCH)?.cancel;
+        let old = cancel.disabled.swap(disabled, Ordering::AcqRel);
+        if !oldstate.is_null() {
+            unsafe {
+                *oldstate = if old {
+                    ctypes::PTHREAD_CANCEL_DISABLE
+                } else {
+                    ctypes::PTHREAD_CANCEL_ENABLE
+                } as c_int
+            };
+        }
+        if cancel.asynchronous.load(Ordering::Acquire) {
+            test_cancel();
+        }
+        Ok(0)
+    })
+}
+
+/// Sets the cancelability type of the current thread, and stores the old
+/// one in `oldtype` if it is not null.
+pub unsafe fn sys_pthread_setcanceltype(ty: c_int, oldtype: *mut c_int) -> c_int {
+    debug!("sys_pthread_setcanceltype <= {}", ty);
+    syscall_body!(sys_pthread_setcanceltype, {
+        let asynchronous = match ty as u32 {
+            ctypes::PTHREAD_CANCEL_DEFERRED => false,
+            ctypes::PTHREAD_CANCEL_ASYNCHRONOUS => true,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let cancel = &Pthread::current().ok_or(LinuxError::ESRCH)?.cancel;
+        let old = cancel.asynchronous.swap(asynchronous, Ordering::AcqRel);
+        if !oldtype.is_null() {
+            unsafe {
+                *oldtype = if old {
+                    ctypes::PTHREAD_CANCEL_ASYNCHRONOUS
+                } else {
+                    ctypes::PTHREAD_CANCEL_DEFERRED
+                } as c_int
+            };
+        }
+        if asynchronous {
+            test_cancel();
+        }
+        Ok(0)
+    })
+}
+
+/// Creates a cancellation point in the current thread.
+pub fn sys_pthread_testcancel() {
+    test_cancel();
+}
+
+/// Pushes the cleanup handler `cb` of the current thread, which calls
+/// `routine(arg)` if the thread exits by `pthread_exit` or a cancellation
+/// before the handler is popped.
+///
+/// `cb` is allocated by the `pthread_cleanup_push` macro on the stack.
+#[cfg(not(feature = "musl"))]
+pub unsafe fn sys_pthread_cleanup_push(
+    cb: *mut ctypes::__ptcb,
+    routine: Option<unsafe extern "C" fn(arg: *mut c_void)>,
+    arg: *mut c_void,
+) {
+    debug!("sys_pthread_cleanup_push <= {:#x}", cb as usize);
+    let thread = Pthread::current().expect("fail to get current thread");
+    unsafe {
+        (*cb).__f = routine;
+        (*cb).__x = arg;
+        (*cb).__next = thread.cleanup.load(Ordering::Acquire);
+    }
+    thread.cleanup.store(cb, Ordering::Release);
+}
+
+/// Pops the cleanup handler `cb`, which is the last pushed by the current
+/// thread, and runs it if `execute` is not 0.
+#[cfg(not(feature = "musl"))]
+pub unsafe fn sys_pthread_cleanup_pop(cb: *mut ctypes::__ptcb, execute: c_int) {
+    debug!("sys_pthread_cleanup_pop <= {:#x}, {}", cb as usize, execute);
+    let thread = Pthread::current().expect("fail to get current thread");
+    let cb = unsafe { &*cb };
+    thread.cleanup.store(cb.__next, Ordering::Release);
+    if execute != 0 {
+        if let Some(routine) = cb.__f {
+            unsafe { routine(cb.__x) };
+        }
+    }
+}
+
 /// Waits for the given thread to exit, and stores the return value in `retval`.
 pub unsafe fn sys_pthread_join(thread: ctypes::pthread_t, retval: *mut *mut c_void) -> c_int {
     debug!("sys_pthread_join <= {:#x}", retval as usize);
@@ -239,6 +587,106 @@ pub unsafe fn sys_pthread_join(thread: ctypes::pthread_t, retval: *mut *mut c_vo
     })
 }
 
+/// Set the CPU affinity of the given thread.
+pub unsafe fn sys_pthread_setaffinity_np(
+    thread: ctypes::pthread_t,
+    cpusetsize: usize,
+    cpuset: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_pthread_setaffinity_np <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_setaffinity_np, {
+        if cpuset.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let thread = unsafe { &*(thread as *const Pthread) };
+        let mask = unsafe { crate::imp::task::cpu_set_to_mask(cpuset, cpusetsize) };
+        if !ruxtask::set_cpu_affinity(&thread.inner, mask) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the CPU affinity of the given thread.
+pub unsafe fn sys_pthread_getaffinity_np(
+    thread: ctypes::pthread_t,
+    cpusetsize: usize,
+    cpuset: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_pthread_getaffinity_np <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_getaffinity_np, {
+        if cpuset.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let thread = unsafe { &*(thread as *const Pthread) };
+        let mask = thread.inner.cpumask();
+        unsafe { crate::imp::task::mask_to_cpu_set(mask, cpuset, cpusetsize)? };
+        Ok(0)
+    })
+}
+
+/// Set the scheduling policy and priority of the given thread.
+pub unsafe fn sys_pthread_setschedparam(
+    thread: ctypes::pthread_t,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    debug!(
+        "sys_pthread_setschedparam <= {:#x} {}",
+        thread as usize, policy
+    );
+    syscall_body!(sys_pthread_setschedparam, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let thread = unsafe { &*(thread as *const Pthread) };
+        let priority = unsafe { (*param).sched_priority };
+        let policy = crate::imp::task::rt_sched_policy(policy, priority)?;
+        crate::imp::task::set_sched_policy(&thread.inner, policy)?;
+        Ok(0)
+    })
+}
+
+/// Get the scheduling policy and priority of the given thread.
+pub unsafe fn sys_pthread_getschedparam(
+    thread: ctypes::pthread_t,
+    policy: *mut c_int,
+    param: *mut ctypes::sched_param,
+) -> c_int {
+    debug!("sys_pthread_getschedparam <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_getschedparam, {
+        if policy.is_null() || param.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let thread = unsafe { &*(thread as *const Pthread) };
+        let sched_policy = ruxtask::sched_policy(&thread.inner);
+        unsafe {
+            *policy = crate::imp::task::sched_policy_id(sched_policy);
+            (*param).sched_priority = crate::imp::task::sched_priority(sched_policy);
+        }
+        Ok(0)
+    })
+}
+
+/// Set the scheduling priority of the given thread, keeping its policy.
+pub unsafe fn sys_pthread_setschedprio(thread: ctypes::pthread_t, prio: c_int) -> c_int {
+    debug!(
+        "sys_pthread_setschedprio <= {:#x} {}",
+        thread as usize, prio
+    );
+    syscall_body!(sys_pthread_setschedprio, {
+        let thread = unsafe { &*(thread as *const Pthread) };
+        let policy = ruxtask::sched_policy(&thread.inner);
+        if matches!(policy, ruxtask::SchedPolicy::Deadline { .. }) {
+            return Err(LinuxError::EPERM);
+        }
+        let policy_id = crate::imp::task::sched_policy_id(policy);
+        let policy = crate::imp::task::rt_sched_policy(policy_id, prio)?;
+        crate::imp::task::set_sched_policy(&thread.inner, policy)?;
+        Ok(0)
+    })
+}
+
 #[derive(Clone, Copy)]
 struct ForceSendSync<T>(T);
 
@@ -14,32 +14,89 @@ use axsync::Mutex;
 
 use core::ffi::c_int;
 use core::mem::{size_of, ManuallyDrop};
+use core::sync::atomic::{AtomicU32, Ordering};
 
 static_assertions::const_assert_eq!(
     size_of::<PthreadMutex>(),
     size_of::<ctypes::pthread_mutex_t>()
 );
 
+/// The bit of `pthread_mutexattr_t` for the `PTHREAD_PRIO_INHERIT` protocol,
+/// the same as musl.
+const MUTEXATTR_PRIO_INHERIT: u32 = 8;
+
+/// A mutex of pthread.
+///
+/// A mutex with the `PTHREAD_PRIO_INHERIT` protocol is locked through the
+/// priority-inheritance futex `pi_futex`, so that the owner inherits the
+/// scheduling policy of the threads waiting for it. Others use `mutex`.
 #[repr(C)]
-pub struct PthreadMutex(Mutex<()>);
+pub struct PthreadMutex {
+    mutex: Mutex<()>,
+    /// Holds the thread ID of the owner, or 0 if unlocked.
+    pi_futex: AtomicU32,
+    protocol: u32,
+}
 
 impl PthreadMutex {
-    const fn new() -> Self {
-        Self(Mutex::new(()))
+    const fn new(protocol: u32) -> Self {
+        Self {
+            mutex: Mutex::new(()),
+            pi_futex: AtomicU32::new(0),
+            protocol,
+        }
+    }
+
+    fn is_pi(&self) -> bool {
+        self.protocol == ctypes::PTHREAD_PRIO_INHERIT
+    }
+
+    fn pi_futex_addr(&self) -> *const i32 {
+        self.pi_futex.as_ptr() as *const i32
     }
 
     fn lock(&self) -> LinuxResult {
-        let _guard = ManuallyDrop::new(self.0.lock());
+        if self.is_pi() {
+            // Fast path, the futex is only needed when it is contended.
+            if self.pi_lock_fast() {
+                return Ok(());
+            }
+            return ruxfutex::futex_lock_pi(self.pi_futex_addr(), None, |tid| {
+                super::task_by_tid(tid as _).ok()
+            });
+        }
+        let _guard = ManuallyDrop::new(self.mutex.lock());
         Ok(())
     }
 
     fn unlock(&self) -> LinuxResult {
-        unsafe { self.0.force_unlock() };
+        if self.is_pi() {
+            let tid = current_tid();
+            if self
+                .pi_futex
+                .compare_exchange(tid, 0, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Ok(());
+            }
+            // There are waiters, or we are not the owner.
+            return ruxfutex::futex_unlock_pi(self.pi_futex_addr());
+        }
+        unsafe { self.mutex.force_unlock() };
         Ok(())
     }
 
     fn trylock(&self) -> LinuxResult {
-        match self.0.try_lock() {
+        if self.is_pi() {
+            if self.pi_lock_fast() {
+                return Ok(());
+            }
+            return match ruxfutex::futex_trylock_pi(self.pi_futex_addr()) {
+                Err(LinuxError::EAGAIN | LinuxError::EDEADLK) => Err(LinuxError::EBUSY),
+                res => res,
+            };
+        }
+        match self.mutex.try_lock() {
             Some(mutex_guard) => {
                 let _guard = ManuallyDrop::new(mutex_guard);
                 Ok(())
@@ -47,18 +104,97 @@ impl PthreadMutex {
             None => Err(LinuxError::EBUSY),
         }
     }
+
+    fn pi_lock_fast(&self) -> bool {
+        self.pi_futex
+            .compare_exchange(0, current_tid(), Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+}
+
+fn current_tid() -> u32 {
+    ruxtask::current().id().as_u64() as u32
+}
+
+/// Initialize a mutex attributes object with the default attributes.
+pub unsafe fn sys_pthread_mutexattr_init(attr: *mut ctypes::pthread_mutexattr_t) -> c_int {
+    debug!("sys_pthread_mutexattr_init <= {:#x}", attr as usize);
+    syscall_body!(sys_pthread_mutexattr_init, {
+        check_null_mut_ptr(attr)?;
+        unsafe { (*attr).__attr = 0 };
+        Ok(0)
+    })
+}
+
+/// Destroy a mutex attributes object.
+pub unsafe fn sys_pthread_mutexattr_destroy(attr: *mut ctypes::pthread_mutexattr_t) -> c_int {
+    debug!("sys_pthread_mutexattr_destroy <= {:#x}", attr as usize);
+    syscall_body!(sys_pthread_mutexattr_destroy, {
+        check_null_mut_ptr(attr)?;
+        Ok(0)
+    })
+}
+
+/// Set the protocol of a mutex attributes object.
+///
+/// `PTHREAD_PRIO_NONE` and `PTHREAD_PRIO_INHERIT` are supported.
+pub unsafe fn sys_pthread_mutexattr_setprotocol(
+    attr: *mut ctypes::pthread_mutexattr_t,
+    protocol: c_int,
+) -> c_int {
+    debug!(
+        "sys_pthread_mutexattr_setprotocol <= {:#x}, {}",
+        attr as usize, protocol
+    );
+    syscall_body!(sys_pthread_mutexattr_setprotocol, {
+        check_null_mut_ptr(attr)?;
+        let attr = unsafe { &mut (*attr).__attr };
+        match protocol as u32 {
+            ctypes::PTHREAD_PRIO_NONE => *attr &= !MUTEXATTR_PRIO_INHERIT,
+            ctypes::PTHREAD_PRIO_INHERIT => *attr |= MUTEXATTR_PRIO_INHERIT,
+            ctypes::PTHREAD_PRIO_PROTECT => return Err(LinuxError::EOPNOTSUPP),
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
+/// Get the protocol of a mutex attributes object.
+pub unsafe fn sys_pthread_mutexattr_getprotocol(
+    attr: *const ctypes::pthread_mutexattr_t,
+    protocol: *mut c_int,
+) -> c_int {
+    debug!("sys_pthread_mutexattr_getprotocol <= {:#x}", attr as usize);
+    syscall_body!(sys_pthread_mutexattr_getprotocol, {
+        if attr.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        check_null_mut_ptr(protocol)?;
+        unsafe { *protocol = attr_protocol(attr) as c_int };
+        Ok(0)
+    })
+}
+
+fn attr_protocol(attr: *const ctypes::pthread_mutexattr_t) -> u32 {
+    if !attr.is_null() && unsafe { (*attr).__attr } & MUTEXATTR_PRIO_INHERIT != 0 {
+        ctypes::PTHREAD_PRIO_INHERIT
+    } else {
+        ctypes::PTHREAD_PRIO_NONE
+    }
 }
 
 /// Initialize a mutex.
 pub fn sys_pthread_mutex_init(
     mutex: *mut ctypes::pthread_mutex_t,
-    _attr: *const ctypes::pthread_mutexattr_t,
+    attr: *const ctypes::pthread_mutexattr_t,
 ) -> c_int {
     debug!("sys_pthread_mutex_init <= {:#x}", mutex as usize);
     syscall_body!(sys_pthread_mutex_init, {
         check_null_mut_ptr(mutex)?;
         unsafe {
-            mutex.cast::<PthreadMutex>().write(PthreadMutex::new());
+            mutex
+                .cast::<PthreadMutex>()
+                .write(PthreadMutex::new(attr_protocol(attr)));
         }
         Ok(0)
     })
@@ -12,6 +12,9 @@ use axerrno::LinuxError;
 use core::ffi::{c_int, c_void};
 use ruxtask::tsd::DestrFunction;
 
+// Applications learn the limit from <limits.h>.
+const _: () = assert!(ctypes::PTHREAD_KEYS_MAX as usize == ruxconfig::PTHREAD_KEY_MAX);
+
 /// Allocate a specific key for a process shared by all threads.
 pub unsafe fn sys_pthread_key_create(
     key: *mut ctypes::pthread_key_t,
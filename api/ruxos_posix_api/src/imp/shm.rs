@@ -0,0 +1,373 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Shared memory objects.
+//!
+//! All of them are files on the tmpfs mounted on `/dev/shm`, so they are
+//! shared through the page cache when mapped with `MAP_SHARED`:
+//!
+//! - `memfd_create` creates a file which is unlinked at once, it can be sealed.
+//! - `shm_open` opens a file on `/dev/shm` by name.
+//! - System V segments are unlinked files kept in a table by id, and they are
+//!   destroyed when removed and detached by all.
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc};
+use core::{
+    ffi::{c_char, c_int, c_uint, c_void},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use memory_addr::{align_down, align_up_4k};
+use ruxfs::fops::OpenOptions;
+
+use super::{
+    fs::{flags_to_options, File},
+    mmap::mmap_file,
+};
+use crate::{ctypes, utils::char_ptr_to_str};
+
+/// Where the tmpfs of the shared memory objects is mounted.
+const SHM_DIR: &str = "/dev/shm";
+
+/// Max length of the name of `memfd_create`, excluding the prefix `memfd:`.
+const MFD_NAME_MAX: usize = 249;
+
+/// Max length of the name of `shm_open`.
+const NAME_MAX: usize = 255;
+
+/// The key of a System V segment which is never found by `shmget`.
+const IPC_PRIVATE: ctypes::key_t = 0;
+
+/// Min and max size of a System V segment.
+const SHMMIN: usize = 1;
+const SHMMAX: usize = isize::MAX as usize;
+
+static NEXT_ANON_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Create a file on `/dev/shm` and unlink it, it's freed after it's closed
+/// and unmapped by all.
+fn create_anon_file(prefix: &str) -> LinuxResult<ruxfs::fops::File> {
+    let path = format!(
+        "{SHM_DIR}/.{prefix}-{}",
+        NEXT_ANON_FILE.fetch_add(1, Ordering::Relaxed)
+    );
+    let mut options = OpenOptions::new();
+    options.read(true);
+    options.write(true);
+    options.create_new(true);
+    let file = ruxfs::fops::File::open(&path, &options)?;
+    ruxfs::api::remove_file(&path)?;
+    Ok(file)
+}
+
+/// Create an anonymous file which behaves like a regular file, and return its fd.
+///
+/// The file can be sealed by `F_ADD_SEALS` if created with `MFD_ALLOW_SEALING`.
+pub fn sys_memfd_create(name: *const c_char, flags: c_uint) -> c_int {
+    let name = char_ptr_to_str(name);
+    debug!("sys_memfd_create <= name: {:?}, flags: {:#x}", name, flags);
+    syscall_body!(sys_memfd_create, {
+        if name?.len() > MFD_NAME_MAX
+            || flags & !(ctypes::MFD_CLOEXEC | ctypes::MFD_ALLOW_SEALING) != 0
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let seals = if flags & ctypes::MFD_ALLOW_SEALING != 0 {
+            0
+        } else {
+            ctypes::F_SEAL_SEAL
+        };
+        File::new(create_anon_file("memfd")?)
+            .with_seals(seals)
+            .add_to_fd_table()
+    })
+}
+
+/// Path of the POSIX shared memory object `name` on `/dev/shm`.
+fn shm_path(name: &str) -> LinuxResult<String> {
+    let name = name.trim_start_matches('/');
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(LinuxError::EINVAL);
+    }
+    if name.len() > NAME_MAX {
+        return Err(LinuxError::ENAMETOOLONG);
+    }
+    Ok(format!("{SHM_DIR}/{name}"))
+}
+
+/// Open the POSIX shared memory object `name`, and return its fd.
+pub fn sys_shm_open(name: *const c_char, oflag: c_int, mode: ctypes::mode_t) -> c_int {
+    let name = char_ptr_to_str(name);
+    debug!(
+        "sys_shm_open <= name: {:?}, oflag: {:#o}, mode: {:#o}",
+        name, oflag, mode
+    );
+    syscall_body!(sys_shm_open, {
+        let mut options = flags_to_options(oflag, mode);
+        let excl = ctypes::O_CREAT | ctypes::O_EXCL;
+        if oflag as u32 & excl == excl {
+            options.create_new(true);
+        }
+        let file = ruxfs::fops::File::open(&shm_path(name?)?, &options)?;
+        File::new(file).add_to_fd_table()
+    })
+}
+
+/// Remove the POSIX shared memory object `name`.
+///
+/// It's freed after it's closed and unmapped by all.
+pub fn sys_shm_unlink(name: *const c_char) -> c_int {
+    let name = char_ptr_to_str(name);
+    debug!("sys_shm_unlink <= name: {:?}", name);
+    syscall_body!(sys_shm_unlink, {
+        ruxfs::api::remove_file(&shm_path(name?)?)?;
+        Ok(0)
+    })
+}
+
+/// A System V shared memory segment.
+struct ShmSegment {
+    key: ctypes::key_t,
+    file: Arc<File>,
+    size: usize,
+    mode: ctypes::mode_t,
+    cpid: ctypes::pid_t,
+    lpid: ctypes::pid_t,
+    atime: ctypes::time_t,
+    dtime: ctypes::time_t,
+    ctime: ctypes::time_t,
+    nattch: usize,
+    /// Removed by `IPC_RMID`, it's destroyed after it's detached by all.
+    removed: bool,
+}
+
+impl ShmSegment {
+    fn stat(&self) -> ctypes::shmid_ds {
+        ctypes::shmid_ds {
+            shm_perm: ctypes::ipc_perm {
+                __ipc_perm_key: self.key,
+                mode: self.mode,
+                ..Default::default()
+            },
+            shm_segsz: self.size as _,
+            shm_atime: self.atime,
+            shm_dtime: self.dtime,
+            shm_ctime: self.ctime,
+            shm_cpid: self.cpid,
+            shm_lpid: self.lpid,
+            shm_nattch: self.nattch as _,
+            ..Default::default()
+        }
+    }
+}
+
+/// The System V shared memory segments.
+struct ShmIds {
+    next_id: c_int,
+    segments: BTreeMap<c_int, ShmSegment>, // shmid => segment
+    attaches: BTreeMap<usize, (c_int, usize)>, // vaddr => (shmid, len)
+}
+
+impl ShmIds {
+    const fn new() -> Self {
+        Self {
+            next_id: 0,
+            segments: BTreeMap::new(),
+            attaches: BTreeMap::new(),
+        }
+    }
+
+    fn get_mut(&mut self, shmid: c_int) -> LinuxResult<&mut ShmSegment> {
+        self.segments.get_mut(&shmid).ok_or(LinuxError::EINVAL)
+    }
+
+    /// Find the segment of `key`, the removed ones are not found.
+    fn find(&self, key: ctypes::key_t) -> Option<(c_int, &ShmSegment)> {
+        self.segments
+            .iter()
+            .find(|(_, seg)| seg.key == key && !seg.removed)
+            .map(|(&shmid, seg)| (shmid, seg))
+    }
+
+    /// Destroy the segment if it has been removed and detached by all.
+    fn try_destroy(&mut self, shmid: c_int) {
+        if let Some(seg) = self.segments.get(&shmid) {
+            if seg.removed && seg.nattch == 0 {
+                self.segments.remove(&shmid);
+            }
+        }
+    }
+}
+
+static SHM_IDS: Mutex<ShmIds> = Mutex::new(ShmIds::new());
+
+fn now() -> ctypes::time_t {
+    ruxhal::time::current_time().as_secs() as _
+}
+
+/// Get the System V shared memory segment of `key`, and return its id.
+pub fn sys_shmget(key: ctypes::key_t, size: ctypes::size_t, shmflg: c_int) -> c_int {
+    debug!(
+        "sys_shmget <= key: {}, size: {:#x}, shmflg: {:#o}",
+        key, size, shmflg
+    );
+    syscall_body!(sys_shmget, {
+        let shmflg = shmflg as u32;
+        let mut ids = SHM_IDS.lock();
+        if key != IPC_PRIVATE {
+            if let Some((shmid, seg)) = ids.find(key) {
+                if shmflg & ctypes::IPC_CREAT != 0 && shmflg & ctypes::IPC_EXCL != 0 {
+                    return Err(LinuxError::EEXIST);
+                }
+                if size > seg.size {
+                    return Err(LinuxError::EINVAL);
+                }
+                return Ok(shmid);
+            }
+            if shmflg & ctypes::IPC_CREAT == 0 {
+                return Err(LinuxError::ENOENT);
+            }
+        }
+        if !(SHMMIN..=SHMMAX).contains(&size) {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let file = File::new(create_anon_file("sysv")?);
+        file.truncate(size as u64)?;
+        let now = now();
+        let segment = ShmSegment {
+            key,
+            file: Arc::new(file),
+            size,
+            mode: (shmflg & 0o777) as _,
+            cpid: crate::sys_getpid(),
+            lpid: 0,
+            atime: 0,
+            dtime: 0,
+            ctime: now,
+            nattch: 0,
+            removed: false,
+        };
+        let shmid = ids.next_id;
+        ids.next_id += 1;
+        ids.segments.insert(shmid, segment);
+        Ok(shmid)
+    })
+}
+
+/// Attach the System V shared memory segment `shmid` at `shmaddr`, or at an
+/// address chosen by the system if `shmaddr` is NULL.
+pub fn sys_shmat(shmid: c_int, shmaddr: *const c_void, shmflg: c_int) -> *mut c_void {
+    debug!(
+        "sys_shmat <= shmid: {}, shmaddr: {:p}, shmflg: {:#o}",
+        shmid, shmaddr, shmflg
+    );
+    syscall_body!(sys_shmat, {
+        let shmflg = shmflg as u32;
+        let mut addr = shmaddr as usize;
+        if addr % ctypes::SHMLBA as usize != 0 {
+            if shmflg & ctypes::SHM_RND == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            addr = align_down(addr, ctypes::SHMLBA as usize);
+        }
+        let mut prot = ctypes::PROT_READ;
+        if shmflg & ctypes::SHM_RDONLY == 0 {
+            prot |= ctypes::PROT_WRITE;
+        }
+        if shmflg & ctypes::SHM_EXEC != 0 {
+            prot |= ctypes::PROT_EXEC;
+        }
+        let mut flags = ctypes::MAP_SHARED;
+        if addr != 0 {
+            flags |= ctypes::MAP_FIXED;
+        }
+
+        let mut ids = SHM_IDS.lock();
+        let seg = ids.get_mut(shmid)?;
+        let len = align_up_4k(seg.size);
+        let vaddr = mmap_file(addr, len, prot, flags, seg.file.clone())?;
+        seg.nattch += 1;
+        seg.atime = now();
+        seg.lpid = crate::sys_getpid();
+        ids.attaches.insert(vaddr, (shmid, len));
+        Ok(vaddr as *mut c_void)
+    })
+}
+
+/// Detach the System V shared memory segment attached at `shmaddr`.
+pub fn sys_shmdt(shmaddr: *const c_void) -> c_int {
+    debug!("sys_shmdt <= shmaddr: {:p}", shmaddr);
+    syscall_body!(sys_shmdt, {
+        let mut ids = SHM_IDS.lock();
+        let (shmid, len) = ids
+            .attaches
+            .remove(&(shmaddr as usize))
+            .ok_or(LinuxError::EINVAL)?;
+        if crate::sys_munmap(shmaddr as *mut c_void, len) < 0 {
+            warn!("sys_shmdt: segment {shmid} at {shmaddr:p} has been unmapped");
+        }
+        if let Ok(seg) = ids.get_mut(shmid) {
+            seg.nattch -= 1;
+            seg.dtime = now();
+            seg.lpid = crate::sys_getpid();
+        }
+        ids.try_destroy(shmid);
+        Ok(0)
+    })
+}
+
+/// Control the System V shared memory segment `shmid`.
+pub fn sys_shmctl(shmid: c_int, cmd: c_int, buf: *mut ctypes::shmid_ds) -> c_int {
+    debug!(
+        "sys_shmctl <= shmid: {}, cmd: {}, buf: {:p}",
+        shmid, cmd, buf
+    );
+    syscall_body!(sys_shmctl, {
+        let mut ids = SHM_IDS.lock();
+        match cmd as u32 {
+            ctypes::IPC_STAT | ctypes::SHM_STAT => {
+                if buf.is_null() {
+                    return Err(LinuxError::EFAULT);
+                }
+                unsafe { *buf = ids.get_mut(shmid)?.stat() };
+                Ok(if cmd as u32 == ctypes::SHM_STAT {
+                    shmid
+                } else {
+                    0
+                })
+            }
+            ctypes::IPC_SET => {
+                if buf.is_null() {
+                    return Err(LinuxError::EFAULT);
+                }
+                let mode = unsafe { (*buf).shm_perm.mode };
+                let seg = ids.get_mut(shmid)?;
+                seg.mode = (seg.mode & !0o777) | (mode & 0o777);
+                seg.ctime = now();
+                Ok(0)
+            }
+            ctypes::IPC_RMID => {
+                let seg = ids.get_mut(shmid)?;
+                seg.removed = true;
+                seg.ctime = now();
+                ids.try_destroy(shmid);
+                Ok(0)
+            }
+            // The segments are on the tmpfs, which stays in memory.
+            ctypes::SHM_LOCK | ctypes::SHM_UNLOCK => {
+                ids.get_mut(shmid)?;
+                Ok(0)
+            }
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
@@ -19,8 +19,42 @@ use {
     core::sync::atomic::{AtomicBool, Ordering},
 };
 
+/// Typing this key (`Ctrl-T`, like `SIGINFO` on BSDs) on the console dumps
+/// all tasks instead of being read, with the `task-dump` feature.
+#[cfg(feature = "task-dump")]
+const DUMP_TASKS_KEY: u8 = 0x14;
+
+/// Typing this key (`Ctrl-Y`) on the console dumps the memory usage and the
+/// live heap allocations instead of being read, with the `alloc-debug`
+/// feature.
+#[cfg(feature = "alloc-debug")]
+const DUMP_ALLOCATIONS_KEY: u8 = 0x19;
+
+/// Writes to the console directly, without any lock.
+#[cfg(feature = "alloc-debug")]
+struct ConsoleWriter;
+
+#[cfg(feature = "alloc-debug")]
+impl core::fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        ruxhal::console::write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
 fn console_read_bytes() -> Option<u8> {
     let ret = ruxhal::console::getchar().map(|c| if c == b'\r' { b'\n' } else { c });
+    #[cfg(feature = "task-dump")]
+    if ret == Some(DUMP_TASKS_KEY) {
+        ruxtask::dump_tasks();
+        return None;
+    }
+    #[cfg(feature = "alloc-debug")]
+    if ret == Some(DUMP_ALLOCATIONS_KEY) {
+        let _ = axalloc::dump_memory_usage_to(&mut ConsoleWriter);
+        let _ = axalloc::dump_allocations_to(&mut ConsoleWriter);
+        return None;
+    }
     if let Some(c) = ret {
         let _ = console_write_bytes(&[c]);
     }
@@ -80,6 +114,11 @@ impl Stdin {
             if read_len > 0 {
                 return Ok(read_len);
             }
+            // Give up if woken up by `ruxtask::interrupt`.
+            #[cfg(feature = "multitask")]
+            if ruxtask::interrupted() {
+                return Err(axerrno::AxError::Interrupted);
+            }
             crate::sys_sched_yield();
         }
     }
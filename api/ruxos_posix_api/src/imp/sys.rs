@@ -43,9 +43,14 @@ pub unsafe extern "C" fn sys_sysinfo(info: *mut ctypes::sysinfo) -> c_int {
             info_mut.totalram = info_mut.freeram + allocator.used_bytes() as c_ulong;
         }
 
-        // TODO
         info_mut.totalswap = 0;
         info_mut.freeswap = 0;
+        #[cfg(feature = "fs")]
+        for stat in crate::imp::mmap::swap::swap_stats() {
+            use core::ffi::c_ulong;
+            info_mut.totalswap += stat.size as c_ulong;
+            info_mut.freeswap += (stat.size - stat.used) as c_ulong;
+        }
 
         info_mut.procs = 1;
 
@@ -7,7 +7,21 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-use core::ffi::c_int;
+use core::ffi::{c_int, c_uint};
+
+#[cfg(feature = "multitask")]
+use {
+    crate::ctypes,
+    axerrno::{LinuxError, LinuxResult},
+    core::ffi::c_ulong,
+    ruxtask::{AxTaskRef, CpuMask, SchedPolicy},
+};
+
+/// Size of the CPU masks in bytes, rounded up to `c_ulong`s like Linux.
+#[cfg(feature = "multitask")]
+const CPU_MASK_SIZE: usize = (CpuMask::MAX_CPUS + c_ulong::BITS as usize - 1)
+    / c_ulong::BITS as usize
+    * core::mem::size_of::<c_ulong>();
 
 /// Relinquish the CPU, and switches to another task.
 ///
@@ -57,3 +71,331 @@ pub fn sys_exit(exit_code: c_int) -> ! {
     #[cfg(not(feature = "multitask"))]
     ruxhal::misc::terminate();
 }
+
+/// Get the CPU and NUMA node the current thread is running on.
+pub unsafe fn sys_getcpu(cpu: *mut c_uint, node: *mut c_uint) -> c_int {
+    syscall_body!(sys_getcpu, {
+        if !cpu.is_null() {
+            unsafe { *cpu = ruxhal::cpu::this_cpu_id() as c_uint };
+        }
+        if !node.is_null() {
+            unsafe { *node = 0 };
+        }
+        Ok(0)
+    })
+}
+
+/// Get the maximum priority of the scheduling policy.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_get_priority_max(policy: c_int) -> c_int {
+    syscall_body!(sys_sched_get_priority_max, {
+        match policy as u32 {
+            ctypes::SCHED_FIFO | ctypes::SCHED_RR => Ok(ruxtask::RT_PRIO_MAX as c_int),
+            ctypes::SCHED_OTHER
+            | ctypes::SCHED_BATCH
+            | ctypes::SCHED_IDLE
+            | ctypes::SCHED_DEADLINE => Ok(0),
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
+
+/// Get the minimum priority of the scheduling policy.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_get_priority_min(policy: c_int) -> c_int {
+    syscall_body!(sys_sched_get_priority_min, {
+        match policy as u32 {
+            ctypes::SCHED_FIFO | ctypes::SCHED_RR => Ok(ruxtask::RT_PRIO_MIN as c_int),
+            ctypes::SCHED_OTHER
+            | ctypes::SCHED_BATCH
+            | ctypes::SCHED_IDLE
+            | ctypes::SCHED_DEADLINE => Ok(0),
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
+
+/// Set the scheduling policy and priority of the thread `pid`, or the current
+/// thread if `pid` is 0.
+///
+/// `SCHED_BATCH` and `SCHED_IDLE` are treated as `SCHED_OTHER`, and
+/// `SCHED_DEADLINE` can only be set by [`sys_sched_setattr`].
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setscheduler(
+    pid: ctypes::pid_t,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    debug!("sys_sched_setscheduler <= {} {}", pid, policy);
+    syscall_body!(sys_sched_setscheduler, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let priority = unsafe { (*param).sched_priority };
+        set_sched_policy(&task, rt_sched_policy(policy, priority)?)?;
+        Ok(0)
+    })
+}
+
+/// Get the scheduling policy of the thread `pid`, or the current thread if
+/// `pid` is 0.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_getscheduler(pid: ctypes::pid_t) -> c_int {
+    debug!("sys_sched_getscheduler <= {}", pid);
+    syscall_body!(sys_sched_getscheduler, {
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        Ok(sched_policy_id(ruxtask::sched_policy(&task)))
+    })
+}
+
+/// Set the scheduling priority of the thread `pid`, or the current thread if
+/// `pid` is 0, keeping its policy.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setparam(pid: ctypes::pid_t, param: *const ctypes::sched_param) -> c_int {
+    debug!("sys_sched_setparam <= {}", pid);
+    syscall_body!(sys_sched_setparam, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let priority = unsafe { (*param).sched_priority };
+        let policy = match ruxtask::sched_policy(&task) {
+            policy @ SchedPolicy::Deadline { .. } if priority == 0 => policy,
+            SchedPolicy::Deadline { .. } => return Err(LinuxError::EINVAL),
+            policy => rt_sched_policy(sched_policy_id(policy), priority)?,
+        };
+        set_sched_policy(&task, policy)?;
+        Ok(0)
+    })
+}
+
+/// Get the scheduling priority of the thread `pid`, or the current thread if
+/// `pid` is 0.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getparam(pid: ctypes::pid_t, param: *mut ctypes::sched_param) -> c_int {
+    debug!("sys_sched_getparam <= {}", pid);
+    syscall_body!(sys_sched_getparam, {
+        if param.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        unsafe { (*param).sched_priority = sched_priority(ruxtask::sched_policy(&task)) };
+        Ok(0)
+    })
+}
+
+/// Set the scheduling policy and attributes of the thread `pid`, or the
+/// current thread if `pid` is 0.
+///
+/// The nice value in `attr` is only applied to the current thread.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setattr(
+    pid: ctypes::pid_t,
+    attr: *const ctypes::sched_attr,
+    flags: c_uint,
+) -> c_int {
+    debug!("sys_sched_setattr <= {} {}", pid, flags);
+    syscall_body!(sys_sched_setattr, {
+        if attr.is_null() || flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let attr = unsafe { &*attr };
+        if (attr.size as usize) < core::mem::size_of::<ctypes::sched_attr>() && attr.size != 0 {
+            return Err(LinuxError::E2BIG);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let policy = if attr.sched_policy == ctypes::SCHED_DEADLINE {
+            SchedPolicy::Deadline {
+                runtime: attr.sched_runtime,
+                deadline: attr.sched_deadline,
+                // The period is the same as the deadline if not specified.
+                period: if attr.sched_period == 0 {
+                    attr.sched_deadline
+                } else {
+                    attr.sched_period
+                },
+            }
+        } else {
+            rt_sched_policy(attr.sched_policy as c_int, attr.sched_priority as c_int)?
+        };
+        set_sched_policy(&task, policy)?;
+        if policy == SchedPolicy::Normal {
+            if ruxtask::current().ptr_eq(&task) {
+                if !ruxtask::set_priority(attr.sched_nice as isize) {
+                    return Err(LinuxError::EINVAL);
+                }
+            } else if attr.sched_nice != 0 {
+                warn!("sys_sched_setattr: the nice value of other threads is ignored");
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Get the scheduling policy and attributes of the thread `pid`, or the
+/// current thread if `pid` is 0.
+///
+/// At most `size` bytes are written to `attr`.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getattr(
+    pid: ctypes::pid_t,
+    attr: *mut ctypes::sched_attr,
+    size: c_uint,
+    flags: c_uint,
+) -> c_int {
+    debug!("sys_sched_getattr <= {} {} {}", pid, size, flags);
+    syscall_body!(sys_sched_getattr, {
+        let attr_size = core::mem::size_of::<ctypes::sched_attr>();
+        if attr.is_null() || flags != 0 || (size as usize) < attr_size {
+            return Err(LinuxError::EINVAL);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let policy = ruxtask::sched_policy(&task);
+        let mut kattr = ctypes::sched_attr {
+            size: attr_size as u32,
+            sched_policy: sched_policy_id(policy) as u32,
+            sched_priority: sched_priority(policy) as u32,
+            ..Default::default()
+        };
+        if let SchedPolicy::Deadline {
+            runtime,
+            deadline,
+            period,
+        } = policy
+        {
+            kattr.sched_runtime = runtime;
+            kattr.sched_deadline = deadline;
+            kattr.sched_period = period;
+        }
+        unsafe { attr.write(kattr) };
+        Ok(0)
+    })
+}
+
+/// Converts a `SCHED_OTHER`, `SCHED_FIFO` or `SCHED_RR` policy with its
+/// priority to [`SchedPolicy`].
+#[cfg(feature = "multitask")]
+pub(crate) fn rt_sched_policy(policy: c_int, priority: c_int) -> LinuxResult<SchedPolicy> {
+    let rt_prio = u8::try_from(priority).map_err(|_| LinuxError::EINVAL)?;
+    match (policy & !ctypes::SCHED_RESET_ON_FORK as c_int) as u32 {
+        ctypes::SCHED_OTHER | ctypes::SCHED_BATCH | ctypes::SCHED_IDLE if priority == 0 => {
+            Ok(SchedPolicy::Normal)
+        }
+        ctypes::SCHED_FIFO => Ok(SchedPolicy::Fifo(rt_prio)),
+        ctypes::SCHED_RR => Ok(SchedPolicy::RoundRobin(rt_prio)),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Returns the `SCHED_*` value of the policy.
+#[cfg(feature = "multitask")]
+pub(crate) fn sched_policy_id(policy: SchedPolicy) -> c_int {
+    let id = match policy {
+        SchedPolicy::Normal => ctypes::SCHED_OTHER,
+        SchedPolicy::Fifo(_) => ctypes::SCHED_FIFO,
+        SchedPolicy::RoundRobin(_) => ctypes::SCHED_RR,
+        SchedPolicy::Deadline { .. } => ctypes::SCHED_DEADLINE,
+    };
+    id as c_int
+}
+
+/// Returns the priority of the policy, which is 0 for non-real-time policies.
+#[cfg(feature = "multitask")]
+pub(crate) fn sched_priority(policy: SchedPolicy) -> c_int {
+    match policy {
+        SchedPolicy::Fifo(prio) | SchedPolicy::RoundRobin(prio) => prio as c_int,
+        _ => 0,
+    }
+}
+
+/// Sets the scheduling policy of `task`.
+#[cfg(feature = "multitask")]
+pub(crate) fn set_sched_policy(task: &AxTaskRef, policy: SchedPolicy) -> LinuxResult {
+    if !policy.is_valid() {
+        Err(LinuxError::EINVAL)
+    } else if ruxtask::set_sched_policy(task, policy) {
+        Ok(())
+    } else if matches!(policy, SchedPolicy::Deadline { .. }) {
+        // Not enough CPU bandwidth, or not supported by the scheduler.
+        Err(LinuxError::EBUSY)
+    } else {
+        // Not supported by the scheduler.
+        Err(LinuxError::EPERM)
+    }
+}
+
+/// Set the CPU affinity of the thread `pid`, or the current thread if `pid` is 0.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setaffinity(
+    pid: ctypes::pid_t,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_sched_setaffinity <= {} {}", pid, cpusetsize);
+    syscall_body!(sys_sched_setaffinity, {
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let mask = unsafe { cpu_set_to_mask(mask, cpusetsize) };
+        if !ruxtask::set_cpu_affinity(&task, mask) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the CPU affinity of the thread `pid`, or the current thread if `pid` is 0.
+///
+/// Returns the size of the CPU mask in bytes like the Linux syscall, and the
+/// rest of `mask` is zeroed.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getaffinity(
+    pid: ctypes::pid_t,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!("sys_sched_getaffinity <= {} {}", pid, cpusetsize);
+    syscall_body!(sys_sched_getaffinity, {
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let task = crate::imp::pthread::task_by_tid(pid)?;
+        let size = unsafe { mask_to_cpu_set(task.cpumask(), mask, cpusetsize)? };
+        Ok(size as c_int)
+    })
+}
+
+/// Converts the first `size` bytes of `set` to a [`CpuMask`], CPUs beyond
+/// [`CpuMask::MAX_CPUS`] are ignored.
+#[cfg(feature = "multitask")]
+pub(crate) unsafe fn cpu_set_to_mask(set: *const ctypes::cpu_set_t, size: usize) -> CpuMask {
+    // Bits in a `cpu_set_t` are in `c_ulong`s, which is the same as in bytes on
+    // little-endian architectures.
+    let bytes = core::slice::from_raw_parts(set as *const u8, size);
+    let mut mask = CpuMask::new();
+    for cpu_id in 0..CpuMask::MAX_CPUS.min(size * 8) {
+        mask.set(cpu_id, bytes[cpu_id / 8] & (1 << (cpu_id % 8)) != 0);
+    }
+    mask
+}
+
+/// Stores the online CPUs in `mask` to the `size` bytes of `set`, returns
+/// the number of bytes used.
+#[cfg(feature = "multitask")]
+pub(crate) unsafe fn mask_to_cpu_set(
+    mask: CpuMask,
+    set: *mut ctypes::cpu_set_t,
+    size: usize,
+) -> LinuxResult<usize> {
+    if size * 8 < CpuMask::MAX_CPUS || size % core::mem::size_of::<c_ulong>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let bytes = core::slice::from_raw_parts_mut(set as *mut u8, size);
+    bytes.fill(0);
+    for cpu_id in mask.and(&ruxtask::online_cpus()).iter() {
+        bytes[cpu_id / 8] |= 1 << (cpu_id % 8);
+    }
+    Ok(size.min(CPU_MASK_SIZE))
+}
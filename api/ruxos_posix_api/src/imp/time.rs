@@ -92,8 +92,12 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
 
         let now = ruxhal::time::current_time();
 
+        // It is a cancellation point.
         #[cfg(feature = "multitask")]
-        ruxtask::sleep(dur);
+        {
+            crate::imp::pthread::cancelable(|| ruxtask::sleep(dur));
+            crate::imp::pthread::test_cancel();
+        }
         #[cfg(not(feature = "multitask"))]
         ruxhal::time::busy_wait(dur);
 
@@ -57,7 +57,7 @@ pub use imp::stat::{
 };
 pub use imp::sys::{sys_sysinfo, sys_uname};
 pub use imp::sys_invalid;
-pub use imp::task::{sys_exit, sys_getpid, sys_getppid, sys_gettid, sys_sched_yield};
+pub use imp::task::{sys_exit, sys_getcpu, sys_getpid, sys_getppid, sys_gettid, sys_sched_yield};
 pub use imp::time::{
     sys_clock_gettime, sys_clock_settime, sys_gettimeofday, sys_nanosleep, sys_times,
 };
@@ -68,10 +68,11 @@ pub use imp::fd_ops::sys_dup3;
 pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl};
 #[cfg(feature = "fs")]
 pub use imp::fs::{
-    sys_chdir, sys_faccessat, sys_fchownat, sys_fdatasync, sys_fstat, sys_fsync, sys_getcwd,
-    sys_getdents64, sys_lseek, sys_lstat, sys_mkdir, sys_mkdirat, sys_newfstatat, sys_open,
-    sys_openat, sys_pread64, sys_preadv, sys_pwrite64, sys_readlinkat, sys_rename, sys_renameat,
-    sys_rmdir, sys_stat, sys_unlink, sys_unlinkat,
+    sys_chdir, sys_copy_file_range, sys_faccessat, sys_fchownat, sys_fdatasync, sys_fstat,
+    sys_fsync, sys_ftruncate, sys_getcwd, sys_getdents64, sys_lseek, sys_lstat, sys_mkdir,
+    sys_mkdirat, sys_newfstatat, sys_open, sys_openat, sys_pread64, sys_preadv, sys_pwrite64,
+    sys_readlinkat, sys_rename, sys_renameat, sys_rmdir, sys_sendfile, sys_stat, sys_unlink,
+    sys_unlinkat,
 };
 #[cfg(feature = "epoll")]
 pub use imp::io_mpx::{sys_epoll_create, sys_epoll_ctl, sys_epoll_pwait, sys_epoll_wait};
@@ -81,16 +82,27 @@ pub use imp::io_mpx::{sys_poll, sys_ppoll};
 pub use imp::io_mpx::{sys_pselect6, sys_select};
 #[cfg(feature = "fd")]
 pub use imp::ioctl::sys_ioctl;
+#[cfg(feature = "zram")]
+pub use imp::mmap::swap::ZramSwap;
+#[cfg(feature = "fs")]
+pub use imp::mmap::swap::{
+    proc_swaps, swap_stats, swapoff, swapon, SwapBackend, SwapFile, SwapPartition, SwapStat,
+};
 #[cfg(feature = "alloc")]
-pub use imp::mmap::{sys_madvise, sys_mmap, sys_mprotect, sys_mremap, sys_msync, sys_munmap};
+pub use imp::mmap::{
+    sys_madvise, sys_mincore, sys_mlock, sys_mlock2, sys_mlockall, sys_mmap, sys_mprotect,
+    sys_mremap, sys_msync, sys_munlock, sys_munlockall, sys_munmap,
+};
+#[cfg(feature = "fs")]
+pub use imp::mmap::{sys_swapoff, sys_swapon};
 #[cfg(feature = "net")]
 pub use imp::net::{
-    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getpeername,
-    sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendmsg, sys_sendto,
-    sys_setsockopt, sys_shutdown, sys_socket,
+    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getnameinfo,
+    sys_getpeername, sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendmsg,
+    sys_sendto, sys_setsockopt, sys_shutdown, sys_socket,
 };
 #[cfg(feature = "pipe")]
-pub use imp::pipe::{sys_pipe, sys_pipe2};
+pub use imp::pipe::{sys_pipe, sys_pipe2, sys_splice};
 #[cfg(feature = "multitask")]
 pub use imp::pthread::condvar::{
     sys_pthread_cond_broadcast, sys_pthread_cond_destroy, sys_pthread_cond_init,
@@ -99,24 +111,50 @@ pub use imp::pthread::condvar::{
 #[cfg(feature = "multitask")]
 pub use imp::pthread::mutex::{
     sys_pthread_mutex_destroy, sys_pthread_mutex_init, sys_pthread_mutex_lock,
-    sys_pthread_mutex_trylock, sys_pthread_mutex_unlock,
+    sys_pthread_mutex_trylock, sys_pthread_mutex_unlock, sys_pthread_mutexattr_destroy,
+    sys_pthread_mutexattr_getprotocol, sys_pthread_mutexattr_init,
+    sys_pthread_mutexattr_setprotocol,
 };
 #[cfg(feature = "multitask")]
 pub use imp::pthread::{
     sys_pthread_getspecific, sys_pthread_key_create, sys_pthread_key_delete,
     sys_pthread_setspecific,
 };
+#[cfg(feature = "fs")]
+pub use imp::shm::{
+    sys_memfd_create, sys_shm_open, sys_shm_unlink, sys_shmat, sys_shmctl, sys_shmdt, sys_shmget,
+};
 #[cfg(feature = "signal")]
 pub use imp::signal::{sys_getitimer, sys_kill, sys_setitimer, sys_sigaction, sys_sigaltstack};
 
 #[cfg(feature = "multitask")]
-pub use imp::pthread::futex::sys_futex;
+pub use imp::pthread::futex::{sys_futex, sys_get_robust_list, sys_set_robust_list};
 #[cfg(all(feature = "multitask", feature = "musl"))]
 pub use imp::pthread::sys_clone;
 #[cfg(all(feature = "multitask", feature = "musl"))]
 pub use imp::pthread::sys_set_tid_address;
 #[cfg(feature = "multitask")]
+pub use imp::pthread::{
+    sys_pthread_cancel, sys_pthread_detach, sys_pthread_setcancelstate, sys_pthread_setcanceltype,
+    sys_pthread_testcancel,
+};
+#[cfg(all(feature = "multitask", not(feature = "musl")))]
+pub use imp::pthread::{sys_pthread_cleanup_pop, sys_pthread_cleanup_push};
+#[cfg(feature = "multitask")]
 pub use imp::pthread::{sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_self};
+#[cfg(feature = "multitask")]
+pub use imp::pthread::{sys_pthread_getaffinity_np, sys_pthread_setaffinity_np};
+#[cfg(feature = "multitask")]
+pub use imp::pthread::{
+    sys_pthread_getschedparam, sys_pthread_setschedparam, sys_pthread_setschedprio,
+};
+#[cfg(feature = "multitask")]
+pub use imp::task::{
+    sys_sched_get_priority_max, sys_sched_get_priority_min, sys_sched_getattr, sys_sched_getparam,
+    sys_sched_getscheduler, sys_sched_setattr, sys_sched_setparam, sys_sched_setscheduler,
+};
+#[cfg(feature = "multitask")]
+pub use imp::task::{sys_sched_getaffinity, sys_sched_setaffinity};
 
 #[cfg(feature = "fs")]
 pub use imp::execve::sys_execve;
@@ -39,8 +39,29 @@ pub fn check_null_mut_ptr<T>(ptr: *mut T) -> LinuxResult {
     }
 }
 
+/// Writes all of `data` with `write`, which may write only a part of it.
+///
+/// Return the length of `data`.
+#[cfg(any(feature = "fs", feature = "pipe"))]
+pub fn write_all<W>(data: &[u8], mut write: W) -> LinuxResult<usize>
+where
+    W: FnMut(&[u8]) -> LinuxResult<usize>,
+{
+    let mut written = 0;
+    while written < data.len() {
+        match write(&data[written..])? {
+            0 => return Err(LinuxError::EIO),
+            n => written += n,
+        }
+    }
+    Ok(data.len())
+}
+
 macro_rules! syscall_body {
     ($fn: ident, $($stmt: tt)*) => {{
+        // A killed task is not forced to exit in the middle of a syscall.
+        #[cfg(feature = "multitask")]
+        let _kernel = ruxtask::KernelSection::enter();
         #[allow(clippy::redundant_closure_call)]
         let res = (|| -> axerrno::LinuxResult<_> { $($stmt)* })();
         match res {
@@ -58,6 +79,8 @@ macro_rules! syscall_body {
 
 macro_rules! syscall_body_no_debug {
     ($($stmt: tt)*) => {{
+        #[cfg(feature = "multitask")]
+        let _kernel = ruxtask::KernelSection::enter();
         #[allow(clippy::redundant_closure_call)]
         let res = (|| -> axerrno::LinuxResult<_> { $($stmt)* })();
         match res {
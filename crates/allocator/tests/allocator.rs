@@ -14,7 +14,10 @@ use std::alloc::{Allocator, Layout};
 use std::collections::BTreeMap;
 use std::io::Write;
 
-use allocator::{AllocatorRc, BuddyByteAllocator, SlabByteAllocator, TlsfByteAllocator};
+use allocator::{
+    AllocatorRc, BaseAllocator, BitmapPageAllocator, BuddyByteAllocator, PageAllocator,
+    SlabByteAllocator, TlsfByteAllocator,
+};
 use rand::{prelude::SliceRandom, Rng};
 
 const POOL_SIZE: usize = 1024 * 1024 * 128;
@@ -150,3 +153,42 @@ fn tlsf_alloc() {
         test_btree_map(50_000, &alloc);
     })
 }
+
+#[test]
+fn bitmap_alloc_contiguous() {
+    const PAGE_SIZE: usize = 4096;
+    const HUGE_PAGES: usize = 512;
+    run_test(|pool| {
+        let mut alloc = BitmapPageAllocator::<PAGE_SIZE>::new();
+        alloc.init(pool.as_ptr() as usize, pool.len());
+        let total = alloc.available_pages();
+
+        let huge = alloc
+            .alloc_pages(HUGE_PAGES, HUGE_PAGES * PAGE_SIZE)
+            .unwrap();
+        assert_eq!(huge % (HUGE_PAGES * PAGE_SIZE), 0);
+        assert_eq!(alloc.available_pages(), total - HUGE_PAGES);
+
+        // All pages of a contiguous allocation are freed at once.
+        alloc.dealloc_pages(huge, HUGE_PAGES);
+        assert_eq!(alloc.available_pages(), total);
+        assert_eq!(
+            alloc
+                .alloc_pages(HUGE_PAGES, HUGE_PAGES * PAGE_SIZE)
+                .unwrap(),
+            huge
+        );
+
+        // Or one by one, e.g. after the huge page is split.
+        for i in 0..HUGE_PAGES {
+            alloc.dealloc_pages(huge + i * PAGE_SIZE, 1);
+        }
+        assert_eq!(alloc.available_pages(), total);
+        assert_eq!(
+            alloc
+                .alloc_pages(HUGE_PAGES, HUGE_PAGES * PAGE_SIZE)
+                .unwrap(),
+            huge
+        );
+    })
+}
@@ -13,7 +13,7 @@
 
 use core::ptr::NonNull;
 
-use crate::{TriggerMode, GIC_MAX_IRQ, SPI_RANGE};
+use crate::{TriggerMode, GIC_MAX_IRQ, SGI_RANGE, SPI_RANGE};
 use tock_registers::interfaces::{Readable, Writeable};
 use tock_registers::register_structs;
 use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
@@ -186,6 +186,17 @@ impl GicDistributor {
         }
     }
 
+    /// Forwards the given SGI to the CPU interface `cpu_id`.
+    pub fn send_sgi(&mut self, vector: usize, cpu_id: usize) {
+        if vector >= SGI_RANGE.end || cpu_id >= 8 {
+            return;
+        }
+        // TargetListFilter = 0: forward to the CPUs in CPUTargetList.
+        self.regs()
+            .SGIR
+            .set(((1 << cpu_id) << 16) as u32 | vector as u32);
+    }
+
     /// Initializes the GIC distributor.
     ///
     /// It disables all interrupts, sets the target of all SPIs to CPU 0,
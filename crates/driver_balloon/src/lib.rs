@@ -0,0 +1,117 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Common traits and types for memory balloon device drivers.
+//!
+//! A memory balloon lets the host reclaim the guest memory: the guest
+//! *inflates* the balloon by giving free pages to the host, and *deflates* it
+//! to take them back.
+
+#![no_std]
+#![feature(doc_auto_cfg)]
+
+#[doc(no_inline)]
+pub use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
+
+/// The size of the pages in the balloon, which is always 4 KiB whatever the
+/// page size of the guest is.
+pub const BALLOON_PAGE_SIZE: usize = 0x1000;
+
+/// The memory statistics the guest can report to the host.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StatTag {
+    /// Amount of memory swapped in, in bytes.
+    SwapIn = 0,
+    /// Amount of memory swapped out, in bytes.
+    SwapOut = 1,
+    /// Number of major page faults.
+    MajorFaults = 2,
+    /// Number of minor page faults.
+    MinorFaults = 3,
+    /// Amount of memory not used for any purpose, in bytes.
+    FreeMemory = 4,
+    /// Total amount of memory available to the guest, in bytes.
+    TotalMemory = 5,
+    /// Amount of memory that can be used without pushing the guest to
+    /// swap, in bytes.
+    AvailableMemory = 6,
+    /// Amount of memory used by the disk caches, which can be freed quickly,
+    /// in bytes.
+    DiskCaches = 7,
+}
+
+/// A memory statistic reported to the host.
+#[derive(Debug, Clone, Copy)]
+pub struct BalloonStat {
+    /// What the value is.
+    pub tag: StatTag,
+    /// The value.
+    pub val: u64,
+}
+
+impl BalloonStat {
+    /// Creates a new statistic.
+    pub const fn new(tag: StatTag, val: u64) -> Self {
+        Self { tag, val }
+    }
+}
+
+/// Operations that require a memory balloon driver to implement.
+///
+/// Pages are identified by their page frame numbers, i.e. the physical
+/// address divided by [`BALLOON_PAGE_SIZE`].
+pub trait BalloonDriverOps: BaseDriverOps {
+    /// The number of pages the host wants in the balloon.
+    fn target_pages(&self) -> usize;
+
+    /// The number of pages in the balloon, as last told to the host.
+    fn actual_pages(&self) -> usize;
+
+    /// Tells the host the number of pages in the balloon.
+    fn set_actual_pages(&mut self, num_pages: usize);
+
+    /// The maximum number of pages per [`inflate`](Self::inflate) or
+    /// [`deflate`](Self::deflate) request.
+    fn max_pages_per_request(&self) -> usize;
+
+    /// Gives the pages to the host. The guest must not touch them until they
+    /// are deflated.
+    fn inflate(&mut self, pfns: &[u32]) -> DevResult;
+
+    /// Takes the pages back from the host. They can be used once it returns.
+    fn deflate(&mut self, pfns: &[u32]) -> DevResult;
+
+    /// Whether the pages can be taken back when the guest runs out of memory,
+    /// even if the balloon is not larger than the target.
+    fn deflate_on_oom(&self) -> bool;
+
+    /// Whether the host asks for the memory statistics, which should be
+    /// answered by [`update_stats`](Self::update_stats).
+    fn stats_requested(&mut self) -> bool;
+
+    /// Reports the memory statistics to the host.
+    ///
+    /// Returns [`DevError::Again`] if they are not asked for yet, or
+    /// [`DevError::Unsupported`] if the device doesn't take statistics.
+    fn update_stats(&mut self, stats: &[BalloonStat]) -> DevResult;
+
+    /// The maximum number of memory ranges per
+    /// [`report_free_pages`](Self::report_free_pages) request, 0 if the free
+    /// page reporting is not supported.
+    fn max_reported_ranges(&self) -> usize;
+
+    /// Reports the free memory ranges, which the host may discard. The guest
+    /// must not touch them until it returns, and their contents are undefined
+    /// after that.
+    fn report_free_pages(&mut self, ranges: &mut [&mut [u8]]) -> DevResult;
+
+    /// Acknowledges a pending interrupt, returns whether there was one.
+    fn ack_interrupt(&mut self) -> bool;
+}
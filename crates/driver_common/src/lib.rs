@@ -13,6 +13,7 @@
 //! You have to use this crate with the following crates for corresponding
 //! device types:
 //!
+//! - [`driver_balloon`][5]: Common traits and types for memory balloon drivers.
 //! - [`driver_block`][2]: Common traits for block storage drivers.
 //! - [`driver_display`][3]: Common traits and types for graphics display drivers.
 //! - [`driver_net`][4]: Common traits and types for network (NIC) drivers.
@@ -21,6 +22,7 @@
 //! [2]: ../driver_block/index.html
 //! [3]: ../driver_display/index.html
 //! [4]: ../driver_net/index.html
+//! [5]: ../driver_balloon/index.html
 
 #![no_std]
 #![feature(const_trait_impl)]
@@ -38,6 +40,8 @@ pub enum DeviceType {
     Display,
     /// Plan-9 device (e.g. 9pfs)
     _9P,
+    /// Memory balloon device.
+    Balloon,
 }
 
 /// The error type for device operation failures.
@@ -24,6 +24,17 @@ const RX_BUFFER_SIZE: usize = 1024;
 const MEM_POOL: usize = 4096;
 const MEM_POOL_ENTRY_SIZE: usize = 2048;
 
+/// Extended Interrupt Cause Register, cleared on read.
+const IXGBE_EICR: usize = 0x00800;
+/// Extended Interrupt Mask Set Register.
+const IXGBE_EIMS: usize = 0x00880;
+/// Interrupt Vector Allocation Register of the queues 0 and 1.
+const IXGBE_IVAR0: usize = 0x00900;
+/// Valid bit of an entry in the IVAR.
+const IXGBE_IVAR_ALLOC_VAL: u32 = 0x80;
+/// The interrupt cause that the receive queue 0 is mapped to.
+const RX_QUEUE0_CAUSE: u32 = 0;
+
 /// The ixgbe NIC device driver.
 ///
 /// `QS` is the ixgbe queue size, `QN` is the ixgbe queue num.
@@ -31,6 +42,8 @@ pub struct IxgbeNic<H: IxgbeHal, const QS: usize, const QN: u16> {
     inner: IxgbeDevice<H, QS>,
     mem_pool: Arc<MemPool>,
     rx_buffer_queue: VecDeque<NetBufPtr>,
+    base: usize,
+    irq: Option<usize>,
 }
 
 unsafe impl<H: IxgbeHal, const QS: usize, const QN: u16> Sync for IxgbeNic<H, QS, QN> {}
@@ -52,8 +65,36 @@ impl<H: IxgbeHal, const QS: usize, const QN: u16> IxgbeNic<H, QS, QN> {
             inner,
             mem_pool,
             rx_buffer_queue,
+            base,
+            irq: None,
         })
     }
+
+    /// Sets the IRQ number of the legacy interrupt line of the NIC.
+    ///
+    /// If the IRQ number is known, the receive queue 0 is mapped to an
+    /// interrupt cause, which is unmasked, so that the NIC raises interrupts
+    /// on incoming packets. Otherwise the NIC keeps its interrupts masked and
+    /// has to be polled.
+    pub fn with_irq(mut self, irq: Option<usize>) -> Self {
+        if irq.is_some() {
+            let ivar = self.read_reg(IXGBE_IVAR0) & !0xff;
+            self.write_reg(IXGBE_IVAR0, ivar | RX_QUEUE0_CAUSE | IXGBE_IVAR_ALLOC_VAL);
+            self.write_reg(IXGBE_EIMS, 1 << RX_QUEUE0_CAUSE);
+        }
+        self.irq = irq;
+        self
+    }
+
+    fn read_reg(&self, reg: usize) -> u32 {
+        // Safe because the registers are mapped by `init`.
+        unsafe { ((self.base + reg) as *const u32).read_volatile() }
+    }
+
+    fn write_reg(&self, reg: usize, val: u32) {
+        // Safe because the registers are mapped by `init`.
+        unsafe { ((self.base + reg) as *mut u32).write_volatile(val) }
+    }
 }
 
 impl<H: IxgbeHal, const QS: usize, const QN: u16> BaseDriverOps for IxgbeNic<H, QS, QN> {
@@ -145,6 +186,15 @@ impl<H: IxgbeHal, const QS: usize, const QN: u16> NetDriverOps for IxgbeNic<H, Q
         let tx_buf = IxgbeNetBuf::alloc(&self.mem_pool, size).map_err(|_| DevError::NoMemory)?;
         Ok(NetBufPtr::from(tx_buf))
     }
+
+    fn irq_num(&self) -> Option<usize> {
+        self.irq
+    }
+
+    fn ack_interrupt(&mut self) -> bool {
+        // Reading the cause register clears it, and so deasserts the line.
+        self.irq.is_some() && self.read_reg(IXGBE_EICR) != 0
+    }
 }
 
 impl From<IxgbeNetBuf> for NetBufPtr {
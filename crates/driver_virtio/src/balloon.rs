@@ -0,0 +1,447 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! The VirtIO memory balloon device, see section 5.5 of the VirtIO spec.
+//!
+//! The `virtio-drivers` crate doesn't have it, so the device and its queues
+//! are driven directly through the [`Transport`]. Every request is waited for
+//! before the next one, except the statistics buffer, which stays in the
+//! stats queue until the host asks for new statistics.
+
+use crate::as_dev_err;
+use alloc::boxed::Box;
+use core::ptr::{addr_of, addr_of_mut, NonNull};
+use core::sync::atomic::{fence, Ordering};
+use driver_balloon::{BalloonDriverOps, BalloonStat};
+use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::{
+    transport::{DeviceStatus, Transport},
+    BufferDirection, Hal, PhysAddr,
+};
+
+extern crate alloc;
+
+const PAGE_SIZE: usize = 0x1000;
+
+const QUEUE_INFLATE: u16 = 0;
+const QUEUE_DEFLATE: u16 = 1;
+/// The stats queue, if negotiated. The reporting queue follows the last
+/// negotiated one.
+const QUEUE_STATS: u16 = 2;
+
+const FEATURE_STATS_VQ: u64 = 1 << 1;
+const FEATURE_DEFLATE_ON_OOM: u64 = 1 << 2;
+const FEATURE_PAGE_REPORTING: u64 = 1 << 5;
+const FEATURE_VERSION_1: u64 = 1 << 32;
+const SUPPORTED_FEATURES: u64 =
+    FEATURE_STATS_VQ | FEATURE_DEFLATE_ON_OOM | FEATURE_PAGE_REPORTING | FEATURE_VERSION_1;
+
+/// The size of all the queues.
+const QUEUE_SIZE: usize = 16;
+/// The maximum number of PFNs per inflate or deflate request, the same as
+/// Linux.
+const MAX_PFNS: usize = 256;
+/// The maximum number of statistics in the stats buffer.
+const MAX_STATS: usize = 8;
+/// The size of a `virtio_balloon_stat`, which is packed.
+const STAT_SIZE: usize = 10;
+
+#[repr(C)]
+struct BalloonConfig {
+    num_pages: u32,
+    actual: u32,
+    free_page_hint_cmd_id: u32,
+    poison_val: u32,
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// The offset of the available ring, after the descriptor table.
+const AVAIL_OFFSET: usize = QUEUE_SIZE * core::mem::size_of::<Descriptor>();
+/// The offset of the used ring, which is page aligned for the legacy
+/// interface.
+const USED_OFFSET: usize = (AVAIL_OFFSET + 6 + 2 * QUEUE_SIZE + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+const QUEUE_PAGES: usize = (USED_OFFSET + 6 + 8 * QUEUE_SIZE).div_ceil(PAGE_SIZE);
+
+/// A split virtqueue with at most one request in flight.
+struct BalloonQueue<H: Hal> {
+    idx: u16,
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    avail_idx: u16,
+    last_used_idx: u16,
+    in_flight: bool,
+    _hal: core::marker::PhantomData<H>,
+}
+
+impl<H: Hal> BalloonQueue<H> {
+    fn new<T: Transport>(transport: &mut T, idx: u16) -> DevResult<Self> {
+        if transport.queue_used(idx) {
+            return Err(DevError::AlreadyExists);
+        }
+        if (transport.max_queue_size(idx) as usize) < QUEUE_SIZE {
+            return Err(DevError::BadState);
+        }
+        let (paddr, vaddr) = H::dma_alloc(QUEUE_PAGES, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        // Safe because the memory is just allocated.
+        unsafe { vaddr.as_ptr().write_bytes(0, QUEUE_PAGES * PAGE_SIZE) };
+        transport.queue_set(
+            idx,
+            QUEUE_SIZE as u32,
+            paddr,
+            paddr + AVAIL_OFFSET,
+            paddr + USED_OFFSET,
+        );
+        Ok(Self {
+            idx,
+            paddr,
+            vaddr,
+            avail_idx: 0,
+            last_used_idx: 0,
+            in_flight: false,
+            _hal: core::marker::PhantomData,
+        })
+    }
+
+    fn field<U>(&self, offset: usize) -> *mut U {
+        // Safe because all the offsets are inside the queue memory.
+        unsafe { self.vaddr.as_ptr().add(offset) as *mut U }
+    }
+
+    /// Makes the buffers available to the device and notifies it, the
+    /// device-readable `inputs` first.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must not be touched until [`pop`](Self::pop) is called
+    /// with the same buffers.
+    unsafe fn add<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> DevResult {
+        let num = inputs.len() + outputs.len();
+        if num == 0 || num > QUEUE_SIZE {
+            return Err(DevError::InvalidParam);
+        }
+        if self.in_flight {
+            return Err(DevError::ResourceBusy);
+        }
+        let buffers = inputs
+            .iter()
+            .map(|buf| (NonNull::from(*buf), BufferDirection::DriverToDevice))
+            .chain(
+                outputs
+                    .iter_mut()
+                    .map(|buf| (NonNull::from(&mut **buf), BufferDirection::DeviceToDriver)),
+            );
+        for (i, (buf, direction)) in buffers.enumerate() {
+            let mut flags = 0;
+            if matches!(direction, BufferDirection::DeviceToDriver) {
+                flags |= DESC_F_WRITE;
+            }
+            if i + 1 < num {
+                flags |= DESC_F_NEXT;
+            }
+            let desc = Descriptor {
+                addr: H::share(buf, direction) as u64,
+                len: buf.len() as u32,
+                flags,
+                next: i as u16 + 1,
+            };
+            self.field::<Descriptor>(i * core::mem::size_of::<Descriptor>())
+                .write_volatile(desc);
+        }
+        // The chain always starts from the first descriptor.
+        let slot = self.avail_idx as usize % QUEUE_SIZE;
+        self.field::<u16>(AVAIL_OFFSET + 4 + 2 * slot)
+            .write_volatile(0);
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        self.field::<u16>(AVAIL_OFFSET + 2)
+            .write_volatile(self.avail_idx);
+        fence(Ordering::SeqCst);
+        self.in_flight = true;
+        transport.notify(self.idx);
+        Ok(())
+    }
+
+    /// Whether the request in flight is done by the device.
+    fn can_pop(&self) -> bool {
+        fence(Ordering::SeqCst);
+        // Safe because the offset is inside the queue memory.
+        let used_idx = unsafe { self.field::<u16>(USED_OFFSET + 2).read_volatile() };
+        self.in_flight && used_idx != self.last_used_idx
+    }
+
+    /// Takes back the buffers of the request done by the device, returns the
+    /// number of bytes written to `outputs`.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must be the same as the ones passed to [`add`](Self::add).
+    unsafe fn pop(&mut self, inputs: &[&[u8]], outputs: &mut [&mut [u8]]) -> DevResult<u32> {
+        if !self.can_pop() {
+            return Err(DevError::Again);
+        }
+        let slot = self.last_used_idx as usize % QUEUE_SIZE;
+        let len = self
+            .field::<u32>(USED_OFFSET + 4 + 8 * slot + 4)
+            .read_volatile();
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        self.in_flight = false;
+        for (i, buf) in inputs.iter().enumerate() {
+            let paddr = self
+                .field::<Descriptor>(i * core::mem::size_of::<Descriptor>())
+                .read_volatile()
+                .addr;
+            H::unshare(
+                paddr as PhysAddr,
+                NonNull::from(*buf),
+                BufferDirection::DriverToDevice,
+            );
+        }
+        for (i, buf) in outputs.iter_mut().enumerate() {
+            let paddr = self
+                .field::<Descriptor>((inputs.len() + i) * core::mem::size_of::<Descriptor>())
+                .read_volatile()
+                .addr;
+            H::unshare(
+                paddr as PhysAddr,
+                NonNull::from(&mut **buf),
+                BufferDirection::DeviceToDriver,
+            );
+        }
+        Ok(len)
+    }
+
+    /// Sends a request, and spins until the device is done with it.
+    fn request<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+    ) -> DevResult<u32> {
+        // Safe because the buffers are borrowed until the request is done.
+        unsafe {
+            self.add(transport, inputs, outputs)?;
+            while !self.can_pop() {
+                core::hint::spin_loop();
+            }
+            self.pop(inputs, outputs)
+        }
+    }
+}
+
+impl<H: Hal> Drop for BalloonQueue<H> {
+    fn drop(&mut self) {
+        // Safe because the memory is allocated in `new`, and the device no
+        // longer uses it.
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, QUEUE_PAGES) };
+    }
+}
+
+/// The VirtIO memory balloon device driver.
+pub struct VirtIoBalloonDev<H: Hal, T: Transport> {
+    transport: T,
+    config: NonNull<BalloonConfig>,
+    features: u64,
+    inflate_queue: BalloonQueue<H>,
+    deflate_queue: BalloonQueue<H>,
+    stats_queue: Option<BalloonQueue<H>>,
+    reporting_queue: Option<BalloonQueue<H>>,
+    /// The statistics buffer, which is owned by the device while it's in the
+    /// stats queue.
+    stats_buf: Box<[u8; MAX_STATS * STAT_SIZE]>,
+    stats_len: usize,
+}
+
+unsafe impl<H: Hal, T: Transport> Send for VirtIoBalloonDev<H, T> {}
+unsafe impl<H: Hal, T: Transport> Sync for VirtIoBalloonDev<H, T> {}
+
+impl<H: Hal, T: Transport> VirtIoBalloonDev<H, T> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & SUPPORTED_FEATURES;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let config = transport
+            .config_space::<BalloonConfig>()
+            .map_err(as_dev_err)?;
+        let inflate_queue = BalloonQueue::new(&mut transport, QUEUE_INFLATE)?;
+        let deflate_queue = BalloonQueue::new(&mut transport, QUEUE_DEFLATE)?;
+        let mut next_queue = QUEUE_STATS;
+        let stats_queue = if features & FEATURE_STATS_VQ != 0 {
+            next_queue += 1;
+            Some(BalloonQueue::new(&mut transport, QUEUE_STATS)?)
+        } else {
+            None
+        };
+        // The free page hinting queue would come next, which is not
+        // negotiated.
+        let reporting_queue = if features & FEATURE_PAGE_REPORTING != 0 {
+            Some(BalloonQueue::new(&mut transport, next_queue)?)
+        } else {
+            None
+        };
+        transport.finish_init();
+
+        log::debug!("virtio-balloon features: {:#x}", features);
+        Ok(Self {
+            transport,
+            config,
+            features,
+            inflate_queue,
+            deflate_queue,
+            stats_queue,
+            reporting_queue,
+            stats_buf: Box::new([0; MAX_STATS * STAT_SIZE]),
+            stats_len: 0,
+        })
+    }
+
+    fn pfn_request(queue: &mut BalloonQueue<H>, transport: &mut T, pfns: &[u32]) -> DevResult {
+        for chunk in pfns.chunks(MAX_PFNS) {
+            let mut buf = [0u8; MAX_PFNS * 4];
+            for (i, pfn) in chunk.iter().enumerate() {
+                buf[i * 4..i * 4 + 4].copy_from_slice(&pfn.to_le_bytes());
+            }
+            queue.request(transport, &[&buf[..chunk.len() * 4]], &mut [])?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoBalloonDev<H, T> {
+    fn drop(&mut self) {
+        // Reset the device, so it no longer uses the queues.
+        self.transport.set_status(DeviceStatus::empty());
+        self.transport.queue_unset(QUEUE_INFLATE);
+        self.transport.queue_unset(QUEUE_DEFLATE);
+        if let Some(queue) = &self.stats_queue {
+            self.transport.queue_unset(queue.idx);
+        }
+        if let Some(queue) = &self.reporting_queue {
+            self.transport.queue_unset(queue.idx);
+        }
+    }
+}
+
+impl<H: Hal, T: Transport> const BaseDriverOps for VirtIoBalloonDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-balloon"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Balloon
+    }
+}
+
+impl<H: Hal, T: Transport> BalloonDriverOps for VirtIoBalloonDev<H, T> {
+    fn target_pages(&self) -> usize {
+        // Safe because the config space is valid as long as the transport.
+        let num_pages = unsafe { addr_of!((*self.config.as_ptr()).num_pages).read_volatile() };
+        u32::from_le(num_pages) as usize
+    }
+
+    fn actual_pages(&self) -> usize {
+        // Safe because the config space is valid as long as the transport.
+        let actual = unsafe { addr_of!((*self.config.as_ptr()).actual).read_volatile() };
+        u32::from_le(actual) as usize
+    }
+
+    fn set_actual_pages(&mut self, num_pages: usize) {
+        let actual = (num_pages as u32).to_le();
+        // Safe because the config space is valid as long as the transport.
+        unsafe { addr_of_mut!((*self.config.as_ptr()).actual).write_volatile(actual) };
+    }
+
+    fn max_pages_per_request(&self) -> usize {
+        MAX_PFNS
+    }
+
+    fn inflate(&mut self, pfns: &[u32]) -> DevResult {
+        Self::pfn_request(&mut self.inflate_queue, &mut self.transport, pfns)
+    }
+
+    fn deflate(&mut self, pfns: &[u32]) -> DevResult {
+        Self::pfn_request(&mut self.deflate_queue, &mut self.transport, pfns)
+    }
+
+    fn deflate_on_oom(&self) -> bool {
+        self.features & FEATURE_DEFLATE_ON_OOM != 0
+    }
+
+    fn stats_requested(&mut self) -> bool {
+        // The first statistics are sent without being asked for.
+        self.stats_queue
+            .as_ref()
+            .is_some_and(|queue| !queue.in_flight || queue.can_pop())
+    }
+
+    fn update_stats(&mut self, stats: &[BalloonStat]) -> DevResult {
+        let queue = self.stats_queue.as_mut().ok_or(DevError::Unsupported)?;
+        if queue.in_flight {
+            // Safe because the buffer is the one in the queue.
+            unsafe { queue.pop(&[&self.stats_buf[..self.stats_len]], &mut [])? };
+        }
+        let stats = &stats[..stats.len().min(MAX_STATS)];
+        for (stat, buf) in stats.iter().zip(self.stats_buf.chunks_exact_mut(STAT_SIZE)) {
+            buf[..2].copy_from_slice(&(stat.tag as u16).to_le_bytes());
+            buf[2..].copy_from_slice(&stat.val.to_le_bytes());
+        }
+        self.stats_len = stats.len() * STAT_SIZE;
+        // Safe because the buffer is boxed and only touched after it's popped.
+        unsafe {
+            queue.add(
+                &mut self.transport,
+                &[&self.stats_buf[..self.stats_len]],
+                &mut [],
+            )
+        }
+    }
+
+    fn max_reported_ranges(&self) -> usize {
+        if self.reporting_queue.is_some() {
+            QUEUE_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn report_free_pages(&mut self, ranges: &mut [&mut [u8]]) -> DevResult {
+        let queue = self.reporting_queue.as_mut().ok_or(DevError::Unsupported)?;
+        queue.request(&mut self.transport, &[], ranges)?;
+        Ok(())
+    }
+
+    fn ack_interrupt(&mut self) -> bool {
+        self.transport.ack_interrupt()
+    }
+}
@@ -23,6 +23,8 @@
 #![feature(const_trait_impl)]
 #![feature(doc_auto_cfg)]
 
+#[cfg(feature = "balloon")]
+mod balloon;
 #[cfg(feature = "block")]
 mod blk;
 #[cfg(feature = "gpu")]
@@ -32,6 +34,8 @@ mod net;
 #[cfg(feature = "v9p")]
 mod v9p;
 
+#[cfg(feature = "balloon")]
+pub use self::balloon::VirtIoBalloonDev;
 #[cfg(feature = "block")]
 pub use self::blk::VirtIoBlkDev;
 #[cfg(feature = "gpu")]
@@ -89,6 +93,7 @@ const fn as_dev_type(t: VirtIoDevType) -> Option<DeviceType> {
         Network => Some(DeviceType::Net),
         GPU => Some(DeviceType::Display),
         _9P => Some(DeviceType::_9P),
+        MemoryBallooning => Some(DeviceType::Balloon),
         _ => None,
     }
 }
@@ -140,6 +140,105 @@ impl<M: PagingMetaData, PTE: GenericPTE, IF: PagingIf> PageTable64<M, PTE, IF> {
         Ok(size)
     }
 
+    /// Clears the dirty state of the mapping starts with `vaddr`, returns
+    /// whether the mapped page has been written since it was last cleared.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn clear_dirty(&mut self, vaddr: VirtAddr) -> PagingResult<bool> {
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let dirty = entry.is_dirty();
+        entry.set_dirty(false);
+        Ok(dirty)
+    }
+
+    /// Marks the mapping starts with `vaddr` as dirty, for the write faults
+    /// raised by the entries write-protected by [`Self::clear_dirty`].
+    ///
+    /// Returns whether the mapping was clean before.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn set_dirty(&mut self, vaddr: VirtAddr) -> PagingResult<bool> {
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let clean = !entry.is_dirty();
+        entry.set_dirty(true);
+        Ok(clean)
+    }
+
+    /// Clears the accessed state of the mapping starts with `vaddr`, returns
+    /// whether the mapped page has been accessed since it was last cleared.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn clear_accessed(&mut self, vaddr: VirtAddr) -> PagingResult<bool> {
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let accessed = entry.is_accessed();
+        entry.set_accessed(false);
+        Ok(accessed)
+    }
+
+    /// Marks the mapping starts with `vaddr` as accessed, for the faults
+    /// raised by the entries cleared by [`Self::clear_accessed`].
+    ///
+    /// Returns whether the mapping was not accessed before.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn set_accessed(&mut self, vaddr: VirtAddr) -> PagingResult<bool> {
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let unaccessed = !entry.is_accessed();
+        entry.set_accessed(true);
+        Ok(unaccessed)
+    }
+
+    /// Splits the huge page mapping which contains `vaddr` into mappings of
+    /// the next smaller page size, i.e., a 1G page into 2M pages and a 2M page
+    /// into 4K pages. They map the same physical frames with the same flags,
+    /// dirty and accessed state.
+    ///
+    /// Returns the page size of the mapping which contains `vaddr` after
+    /// splitting, nothing is changed if it's not a huge page.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn split_huge(&mut self, vaddr: VirtAddr) -> PagingResult<PageSize> {
+        let (entry, size) = self.get_entry_mut(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let next_size = match size {
+            PageSize::Size1G => PageSize::Size2M,
+            PageSize::Size2M => PageSize::Size4K,
+            PageSize::Size4K => return Ok(size),
+        };
+        let (paddr, flags) = (entry.paddr(), entry.flags());
+        let (dirty, accessed) = (entry.is_dirty(), entry.is_accessed());
+
+        let table_paddr = Self::alloc_table()?;
+        self.intrm_tables.push(table_paddr);
+        for (i, pte) in self.table_of_mut(table_paddr).iter_mut().enumerate() {
+            *pte = GenericPTE::new_page(paddr + i * next_size as usize, flags, next_size.is_huge());
+            pte.set_dirty(dirty);
+            pte.set_accessed(accessed);
+        }
+        let (entry, _) = self.get_entry_mut(vaddr)?;
+        *entry = GenericPTE::new_table(table_paddr);
+        Ok(next_size)
+    }
+
     /// Map a contiguous virtual memory region to a contiguous physical memory
     /// region with the given mapping `flags`.
     ///
@@ -43,6 +43,9 @@ bitflags::bitflags! {
         const AF =          1 << 10;
         /// The not global bit.
         const NG =          1 << 11;
+        /// Dirty Bit Modifier: the entry is writable, `AP_RO` only records
+        /// that it has not been written yet.
+        const DBM =         1 <<  51;
         /// Indicates that 16 adjacent translation table entries point to contiguous memory regions.
         const CONTIGUOUS =  1 <<  52;
         /// The Privileged execute-never field.
@@ -126,7 +129,7 @@ impl From<DescriptorAttr> for MappingFlags {
         if attr.contains(DescriptorAttr::VALID) {
             flags |= Self::READ;
         }
-        if !attr.contains(DescriptorAttr::AP_RO) {
+        if !attr.contains(DescriptorAttr::AP_RO) || attr.contains(DescriptorAttr::DBM) {
             flags |= Self::WRITE;
         }
         if attr.contains(DescriptorAttr::AP_EL0) {
@@ -216,11 +219,15 @@ impl GenericPTE for A64PTE {
         self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK)
     }
     fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let clean = !self.is_dirty() && self.flags().contains(MappingFlags::WRITE);
         let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
         if !is_huge {
             attr |= DescriptorAttr::NON_BLOCK;
         }
         self.0 = (self.0 & Self::PHYS_ADDR_MASK) | attr.bits();
+        if clean {
+            self.set_dirty(false);
+        }
     }
 
     fn is_unused(&self) -> bool {
@@ -235,6 +242,35 @@ impl GenericPTE for A64PTE {
     fn clear(&mut self) {
         self.0 = 0
     }
+
+    // Without hardware dirty state management (FEAT_HAFDBS), a write to a
+    // clean entry raises a permission fault instead of clearing `AP_RO`.
+    fn is_dirty(&self) -> bool {
+        !DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::AP_RO)
+    }
+    fn set_dirty(&mut self, dirty: bool) {
+        let attr = DescriptorAttr::from_bits_truncate(self.0);
+        if dirty {
+            if attr.contains(DescriptorAttr::DBM) {
+                self.0 &= !DescriptorAttr::AP_RO.bits();
+            }
+        } else if self.flags().contains(MappingFlags::WRITE) {
+            self.0 |= (DescriptorAttr::AP_RO | DescriptorAttr::DBM).bits();
+        }
+    }
+
+    // Without hardware access flag management, an access to the entry with
+    // `AF` cleared raises an access flag fault.
+    fn is_accessed(&self) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::AF)
+    }
+    fn set_accessed(&mut self, accessed: bool) {
+        if accessed {
+            self.0 |= DescriptorAttr::AF.bits();
+        } else {
+            self.0 &= !DescriptorAttr::AF.bits();
+        }
+    }
 }
 
 impl fmt::Debug for A64PTE {
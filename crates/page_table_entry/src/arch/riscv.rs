@@ -126,6 +126,28 @@ impl GenericPTE for Rv64PTE {
     fn clear(&mut self) {
         self.0 = 0
     }
+
+    fn is_dirty(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as usize).contains(PTEFlags::D)
+    }
+    fn set_dirty(&mut self, dirty: bool) {
+        if dirty {
+            self.0 |= PTEFlags::D.bits() as u64;
+        } else {
+            self.0 &= !(PTEFlags::D.bits() as u64);
+        }
+    }
+
+    fn is_accessed(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as usize).contains(PTEFlags::A)
+    }
+    fn set_accessed(&mut self, accessed: bool) {
+        if accessed {
+            self.0 |= PTEFlags::A.bits() as u64;
+        } else {
+            self.0 &= !(PTEFlags::A.bits() as u64);
+        }
+    }
 }
 
 impl fmt::Debug for Rv64PTE {
@@ -67,6 +67,7 @@ pub struct X64PTE(u64);
 
 impl X64PTE {
     const PHYS_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000; // bits 12..52
+    const STATE_MASK: u64 = PTF::ACCESSED.bits() | PTF::DIRTY.bits(); // kept by `set_flags`
 }
 
 impl GenericPTE for X64PTE {
@@ -95,7 +96,7 @@ impl GenericPTE for X64PTE {
         if is_huge {
             flags |= PTF::HUGE_PAGE;
         }
-        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits()
+        self.0 = (self.0 & (Self::PHYS_ADDR_MASK | Self::STATE_MASK)) | flags.bits()
     }
 
     fn is_unused(&self) -> bool {
@@ -110,6 +111,28 @@ impl GenericPTE for X64PTE {
     fn clear(&mut self) {
         self.0 = 0
     }
+
+    fn is_dirty(&self) -> bool {
+        PTF::from_bits_truncate(self.0).contains(PTF::DIRTY)
+    }
+    fn set_dirty(&mut self, dirty: bool) {
+        if dirty {
+            self.0 |= PTF::DIRTY.bits();
+        } else {
+            self.0 &= !PTF::DIRTY.bits();
+        }
+    }
+
+    fn is_accessed(&self) -> bool {
+        PTF::from_bits_truncate(self.0).contains(PTF::ACCESSED)
+    }
+    fn set_accessed(&mut self, accessed: bool) {
+        if accessed {
+            self.0 |= PTF::ACCESSED.bits();
+        } else {
+            self.0 &= !PTF::ACCESSED.bits();
+        }
+    }
 }
 
 impl fmt::Debug for X64PTE {
@@ -78,4 +78,24 @@ pub trait GenericPTE: Debug + Clone + Copy + Sync + Send + Sized {
     fn is_huge(&self) -> bool;
     /// Set this entry to zero.
     fn clear(&mut self);
+
+    /// Returns whether the mapped page has been written since the dirty
+    /// state was last cleared.
+    fn is_dirty(&self) -> bool;
+    /// Set or clear the dirty state of the entry.
+    ///
+    /// On architectures whose hardware does not update the dirty state,
+    /// clearing it write-protects the entry, so that the next write faults
+    /// and the handler sets it again.
+    fn set_dirty(&mut self, dirty: bool);
+
+    /// Returns whether the mapped page has been accessed since the accessed
+    /// state was last cleared.
+    fn is_accessed(&self) -> bool;
+    /// Set or clear the accessed state of the entry.
+    ///
+    /// On architectures whose hardware does not update the accessed state,
+    /// the next access to a cleared entry faults, and the handler sets it
+    /// again.
+    fn set_accessed(&mut self, accessed: bool);
 }
@@ -108,4 +108,20 @@ fn test_percpu() {
         assert_eq!(s.foo, 0x2333);
         assert_eq!(s.bar, 100);
     });
+
+    unsafe {
+        assert_eq!(STRUCT.remote_ptr(0), STRUCT.current_ptr());
+        assert_eq!(STRUCT.remote_ref_raw(0).foo, 0x2333);
+    }
+
+    #[cfg(not(feature = "sp-naive"))]
+    unsafe {
+        assert_eq!(
+            U32.remote_ptr(1) as usize,
+            percpu_area_base(1) + U32.offset()
+        );
+        *U32.remote_ref_mut_raw(1) = 0xcafe;
+        assert_eq!(*U32.remote_ref_raw(1), 0xcafe);
+        assert_eq!(U32.read_current(), 0xdead_beef);
+    }
 }
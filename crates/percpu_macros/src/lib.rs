@@ -108,6 +108,7 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let offset = arch::gen_offset(inner_symbol_name);
     let current_ptr = arch::gen_current_ptr(inner_symbol_name, ty);
+    let remote_ptr = arch::gen_remote_ptr(&format_ident!("cpu_id"), ty);
     quote! {
         #[cfg_attr(not(target_os = "macos"), link_section = ".percpu")] // unimplemented on macos
         #(#attrs)*
@@ -158,6 +159,43 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
                 &mut *(self.current_ptr() as *mut #ty)
             }
 
+            /// Returns the raw pointer of this per-CPU data on the given CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that `cpu_id` is less than the number of CPUs
+            /// passed to `percpu::init`, and that accesses from different CPUs
+            /// are synchronized.
+            #[inline]
+            pub unsafe fn remote_ptr(&self, cpu_id: usize) -> *const #ty {
+                #remote_ptr
+            }
+
+            /// Returns the reference of the per-CPU data on the given CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that `cpu_id` is less than the number of CPUs
+            /// passed to `percpu::init`, and that accesses from different CPUs
+            /// are synchronized.
+            #[inline]
+            pub unsafe fn remote_ref_raw(&self, cpu_id: usize) -> &#ty {
+                &*self.remote_ptr(cpu_id)
+            }
+
+            /// Returns the mutable reference of the per-CPU data on the given CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that `cpu_id` is less than the number of CPUs
+            /// passed to `percpu::init`, and that accesses from different CPUs
+            /// are synchronized.
+            #[inline]
+            #[allow(clippy::mut_from_ref)]
+            pub unsafe fn remote_ref_mut_raw(&self, cpu_id: usize) -> &mut #ty {
+                &mut *(self.remote_ptr(cpu_id) as *mut #ty)
+            }
+
             /// Manipulate the per-CPU data on the current CPU in the given closure.
             /// Preemption will be disabled during the call.
             pub fn with_current<F, T>(&self, f: F) -> T
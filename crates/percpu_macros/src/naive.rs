@@ -24,6 +24,12 @@ pub fn gen_current_ptr(symbol: &Ident, _ty: &Type) -> proc_macro2::TokenStream {
     }
 }
 
+pub fn gen_remote_ptr(_cpu_id: &Ident, _ty: &Type) -> proc_macro2::TokenStream {
+    quote! {
+        self.current_ptr()
+    }
+}
+
 pub fn gen_read_current_raw(_symbol: &Ident, _ty: &Type) -> proc_macro2::TokenStream {
     quote! {
         *self.current_ptr()
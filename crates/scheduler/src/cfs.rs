@@ -59,11 +59,11 @@ impl<T> CFSTask<T> {
         }
     }
 
-    fn get_id(&self) -> isize {
+    pub(crate) fn get_id(&self) -> isize {
         self.id.load(Ordering::Acquire)
     }
 
-    fn get_vruntime(&self) -> isize {
+    pub(crate) fn get_vruntime(&self) -> isize {
         if self.nice.load(Ordering::Acquire) == 0 {
             self.init_vruntime.load(Ordering::Acquire) + self.delta.load(Ordering::Acquire)
         } else {
@@ -72,13 +72,13 @@ impl<T> CFSTask<T> {
         }
     }
 
-    fn set_vruntime(&self, v: isize) {
+    pub(crate) fn set_vruntime(&self, v: isize) {
         self.init_vruntime.store(v, Ordering::Release);
     }
 
     // Simple Implementation: no change in vruntime.
     // Only modifying priority of current process is supported currently.
-    fn set_priority(&self, nice: isize) {
+    pub(crate) fn set_priority(&self, nice: isize) {
         let current_init_vruntime = self.get_vruntime();
         self.init_vruntime
             .store(current_init_vruntime, Ordering::Release);
@@ -86,11 +86,11 @@ impl<T> CFSTask<T> {
         self.nice.store(nice, Ordering::Release);
     }
 
-    fn set_id(&self, id: isize) {
+    pub(crate) fn set_id(&self, id: isize) {
         self.id.store(id, Ordering::Release);
     }
 
-    fn task_tick(&self) {
+    pub(crate) fn task_tick(&self) {
         self.delta.fetch_add(1, Ordering::Release);
     }
 
@@ -189,6 +189,14 @@ impl<T> BaseScheduler for CFScheduler<T> {
             .insert((prev.clone().get_vruntime(), taskid), prev);
     }
 
+    fn steal_task<F>(&mut self, mut f: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        let task = self.ready_queue.values().find(|t| f(t))?.clone();
+        self.remove_task(&task)
+    }
+
     fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
         current.task_tick();
         self.min_vruntime.is_none()
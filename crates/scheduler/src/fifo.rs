@@ -107,6 +107,23 @@ impl<T> BaseScheduler for FifoScheduler<T> {
         self.ready_queue.push_back(prev);
     }
 
+    fn steal_task<F>(&mut self, mut f: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        // Rotate the whole queue once, so that the order is kept.
+        let mut stolen = None;
+        for _ in 0..self.ready_queue.iter().count() {
+            let task = self.ready_queue.pop_front()?;
+            if stolen.is_none() && f(&task) {
+                stolen = Some(task);
+            } else {
+                self.ready_queue.push_back(task);
+            }
+        }
+        stolen
+    }
+
     fn task_tick(&mut self, _current: &Self::SchedItem) -> bool {
         false // no reschedule
     }
@@ -14,6 +14,8 @@
 //! - [`FifoScheduler`]: FIFO (First-In-First-Out) scheduler (cooperative).
 //! - [`RRScheduler`]: Round-robin scheduler (preemptive).
 //! - [`CFScheduler`]: Completely Fair Scheduler (preemptive).
+//! - [`RTScheduler`]: Real-time scheduler with `SCHED_DEADLINE`, `SCHED_FIFO`,
+//!   `SCHED_RR` classes, and CFS for normal tasks (preemptive).
 
 #![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
@@ -21,6 +23,7 @@
 mod cfs;
 mod fifo;
 mod round_robin;
+mod rt;
 
 #[cfg(test)]
 mod tests;
@@ -30,6 +33,7 @@ extern crate alloc;
 pub use cfs::{CFSTask, CFScheduler};
 pub use fifo::{FifoScheduler, FifoTask};
 pub use round_robin::{RRScheduler, RRTask};
+pub use rt::{RTScheduler, RTTask, SchedPolicy, RT_PRIO_MAX, RT_PRIO_MIN};
 
 /// The base scheduler trait that all schedulers should implement.
 ///
@@ -67,6 +71,21 @@ pub trait BaseScheduler {
     /// ready queue.
     fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool);
 
+    /// Removes the first task for which `f` returns `true`, in the order they
+    /// would be picked. Unlike [`pick_next_task`](Self::pick_next_task) and
+    /// [`put_prev_task`](Self::put_prev_task), the state of other tasks is
+    /// untouched, and the removed task keeps its state (e.g. time slice), to
+    /// be moved to another scheduler by [`migrate_task`](Self::migrate_task).
+    fn steal_task<F>(&mut self, f: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool;
+
+    /// Adds a task removed from another scheduler by
+    /// [`steal_task`](Self::steal_task), keeping its state.
+    fn migrate_task(&mut self, task: Self::SchedItem) {
+        self.add_task(task);
+    }
+
     /// Advances the scheduler state at each timer tick. Returns `true` if
     /// re-scheduling is required.
     ///
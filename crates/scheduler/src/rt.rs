@@ -0,0 +1,549 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+use alloc::{collections::BTreeMap, collections::VecDeque, sync::Arc};
+use core::fmt::Debug;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicI64, AtomicIsize, AtomicU64, AtomicU8, Ordering};
+
+use crate::{BaseScheduler, CFSTask};
+
+/// The lowest real-time priority.
+pub const RT_PRIO_MIN: u8 = 1;
+/// The highest real-time priority.
+pub const RT_PRIO_MAX: u8 = 99;
+
+const POLICY_NORMAL: u8 = 0;
+const POLICY_FIFO: u8 = 1;
+const POLICY_RR: u8 = 2;
+const POLICY_DEADLINE: u8 = 6;
+
+/// Scheduling policy of a task, with its parameters.
+///
+/// The numeric values of the policies are the same as `SCHED_*` in Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`), scheduled by CFS.
+    Normal,
+    /// First-in first-out real-time policy (`SCHED_FIFO`), with priority
+    /// [`RT_PRIO_MIN`] to [`RT_PRIO_MAX`].
+    Fifo(u8),
+    /// Round-robin real-time policy (`SCHED_RR`), with priority
+    /// [`RT_PRIO_MIN`] to [`RT_PRIO_MAX`].
+    RoundRobin(u8),
+    /// Earliest deadline first policy (`SCHED_DEADLINE`). The task runs for
+    /// at most `runtime` nanoseconds every `period` nanoseconds, and each
+    /// run should be finished within `deadline` nanoseconds.
+    Deadline {
+        /// Runtime budget in each period, in nanoseconds.
+        runtime: u64,
+        /// Relative deadline, in nanoseconds.
+        deadline: u64,
+        /// Period, in nanoseconds.
+        period: u64,
+    },
+}
+
+impl SchedPolicy {
+    /// Whether the parameters are valid.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Self::Normal => true,
+            Self::Fifo(prio) | Self::RoundRobin(prio) => {
+                (RT_PRIO_MIN..=RT_PRIO_MAX).contains(&prio)
+            }
+            Self::Deadline {
+                runtime,
+                deadline,
+                period,
+            } => runtime > 0 && runtime <= deadline && deadline <= period,
+        }
+    }
+
+    /// Whether it is a real-time policy, i.e., not [`SchedPolicy::Normal`].
+    pub fn is_realtime(&self) -> bool {
+        !matches!(self, Self::Normal)
+    }
+
+    /// Whether tasks with this policy run before the ones with `other`.
+    ///
+    /// `SCHED_DEADLINE` tasks run first, the one with a shorter relative
+    /// deadline is considered more urgent. Then real-time tasks with higher
+    /// priorities, and at last normal tasks.
+    pub fn precedes(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Deadline { deadline: a, .. }, Self::Deadline { deadline: b, .. }) => a < b,
+            (Self::Deadline { .. }, _) => true,
+            (_, Self::Deadline { .. }) => false,
+            (Self::Normal, _) => false,
+            (_, Self::Normal) => true,
+            (Self::Fifo(a) | Self::RoundRobin(a), Self::Fifo(b) | Self::RoundRobin(b)) => a > b,
+        }
+    }
+
+    /// The CPU bandwidth reserved by the deadline policy, in units of
+    /// `1 / (1 << 20)` of a CPU. Zero for other policies.
+    pub fn bandwidth(&self) -> u64 {
+        match *self {
+            Self::Deadline {
+                runtime, period, ..
+            } => ((runtime as u128) << 20).div_ceil(period as u128) as u64,
+            _ => 0,
+        }
+    }
+}
+
+/// A task wrapper for the [`RTScheduler`].
+///
+/// Besides the states used by CFS, it records the scheduling policy of the
+/// task, the time slice for `SCHED_RR`, and the runtime budget and absolute
+/// deadline for `SCHED_DEADLINE`.
+pub struct RTTask<T, const RR_TIME_SLICE: usize> {
+    fair: CFSTask<T>,
+    policy: AtomicU8,
+    rt_prio: AtomicU8,
+    time_slice: AtomicIsize,
+    dl_runtime: AtomicU64,
+    dl_deadline: AtomicU64,
+    dl_period: AtomicU64,
+    /// Absolute deadline of the current period.
+    dl_abs_deadline: AtomicU64,
+    /// Remaining runtime in the current period, can be negative on overrun.
+    dl_remaining: AtomicI64,
+    /// When the task starts running or is accounted last time.
+    exec_start: AtomicU64,
+    /// Sequence number in the deadline queue, to break ties.
+    dl_seq: AtomicU64,
+}
+
+impl<T, const S: usize> RTTask<T, S> {
+    /// Creates a new [`RTTask`] from the inner task struct, with the
+    /// [`SchedPolicy::Normal`] policy.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            fair: CFSTask::new(inner),
+            policy: AtomicU8::new(POLICY_NORMAL),
+            rt_prio: AtomicU8::new(0),
+            time_slice: AtomicIsize::new(S as isize),
+            dl_runtime: AtomicU64::new(0),
+            dl_deadline: AtomicU64::new(0),
+            dl_period: AtomicU64::new(0),
+            dl_abs_deadline: AtomicU64::new(0),
+            dl_remaining: AtomicI64::new(0),
+            exec_start: AtomicU64::new(0),
+            dl_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the scheduling policy of the task.
+    pub fn policy(&self) -> SchedPolicy {
+        match self.policy.load(Ordering::Acquire) {
+            POLICY_FIFO => SchedPolicy::Fifo(self.rt_prio()),
+            POLICY_RR => SchedPolicy::RoundRobin(self.rt_prio()),
+            POLICY_DEADLINE => SchedPolicy::Deadline {
+                runtime: self.dl_runtime.load(Ordering::Acquire),
+                deadline: self.dl_deadline.load(Ordering::Acquire),
+                period: self.dl_period.load(Ordering::Acquire),
+            },
+            _ => SchedPolicy::Normal,
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        self.fair.inner()
+    }
+
+    fn policy_id(&self) -> u8 {
+        self.policy.load(Ordering::Acquire)
+    }
+
+    fn rt_prio(&self) -> u8 {
+        self.rt_prio.load(Ordering::Acquire)
+    }
+
+    fn abs_deadline(&self) -> u64 {
+        self.dl_abs_deadline.load(Ordering::Acquire)
+    }
+
+    fn dl_key(&self) -> (u64, u64) {
+        (self.abs_deadline(), self.dl_seq.load(Ordering::Acquire))
+    }
+
+    fn reset_time_slice(&self) {
+        self.time_slice.store(S as isize, Ordering::Release);
+    }
+
+    /// Starts a new period at `now`, with the full runtime budget.
+    fn replenish(&self, now: u64) {
+        let deadline = self.dl_deadline.load(Ordering::Acquire);
+        let runtime = self.dl_runtime.load(Ordering::Acquire);
+        self.dl_abs_deadline
+            .store(now + deadline, Ordering::Release);
+        self.dl_remaining.store(runtime as i64, Ordering::Release);
+    }
+
+    /// Charges the time since the last accounting to the runtime budget.
+    /// Returns `true` if the budget is exhausted.
+    fn update_runtime(&self, now: u64) -> bool {
+        let start = self.exec_start.swap(now, Ordering::AcqRel);
+        let delta = now.saturating_sub(start) as i64;
+        self.dl_remaining.fetch_sub(delta, Ordering::AcqRel) - delta <= 0
+    }
+
+    /// Moves an exhausted task to its next period with a positive budget,
+    /// the overrun is paid from the new budget. Returns the start time of
+    /// that period, before which the task should be throttled.
+    fn next_period(&self) -> u64 {
+        let remaining = self.dl_remaining.load(Ordering::Acquire);
+        let runtime = self.dl_runtime.load(Ordering::Acquire) as i64;
+        let deadline = self.dl_deadline.load(Ordering::Acquire);
+        let period = self.dl_period.load(Ordering::Acquire);
+        let periods = (-remaining) / runtime + 1;
+        let start = self.abs_deadline() - deadline + periods as u64 * period;
+        self.dl_remaining
+            .store(remaining + periods * runtime, Ordering::Release);
+        self.dl_abs_deadline
+            .store(start + deadline, Ordering::Release);
+        start
+    }
+
+    fn set_policy(&self, policy: SchedPolicy, now: u64) {
+        match policy {
+            SchedPolicy::Normal => {
+                self.rt_prio.store(0, Ordering::Release);
+                self.policy.store(POLICY_NORMAL, Ordering::Release);
+            }
+            SchedPolicy::Fifo(prio) => {
+                self.rt_prio.store(prio, Ordering::Release);
+                self.policy.store(POLICY_FIFO, Ordering::Release);
+            }
+            SchedPolicy::RoundRobin(prio) => {
+                self.rt_prio.store(prio, Ordering::Release);
+                self.reset_time_slice();
+                self.policy.store(POLICY_RR, Ordering::Release);
+            }
+            SchedPolicy::Deadline {
+                runtime,
+                deadline,
+                period,
+            } => {
+                self.dl_runtime.store(runtime, Ordering::Release);
+                self.dl_deadline.store(deadline, Ordering::Release);
+                self.dl_period.store(period, Ordering::Release);
+                self.replenish(now);
+                self.exec_start.store(now, Ordering::Release);
+                self.policy.store(POLICY_DEADLINE, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl<T, const S: usize> Deref for RTTask<T, S> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.fair.inner()
+    }
+}
+
+impl<T: Debug, const S: usize> Debug for RTTask<T, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self.inner())
+    }
+}
+
+/// A composite scheduler with real-time scheduling classes.
+///
+/// Tasks are scheduled by their policies, in the order of:
+///
+/// 1. `SCHED_DEADLINE` tasks, the one with the earliest absolute deadline
+///    first ([EDF]).
+/// 2. `SCHED_FIFO` and `SCHED_RR` tasks, the one with the highest priority
+///    first. Tasks with the same priority run in FIFO order, and `SCHED_RR`
+///    tasks are also preempted when their time slices are used up.
+/// 3. `SCHED_OTHER` tasks, scheduled as in the [`CFScheduler`].
+///
+/// Time of the deadline class is measured by the clock given in
+/// [`RTScheduler::update_clock`], which should be advanced before each
+/// operation.
+///
+/// [EDF]: https://en.wikipedia.org/wiki/Earliest_deadline_first_scheduling
+/// [`CFScheduler`]: crate::CFScheduler
+pub struct RTScheduler<T, const RR_TIME_SLICE: usize> {
+    dl_queue: BTreeMap<(u64, u64), Arc<RTTask<T, RR_TIME_SLICE>>>, // (deadline, seq)
+    dl_seq: u64,
+    rt_queues: [VecDeque<Arc<RTTask<T, RR_TIME_SLICE>>>; RT_PRIO_MAX as usize + 1],
+    rt_bitmap: u128,
+    fair_queue: BTreeMap<(isize, isize), Arc<RTTask<T, RR_TIME_SLICE>>>, // (vruntime, taskid)
+    min_vruntime: isize,
+    id_pool: isize,
+    clock: u64,
+}
+
+impl<T, const S: usize> RTScheduler<T, S> {
+    const EMPTY_QUEUE: VecDeque<Arc<RTTask<T, S>>> = VecDeque::new();
+
+    /// Creates a new empty [`RTScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            dl_queue: BTreeMap::new(),
+            dl_seq: 0,
+            rt_queues: [Self::EMPTY_QUEUE; RT_PRIO_MAX as usize + 1],
+            rt_bitmap: 0,
+            fair_queue: BTreeMap::new(),
+            min_vruntime: 0,
+            id_pool: 0,
+            clock: 0,
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time"
+    }
+
+    /// Advances the clock of the deadline class to `now`, in nanoseconds.
+    pub fn update_clock(&mut self, now: u64) {
+        self.clock = self.clock.max(now);
+    }
+
+    /// Sets the scheduling policy of `task`. Returns `false` if the
+    /// parameters are invalid.
+    ///
+    /// The task must not be in the ready queue, i.e., it is running, blocked,
+    /// or has been removed by [`BaseScheduler::remove_task`].
+    pub fn set_policy(&mut self, task: &Arc<RTTask<T, S>>, policy: SchedPolicy) -> bool {
+        if !policy.is_valid() {
+            return false;
+        }
+        task.set_policy(policy, self.clock);
+        true
+    }
+
+    /// Throttles the `SCHED_DEADLINE` task `prev` being switched out, if it
+    /// has used up its runtime budget, as the constant bandwidth server
+    /// ([CBS]) does.
+    ///
+    /// Returns the time when the next period of the task starts, with its
+    /// budget replenished. The task should be put back by
+    /// [`BaseScheduler::add_task`] at that time, instead of
+    /// [`BaseScheduler::put_prev_task`] now. Returns `None` if the task can
+    /// be put back now.
+    ///
+    /// [CBS]: https://docs.kernel.org/scheduler/sched-deadline.html
+    pub fn throttle_prev_task(&mut self, prev: &Arc<RTTask<T, S>>) -> Option<u64> {
+        if prev.policy_id() == POLICY_DEADLINE && prev.update_runtime(self.clock) {
+            Some(prev.next_period())
+        } else {
+            None
+        }
+    }
+
+    /// Whether `task` should preempt `current` once it becomes ready.
+    pub fn task_preempts(&self, current: &Arc<RTTask<T, S>>, task: &Arc<RTTask<T, S>>) -> bool {
+        match (task.policy_id(), current.policy_id()) {
+            (POLICY_DEADLINE, POLICY_DEADLINE) => task.abs_deadline() < current.abs_deadline(),
+            (POLICY_DEADLINE, _) => true,
+            (_, POLICY_DEADLINE) => false,
+            (POLICY_NORMAL, _) => false,
+            (_, POLICY_NORMAL) => true,
+            _ => task.rt_prio() > current.rt_prio(),
+        }
+    }
+
+    fn highest_rt_prio(&self) -> Option<u8> {
+        if self.rt_bitmap == 0 {
+            None
+        } else {
+            Some((127 - self.rt_bitmap.leading_zeros()) as u8)
+        }
+    }
+
+    fn enqueue_dl(&mut self, task: Arc<RTTask<T, S>>) {
+        task.dl_seq.store(self.dl_seq, Ordering::Release);
+        self.dl_seq += 1;
+        self.dl_queue.insert(task.dl_key(), task);
+    }
+
+    fn enqueue_rt(&mut self, task: Arc<RTTask<T, S>>, front: bool) {
+        let prio = task.rt_prio();
+        if front {
+            self.rt_queues[prio as usize].push_front(task);
+        } else {
+            self.rt_queues[prio as usize].push_back(task);
+        }
+        self.rt_bitmap |= 1 << prio;
+    }
+
+    fn dequeue_rt(&mut self, prio: u8, idx: usize) -> Option<Arc<RTTask<T, S>>> {
+        let queue = &mut self.rt_queues[prio as usize];
+        let task = queue.remove(idx);
+        if queue.is_empty() {
+            self.rt_bitmap &= !(1 << prio);
+        }
+        task
+    }
+
+    fn enqueue_fair(&mut self, task: Arc<RTTask<T, S>>) {
+        let taskid = self.id_pool;
+        self.id_pool += 1;
+        task.fair.set_id(taskid);
+        self.fair_queue
+            .insert((task.fair.get_vruntime(), taskid), task);
+        self.update_min_vruntime();
+    }
+
+    fn update_min_vruntime(&mut self) {
+        if let Some(((min_vruntime, _), _)) = self.fair_queue.first_key_value() {
+            self.min_vruntime = *min_vruntime;
+        }
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for RTScheduler<T, S> {
+    type SchedItem = Arc<RTTask<T, S>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        match task.policy_id() {
+            POLICY_DEADLINE => {
+                if task.abs_deadline() <= self.clock {
+                    task.replenish(self.clock);
+                }
+                self.enqueue_dl(task);
+            }
+            POLICY_FIFO | POLICY_RR => {
+                task.reset_time_slice();
+                self.enqueue_rt(task, false);
+            }
+            _ => {
+                task.fair.set_vruntime(self.min_vruntime);
+                self.enqueue_fair(task);
+            }
+        }
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        match task.policy_id() {
+            POLICY_DEADLINE => self.dl_queue.remove(&task.dl_key()),
+            POLICY_FIFO | POLICY_RR => {
+                let prio = task.rt_prio();
+                let idx = self.rt_queues[prio as usize]
+                    .iter()
+                    .position(|t| Arc::ptr_eq(t, task))?;
+                self.dequeue_rt(prio, idx)
+            }
+            _ => {
+                let removed = self
+                    .fair_queue
+                    .remove(&(task.fair.get_vruntime(), task.fair.get_id()));
+                self.update_min_vruntime();
+                removed
+            }
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let task = if let Some((_, task)) = self.dl_queue.pop_first() {
+            task
+        } else if let Some(prio) = self.highest_rt_prio() {
+            self.dequeue_rt(prio, 0)?
+        } else {
+            self.fair_queue.pop_first()?.1
+        };
+        task.exec_start.store(self.clock, Ordering::Release);
+        Some(task)
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        match prev.policy_id() {
+            POLICY_DEADLINE => {
+                // Not throttled by `throttle_prev_task`, at least postpone
+                // the deadline so that it can not delay other tasks.
+                if prev.update_runtime(self.clock) {
+                    prev.next_period();
+                }
+                self.enqueue_dl(prev);
+            }
+            POLICY_FIFO => self.enqueue_rt(prev, preempt),
+            POLICY_RR => {
+                if prev.time_slice.load(Ordering::Acquire) > 0 && preempt {
+                    self.enqueue_rt(prev, true);
+                } else {
+                    prev.reset_time_slice();
+                    self.enqueue_rt(prev, false);
+                }
+            }
+            _ => self.enqueue_fair(prev),
+        }
+    }
+
+    fn steal_task<F>(&mut self, mut f: F) -> Option<Self::SchedItem>
+    where
+        F: FnMut(&Self::SchedItem) -> bool,
+    {
+        let task = self
+            .dl_queue
+            .values()
+            .chain(self.rt_queues.iter().rev().flatten())
+            .chain(self.fair_queue.values())
+            .find(|t| f(t))?
+            .clone();
+        let task = self.remove_task(&task)?;
+        task.exec_start.store(self.clock, Ordering::Release);
+        Some(task)
+    }
+
+    fn migrate_task(&mut self, task: Self::SchedItem) {
+        match task.policy_id() {
+            // Keep the time slice, which is reset by `add_task`.
+            POLICY_FIFO | POLICY_RR => self.enqueue_rt(task, false),
+            _ => self.add_task(task),
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        match current.policy_id() {
+            POLICY_DEADLINE => {
+                current.update_runtime(self.clock)
+                    || self
+                        .dl_queue
+                        .first_key_value()
+                        .is_some_and(|(&(deadline, _), _)| deadline < current.abs_deadline())
+            }
+            policy @ (POLICY_FIFO | POLICY_RR) => {
+                let expired =
+                    policy == POLICY_RR && current.time_slice.fetch_sub(1, Ordering::Release) <= 1;
+                expired
+                    || !self.dl_queue.is_empty()
+                    || self
+                        .highest_rt_prio()
+                        .is_some_and(|prio| prio > current.rt_prio())
+            }
+            _ => {
+                current.fair.task_tick();
+                !self.dl_queue.is_empty()
+                    || self.rt_bitmap != 0
+                    || self.fair_queue.is_empty()
+                    || current.fair.get_vruntime() > self.min_vruntime
+            }
+        }
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if (-20..=19).contains(&prio) {
+            task.fair.set_priority(prio);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -37,6 +37,26 @@ macro_rules! def_test_sched {
                 assert_eq!(n, NUM_TASKS);
             }
 
+            #[test]
+            fn test_steal() {
+                const NUM_TASKS: usize = 11;
+
+                let mut scheduler = <$scheduler>::new();
+                for i in 0..NUM_TASKS {
+                    scheduler.add_task(Arc::new(<$task>::new(i)));
+                }
+
+                assert!(scheduler.steal_task(|t| *t.inner() == NUM_TASKS).is_none());
+                let stolen = scheduler.steal_task(|t| *t.inner() % 2 == 1).unwrap();
+                assert_eq!(*stolen.inner(), 1);
+
+                // The order of the remaining tasks is kept.
+                for i in (0..NUM_TASKS).filter(|&i| i != 1) {
+                    assert_eq!(*scheduler.pick_next_task().unwrap().inner(), i);
+                }
+                assert!(scheduler.pick_next_task().is_none());
+            }
+
             #[test]
             fn bench_yield() {
                 const NUM_TASKS: usize = 1_000_000;
@@ -91,3 +111,180 @@ macro_rules! def_test_sched {
 def_test_sched!(fifo, FifoScheduler::<usize>, FifoTask::<usize>);
 def_test_sched!(rr, RRScheduler::<usize, 5>, RRTask::<usize, 5>);
 def_test_sched!(cfs, CFScheduler::<usize>, CFSTask::<usize>);
+def_test_sched!(rt, RTScheduler::<usize, 5>, RTTask::<usize, 5>);
+
+mod rt_classes {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    type Task = Arc<RTTask<usize, 5>>;
+
+    fn new_task(i: usize, scheduler: &mut RTScheduler<usize, 5>, policy: SchedPolicy) -> Task {
+        let t = Arc::new(RTTask::new(i));
+        assert!(scheduler.set_policy(&t, policy));
+        t
+    }
+
+    #[test]
+    fn test_rt_precedence() {
+        let mut scheduler = RTScheduler::<usize, 5>::new();
+        let normal = new_task(0, &mut scheduler, SchedPolicy::Normal);
+        let low = new_task(1, &mut scheduler, SchedPolicy::Fifo(10));
+        let high = new_task(2, &mut scheduler, SchedPolicy::RoundRobin(50));
+        let dl = new_task(
+            3,
+            &mut scheduler,
+            SchedPolicy::Deadline {
+                runtime: 1_000,
+                deadline: 10_000,
+                period: 10_000,
+            },
+        );
+        assert!(!scheduler.set_policy(&normal, SchedPolicy::Fifo(100)));
+
+        for t in [&normal, &low, &high, &dl] {
+            scheduler.add_task(t.clone());
+        }
+        assert!(scheduler.task_preempts(&normal, &low));
+        assert!(scheduler.task_preempts(&low, &high));
+        assert!(!scheduler.task_preempts(&high, &low));
+        assert!(scheduler.task_preempts(&high, &dl));
+
+        for i in (0..4).rev() {
+            assert_eq!(*scheduler.pick_next_task().unwrap().inner(), i);
+        }
+        assert!(scheduler.pick_next_task().is_none());
+    }
+
+    #[test]
+    fn test_rt_tick() {
+        let mut scheduler = RTScheduler::<usize, 5>::new();
+        let fifo = new_task(0, &mut scheduler, SchedPolicy::Fifo(1));
+        let rr = [1, 2].map(|i| new_task(i, &mut scheduler, SchedPolicy::RoundRobin(1)));
+        scheduler.add_task(fifo.clone());
+
+        // FIFO tasks are never preempted by ticks.
+        let curr = scheduler.pick_next_task().unwrap();
+        for _ in 0..10 {
+            assert!(!scheduler.task_tick(&curr));
+        }
+        // but by tasks with higher priority.
+        let high = new_task(3, &mut scheduler, SchedPolicy::Fifo(2));
+        scheduler.add_task(high);
+        assert!(scheduler.task_tick(&curr));
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 3);
+        scheduler.put_prev_task(curr, false);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+
+        // RR tasks are preempted when the time slices are used up.
+        for t in &rr {
+            scheduler.add_task(t.clone());
+        }
+        for i in 0..6 {
+            let curr = scheduler.pick_next_task().unwrap();
+            assert_eq!(*curr.inner(), i % 2 + 1);
+            for _ in 0..4 {
+                assert!(!scheduler.task_tick(&curr));
+            }
+            assert!(scheduler.task_tick(&curr));
+            scheduler.put_prev_task(curr, true);
+        }
+    }
+
+    #[test]
+    fn test_rt_migrate() {
+        let mut src = RTScheduler::<usize, 5>::new();
+        let mut dst = RTScheduler::<usize, 5>::new();
+        let rr = new_task(0, &mut src, SchedPolicy::RoundRobin(1));
+        src.add_task(rr);
+        let curr = src.pick_next_task().unwrap();
+        for _ in 0..2 {
+            assert!(!src.task_tick(&curr));
+        }
+        src.put_prev_task(curr, true);
+
+        // The time slice is kept across CPUs.
+        let stolen = src.steal_task(|_| true).unwrap();
+        dst.migrate_task(stolen);
+        let curr = dst.pick_next_task().unwrap();
+        for _ in 0..2 {
+            assert!(!dst.task_tick(&curr));
+        }
+        assert!(dst.task_tick(&curr));
+    }
+
+    #[test]
+    fn test_edf() {
+        let mut scheduler = RTScheduler::<usize, 5>::new();
+        let policy = |deadline| SchedPolicy::Deadline {
+            runtime: 100,
+            deadline,
+            period: 1_000,
+        };
+        let late = new_task(0, &mut scheduler, policy(500));
+        let early = new_task(1, &mut scheduler, policy(200));
+        scheduler.add_task(late.clone());
+        scheduler.add_task(early.clone());
+
+        let curr = scheduler.pick_next_task().unwrap();
+        assert_eq!(*curr.inner(), 1);
+        scheduler.update_clock(50);
+        assert!(!scheduler.task_tick(&curr));
+        // The budget is exhausted, the task is throttled until the next
+        // period starts at 1000, with the deadline 1200.
+        scheduler.update_clock(120);
+        assert!(scheduler.task_tick(&curr));
+        assert_eq!(scheduler.throttle_prev_task(&curr), Some(1_000));
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+        assert!(scheduler.pick_next_task().is_none());
+
+        scheduler.update_clock(1_000);
+        scheduler.add_task(curr);
+        let curr = scheduler.pick_next_task().unwrap();
+        assert_eq!(*curr.inner(), 1);
+        // The overrun of 20 is paid from the new budget.
+        scheduler.update_clock(1_079);
+        assert!(!scheduler.task_tick(&curr));
+        scheduler.update_clock(1_080);
+        assert!(scheduler.task_tick(&curr));
+        assert_eq!(scheduler.throttle_prev_task(&curr), Some(2_000));
+    }
+
+    #[test]
+    fn test_dl_put_prev() {
+        let mut scheduler = RTScheduler::<usize, 5>::new();
+        let policy = |deadline| SchedPolicy::Deadline {
+            runtime: 100,
+            deadline,
+            period: 1_000,
+        };
+        let late = new_task(0, &mut scheduler, policy(500));
+        let early = new_task(1, &mut scheduler, policy(200));
+        scheduler.add_task(late.clone());
+        scheduler.add_task(early.clone());
+
+        // If not throttled, an exhausted task is put back with the deadline
+        // postponed to 1200.
+        let curr = scheduler.pick_next_task().unwrap();
+        scheduler.update_clock(120);
+        scheduler.put_prev_task(curr, true);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 0);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+    }
+
+    #[test]
+    fn test_policy_precedes() {
+        let dl = |deadline| SchedPolicy::Deadline {
+            runtime: 1_000,
+            deadline,
+            period: 100_000,
+        };
+        assert!(dl(50_000).precedes(&SchedPolicy::Fifo(99)));
+        assert!(dl(10_000).precedes(&dl(20_000)));
+        assert!(!dl(20_000).precedes(&dl(20_000)));
+        assert!(SchedPolicy::RoundRobin(20).precedes(&SchedPolicy::Fifo(10)));
+        assert!(!SchedPolicy::Fifo(10).precedes(&SchedPolicy::RoundRobin(10)));
+        assert!(SchedPolicy::Fifo(1).precedes(&SchedPolicy::Normal));
+        assert!(!SchedPolicy::Normal.precedes(&SchedPolicy::Normal));
+    }
+}
@@ -0,0 +1,158 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Per-CPU caches of small memory blocks.
+//!
+//! Each CPU keeps a magazine of free blocks for every size class up to
+//! [`MAX_CACHED_SIZE`] bytes, so that most small allocations are served
+//! without taking the lock of the global byte allocator. Blocks are moved
+//! between a magazine and the byte allocator in batches of [`BATCH`], and
+//! all the magazines are drained back to the byte allocator on memory
+//! pressure.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use spinlock::SpinNoIrq;
+
+/// The shift of the smallest size class (8 bytes).
+const MIN_CLASS_SHIFT: usize = 3;
+/// The number of size classes, from 8 bytes to 512 bytes.
+const NUM_CLASSES: usize = 7;
+/// The maximum size of the cached blocks.
+pub(crate) const MAX_CACHED_SIZE: usize = 1 << (MIN_CLASS_SHIFT + NUM_CLASSES - 1);
+/// The maximum alignment of the cached blocks.
+const MAX_CACHED_ALIGN: usize = 16;
+/// The number of blocks moved between a magazine and the byte allocator at once.
+pub(crate) const BATCH: usize = 16;
+/// The maximum number of blocks in a magazine.
+const CAPACITY: usize = 4 * BATCH;
+
+/// Returns the size class of `layout`, or `None` if it is not cached.
+pub(crate) fn size_class(layout: &Layout) -> Option<usize> {
+    if layout.size() > MAX_CACHED_SIZE || layout.align() > MAX_CACHED_ALIGN {
+        return None;
+    }
+    let size = layout.size().max(1 << MIN_CLASS_SHIFT).next_power_of_two();
+    Some(size.trailing_zeros() as usize - MIN_CLASS_SHIFT)
+}
+
+/// Returns the layout used to allocate the blocks of size class `class` from
+/// the byte allocator.
+pub(crate) fn class_layout(class: usize) -> Layout {
+    let size = 1 << (class + MIN_CLASS_SHIFT);
+    Layout::from_size_align(size, size.min(MAX_CACHED_ALIGN)).unwrap()
+}
+
+/// A singly linked list of free blocks of one size class. The link is stored
+/// in the first word of each free block.
+#[derive(Clone, Copy)]
+struct Magazine {
+    head: usize,
+    len: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self { head: 0, len: 0 }
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let ptr = NonNull::new(self.head as *mut u8)?;
+        // Safe because the block is free and owned by this magazine.
+        self.head = unsafe { *(self.head as *const usize) };
+        self.len -= 1;
+        Some(ptr)
+    }
+
+    fn push(&mut self, ptr: NonNull<u8>) {
+        // Safe because the block is free and at least one word large.
+        unsafe { *(ptr.as_ptr() as *mut usize) = self.head };
+        self.head = ptr.as_ptr() as usize;
+        self.len += 1;
+    }
+}
+
+/// The cache of a CPU, with a magazine for each size class.
+pub(crate) struct CpuCache {
+    magazines: [Magazine; NUM_CLASSES],
+    /// Number of allocations served by the cache.
+    pub hits: usize,
+    /// Number of allocations that refill the cache from the byte allocator.
+    pub misses: usize,
+    /// Number of batches flushed back to the byte allocator.
+    pub flushes: usize,
+}
+
+impl CpuCache {
+    const fn new() -> Self {
+        Self {
+            magazines: [Magazine::new(); NUM_CLASSES],
+            hits: 0,
+            misses: 0,
+            flushes: 0,
+        }
+    }
+
+    /// Takes a free block of size class `class`.
+    pub fn pop(&mut self, class: usize) -> Option<NonNull<u8>> {
+        self.magazines[class].pop()
+    }
+
+    /// Puts a free block of size class `class` into the cache.
+    pub fn push(&mut self, class: usize, ptr: NonNull<u8>) {
+        self.magazines[class].push(ptr)
+    }
+
+    /// Whether the magazine of size class `class` is full.
+    pub fn is_full(&self, class: usize) -> bool {
+        self.magazines[class].len >= CAPACITY
+    }
+
+    /// Returns the number of free bytes held by the cache.
+    pub fn cached_bytes(&self) -> usize {
+        self.magazines
+            .iter()
+            .enumerate()
+            .map(|(class, mag)| mag.len * class_layout(class).size())
+            .sum()
+    }
+
+    /// Takes all the free blocks in the cache, calling `f` with each block and
+    /// the layout of its size class.
+    pub fn drain(&mut self, mut f: impl FnMut(NonNull<u8>, Layout)) {
+        for class in 0..NUM_CLASSES {
+            let layout = class_layout(class);
+            while let Some(ptr) = self.pop(class) {
+                f(ptr, layout);
+            }
+        }
+    }
+}
+
+// Safe because the blocks in the cache are only accessed with the lock held.
+unsafe impl Send for CpuCache {}
+
+#[percpu::def_percpu]
+static CPU_CACHE: SpinNoIrq<CpuCache> = SpinNoIrq::new(CpuCache::new());
+
+/// Returns the cache of the current CPU.
+///
+/// The current task may be migrated to another CPU after this call, which is
+/// still safe since the cache is protected by its lock, it only loses the
+/// locality.
+pub(crate) fn local_cache() -> &'static SpinNoIrq<CpuCache> {
+    // Safe because the per-CPU areas are never freed.
+    unsafe { &*CPU_CACHE.current_ptr() }
+}
+
+/// Returns the caches of all the CPUs.
+pub(crate) fn all_caches() -> impl Iterator<Item = &'static SpinNoIrq<CpuCache>> {
+    // Safe because `percpu::init` is called with `ruxconfig::SMP` CPUs.
+    (0..ruxconfig::SMP).map(|cpu_id| unsafe { &*CPU_CACHE.remote_ptr(cpu_id) })
+}
@@ -0,0 +1,426 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Heap debugging, enabled by the `debug` feature.
+//!
+//! Every allocated block is laid out as:
+//!
+//! ```text
+//! | Header | front redzone | user memory | back redzone |
+//! ```
+//!
+//! The header records the allocation and the backtrace of its call site, and
+//! links the block into the list of live blocks. The redzones are filled with
+//! canaries, which are checked when the block is freed to detect overflows.
+//! Freed blocks are poisoned and put into a quarantine, so that double frees
+//! and writes after free can be detected before the memory is reused.
+//!
+//! Backtraces are unwound by following the frame pointers, so they are only
+//! meaningful if the kernel is built with frame pointers enabled (e.g.,
+//! `-C force-frame-pointers=yes`).
+
+use core::alloc::Layout;
+use core::fmt::{self, Write};
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult};
+use spinlock::SpinNoIrq;
+
+use crate::GlobalAllocator;
+
+/// Number of return addresses recorded for each block.
+const BACKTRACE_DEPTH: usize = 8;
+/// Size of each redzone.
+const REDZONE: usize = 16;
+/// Maximum number of freed blocks in the quarantine.
+const QUARANTINE_LEN: usize = 256;
+/// Maximum number of call sites reported by [`dump_allocations_to`].
+const MAX_SITES: usize = 32;
+/// Frame records further than this from the current stack pointer are not
+/// accessed.
+const MAX_STACK_SCAN: usize = 0x10_0000;
+
+const MAGIC_LIVE: usize = 0x4c49_5645;
+const MAGIC_FREED: usize = 0x4652_4545;
+const CANARY: u8 = 0xcc;
+const POISON_INUSE: u8 = 0x5a;
+const POISON_FREE: u8 = 0x6b;
+
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+    prev: usize,
+    next: usize,
+    backtrace: [usize; BACKTRACE_DEPTH],
+}
+
+/// The live blocks and the quarantine.
+struct DebugState {
+    /// Header address of the most recently allocated live block.
+    live: usize,
+    live_blocks: usize,
+    live_bytes: usize,
+    /// Ring buffer of the header addresses of quarantined blocks.
+    quarantine: [usize; QUARANTINE_LEN],
+    quarantine_head: usize,
+    quarantine_len: usize,
+}
+
+static STATE: SpinNoIrq<DebugState> = SpinNoIrq::new(DebugState {
+    live: 0,
+    live_blocks: 0,
+    live_bytes: 0,
+    quarantine: [0; QUARANTINE_LEN],
+    quarantine_head: 0,
+    quarantine_len: 0,
+});
+
+/// Returns the offset of the user memory from the header, and the layout of
+/// the whole block, for a user allocation of `layout`.
+fn block_layout(layout: &Layout) -> (usize, Layout) {
+    let align = layout.align().max(align_of::<Header>());
+    let front = (size_of::<Header>() + REDZONE + align - 1) & !(align - 1);
+    let size = front + layout.size() + REDZONE;
+    (front, Layout::from_size_align(size, align).unwrap())
+}
+
+/// Heap corruptions detected on free.
+#[derive(Debug, Clone, Copy)]
+enum Corruption {
+    DoubleFree,
+    InvalidFree,
+    LayoutMismatch,
+    Underflow,
+    Overflow,
+    UseAfterFree,
+}
+
+impl Corruption {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::DoubleFree => "double free",
+            Self::InvalidFree => "invalid free",
+            Self::LayoutMismatch => "free with a mismatched layout",
+            Self::Underflow => "heap buffer underflow",
+            Self::Overflow => "heap buffer overflow",
+            Self::UseAfterFree => "write after free",
+        }
+    }
+}
+
+/// Formats the return addresses of a backtrace.
+struct Backtrace<'a>(&'a [usize]);
+
+impl fmt::Display for Backtrace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, ra) in self.0.iter().take_while(|ra| **ra != 0).enumerate() {
+            write!(f, "\n    #{:<2} {:#018x}", depth, ra)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports the heap corruption of the block with header `header` at user
+/// address `ptr`. It must be called without the lock of [`STATE`] held.
+fn report(kind: Corruption, ptr: usize, header: Option<&Header>) -> ! {
+    match header {
+        Some(header) => panic!(
+            "{} of block {:#x} (size {}, align {}), allocated at:{}",
+            kind.as_str(),
+            ptr,
+            header.size,
+            header.align,
+            Backtrace(&header.backtrace)
+        ),
+        None => panic!("{} of block {:#x}", kind.as_str(), ptr),
+    }
+}
+
+fn is_filled(start: usize, len: usize, byte: u8) -> bool {
+    // Safe because the range is inside a block owned by the caller.
+    unsafe { core::slice::from_raw_parts(start as *const u8, len) }
+        .iter()
+        .all(|b| *b == byte)
+}
+
+/// Gets the frame pointer of the caller.
+#[inline(always)]
+fn current_frame_pointer() -> usize {
+    let fp: usize;
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+        } else {
+            fp = 0;
+        }
+    }
+    fp
+}
+
+/// Records the return addresses of the current call stack into `backtrace`.
+#[inline(never)]
+fn capture_backtrace(backtrace: &mut [usize; BACKTRACE_DEPTH]) {
+    const WORD: usize = size_of::<usize>();
+    // The frames of the current call stack are above the local variables.
+    let anchor = 0u8;
+    let bottom = &anchor as *const u8 as usize;
+    let top = bottom.saturating_add(MAX_STACK_SCAN);
+    let mut fp = current_frame_pointer();
+    for slot in backtrace.iter_mut() {
+        // `s0` points to the top of the frame on RISC-V, with the record right
+        // below, while the frame pointer points to the record on others.
+        let record = if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+            fp.wrapping_sub(2 * WORD)
+        } else {
+            fp
+        };
+        if fp % WORD != 0 || record < bottom || record + 2 * WORD > top {
+            break;
+        }
+        let (prev_fp, ra) = unsafe {
+            (
+                (record as *const usize).read_volatile(),
+                ((record + WORD) as *const usize).read_volatile(),
+            )
+        };
+        if ra == 0 {
+            break;
+        }
+        *slot = ra;
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+}
+
+/// Allocates a block for `layout` with redzones, and tracks it as live.
+pub(crate) fn alloc(galloc: &GlobalAllocator, layout: Layout) -> AllocResult<NonNull<u8>> {
+    let (front, block) = block_layout(&layout);
+    let raw = match galloc.alloc_raw(block) {
+        Err(AllocError::NoMemory) => {
+            flush_quarantine(galloc);
+            galloc.alloc_raw(block)?
+        }
+        ret => ret?,
+    }
+    .as_ptr() as usize;
+    let ptr = raw + front;
+    let header = raw as *mut Header;
+    // Safe because the memory is allocated here.
+    unsafe {
+        header.write(Header {
+            magic: MAGIC_LIVE,
+            size: layout.size(),
+            align: layout.align(),
+            prev: 0,
+            next: 0,
+            backtrace: [0; BACKTRACE_DEPTH],
+        });
+        capture_backtrace(&mut (*header).backtrace);
+        let front_redzone = raw + size_of::<Header>();
+        (front_redzone as *mut u8).write_bytes(CANARY, ptr - front_redzone);
+        (ptr as *mut u8).write_bytes(POISON_INUSE, layout.size());
+        ((ptr + layout.size()) as *mut u8).write_bytes(CANARY, REDZONE);
+    }
+
+    let mut state = STATE.lock();
+    // Safe because the live blocks are only linked with the lock held.
+    unsafe {
+        (*header).next = state.live;
+        if state.live != 0 {
+            (*(state.live as *mut Header)).prev = raw;
+        }
+    }
+    state.live = raw;
+    state.live_blocks += 1;
+    state.live_bytes += layout.size();
+    Ok(unsafe { NonNull::new_unchecked(ptr as *mut u8) })
+}
+
+/// Checks the block at `ptr` allocated for `layout`, then poisons it and puts
+/// it into the quarantine. Panics if any heap corruption is detected.
+pub(crate) fn dealloc(galloc: &GlobalAllocator, pos: NonNull<u8>, layout: Layout) {
+    let ptr = pos.as_ptr() as usize;
+    let (front, _) = block_layout(&layout);
+    let raw = ptr - front;
+    let header = raw as *mut Header;
+
+    let evicted = {
+        let mut state = STATE.lock();
+        // Safe because the header is checked before any other field is used.
+        let hdr = unsafe { &mut *header };
+        let error = match hdr.magic {
+            MAGIC_LIVE if hdr.size != layout.size() || hdr.align != layout.align() => {
+                Some(Corruption::LayoutMismatch)
+            }
+            MAGIC_LIVE
+                if !is_filled(
+                    raw + size_of::<Header>(),
+                    ptr - raw - size_of::<Header>(),
+                    CANARY,
+                ) =>
+            {
+                Some(Corruption::Underflow)
+            }
+            MAGIC_LIVE if !is_filled(ptr + hdr.size, REDZONE, CANARY) => Some(Corruption::Overflow),
+            MAGIC_LIVE => None,
+            MAGIC_FREED => Some(Corruption::DoubleFree),
+            _ => Some(Corruption::InvalidFree),
+        };
+        if let Some(kind) = error {
+            drop(state);
+            let known = matches!(hdr.magic, MAGIC_LIVE | MAGIC_FREED);
+            report(kind, ptr, known.then_some(&*hdr));
+        }
+
+        // Unlink it from the live blocks.
+        unsafe {
+            if hdr.prev != 0 {
+                (*(hdr.prev as *mut Header)).next = hdr.next;
+            } else {
+                state.live = hdr.next;
+            }
+            if hdr.next != 0 {
+                (*(hdr.next as *mut Header)).prev = hdr.prev;
+            }
+        }
+        state.live_blocks -= 1;
+        state.live_bytes -= hdr.size;
+        hdr.magic = MAGIC_FREED;
+        unsafe { (ptr as *mut u8).write_bytes(POISON_FREE, hdr.size) };
+
+        // Put it into the quarantine, evicting the oldest one if it's full.
+        let tail = (state.quarantine_head + state.quarantine_len) % QUARANTINE_LEN;
+        if state.quarantine_len < QUARANTINE_LEN {
+            state.quarantine[tail] = raw;
+            state.quarantine_len += 1;
+            None
+        } else {
+            let oldest = core::mem::replace(&mut state.quarantine[tail], raw);
+            state.quarantine_head = (state.quarantine_head + 1) % QUARANTINE_LEN;
+            Some(oldest)
+        }
+    };
+
+    if let Some(raw) = evicted {
+        release(galloc, raw);
+    }
+}
+
+/// Gives back a quarantined block to the allocator, after checking that it's
+/// not written after free.
+fn release(galloc: &GlobalAllocator, raw: usize) {
+    // Safe because the block is owned by the quarantine.
+    let header = unsafe { &*(raw as *const Header) };
+    let layout = Layout::from_size_align(header.size, header.align).unwrap();
+    let (front, block) = block_layout(&layout);
+    if !is_filled(raw + front, header.size, POISON_FREE) {
+        report(Corruption::UseAfterFree, raw + front, Some(header));
+    }
+    galloc.dealloc_raw(unsafe { NonNull::new_unchecked(raw as *mut u8) }, block);
+}
+
+/// Gives back all the quarantined blocks to the allocator.
+fn flush_quarantine(galloc: &GlobalAllocator) {
+    loop {
+        let raw = {
+            let mut state = STATE.lock();
+            if state.quarantine_len == 0 {
+                return;
+            }
+            let raw = state.quarantine[state.quarantine_head];
+            state.quarantine_head = (state.quarantine_head + 1) % QUARANTINE_LEN;
+            state.quarantine_len -= 1;
+            raw
+        };
+        release(galloc, raw);
+    }
+}
+
+/// Live blocks allocated at the same call site.
+#[derive(Clone, Copy)]
+struct Site {
+    backtrace: [usize; BACKTRACE_DEPTH],
+    blocks: usize,
+    bytes: usize,
+}
+
+/// Writes the live allocations to `out`, grouped by their call sites and
+/// sorted by the number of bytes.
+pub(crate) fn dump_allocations_to(out: &mut dyn Write) -> fmt::Result {
+    let mut sites = [Site {
+        backtrace: [0; BACKTRACE_DEPTH],
+        blocks: 0,
+        bytes: 0,
+    }; MAX_SITES];
+    let mut num_sites = 0;
+    let mut other = (0, 0);
+    let (live_blocks, live_bytes, quarantined) = {
+        // Nothing is allocated with the lock held, so that `out` can allocate
+        // memory when written later.
+        let state = STATE.lock();
+        let mut raw = state.live;
+        while raw != 0 {
+            // Safe because the live blocks are only linked with the lock held.
+            let header = unsafe { &*(raw as *const Header) };
+            let site = sites[..num_sites]
+                .iter_mut()
+                .find(|site| site.backtrace == header.backtrace);
+            match site {
+                Some(site) => {
+                    site.blocks += 1;
+                    site.bytes += header.size;
+                }
+                None if num_sites < MAX_SITES => {
+                    sites[num_sites] = Site {
+                        backtrace: header.backtrace,
+                        blocks: 1,
+                        bytes: header.size,
+                    };
+                    num_sites += 1;
+                }
+                None => {
+                    other.0 += 1;
+                    other.1 += header.size;
+                }
+            }
+            raw = header.next;
+        }
+        (state.live_blocks, state.live_bytes, state.quarantine_len)
+    };
+
+    let sites = &mut sites[..num_sites];
+    sites.sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+    writeln!(
+        out,
+        "---- {} live blocks, {} bytes, {} blocks in quarantine ----",
+        live_blocks, live_bytes, quarantined
+    )?;
+    for site in sites.iter() {
+        writeln!(
+            out,
+            "{} blocks, {} bytes, allocated at:{}",
+            site.blocks,
+            site.bytes,
+            Backtrace(&site.backtrace)
+        )?;
+    }
+    if other.0 != 0 {
+        writeln!(out, "{} blocks, {} bytes, at other sites", other.0, other.1)?;
+    }
+    writeln!(out, "----")
+}
@@ -13,6 +13,17 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! With the `smp` feature, small allocations are served from per-CPU caches
+//! in front of the global allocator, see [`GlobalAllocator`]. With the
+//! `debug` feature, the heap is checked for overflows, double frees and writes
+//! after free, and the live allocations can be dumped by
+//! [`dump_allocations_to`] to find memory leaks.
+//!
+//! The memory usage is accounted by subsystems, and by tasks with the
+//! `task-account` feature, see [`MemTag`] and [`MemOwner`]. When the memory
+//! runs out, the allocator tries to reclaim memory, then acts according to
+//! the [`OomPolicy`].
 
 #![no_std]
 
@@ -20,17 +31,34 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(feature = "smp")]
+mod cache;
+#[cfg(feature = "debug")]
+mod debug;
+mod oom;
 mod page;
+mod usage;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{
+    AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator,
+};
 use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
 use core::ptr::NonNull;
-use spinlock::SpinNoIrq;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spinlock::{SpinNoIrq, SpinNoIrqGuard};
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+pub use oom::{
+    oom_policy, out_of_memory, register_shrinker, set_oom_killer, set_oom_policy, OomKill,
+    OomKiller, OomPolicy, OomWaiter, Shrinker,
+};
 pub use page::GlobalPage;
+pub use usage::{
+    charge, set_task_account, uncharge, usage, MemOwner, MemOwnerRef, MemTag, MemUsage,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
@@ -52,10 +80,66 @@ cfg_if::cfg_if! {
 /// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
 /// [`BitmapPageAllocator`] is used as the page allocator.
 ///
+/// With the `smp` feature, each CPU caches free blocks of the small size
+/// classes, which are refilled from and flushed to the byte allocator in
+/// batches, so that most small allocations don't contend for its lock. The
+/// caches are drained back to the byte allocator when it runs out of memory.
+///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    balloc_contended: AtomicUsize,
+    palloc_contended: AtomicUsize,
+    cache_drains: AtomicUsize,
+    peak_used_bytes: AtomicUsize,
+    peak_used_pages: AtomicUsize,
+}
+
+/// Statistics of the [`GlobalAllocator`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocStats {
+    /// Number of bytes added to the byte allocator.
+    pub heap_bytes: usize,
+    /// Number of allocated bytes in the byte allocator, including the free
+    /// blocks held by the per-CPU caches.
+    pub used_bytes: usize,
+    /// Number of free bytes held by the per-CPU caches.
+    pub cached_bytes: usize,
+    /// Number of allocated pages in the page allocator.
+    pub used_pages: usize,
+    /// Number of available pages in the page allocator.
+    pub available_pages: usize,
+    /// Number of allocations served by the per-CPU caches.
+    pub cache_hits: usize,
+    /// Number of allocations that refill the per-CPU caches.
+    pub cache_misses: usize,
+    /// Number of batches flushed from the per-CPU caches.
+    pub cache_flushes: usize,
+    /// Number of times the per-CPU caches are drained on memory pressure.
+    pub cache_drains: usize,
+    /// Number of times the byte allocator lock is found held by others.
+    pub balloc_contended: usize,
+    /// Number of times the page allocator lock is found held by others.
+    pub palloc_contended: usize,
+    /// Maximum number of allocated bytes ever in the byte allocator.
+    pub peak_used_bytes: usize,
+    /// Maximum number of allocated pages ever in the page allocator.
+    pub peak_used_pages: usize,
+    /// Number of times the memory runs out and nothing can be reclaimed.
+    pub oom_events: usize,
+    /// Number of tasks killed when the memory runs out.
+    pub oom_kills: usize,
+}
+
+impl AllocStats {
+    /// Returns the fragmentation of the heap in permille, that is, the ratio
+    /// of the heap bytes which are free but can't be returned to the page
+    /// allocator, including the ones held by the per-CPU caches.
+    pub fn fragmentation(&self) -> usize {
+        let free = (self.heap_bytes + self.cached_bytes).saturating_sub(self.used_bytes);
+        free * 1000 / self.heap_bytes.max(1)
+    }
 }
 
 impl GlobalAllocator {
@@ -64,9 +148,30 @@ impl GlobalAllocator {
         Self {
             balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            balloc_contended: AtomicUsize::new(0),
+            palloc_contended: AtomicUsize::new(0),
+            cache_drains: AtomicUsize::new(0),
+            peak_used_bytes: AtomicUsize::new(0),
+            peak_used_pages: AtomicUsize::new(0),
         }
     }
 
+    /// Locks the byte allocator, counting the contention.
+    fn lock_balloc(&self) -> SpinNoIrqGuard<DefaultByteAllocator> {
+        self.balloc.try_lock().unwrap_or_else(|| {
+            self.balloc_contended.fetch_add(1, Ordering::Relaxed);
+            self.balloc.lock()
+        })
+    }
+
+    /// Locks the page allocator, counting the contention.
+    fn lock_palloc(&self) -> SpinNoIrqGuard<BitmapPageAllocator<PAGE_SIZE>> {
+        self.palloc.try_lock().unwrap_or_else(|| {
+            self.palloc_contended.fetch_add(1, Ordering::Relaxed);
+            self.palloc.lock()
+        })
+    }
+
     /// Returns the name of the allocator.
     pub const fn name(&self) -> &'static str {
         cfg_if::cfg_if! {
@@ -99,7 +204,7 @@ impl GlobalAllocator {
     ///
     /// It will add the whole region to the byte allocator.
     pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
-        self.balloc.lock().add_memory(start_vaddr, size)
+        self.lock_balloc().add_memory(start_vaddr, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
@@ -111,11 +216,52 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     ///  aligned to it.
+    ///
+    /// If there is no memory at all, it calls [`out_of_memory`] to reclaim
+    /// some memory and retries, or fails according to the [`OomPolicy`].
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let owned = usage::owned_layout(layout)?;
+        loop {
+            #[cfg(feature = "debug")]
+            let ret = debug::alloc(self, owned);
+            #[cfg(not(feature = "debug"))]
+            let ret = self.alloc_raw(owned);
+            match ret {
+                Ok(ptr) => return Ok(usage::set_owner(ptr, layout)),
+                Err(AllocError::NoMemory) if out_of_memory(layout.size()) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Allocates without the heap debugging.
+    fn alloc_raw(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "smp")]
+        if let Some(class) = cache::size_class(&layout) {
+            return self.alloc_cached(class);
+        }
+        self.alloc_global(layout)
+    }
+
+    /// Allocates from the byte allocator, draining the per-CPU caches and
+    /// retrying if there is no memory.
+    fn alloc_global(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let ret = self.alloc_bytes(layout);
+        #[cfg(feature = "smp")]
+        if let Err(AllocError::NoMemory) = ret {
+            self.drain_caches();
+            return self.alloc_bytes(layout);
+        }
+        ret
+    }
+
+    fn alloc_bytes(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
-        let mut balloc = self.balloc.lock();
+        let mut balloc = self.lock_balloc();
         loop {
             if let Ok(ptr) = balloc.alloc(layout) {
+                self.peak_used_bytes
+                    .fetch_max(balloc.used_bytes(), Ordering::Relaxed);
                 return Ok(ptr);
             } else {
                 let old_size = balloc.total_bytes();
@@ -142,7 +288,94 @@ impl GlobalAllocator {
     ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
-        self.balloc.lock().dealloc(pos, layout)
+        let (block, owned, owner) = usage::take_owner(pos, layout);
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "debug")] {
+                debug::dealloc(self, block, owned)
+            } else {
+                self.dealloc_raw(block, owned)
+            }
+        }
+        usage::put_owner_bytes(owner, layout.size());
+    }
+
+    /// Deallocates without the heap debugging.
+    fn dealloc_raw(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "smp")]
+        if let Some(class) = cache::size_class(&layout) {
+            return self.dealloc_cached(pos, class);
+        }
+        self.lock_balloc().dealloc(pos, layout)
+    }
+
+    /// Allocates a block of size class `class` from the cache of the current
+    /// CPU, refilling it with a batch of blocks from the byte allocator if
+    /// it's empty.
+    #[cfg(feature = "smp")]
+    fn alloc_cached(&self, class: usize) -> AllocResult<NonNull<u8>> {
+        {
+            let mut cache = cache::local_cache().lock();
+            if let Some(ptr) = cache.pop(class) {
+                cache.hits += 1;
+                return Ok(ptr);
+            }
+            cache.misses += 1;
+        }
+
+        // The cache lock is not held here, since draining on memory pressure
+        // locks the caches of all CPUs.
+        let layout = cache::class_layout(class);
+        let ptr = self.alloc_global(layout)?;
+        let mut batch = [None; cache::BATCH - 1];
+        {
+            let mut balloc = self.lock_balloc();
+            for slot in batch.iter_mut() {
+                match balloc.alloc(layout) {
+                    Ok(block) => *slot = Some(block),
+                    Err(_) => break,
+                }
+            }
+        }
+        let mut cache = cache::local_cache().lock();
+        for block in batch.into_iter().flatten() {
+            cache.push(class, block);
+        }
+        Ok(ptr)
+    }
+
+    /// Gives back a block of size class `class` to the cache of the current
+    /// CPU, flushing a batch of blocks to the byte allocator if it's full.
+    #[cfg(feature = "smp")]
+    fn dealloc_cached(&self, pos: NonNull<u8>, class: usize) {
+        let mut cache = cache::local_cache().lock();
+        if cache.is_full(class) {
+            let layout = cache::class_layout(class);
+            let mut balloc = self.lock_balloc();
+            for _ in 0..cache::BATCH {
+                if let Some(block) = cache.pop(class) {
+                    balloc.dealloc(block, layout);
+                }
+            }
+            cache.flushes += 1;
+        }
+        cache.push(class, pos);
+    }
+
+    /// Gives back all the free blocks held by the per-CPU caches to the byte
+    /// allocator.
+    ///
+    /// It's called when the byte allocator runs out of memory, and can also be
+    /// called to reduce the memory footprint.
+    pub fn drain_caches(&self) {
+        #[cfg(feature = "smp")]
+        {
+            self.cache_drains.fetch_add(1, Ordering::Relaxed);
+            for cache in cache::all_caches() {
+                let mut cache = cache.lock();
+                let mut balloc = self.lock_balloc();
+                cache.drain(|block, layout| balloc.dealloc(block, layout));
+            }
+        }
     }
 
     /// Allocates contiguous pages.
@@ -152,7 +385,11 @@ impl GlobalAllocator {
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        let mut palloc = self.lock_palloc();
+        let pos = palloc.alloc_pages(num_pages, align_pow2)?;
+        self.peak_used_pages
+            .fetch_max(palloc.used_pages(), Ordering::Relaxed);
+        Ok(pos)
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -163,7 +400,7 @@ impl GlobalAllocator {
     ///
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
-        self.palloc.lock().dealloc_pages(pos, num_pages)
+        self.lock_palloc().dealloc_pages(pos, num_pages)
     }
 
     /// Returns the number of allocated bytes in the byte allocator.
@@ -185,15 +422,44 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.palloc.lock().available_pages()
     }
+
+    /// Returns the statistics of the allocator.
+    pub fn stats(&self) -> AllocStats {
+        let mut stats = AllocStats {
+            cache_drains: self.cache_drains.load(Ordering::Relaxed),
+            balloc_contended: self.balloc_contended.load(Ordering::Relaxed),
+            palloc_contended: self.palloc_contended.load(Ordering::Relaxed),
+            peak_used_bytes: self.peak_used_bytes.load(Ordering::Relaxed),
+            peak_used_pages: self.peak_used_pages.load(Ordering::Relaxed),
+            oom_events: oom::OOM_EVENTS.load(Ordering::Relaxed),
+            oom_kills: oom::OOM_KILLS.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        #[cfg(feature = "smp")]
+        for cache in cache::all_caches() {
+            let cache = cache.lock();
+            stats.cached_bytes += cache.cached_bytes();
+            stats.cache_hits += cache.hits;
+            stats.cache_misses += cache.misses;
+            stats.cache_flushes += cache.flushes;
+        }
+        {
+            let balloc = self.balloc.lock();
+            stats.heap_bytes = balloc.total_bytes();
+            stats.used_bytes = balloc.used_bytes();
+        }
+        let palloc = self.palloc.lock();
+        stats.used_pages = palloc.used_pages();
+        stats.available_pages = palloc.available_pages();
+        stats
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
-            ptr.as_ptr()
-        } else {
-            alloc::alloc::handle_alloc_error(layout)
-        }
+        // The failure is handled by the caller, e.g., `malloc` returns NULL,
+        // while the Rust collections call `handle_alloc_error` to panic.
+        GlobalAllocator::alloc(self, layout).map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
@@ -240,3 +506,66 @@ pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     );
     GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
+
+/// Writes the live allocations of the global allocator to `out`, grouped by
+/// their call sites.
+///
+/// The call sites are only recorded with the `debug` feature.
+pub fn dump_allocations_to(out: &mut dyn fmt::Write) -> fmt::Result {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "debug")] {
+            debug::dump_allocations_to(out)
+        } else {
+            writeln!(out, "allocations are not tracked, enable the `debug` feature of axalloc")
+        }
+    }
+}
+
+/// Writes the statistics of the global allocator and the memory usage of the
+/// subsystems to `out`.
+pub fn dump_memory_usage_to(out: &mut dyn fmt::Write) -> fmt::Result {
+    let stats = GLOBAL_ALLOCATOR.stats();
+    writeln!(
+        out,
+        "heap: {} used, {} peak, {} total, {} cached, fragmentation {}.{}%",
+        stats.used_bytes,
+        stats.peak_used_bytes,
+        stats.heap_bytes,
+        stats.cached_bytes,
+        stats.fragmentation() / 10,
+        stats.fragmentation() % 10
+    )?;
+    writeln!(
+        out,
+        "pages: {} used, {} peak, {} available",
+        stats.used_pages, stats.peak_used_pages, stats.available_pages
+    )?;
+    writeln!(
+        out,
+        "cache: {} hits, {} misses, {} flushes, {} drains; contended: heap {}, pages {}",
+        stats.cache_hits,
+        stats.cache_misses,
+        stats.cache_flushes,
+        stats.cache_drains,
+        stats.balloc_contended,
+        stats.palloc_contended
+    )?;
+    writeln!(
+        out,
+        "oom: {} events, {} kills, policy {:?}",
+        stats.oom_events,
+        stats.oom_kills,
+        oom_policy()
+    )?;
+    for tag in MemTag::ALL {
+        let usage = usage(tag);
+        writeln!(
+            out,
+            "{}: {} used, {} peak",
+            tag.name(),
+            usage.current,
+            usage.peak
+        )?;
+    }
+    Ok(())
+}
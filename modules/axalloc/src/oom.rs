@@ -0,0 +1,243 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Handling of out of memory.
+//!
+//! When a heap allocation fails, the shrinkers registered by
+//! [`register_shrinker`] are called to free some pages, e.g., by dropping the
+//! file cache or swapping out pages, and the allocation is retried. If no
+//! page can be freed, the allocator acts according to the [`OomPolicy`],
+//! e.g., it waits for the task killed by the OOM killer to exit, and retries.
+//!
+//! The shrinkers and the killer run one at a time with IRQs disabled, while
+//! the allocations failing on other CPUs wait for them and retry.
+
+#[cfg(not(feature = "smp"))]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use spinlock::SpinNoIrq;
+
+use crate::PAGE_SIZE;
+
+/// Maximum number of shrinkers.
+const MAX_SHRINKERS: usize = 4;
+
+/// What to do when the memory runs out and nothing can be reclaimed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomPolicy {
+    /// Fails the allocation, e.g., `malloc` returns NULL and `mmap` fails
+    /// with `ENOMEM`.
+    Fail = 0,
+    /// Kills the task using the most memory by the killer set by
+    /// [`set_oom_killer`], and retries the allocation after it exits.
+    Kill = 1,
+    /// Panics the whole kernel.
+    Panic = 2,
+}
+
+/// Frees at most the given number of pages, and returns the number of pages
+/// freed.
+///
+/// It's called in the allocator, where any lock may be held by the current
+/// task, so it should only try to take locks.
+pub type Shrinker = fn(usize) -> usize;
+
+/// Kills the task using the most memory, or finds the one killed before
+/// which is still exiting.
+///
+/// It's called in the allocator with IRQs disabled, so it should only try to
+/// take locks, and never block.
+pub type OomKiller = fn() -> OomKill;
+
+/// Waits for the task killed by the [`OomKiller`] to exit, and returns
+/// whether it has exited. It returns `false` at once if the current task
+/// can't block, e.g., it holds a lock which the victim may need to exit.
+pub type OomWaiter = fn() -> bool;
+
+/// What the [`OomKiller`] has done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomKill {
+    /// No task can be killed.
+    NoVictim,
+    /// A task is killed.
+    Killed,
+    /// A task killed before is still exiting, it's waited for instead of
+    /// killing another one.
+    Exiting,
+}
+
+/// The OOM policy, or `u8::MAX` for the one in the config.
+static POLICY: AtomicU8 = AtomicU8::new(u8::MAX);
+static SHRINKERS: SpinNoIrq<[Option<Shrinker>; MAX_SHRINKERS]> =
+    SpinNoIrq::new([None; MAX_SHRINKERS]);
+/// The OOM killer and waiter, 0 if not set.
+static KILLER: AtomicUsize = AtomicUsize::new(0);
+static WAITER: AtomicUsize = AtomicUsize::new(0);
+/// Serializes the shrinkers and the OOM killer. It's held with IRQs
+/// disabled, so the allocations racing with them on other CPUs wait for
+/// them to finish, and they can't be preempted by the waiters on this CPU.
+static RECLAIM_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+/// Whether the current CPU holds [`RECLAIM_LOCK`], so the allocations made by
+/// the shrinkers or the killer themselves fail instead of deadlocking.
+#[cfg(feature = "smp")]
+#[percpu::def_percpu]
+static RECLAIMING: bool = false;
+#[cfg(not(feature = "smp"))]
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+
+fn is_reclaiming() -> bool {
+    // Safe because it's only accessed with IRQs disabled.
+    #[cfg(feature = "smp")]
+    return unsafe { RECLAIMING.read_current_raw() };
+    #[cfg(not(feature = "smp"))]
+    return RECLAIMING.load(Ordering::Relaxed);
+}
+
+fn set_reclaiming(reclaiming: bool) {
+    // Safe because it's only accessed with IRQs disabled.
+    #[cfg(feature = "smp")]
+    unsafe {
+        RECLAIMING.write_current_raw(reclaiming)
+    };
+    #[cfg(not(feature = "smp"))]
+    RECLAIMING.store(reclaiming, Ordering::Relaxed);
+}
+
+/// Runs `f` with [`RECLAIM_LOCK`] held.
+///
+/// Returns `None` if it's held by the current CPU, i.e., the allocation is
+/// made by the shrinkers or the killer. Returns `Some(None)` if it's held by
+/// another CPU, after waiting for it to be released, so the caller should
+/// retry the allocation.
+fn reclaim_locked<T>(f: impl FnOnce() -> T) -> Option<Option<T>> {
+    let guard = match RECLAIM_LOCK.try_lock() {
+        Some(guard) => guard,
+        None if is_reclaiming() => return None,
+        None => {
+            drop(RECLAIM_LOCK.lock());
+            return Some(None);
+        }
+    };
+    set_reclaiming(true);
+    let ret = f();
+    set_reclaiming(false);
+    drop(guard);
+    Some(Some(ret))
+}
+
+pub(crate) static OOM_EVENTS: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static OOM_KILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the OOM policy, which is `oom-policy` in the config by default.
+pub fn oom_policy() -> OomPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        0 => OomPolicy::Fail,
+        1 => OomPolicy::Kill,
+        2 => OomPolicy::Panic,
+        _ => match ruxconfig::OOM_POLICY {
+            "fail" => OomPolicy::Fail,
+            "panic" => OomPolicy::Panic,
+            _ => OomPolicy::Kill,
+        },
+    }
+}
+
+/// Sets the OOM policy.
+pub fn set_oom_policy(policy: OomPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Registers a shrinker called when the memory runs out. Registering the
+/// same shrinker again has no effect.
+pub fn register_shrinker(shrinker: Shrinker) {
+    let mut shrinkers = SHRINKERS.lock();
+    if shrinkers
+        .iter()
+        .flatten()
+        .any(|s| *s as usize == shrinker as usize)
+    {
+        return;
+    }
+    match shrinkers.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => *slot = Some(shrinker),
+        None => warn!("too many shrinkers, {:#x} is ignored", shrinker as usize),
+    }
+}
+
+/// Sets the killer and the waiter used by the [`OomPolicy::Kill`] policy.
+pub fn set_oom_killer(killer: OomKiller, waiter: OomWaiter) {
+    WAITER.store(waiter as usize, Ordering::Release);
+    KILLER.store(killer as usize, Ordering::Release);
+}
+
+/// Handles the failure to allocate `size` bytes.
+///
+/// Returns `true` if some pages are freed by the shrinkers, so the allocation
+/// should be retried. Otherwise, it acts according to the [`OomPolicy`], and
+/// returns `true` if the task killed has exited.
+///
+/// If the shrinkers or the OOM killer are running on another CPU, it waits
+/// for them and returns `true` to retry. It returns `false` at once if the
+/// allocation is made by themselves.
+pub fn out_of_memory(size: usize) -> bool {
+    let pages = size.div_ceil(PAGE_SIZE);
+    let freed = reclaim_locked(|| {
+        let shrinkers = *SHRINKERS.lock();
+        let mut freed = 0;
+        for shrinker in shrinkers.iter().flatten() {
+            freed += shrinker(pages - freed);
+            if freed >= pages {
+                break;
+            }
+        }
+        freed
+    });
+    match freed {
+        None => return false,
+        Some(None) => return true,
+        Some(Some(0)) => {}
+        Some(Some(freed)) => {
+            debug!("out of memory: {} pages reclaimed", freed);
+            return true;
+        }
+    }
+
+    OOM_EVENTS.fetch_add(1, Ordering::Relaxed);
+    match oom_policy() {
+        OomPolicy::Fail => warn!("out of memory: failed to allocate {} bytes", size),
+        OomPolicy::Kill => {
+            let (killer, waiter) = (
+                KILLER.load(Ordering::Acquire),
+                WAITER.load(Ordering::Acquire),
+            );
+            if killer == 0 {
+                warn!("out of memory: no task to kill for {} bytes", size);
+                return false;
+            }
+            // Safe because they're only set by `set_oom_killer`.
+            let (killer, waiter): (OomKiller, OomWaiter) =
+                unsafe { (core::mem::transmute(killer), core::mem::transmute(waiter)) };
+            match reclaim_locked(killer) {
+                None => return false,
+                Some(None) => return true,
+                Some(Some(OomKill::NoVictim)) => {
+                    warn!("out of memory: no task to kill for {} bytes", size)
+                }
+                Some(Some(OomKill::Killed)) => {
+                    OOM_KILLS.fetch_add(1, Ordering::Relaxed);
+                    return waiter();
+                }
+                Some(Some(OomKill::Exiting)) => return waiter(),
+            }
+        }
+        OomPolicy::Panic => panic!("out of memory: failed to allocate {} bytes", size),
+    }
+    false
+}
@@ -0,0 +1,304 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Memory usage accounting.
+//!
+//! Besides the usage of the allocators themselves, the subsystems charge the
+//! memory they use to [`MemTag`]s. With the `task-account` feature, the heap
+//! memory is also charged to the [`MemOwner`] of the task allocating it, which
+//! is recorded in a header before each block. The pages are not charged to
+//! tasks, since they're often freed by others, e.g., the pages mapped by
+//! `mmap`. The peak usage is tracked for all of them.
+
+#[cfg(feature = "task-account")]
+use allocator::AllocError;
+use allocator::AllocResult;
+use core::alloc::Layout;
+#[cfg(feature = "task-account")]
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const NUM_TAGS: usize = 5;
+
+/// Subsystems whose memory usage is accounted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemTag {
+    /// Pages of the file cache, shared by the `MAP_SHARED` file mappings.
+    FsCache = 0,
+    /// Buffers of the network sockets.
+    NetBuffer = 1,
+    /// Stacks of the tasks.
+    TaskStack = 2,
+    /// Pages mapped by `mmap`, except the ones of the file cache.
+    MmapPage = 3,
+    /// Pages given to the host by the memory balloon.
+    Balloon = 4,
+}
+
+impl MemTag {
+    /// All the tags.
+    pub const ALL: [MemTag; NUM_TAGS] = [
+        MemTag::FsCache,
+        MemTag::NetBuffer,
+        MemTag::TaskStack,
+        MemTag::MmapPage,
+        MemTag::Balloon,
+    ];
+
+    /// Returns the name of the tag.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MemTag::FsCache => "fs_cache",
+            MemTag::NetBuffer => "net_buffer",
+            MemTag::TaskStack => "task_stack",
+            MemTag::MmapPage => "mmap_page",
+            MemTag::Balloon => "balloon",
+        }
+    }
+}
+
+/// Current and peak memory usage in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemUsage {
+    /// Number of bytes in use.
+    pub current: usize,
+    /// Maximum number of bytes ever in use.
+    pub peak: usize,
+}
+
+struct Counter {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Counter {
+    const fn new() -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+}
+
+static TAGS: [Counter; NUM_TAGS] = [
+    Counter::new(),
+    Counter::new(),
+    Counter::new(),
+    Counter::new(),
+    Counter::new(),
+];
+
+/// Charges `bytes` bytes used by the subsystem `tag`.
+pub fn charge(tag: MemTag, bytes: usize) {
+    let counter = &TAGS[tag as usize];
+    let current = counter.current.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    counter.peak.fetch_max(current, Ordering::Relaxed);
+}
+
+/// Uncharges `bytes` bytes charged to the subsystem `tag` by [`charge`].
+pub fn uncharge(tag: MemTag, bytes: usize) {
+    TAGS[tag as usize]
+        .current
+        .fetch_sub(bytes, Ordering::Relaxed);
+}
+
+/// Returns the memory usage of the subsystem `tag`.
+pub fn usage(tag: MemTag) -> MemUsage {
+    let counter = &TAGS[tag as usize];
+    MemUsage {
+        current: counter.current.load(Ordering::Relaxed),
+        peak: counter.peak.load(Ordering::Relaxed),
+    }
+}
+
+/// Memory owned by a task, i.e., the heap bytes allocated by it and not freed
+/// yet. Each allocation records its owner, so the bytes are credited back to
+/// the owner whichever task frees them, even after the owner exits.
+pub struct MemOwner {
+    bytes: AtomicUsize,
+    peak: AtomicUsize,
+    /// References from the task and the live allocations charged to it.
+    refs: AtomicUsize,
+}
+
+/// A reference to a [`MemOwner`], held by its task.
+pub struct MemOwnerRef(NonNull<MemOwner>);
+
+// Safe because the owner is only accessed by atomics.
+unsafe impl Send for MemOwnerRef {}
+unsafe impl Sync for MemOwnerRef {}
+
+impl MemOwnerRef {
+    /// Creates an owner without memory charged.
+    ///
+    /// The owner itself is not charged to anyone, it's freed after its task
+    /// and all the allocations charged to it are gone.
+    pub fn new() -> AllocResult<Self> {
+        let owner = crate::global_allocator()
+            .alloc_raw(Layout::new::<MemOwner>())?
+            .cast::<MemOwner>();
+        // Safe because the block is just allocated for it.
+        unsafe {
+            owner.as_ptr().write(MemOwner {
+                bytes: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                refs: AtomicUsize::new(1),
+            })
+        };
+        Ok(Self(owner))
+    }
+
+    /// Returns the memory usage of the owner.
+    pub fn usage(&self) -> MemUsage {
+        // Safe because the owner is kept by the reference.
+        let owner = unsafe { self.0.as_ref() };
+        MemUsage {
+            current: owner.bytes.load(Ordering::Relaxed),
+            peak: owner.peak.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the pointer to the owner, which is returned by the hook set by
+    /// [`set_task_account`] while its task is running.
+    pub fn as_ptr(&self) -> NonNull<MemOwner> {
+        self.0
+    }
+}
+
+impl Drop for MemOwnerRef {
+    fn drop(&mut self) {
+        // Safe because the reference is held until now.
+        unsafe { put_owner(self.0) }
+    }
+}
+
+/// Drops a reference to `owner`, and frees it if it's the last one.
+///
+/// # Safety
+///
+/// The caller must hold a reference to `owner`.
+unsafe fn put_owner(owner: NonNull<MemOwner>) {
+    if owner.as_ref().refs.fetch_sub(1, Ordering::Release) == 1 {
+        core::sync::atomic::fence(Ordering::Acquire);
+        crate::global_allocator().dealloc_raw(owner.cast(), Layout::new::<MemOwner>());
+    }
+}
+
+/// The hook returning the owner of the current task, 0 if not set.
+static TASK_ACCOUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the hook returning the [`MemOwner`] of the current task, to which
+/// the heap memory allocated is charged, for per-task accounting. It only
+/// takes effect with the `task-account` feature.
+///
+/// The hook is called in the allocator, so it must not allocate memory.
+pub fn set_task_account(hook: fn() -> Option<NonNull<MemOwner>>) {
+    TASK_ACCOUNT.store(hook as usize, Ordering::Release);
+}
+
+#[cfg(feature = "task-account")]
+fn current_owner() -> Option<NonNull<MemOwner>> {
+    let hook = TASK_ACCOUNT.load(Ordering::Acquire);
+    if hook == 0 {
+        return None;
+    }
+    // Safe because it's only set by `set_task_account`.
+    let hook: fn() -> Option<NonNull<MemOwner>> = unsafe { core::mem::transmute(hook) };
+    hook()
+}
+
+/// Size of the header before each block, recording its owner in the last
+/// word. It keeps the block aligned.
+#[cfg(feature = "task-account")]
+const fn header_size(layout: &Layout) -> usize {
+    if layout.align() > size_of::<usize>() {
+        layout.align()
+    } else {
+        size_of::<usize>()
+    }
+}
+
+/// Returns the layout to allocate for `layout`, with the header recording the
+/// owner if the `task-account` feature is enabled.
+pub(crate) fn owned_layout(layout: Layout) -> AllocResult<Layout> {
+    #[cfg(feature = "task-account")]
+    return Layout::from_size_align(
+        layout.size() + header_size(&layout),
+        layout.align().max(align_of::<usize>()),
+    )
+    .map_err(|_| AllocError::InvalidParam);
+    #[cfg(not(feature = "task-account"))]
+    Ok(layout)
+}
+
+/// Charges the block at `ptr` allocated by [`owned_layout`] to the current
+/// task, and returns the part for the caller.
+pub(crate) fn set_owner(ptr: NonNull<u8>, layout: Layout) -> NonNull<u8> {
+    #[cfg(feature = "task-account")]
+    {
+        let owner = current_owner();
+        if let Some(owner) = owner {
+            // Safe because the owner is kept by the current task.
+            let owner = unsafe { owner.as_ref() };
+            owner.refs.fetch_add(1, Ordering::Relaxed);
+            let now = owner.bytes.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            owner.peak.fetch_max(now, Ordering::Relaxed);
+        }
+        // Safe because the header is allocated before the part for the caller.
+        unsafe {
+            let ptr = ptr.as_ptr().add(header_size(&layout));
+            (ptr as *mut usize)
+                .sub(1)
+                .write(owner.map_or(0, |owner| owner.as_ptr() as usize));
+            NonNull::new_unchecked(ptr)
+        }
+    }
+    #[cfg(not(feature = "task-account"))]
+    {
+        let _ = layout;
+        ptr
+    }
+}
+
+/// Takes the owner of the part at `ptr` returned by [`set_owner`], and returns
+/// the block to free with its layout.
+///
+/// The owner should be uncharged by [`put_owner_bytes`] after the block is freed.
+pub(crate) fn take_owner(
+    ptr: NonNull<u8>,
+    layout: Layout,
+) -> (NonNull<u8>, Layout, Option<NonNull<MemOwner>>) {
+    #[cfg(feature = "task-account")]
+    {
+        // Safe because `ptr` is returned by `set_owner` with the same layout.
+        unsafe {
+            let owner = (ptr.as_ptr() as *const usize).sub(1).read();
+            let block = ptr.as_ptr().sub(header_size(&layout));
+            (
+                NonNull::new_unchecked(block),
+                owned_layout(layout).unwrap(),
+                NonNull::new(owner as *mut MemOwner),
+            )
+        }
+    }
+    #[cfg(not(feature = "task-account"))]
+    (ptr, layout, None)
+}
+
+/// Uncharges `bytes` bytes from `owner` returned by [`take_owner`].
+pub(crate) fn put_owner_bytes(owner: Option<NonNull<MemOwner>>, bytes: usize) {
+    if let Some(owner) = owner {
+        // Safe because the allocation charged holds a reference.
+        unsafe {
+            owner.as_ref().bytes.fetch_sub(bytes, Ordering::Relaxed);
+            put_owner(owner);
+        }
+    }
+}
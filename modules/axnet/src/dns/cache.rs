@@ -0,0 +1,73 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Cache of the answers from nameservers, honoring their TTLs.
+
+use alloc::{collections::BTreeMap, string::String};
+use core::time::Duration;
+
+use axsync::Mutex;
+
+use super::message::RecordType;
+use super::{now, Answer};
+
+const CACHE_CAPACITY: usize = 256;
+/// Records are never cached longer than this, whatever their TTLs are.
+const MAX_TTL_SECS: u32 = 24 * 60 * 60;
+
+struct Entry {
+    expires: Duration,
+    /// `None` for a negative answer (no such name, or no records of the type).
+    answer: Option<Answer>,
+}
+
+static CACHE: Mutex<BTreeMap<(String, RecordType), Entry>> = Mutex::new(BTreeMap::new());
+
+/// Looks up the cached answer of a query.
+///
+/// Returns `None` if nothing is cached, or `Some(None)` if a negative answer
+/// is cached.
+pub fn get(name: &str, qtype: RecordType) -> Option<Option<Answer>> {
+    let key = (name.to_ascii_lowercase(), qtype);
+    let mut cache = CACHE.lock();
+    let entry = cache.get(&key)?;
+    if entry.expires <= now() {
+        cache.remove(&key);
+        return None;
+    }
+    Some(entry.answer.clone())
+}
+
+/// Caches the answer of a query for `ttl` seconds.
+pub fn put(name: &str, qtype: RecordType, answer: Option<Answer>, ttl: u32) {
+    let ttl = ttl.min(MAX_TTL_SECS);
+    if ttl == 0 {
+        return;
+    }
+    let now = now();
+    let mut cache = CACHE.lock();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, entry| entry.expires > now);
+    }
+    if cache.len() >= CACHE_CAPACITY {
+        // Evict the entry which expires first.
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            cache.remove(&key);
+        }
+    }
+    let entry = Entry {
+        expires: now + Duration::from_secs(ttl as u64),
+        answer,
+    };
+    cache.insert((name.to_ascii_lowercase(), qtype), entry);
+}
@@ -0,0 +1,229 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Parsing of `/etc/resolv.conf` and `/etc/hosts`.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr};
+use core::time::Duration;
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const HOSTS_PATH: &str = "/etc/hosts";
+
+/// Used when no usable nameserver is configured.
+const DEFAULT_NAMESERVER: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+/// At most this many nameservers are used, same as `MAXNS` of glibc.
+const MAX_NAMESERVERS: usize = 3;
+const MAX_SEARCH_DOMAINS: usize = 6;
+const MAX_TIMEOUT_SECS: u64 = 30;
+const MAX_ATTEMPTS: usize = 5;
+const MAX_NDOTS: usize = 15;
+
+/// Resolver configuration, see `resolv.conf(5)`.
+#[derive(Debug)]
+pub struct ResolvConf {
+    pub nameservers: Vec<IpAddr>,
+    pub search: Vec<String>,
+    pub ndots: usize,
+    /// How long to wait for the response from a nameserver.
+    pub timeout: Duration,
+    /// How many times to try all the nameservers.
+    pub attempts: usize,
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![DEFAULT_NAMESERVER],
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+        }
+    }
+}
+
+impl ResolvConf {
+    /// Loads the configuration from `/etc/resolv.conf`, or returns the
+    /// default one if the file is unavailable.
+    pub fn load() -> Self {
+        read_file(RESOLV_CONF_PATH).map_or_else(Self::default, |s| Self::parse(&s))
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut conf = Self {
+            nameservers: Vec::new(),
+            ..Self::default()
+        };
+        for line in content.lines() {
+            let mut words = strip_comment(line).split_ascii_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    let Some(addr) = words.next().and_then(|w| w.parse::<IpAddr>().ok()) else {
+                        continue;
+                    };
+                    // UDP and TCP sockets are IPv4 only, and there is no
+                    // local resolver behind a loopback address (such as the
+                    // `127.0.0.53` of systemd-resolved), so the default one is
+                    // used instead.
+                    if addr.is_ipv4()
+                        && !addr.is_loopback()
+                        && conf.nameservers.len() < MAX_NAMESERVERS
+                    {
+                        conf.nameservers.push(addr);
+                    }
+                }
+                // The last of `domain` and `search` wins.
+                Some("domain") => {
+                    conf.search = words.next().map(String::from).into_iter().collect();
+                }
+                Some("search") => {
+                    conf.search = words.take(MAX_SEARCH_DOMAINS).map(String::from).collect();
+                }
+                Some("options") => {
+                    for option in words {
+                        let (key, value) = option.split_once(':').unwrap_or((option, ""));
+                        let Ok(value) = value.parse::<usize>() else {
+                            continue;
+                        };
+                        match key {
+                            "ndots" => conf.ndots = value.min(MAX_NDOTS),
+                            "timeout" => {
+                                conf.timeout =
+                                    Duration::from_secs((value as u64).clamp(1, MAX_TIMEOUT_SECS))
+                            }
+                            "attempts" => conf.attempts = value.clamp(1, MAX_ATTEMPTS),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if conf.nameservers.is_empty() {
+            conf.nameservers.push(DEFAULT_NAMESERVER);
+        }
+        conf
+    }
+}
+
+/// An entry of `/etc/hosts`.
+pub struct HostEntry {
+    pub addr: IpAddr,
+    /// The canonical name followed by the aliases.
+    pub names: Vec<String>,
+}
+
+impl HostEntry {
+    pub fn canonical_name(&self) -> &str {
+        &self.names[0]
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Loads the entries of `/etc/hosts`.
+pub fn load_hosts() -> Vec<HostEntry> {
+    read_file(HOSTS_PATH).map_or_else(Vec::new, |s| parse_hosts(&s))
+}
+
+fn parse_hosts(content: &str) -> Vec<HostEntry> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut words = strip_comment(line).split_ascii_whitespace();
+            let addr = words.next()?.parse::<IpAddr>().ok()?;
+            let names: Vec<String> = words.map(String::from).collect();
+            (!names.is_empty()).then_some(HostEntry { addr, names })
+        })
+        .collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split(['#', ';']).next().unwrap_or("")
+}
+
+#[cfg(feature = "fs")]
+fn read_file(path: &str) -> Option<String> {
+    ruxfs::api::read_to_string(path).ok()
+}
+
+#[cfg(not(feature = "fs"))]
+fn read_file(_path: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolv_conf() {
+        let conf = ResolvConf::parse(
+            "# comment\n\
+             nameserver 10.0.2.3\n\
+             nameserver 127.0.0.53\n\
+             nameserver ::1\n\
+             nameserver bad\n\
+             nameserver 1.1.1.1 ; trailing comment\n\
+             nameserver 8.8.4.4\n\
+             nameserver 9.9.9.9\n\
+             domain example.org\n\
+             search lan corp.example.com\n\
+             options ndots:2 timeout:0 attempts:9 edns0\n",
+        );
+        assert_eq!(
+            conf.nameservers,
+            [
+                IpAddr::V4(Ipv4Addr::new(10, 0, 2, 3)),
+                IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+                IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4)),
+            ]
+        );
+        assert_eq!(conf.search, ["lan", "corp.example.com"]);
+        assert_eq!(conf.ndots, 2);
+        assert_eq!(conf.timeout, Duration::from_secs(1));
+        assert_eq!(conf.attempts, MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn resolv_conf_defaults() {
+        let conf = ResolvConf::parse("nameserver 127.0.0.53\noptions edns0 trust-ad\n");
+        assert_eq!(conf.nameservers, [DEFAULT_NAMESERVER]);
+        assert!(conf.search.is_empty());
+        assert_eq!(conf.ndots, 1);
+        assert_eq!(conf.timeout, Duration::from_secs(5));
+        assert_eq!(conf.attempts, 2);
+
+        let conf = ResolvConf::parse("search a.example\ndomain b.example\n");
+        assert_eq!(conf.search, ["b.example"]);
+    }
+
+    #[test]
+    fn hosts() {
+        let hosts = parse_hosts(
+            "127.0.0.1 localhost\n\
+             # 10.0.0.1 commented\n\
+             ::1 localhost ip6-localhost ip6-loopback\n\
+             10.0.0.2\n\
+             not-an-address name\n\
+             10.0.0.3 Server.lan server # alias\n",
+        );
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts[0].addr, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(hosts[0].canonical_name(), "localhost");
+        assert!(hosts[1].addr.is_ipv6());
+        assert!(hosts[1].matches("ip6-loopback"));
+        assert_eq!(hosts[2].canonical_name(), "Server.lan");
+        assert!(hosts[2].matches("SERVER"));
+        assert!(!hosts[2].matches("alias"));
+    }
+}
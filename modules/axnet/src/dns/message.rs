@@ -0,0 +1,452 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Encoding of DNS queries and decoding of DNS responses (RFC 1035).
+
+use alloc::{string::String, vec::Vec};
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+
+const HEADER_LEN: usize = 12;
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+const RCODE_MASK: u16 = 0x000f;
+
+const TYPE_SOA: u16 = 6;
+const CLASS_IN: u16 = 1;
+
+/// Response codes that the resolver cares about.
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_NXDOMAIN: u8 = 3;
+
+/// Types of the records that can be queried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Ptr,
+}
+
+impl RecordType {
+    const fn code(self) -> u16 {
+        match self {
+            Self::A => 1,
+            Self::Cname => 5,
+            Self::Ptr => 12,
+            Self::Aaaa => 28,
+        }
+    }
+
+    const fn from_code(code: u16) -> Option<Self> {
+        match code {
+            1 => Some(Self::A),
+            5 => Some(Self::Cname),
+            12 => Some(Self::Ptr),
+            28 => Some(Self::Aaaa),
+            _ => None,
+        }
+    }
+}
+
+/// Data of a resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ptr(String),
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Aaaa(_) => RecordType::Aaaa,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Ptr(_) => RecordType::Ptr,
+        }
+    }
+}
+
+/// A resource record in the answer section.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+/// A decoded DNS response.
+#[derive(Debug)]
+pub struct Response {
+    /// The response is truncated and should be retried over TCP.
+    pub truncated: bool,
+    pub rcode: u8,
+    /// Records of the known types in the answer section.
+    pub answers: Vec<Record>,
+    /// How long a negative answer can be cached, from the SOA record in the
+    /// authority section.
+    pub negative_ttl: Option<u32>,
+}
+
+/// Encodes a recursive query of `name` for records of `qtype`.
+pub fn build_query(id: u16, name: &str, qtype: RecordType) -> AxResult<Vec<u8>> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return ax_err!(InvalidInput, "DNS query failed: invalid name length");
+    }
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&FLAG_RD.to_be_bytes());
+    buf.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    buf.extend_from_slice(&[0; 6]); // ANCOUNT, NSCOUNT, ARCOUNT
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL_LEN {
+            return ax_err!(InvalidInput, "DNS query failed: invalid label");
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&qtype.code().to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(buf)
+}
+
+/// Decodes the response to the query built by [`build_query`] with the same
+/// `id`, `name` and `qtype`.
+pub fn parse_response(buf: &[u8], id: u16, name: &str, qtype: RecordType) -> AxResult<Response> {
+    let mut reader = Reader { buf, pos: 0 };
+    if reader.u16()? != id {
+        return ax_err!(InvalidData, "DNS response: ID mismatch");
+    }
+    let flags = reader.u16()?;
+    if flags & FLAG_QR == 0 {
+        return ax_err!(InvalidData, "DNS response: not a response");
+    }
+    let qdcount = reader.u16()?;
+    let ancount = reader.u16()?;
+    let nscount = reader.u16()?;
+    let _arcount = reader.u16()?;
+
+    let truncated = flags & FLAG_TC != 0;
+    let rcode = (flags & RCODE_MASK) as u8;
+    if truncated {
+        // The rest may be cut at any place, do not bother to parse it.
+        return Ok(Response {
+            truncated,
+            rcode,
+            answers: Vec::new(),
+            negative_ttl: None,
+        });
+    }
+
+    let name = name.strip_suffix('.').unwrap_or(name);
+    for _ in 0..qdcount {
+        let qname = reader.name()?;
+        let (ty, class) = (reader.u16()?, reader.u16()?);
+        if !qname.eq_ignore_ascii_case(name) || ty != qtype.code() || class != CLASS_IN {
+            return ax_err!(InvalidData, "DNS response: question mismatch");
+        }
+    }
+
+    let mut answers = Vec::new();
+    for _ in 0..ancount {
+        let (name, ty, class, ttl, rdata) = reader.record()?;
+        if class != CLASS_IN {
+            continue;
+        }
+        let data = match RecordType::from_code(ty) {
+            Some(RecordType::A) => {
+                let octets: [u8; 4] = buf[rdata.clone()]
+                    .try_into()
+                    .map_err(|_| ax_err_type!(InvalidData, "DNS response: bad A record"))?;
+                RecordData::A(Ipv4Addr::from(octets))
+            }
+            Some(RecordType::Aaaa) => {
+                let octets: [u8; 16] = buf[rdata.clone()]
+                    .try_into()
+                    .map_err(|_| ax_err_type!(InvalidData, "DNS response: bad AAAA record"))?;
+                RecordData::Aaaa(Ipv6Addr::from(octets))
+            }
+            Some(RecordType::Cname) => RecordData::Cname(Reader::at(buf, rdata.start).name()?),
+            Some(RecordType::Ptr) => RecordData::Ptr(Reader::at(buf, rdata.start).name()?),
+            None => continue,
+        };
+        answers.push(Record { name, ttl, data });
+    }
+
+    let mut negative_ttl = None;
+    for _ in 0..nscount {
+        let (_, ty, _, ttl, rdata) = reader.record()?;
+        if ty == TYPE_SOA && rdata.len() >= 4 {
+            let minimum = u32::from_be_bytes(buf[rdata.end - 4..rdata.end].try_into().unwrap());
+            negative_ttl = Some(ttl.min(minimum));
+        }
+    }
+
+    Ok(Response {
+        truncated,
+        rcode,
+        answers,
+        negative_ttl,
+    })
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at(buf: &'a [u8], pos: usize) -> Self {
+        Self { buf, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> AxResult<&'a [u8]> {
+        let end = self.pos + len;
+        let bytes = self
+            .buf
+            .get(self.pos..end)
+            .ok_or_else(|| ax_err_type!(InvalidData, "DNS response: unexpected end"))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> AxResult<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> AxResult<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a possibly compressed domain name, without the trailing dot.
+    fn name(&mut self) -> AxResult<String> {
+        let mut name = String::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+        // Each jump goes strictly backwards, so it cannot loop forever.
+        let mut limit = pos;
+        loop {
+            let len = *self
+                .buf
+                .get(pos)
+                .ok_or_else(|| ax_err_type!(InvalidData, "DNS response: unexpected end"))?
+                as usize;
+            match len {
+                0 => {
+                    if !jumped {
+                        self.pos = pos + 1;
+                    }
+                    return Ok(name);
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let low = *self
+                        .buf
+                        .get(pos + 1)
+                        .ok_or_else(|| ax_err_type!(InvalidData, "DNS response: unexpected end"))?;
+                    let target = ((len & 0x3f) << 8) | low as usize;
+                    if target >= limit {
+                        return ax_err!(InvalidData, "DNS response: bad name pointer");
+                    }
+                    if !jumped {
+                        self.pos = pos + 2;
+                        jumped = true;
+                    }
+                    pos = target;
+                    limit = target;
+                }
+                len if len <= MAX_LABEL_LEN => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + len)
+                        .ok_or_else(|| ax_err_type!(InvalidData, "DNS response: unexpected end"))?;
+                    if !name.is_empty() {
+                        name.push('.');
+                    }
+                    name.extend(label.iter().map(|&c| c as char));
+                    if name.len() > MAX_NAME_LEN {
+                        return ax_err!(InvalidData, "DNS response: name too long");
+                    }
+                    pos += 1 + len;
+                }
+                _ => return ax_err!(InvalidData, "DNS response: bad label"),
+            }
+        }
+    }
+
+    /// Reads a resource record, returns its name, type, class, TTL and the
+    /// range of its data in the message.
+    fn record(&mut self) -> AxResult<(String, u16, u16, u32, core::ops::Range<usize>)> {
+        let name = self.name()?;
+        let ty = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = self.u16()? as usize;
+        let start = self.pos;
+        self.bytes(len)?;
+        Ok((name, ty, class, ttl, start..start + len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const ID: u16 = 0x1234;
+
+    /// Builds a response to the query of `name`, with the given answer and
+    /// authority records already encoded.
+    fn response(
+        flags: u16,
+        name: &str,
+        qtype: RecordType,
+        answers: &[&[u8]],
+        ns: &[&[u8]],
+    ) -> Vec<u8> {
+        let mut buf = build_query(ID, name, qtype).unwrap();
+        buf[2..4].copy_from_slice(&(FLAG_QR | FLAG_RD | flags).to_be_bytes());
+        buf[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        buf[8..10].copy_from_slice(&(ns.len() as u16).to_be_bytes());
+        for record in answers.iter().chain(ns) {
+            buf.extend_from_slice(record);
+        }
+        buf
+    }
+
+    /// Encodes a record whose name points to the question name.
+    fn record(ty: u16, ttl: u32, rdata: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0xc0, HEADER_LEN as u8];
+        buf.extend_from_slice(&ty.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&ttl.to_be_bytes());
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(rdata);
+        buf
+    }
+
+    #[test]
+    fn query() {
+        let query = build_query(ID, "www.example.com.", RecordType::A).unwrap();
+        assert_eq!(
+            &query[..HEADER_LEN],
+            &[0x12, 0x34, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            &query[HEADER_LEN..],
+            b"\x03www\x07example\x03com\x00\x00\x01\x00\x01"
+        );
+
+        assert!(build_query(ID, "", RecordType::A).is_err());
+        assert!(build_query(ID, "a..b", RecordType::A).is_err());
+        assert!(build_query(ID, &"a".repeat(64), RecordType::A).is_err());
+    }
+
+    #[test]
+    fn answers() {
+        let a = record(1, 300, &[93, 184, 216, 34]);
+        // CNAME "alias" + pointer to the question name
+        let cname = record(5, 60, b"\x05alias\xc0\x0c");
+        let unknown = record(16, 60, b"\x04text");
+        let buf = response(
+            0,
+            "example.com",
+            RecordType::A,
+            &[&cname, &a, &unknown],
+            &[],
+        );
+
+        let res = parse_response(&buf, ID, "example.com.", RecordType::A).unwrap();
+        assert!(!res.truncated);
+        assert_eq!(res.rcode, RCODE_NOERROR);
+        assert_eq!(res.answers.len(), 2);
+        assert_eq!(res.answers[0].name, "example.com");
+        assert_eq!(
+            res.answers[0].data,
+            RecordData::Cname("alias.example.com".into())
+        );
+        assert_eq!(res.answers[1].ttl, 300);
+        assert_eq!(
+            res.answers[1].data,
+            RecordData::A(Ipv4Addr::new(93, 184, 216, 34))
+        );
+        assert_eq!(res.negative_ttl, None);
+
+        let aaaa = record(28, 300, &Ipv6Addr::LOCALHOST.octets());
+        let buf = response(0, "example.com", RecordType::Aaaa, &[&aaaa], &[]);
+        let res = parse_response(&buf, ID, "example.com", RecordType::Aaaa).unwrap();
+        assert_eq!(res.answers[0].data, RecordData::Aaaa(Ipv6Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn negative_answer() {
+        // SOA: mname, rname, serial, refresh, retry, expire, minimum
+        let mut soa_rdata = b"\x02ns\xc0\x0c\x04root\xc0\x0c".to_vec();
+        for value in [1u32, 7200, 3600, 86400, 120] {
+            soa_rdata.extend_from_slice(&value.to_be_bytes());
+        }
+        let soa = record(TYPE_SOA, 600, &soa_rdata);
+        let buf = response(
+            RCODE_NXDOMAIN as u16,
+            "nx.example.com",
+            RecordType::A,
+            &[],
+            &[&soa],
+        );
+
+        let res = parse_response(&buf, ID, "nx.example.com", RecordType::A).unwrap();
+        assert_eq!(res.rcode, RCODE_NXDOMAIN);
+        assert!(res.answers.is_empty());
+        assert_eq!(res.negative_ttl, Some(120));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buf = response(FLAG_TC, "example.com", RecordType::A, &[], &[]);
+        buf.truncate(HEADER_LEN);
+        let res = parse_response(&buf, ID, "example.com", RecordType::A).unwrap();
+        assert!(res.truncated);
+    }
+
+    #[test]
+    fn bad_responses() {
+        let a = record(1, 300, &[10, 0, 0, 1]);
+        let buf = response(0, "example.com", RecordType::A, &[&a], &[]);
+        // wrong ID, name or type
+        assert!(parse_response(&buf, ID + 1, "example.com", RecordType::A).is_err());
+        assert!(parse_response(&buf, ID, "example.org", RecordType::A).is_err());
+        assert!(parse_response(&buf, ID, "example.com", RecordType::Aaaa).is_err());
+        // cut in the middle of a record
+        assert!(parse_response(&buf[..buf.len() - 2], ID, "example.com", RecordType::A).is_err());
+        // a query, not a response
+        let query = build_query(ID, "example.com", RecordType::A).unwrap();
+        assert!(parse_response(&query, ID, "example.com", RecordType::A).is_err());
+
+        // A record with a bad length
+        let a = record(1, 300, &[10, 0, 0]);
+        let buf = response(0, "example.com", RecordType::A, &[&a], &[]);
+        assert!(parse_response(&buf, ID, "example.com", RecordType::A).is_err());
+
+        // a name pointer to itself
+        let mut buf = response(0, "example.com", RecordType::A, &[], &[]);
+        let pos = buf.len() as u8;
+        buf[6..8].copy_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&[0xc0, pos]);
+        assert!(parse_response(&buf, ID, "example.com", RecordType::A).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! A stub DNS resolver.
+//!
+//! Names are looked up in `/etc/hosts` first, then in the cache, and at last
+//! sent to the nameservers in `/etc/resolv.conf` over UDP, falling back to TCP
+//! if the response is truncated. Both files are only read when the `fs`
+//! feature is enabled, otherwise the default configuration is used.
+
+mod cache;
+mod conf;
+mod message;
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxError, AxResult};
+use ruxhal::time::current_time_nanos;
+
+use self::conf::{HostEntry, ResolvConf};
+use self::message::{RecordData, RecordType, Response, RCODE_NOERROR, RCODE_NXDOMAIN};
+use crate::{TcpSocket, UdpSocket};
+
+const DNS_PORT: u16 = 53;
+/// Max size of a DNS message over UDP without EDNS.
+const MAX_UDP_MESSAGE_LEN: usize = 512;
+/// Max number of CNAME records to follow in a lookup.
+const MAX_CNAME_HOPS: usize = 8;
+/// Start of the dynamic port range that the source ports are picked from.
+const SOURCE_PORT_START: u16 = 49152;
+/// Times to pick a source port before falling back to an ephemeral port.
+const SOURCE_PORT_ATTEMPTS: usize = 8;
+
+/// Address families to look up in [`lookup_host`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrFamily {
+    /// Only IPv4 addresses (A records).
+    Ipv4,
+    /// Only IPv6 addresses (AAAA records).
+    Ipv6,
+    /// IPv4 addresses followed by IPv6 addresses.
+    Any,
+}
+
+/// The result of [`lookup_host`].
+#[derive(Debug, Clone)]
+pub struct HostInfo {
+    /// The canonical name of the host, after following the aliases.
+    pub canonical_name: String,
+    /// Addresses of the host, never empty.
+    pub addrs: Vec<IpAddr>,
+}
+
+/// Records of a name with its canonical name.
+#[derive(Debug, Clone)]
+struct Answer {
+    canonical_name: String,
+    records: Vec<RecordData>,
+}
+
+/// Public function for DNS query.
+///
+/// Returns the IPv4 addresses of `name`.
+pub fn dns_query(name: &str) -> AxResult<Vec<IpAddr>> {
+    lookup_host(name, AddrFamily::Ipv4).map(|info| info.addrs)
+}
+
+/// Looks up the addresses of a host name.
+///
+/// Returns [`Err(NotFound)`](AxError::NotFound) if the name does not exist or
+/// has no addresses of the family, or [`Err(WouldBlock)`](AxError::WouldBlock)
+/// if no nameserver responds in time.
+pub fn lookup_host(name: &str, family: AddrFamily) -> AxResult<HostInfo> {
+    if let Ok(addr) = name.parse::<IpAddr>() {
+        return Ok(HostInfo {
+            canonical_name: name.into(),
+            addrs: alloc::vec![addr],
+        });
+    }
+
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if let Some(info) = lookup_hosts_file(name, family) {
+        return Ok(info);
+    }
+
+    let qtypes: &[RecordType] = match family {
+        AddrFamily::Ipv4 => &[RecordType::A],
+        AddrFamily::Ipv6 => &[RecordType::Aaaa],
+        AddrFamily::Any => &[RecordType::A, RecordType::Aaaa],
+    };
+    let conf = ResolvConf::load();
+    let mut info: Option<HostInfo> = None;
+    for &qtype in qtypes {
+        let answer = match resolve(&conf, name, qtype) {
+            Ok(answer) => answer,
+            // Some addresses are already found.
+            Err(_) if info.is_some() => continue,
+            Err(AxError::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        let addrs = answer.records.iter().filter_map(|data| match *data {
+            RecordData::A(addr) => Some(IpAddr::V4(addr)),
+            RecordData::Aaaa(addr) => Some(IpAddr::V6(addr)),
+            _ => None,
+        });
+        match &mut info {
+            Some(info) => info.addrs.extend(addrs),
+            None => {
+                info = Some(HostInfo {
+                    canonical_name: answer.canonical_name,
+                    addrs: addrs.collect(),
+                })
+            }
+        }
+    }
+    info.ok_or(AxError::NotFound)
+}
+
+/// Looks up the host name of an address (reverse lookup).
+///
+/// Returns [`Err(NotFound)`](AxError::NotFound) if the address has no name,
+/// or [`Err(WouldBlock)`](AxError::WouldBlock) if no nameserver responds in
+/// time.
+pub fn lookup_addr(addr: IpAddr) -> AxResult<String> {
+    if let Some(entry) = conf::load_hosts().iter().find(|e| e.addr == addr) {
+        return Ok(entry.canonical_name().into());
+    }
+
+    let name = reverse_name(addr);
+    let answer = query_chased(&ResolvConf::load(), &name, RecordType::Ptr)?;
+    answer
+        .records
+        .into_iter()
+        .find_map(|data| match data {
+            RecordData::Ptr(name) => Some(name),
+            _ => None,
+        })
+        .ok_or(AxError::NotFound)
+}
+
+/// Current time used for timeouts and TTLs.
+fn now() -> Duration {
+    ruxhal::time::current_time()
+}
+
+fn lookup_hosts_file(name: &str, family: AddrFamily) -> Option<HostInfo> {
+    let hosts = conf::load_hosts();
+    let matched = |want_v4: bool| {
+        hosts
+            .iter()
+            .filter(move |e| e.addr.is_ipv4() == want_v4 && e.matches(name))
+    };
+    let entries: Vec<&HostEntry> = match family {
+        AddrFamily::Ipv4 => matched(true).collect(),
+        AddrFamily::Ipv6 => matched(false).collect(),
+        AddrFamily::Any => matched(true).chain(matched(false)).collect(),
+    };
+    let first = entries.first()?;
+    Some(HostInfo {
+        canonical_name: first.canonical_name().into(),
+        addrs: entries.iter().map(|e| e.addr).collect(),
+    })
+}
+
+/// Resolves `name` with the search domains applied, see `resolv.conf(5)`.
+fn resolve(conf: &ResolvConf, name: &str, qtype: RecordType) -> AxResult<Answer> {
+    let mut candidates = Vec::new();
+    let ndots = name.matches('.').count();
+    if ndots >= conf.ndots {
+        candidates.push(String::from(name));
+    }
+    candidates.extend(
+        conf.search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain)),
+    );
+    if ndots < conf.ndots {
+        candidates.push(String::from(name));
+    }
+
+    for candidate in candidates {
+        match query_chased(conf, &candidate, qtype) {
+            Err(AxError::NotFound) => continue,
+            res => return res,
+        }
+    }
+    Err(AxError::NotFound)
+}
+
+/// Queries the records of `name`, following the CNAME records, with the
+/// answers cached.
+fn query_chased(conf: &ResolvConf, name: &str, qtype: RecordType) -> AxResult<Answer> {
+    if let Some(cached) = cache::get(name, qtype) {
+        return cached.ok_or(AxError::NotFound);
+    }
+
+    let mut target = String::from(name);
+    let mut ttl = u32::MAX;
+    // The aliases followed so far, in all the responses.
+    let mut hops = 0;
+    loop {
+        let response = query_nameservers(conf, &target, qtype)?;
+        if response.rcode == RCODE_NXDOMAIN {
+            if let Some(negative_ttl) = response.negative_ttl {
+                cache::put(name, qtype, None, negative_ttl.min(ttl));
+            }
+            return Err(AxError::NotFound);
+        }
+
+        // The nameserver may have followed the aliases already.
+        let queried_hops = hops;
+        loop {
+            let records: Vec<_> = response
+                .answers
+                .iter()
+                .filter(|r| r.data.record_type() == qtype && r.name.eq_ignore_ascii_case(&target))
+                .collect();
+            if !records.is_empty() {
+                let ttl = records.iter().fold(ttl, |ttl, r| ttl.min(r.ttl));
+                let answer = Answer {
+                    canonical_name: target,
+                    records: records.into_iter().map(|r| r.data.clone()).collect(),
+                };
+                cache::put(name, qtype, Some(answer.clone()), ttl);
+                return Ok(answer);
+            }
+            let alias = response.answers.iter().find_map(|r| match &r.data {
+                RecordData::Cname(alias) if r.name.eq_ignore_ascii_case(&target) => {
+                    Some((alias, r.ttl))
+                }
+                _ => None,
+            });
+            match alias {
+                Some(_) if hops == MAX_CNAME_HOPS => {
+                    return ax_err!(NotFound, "DNS query failed: too many CNAME records");
+                }
+                Some((alias, alias_ttl)) => {
+                    target = alias.clone();
+                    ttl = ttl.min(alias_ttl);
+                    hops += 1;
+                }
+                None => break,
+            }
+        }
+        if hops == queried_hops {
+            // The name exists but has no records of the type.
+            if let Some(negative_ttl) = response.negative_ttl {
+                cache::put(name, qtype, None, negative_ttl.min(ttl));
+            }
+            return Err(AxError::NotFound);
+        }
+    }
+}
+
+/// Sends the query to the nameservers in turn, until one of them answers.
+fn query_nameservers(conf: &ResolvConf, name: &str, qtype: RecordType) -> AxResult<Response> {
+    let id = random_u16();
+    let query = message::build_query(id, name, qtype)?;
+    let mut last_err = AxError::WouldBlock;
+    for _ in 0..conf.attempts {
+        for &server in &conf.nameservers {
+            let server = SocketAddr::new(server, DNS_PORT);
+            let mut res = query_udp(server, &query, id, name, qtype, conf.timeout);
+            if matches!(&res, Ok(response) if response.truncated) {
+                debug!("DNS response from {} truncated, retry over TCP", server);
+                res = query_tcp(server, &query, id, name, qtype, conf.timeout);
+            }
+            match res {
+                Ok(response)
+                    if response.rcode == RCODE_NOERROR || response.rcode == RCODE_NXDOMAIN =>
+                {
+                    return Ok(response)
+                }
+                // SERVFAIL, REFUSED and so on, ask the next nameserver.
+                Ok(response) => {
+                    debug!("DNS query to {} failed: rcode {}", server, response.rcode);
+                    last_err = AxError::ConnectionRefused;
+                }
+                Err(e) => {
+                    debug!("DNS query to {} failed: {:?}", server, e);
+                    if e != AxError::WouldBlock {
+                        last_err = e;
+                    }
+                }
+            }
+        }
+    }
+    Err(last_err)
+}
+
+fn query_udp(
+    server: SocketAddr,
+    query: &[u8],
+    id: u16,
+    name: &str,
+    qtype: RecordType,
+    timeout: Duration,
+) -> AxResult<Response> {
+    let deadline = now() + timeout;
+    let socket = bind_random_port()?;
+    socket.set_nonblocking(true);
+    socket.block_until(Some(deadline), || socket.send_to(query, server))?;
+
+    let mut buf = [0; MAX_UDP_MESSAGE_LEN];
+    socket.block_until(Some(deadline), || {
+        let (len, from) = socket.recv_from(&mut buf)?;
+        if from != server {
+            return Err(AxError::WouldBlock);
+        }
+        // Ignore the forged or stale responses, and keep waiting.
+        message::parse_response(&buf[..len], id, name, qtype).map_err(|_| AxError::WouldBlock)
+    })
+}
+
+fn query_tcp(
+    server: SocketAddr,
+    query: &[u8],
+    id: u16,
+    name: &str,
+    qtype: RecordType,
+    timeout: Duration,
+) -> AxResult<Response> {
+    let deadline = now() + timeout;
+    let socket = TcpSocket::new();
+    socket.set_nonblocking(true);
+    match socket.connect(server) {
+        Ok(()) | Err(AxError::InProgress) => {}
+        Err(e) => return Err(e),
+    }
+    socket.block_until(Some(deadline), || match socket.poll()?.writable {
+        true => Ok(()),
+        false => Err(AxError::WouldBlock),
+    })?;
+    socket.peer_addr()?;
+
+    // Messages over TCP are prefixed with a two-byte length.
+    let mut request = Vec::with_capacity(query.len() + 2);
+    request.extend_from_slice(&(query.len() as u16).to_be_bytes());
+    request.extend_from_slice(query);
+    let mut sent = 0;
+    while sent < request.len() {
+        sent += socket.block_until(Some(deadline), || socket.send(&request[sent..]))?;
+    }
+
+    let mut len = [0; 2];
+    recv_exact(&socket, &mut len, deadline)?;
+    let mut buf = alloc::vec![0; u16::from_be_bytes(len) as usize];
+    recv_exact(&socket, &mut buf, deadline)?;
+    socket.shutdown().ok();
+    message::parse_response(&buf, id, name, qtype)
+}
+
+fn recv_exact(socket: &TcpSocket, buf: &mut [u8], deadline: Duration) -> AxResult {
+    let mut read = 0;
+    while read < buf.len() {
+        match socket.block_until(Some(deadline), || socket.recv(&mut buf[read..], 0))? {
+            0 => return ax_err!(UnexpectedEof, "DNS query failed: connection closed"),
+            n => read += n,
+        }
+    }
+    Ok(())
+}
+
+/// Binds a UDP socket to a random source port in the dynamic range, which
+/// together with the random query ID makes the responses hard to forge.
+fn bind_random_port() -> AxResult<UdpSocket> {
+    let socket = UdpSocket::new();
+    let num_ports = (u16::MAX - SOURCE_PORT_START) as u32 + 1;
+    let port = (0..SOURCE_PORT_ATTEMPTS)
+        .map(|_| SOURCE_PORT_START + (random_u16() as u32 % num_ports) as u16)
+        .find(|&port| !UdpSocket::is_port_bound(port))
+        .unwrap_or(0);
+    socket.bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port))?;
+    Ok(socket)
+}
+
+/// Returns a random number for the query IDs and the source ports.
+///
+/// The random number generator of the CPU is used if there is one, otherwise
+/// the timer is mixed into a SplitMix64 sequence, which is only hard to guess
+/// off-path.
+fn random_u16() -> u16 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    if let Some(val) = ruxhal::arch::hw_random() {
+        return val as u16;
+    }
+    let mut z = STATE
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(current_time_nanos());
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) as u16
+}
+
+/// Returns the name in `in-addr.arpa` or `ip6.arpa` for a reverse lookup.
+fn reverse_name(addr: IpAddr) -> String {
+    let mut name = String::new();
+    match addr {
+        IpAddr::V4(addr) => {
+            for octet in addr.octets().iter().rev() {
+                write!(name, "{}.", octet).unwrap();
+            }
+            name.push_str("in-addr.arpa");
+        }
+        IpAddr::V6(addr) => {
+            for octet in addr.octets().iter().rev() {
+                write!(name, "{:x}.{:x}.", octet & 0xf, octet >> 4).unwrap();
+            }
+            name.push_str("ip6.arpa");
+        }
+    }
+    name
+}
@@ -17,16 +17,32 @@
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
-//! - [`dns_query`]: Function for DNS query.
+//! - [`RawSocket`]: A raw IPv4 or IPv6 socket that provides POSIX-like APIs.
+//! - [`IcmpSocket`]: An ICMP or ICMPv6 "ping" socket that provides POSIX-like
+//!   APIs.
+//! - [`PacketSocket`]: A link layer (`AF_PACKET`) socket that provides
+//!   POSIX-like APIs.
+//! - [`start_capture`]: Function to capture the traffic in the pcap format.
+//! - [`dns_query`], [`lookup_host`], [`lookup_addr`]: Functions for DNS query,
+//!   which consult `/etc/hosts` and `/etc/resolv.conf`.
 //!
 //! # Cargo Features
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `irq`, `multitask`: When both are enabled, a worker task polls the
+//!   interface on NIC interrupts or smoltcp timeouts, and blocked sockets
+//!   sleep until they become ready, instead of busy polling. The VirtIO and
+//!   ixgbe NICs raise interrupts on `aarch64-qemu-virt` and
+//!   `riscv64-qemu-virt`, ixgbe over the PCI INTx line and VirtIO on both the
+//!   MMIO and PCI buses. The NICs without a routed IRQ, such as the PCI NICs
+//!   on `x86_64-pc`, are polled every millisecond instead.
+//! - `fs`: Read the resolver configuration from `/etc/resolv.conf` and
+//!   `/etc/hosts`. Otherwise, the nameserver `8.8.8.8` is used.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(ip_in_core)]
 #![feature(new_uninit)]
 
@@ -34,6 +50,8 @@
 extern crate log;
 extern crate alloc;
 
+mod dns;
+
 cfg_if::cfg_if! {
     if #[cfg(feature = "smoltcp")] {
         mod smoltcp_impl;
@@ -41,10 +59,16 @@ cfg_if::cfg_if! {
     }
 }
 
+pub use self::dns::{dns_query, lookup_addr, lookup_host, AddrFamily, HostInfo};
+pub use self::net_impl::poll_interfaces;
 pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
 pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{
+    start_capture, stop_capture, PacketAddr, PacketKind, PacketSocket, PacketType, ETH0_IFINDEX,
+    ETH_P_ALL,
+};
+pub use self::net_impl::{IcmpSocket, RawSocket};
 
 use ruxdriver::{prelude::*, AxDeviceContainer};
 
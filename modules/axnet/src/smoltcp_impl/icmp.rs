@@ -0,0 +1,300 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, AxError, AxResult};
+use axio::PollState;
+use axsync::Mutex;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::icmp::{self, BindError, Endpoint, SendError};
+use smoltcp::wire::{Icmpv4Message, Icmpv6Message, IpAddress, IpVersion};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::waiter::{self, SocketWaiter};
+use super::{SocketSetWrapper, SOCKET_SET};
+use alloc::sync::Arc;
+
+/// Length of the ICMP echo header (type, code, checksum, identifier, sequence).
+const ECHO_HEADER_LEN: usize = 8;
+
+/// An ICMP or ICMPv6 "ping" socket that provides POSIX-like APIs.
+///
+/// Like unprivileged ping sockets on Linux, it only sends echo requests and
+/// receives the echo replies (and related errors) whose identifier matches
+/// the bound one. The identifier plays the role of the port in addresses.
+pub struct IcmpSocket {
+    handle: SocketHandle,
+    version: IpVersion,
+    ident: RwLock<Option<u16>>,
+    peer_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+}
+
+impl IcmpSocket {
+    /// Creates a new ICMP socket.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_version(IpVersion::Ipv4)
+    }
+
+    /// Creates a new ICMPv6 socket.
+    pub fn new_v6() -> Self {
+        Self::with_version(IpVersion::Ipv6)
+    }
+
+    fn with_version(version: IpVersion) -> Self {
+        let socket = SocketSetWrapper::new_icmp_socket();
+        let handle = SOCKET_SET.add(socket);
+        Self {
+            handle,
+            version,
+            ident: RwLock::new(None),
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the local address with the identifier as the port, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not bound.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        match *self.ident.read() {
+            Some(ident) => Ok(SocketAddr::new(self.unspecified_addr(), ident)),
+            None => Err(AxError::NotConnected),
+        }
+    }
+
+    /// Returns the remote address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<SocketAddr> {
+        match *self.peer_addr.read() {
+            Some(addr) => Ok(SocketAddr::new(into_core_ipaddr(addr), 0)),
+            None => Err(AxError::NotConnected),
+        }
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this ICMP socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds the socket to an echo identifier, which is given as the port of
+    /// `local_addr`. An identifier is allocated if the port is 0.
+    pub fn bind(&self, local_addr: SocketAddr) -> AxResult {
+        let mut self_ident = self.ident.write();
+        if self_ident.is_some() {
+            return ax_err!(InvalidInput, "socket bind() failed: already bound");
+        }
+
+        let ident = match local_addr.port() {
+            0 => get_ephemeral_ident(),
+            ident => ident,
+        };
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            socket.bind(Endpoint::Ident(ident)).or_else(|e| match e {
+                BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
+                BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
+            })
+        })?;
+
+        *self_ident = Some(ident);
+        debug!("ICMP socket {}: bound on ident {}", self.handle, ident);
+        Ok(())
+    }
+
+    /// Connects the socket to a remote address, so that [`send`](Self::send)
+    /// can be used and only replies from that address are received.
+    pub fn connect(&self, addr: SocketAddr) -> AxResult {
+        let peer_addr = self.check_family(addr)?;
+        if self.ident.read().is_none() {
+            self.bind(SocketAddr::new(self.unspecified_addr(), 0))?;
+        }
+        *self.peer_addr.write() = Some(peer_addr);
+        debug!("ICMP socket {}: connected to {}", self.handle, addr.ip());
+        Ok(())
+    }
+
+    /// Sends an echo request to the given address. On success, returns the
+    /// number of bytes written.
+    ///
+    /// `buf` should contain the ICMP header and the payload. The identifier in
+    /// the header is replaced by the bound one, and the checksum is computed.
+    pub fn send_to(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
+        if remote_addr.ip().is_unspecified() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        let remote_ip = self.check_family(remote_addr)?;
+        self.send_impl(buf, remote_ip)
+    }
+
+    /// Sends an echo request to the remote address to which it is connected.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let remote_addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives an ICMP message without its IP header. On success, returns the
+    /// number of bytes read and the origin.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        if self.ident.read().is_none() {
+            return ax_err!(NotConnected, "socket recv() failed");
+        }
+        let peer_addr = *self.peer_addr.read();
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| loop {
+                let (packet, src_addr) = match socket.recv() {
+                    Ok(res) => res,
+                    // no more data
+                    Err(_) => return Err(AxError::WouldBlock),
+                };
+                if peer_addr.is_some_and(|addr| addr != src_addr) {
+                    continue;
+                }
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                return Ok((len, SocketAddr::new(into_core_ipaddr(src_addr), 0)));
+            })
+        })
+    }
+
+    /// Receives an ICMP message from the remote address to which it is
+    /// connected.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv_from(buf).map(|(len, _)| len)
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        SOCKET_SET.poll_interfaces();
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        if self.ident.read().is_none() {
+            return Ok(PollState {
+                readable: false,
+                writable: true,
+            });
+        }
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            Ok(PollState {
+                readable: socket.can_recv(),
+                writable: socket.can_send(),
+            })
+        })
+    }
+}
+
+/// Private methods
+impl IcmpSocket {
+    fn send_impl(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<usize> {
+        let is_echo_request = buf.len() >= ECHO_HEADER_LEN
+            && buf[1] == 0
+            && match self.version {
+                IpVersion::Ipv4 => Icmpv4Message::from(buf[0]) == Icmpv4Message::EchoRequest,
+                IpVersion::Ipv6 => Icmpv6Message::from(buf[0]) == Icmpv6Message::EchoRequest,
+            };
+        if !is_echo_request {
+            return ax_err!(InvalidInput, "socket send() failed: not an echo request");
+        }
+        if self.ident.read().is_none() {
+            self.bind(SocketAddr::new(self.unspecified_addr(), 0))?;
+        }
+        let ident = self.ident.read().unwrap();
+
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+                if buf.len() > socket.payload_send_capacity() {
+                    return ax_err!(InvalidInput, "socket send() failed: message too long");
+                }
+                match socket.send(buf.len(), remote_addr) {
+                    Ok(packet) => {
+                        packet.copy_from_slice(buf);
+                        // The checksum is filled by smoltcp when emitting.
+                        packet[4..6].copy_from_slice(&ident.to_be_bytes());
+                    }
+                    // tx buffer is full
+                    Err(SendError::BufferFull) => return Err(AxError::WouldBlock),
+                    Err(SendError::Unaddressable) => {
+                        return ax_err!(ConnectionRefused, "socket send() failed")
+                    }
+                }
+                waiter::wake_worker();
+                Ok(buf.len())
+            })
+        })
+    }
+
+    fn unspecified_addr(&self) -> IpAddr {
+        match self.version {
+            IpVersion::Ipv4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpVersion::Ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+
+    /// Checks that the address is of the family of this socket.
+    fn check_family(&self, addr: SocketAddr) -> AxResult<IpAddress> {
+        let ip = from_core_ipaddr(addr.ip());
+        if ip.version() != self.version {
+            return ax_err!(InvalidInput, "socket address family mismatch");
+        }
+        Ok(ip)
+    }
+
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            waiter::block_on(|| self.register_waiter(), None, f)
+        }
+    }
+
+    /// Registers the waiter of this socket to smoltcp, so that it is woken up
+    /// when the socket becomes ready.
+    fn register_waiter(&self) -> Arc<SocketWaiter> {
+        let waiter = SocketWaiter::of(self.handle);
+        let waker = waiter.waker();
+        SOCKET_SET.with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+        waiter
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        self.shutdown().ok();
+        SOCKET_SET.remove(self.handle);
+    }
+}
+
+fn get_ephemeral_ident() -> u16 {
+    const IDENT_START: u16 = 0x15b3;
+    static CURR: Mutex<u16> = Mutex::new(IDENT_START);
+    let mut curr = CURR.lock();
+
+    let ident = *curr;
+    *curr = curr.wrapping_add(1).max(IDENT_START);
+    ident
+}
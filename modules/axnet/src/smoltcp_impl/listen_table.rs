@@ -16,13 +16,17 @@ use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
+use super::waiter::SocketWaiter;
 use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
+use alloc::sync::Arc;
 
 const PORT_NUM: usize = 65536;
 
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
     syn_queue: VecDeque<SocketHandle>,
+    /// Woken up when any socket in the SYN queue changes its state.
+    waiter: Arc<SocketWaiter>,
 }
 
 impl ListenTableEntry {
@@ -30,6 +34,7 @@ impl ListenTableEntry {
         Self {
             listen_endpoint,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            waiter: SocketWaiter::new(),
         }
     }
 
@@ -119,6 +124,25 @@ impl ListenTable {
         }
     }
 
+    /// Registers the waiter of the listening port to all sockets in its SYN
+    /// queue, and returns it.
+    pub fn register_waiter(&self, port: u16) -> Arc<SocketWaiter> {
+        // Do not hold the entry lock while locking `SOCKET_SET`, the polling
+        // path takes them in the reverse order.
+        let (waiter, syn_queue) = match self.tcp[port as usize].lock().deref() {
+            Some(entry) => (entry.waiter.clone(), entry.syn_queue.clone()),
+            // Not listening, `accept()` will fail without waiting.
+            None => return SocketWaiter::new(),
+        };
+        let waker = waiter.waker();
+        for handle in syn_queue {
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                socket.register_recv_waker(&waker)
+            });
+        }
+        waiter
+    }
+
     pub fn incoming_tcp_packet(
         &self,
         src: IpEndpoint,
@@ -137,6 +161,8 @@ impl ListenTable {
             }
             let mut socket = SocketSetWrapper::new_tcp_socket();
             if socket.listen(entry.listen_endpoint).is_ok() {
+                // Wake up the acceptor once the handshake completes.
+                socket.register_recv_waker(&entry.waiter.waker());
                 let handle = sockets.add(socket);
                 debug!(
                     "TCP socket {}: prepare for connection {} -> {}",
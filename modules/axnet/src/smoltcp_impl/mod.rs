@@ -9,15 +9,22 @@
 
 mod addr;
 mod bench;
-mod dns;
+mod icmp;
 mod listen_table;
+mod packet;
+mod raw;
 mod tcp;
 mod udp;
+mod waiter;
+#[cfg(all(feature = "irq", feature = "multitask"))]
+mod worker;
 
 use alloc::vec;
 use core::cell::RefCell;
 use core::ops::DerefMut;
 
+use axalloc::MemTag;
+use axerrno::{AxError, AxResult};
 use axsync::Mutex;
 use driver_net::{DevError, NetBufPtr};
 use lazy_init::LazyInit;
@@ -27,11 +34,19 @@ use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Address,
+    Ipv6Address,
+};
 
 use self::listen_table::ListenTable;
 
-pub use self::dns::dns_query;
+pub use self::icmp::IcmpSocket;
+pub use self::packet::{
+    start_capture, stop_capture, PacketAddr, PacketKind, PacketSocket, PacketType, ETH0_IFINDEX,
+    ETH_P_ALL,
+};
+pub use self::raw::RawSocket;
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
 
@@ -46,8 +61,8 @@ macro_rules! env_or_default {
 
 const IP: &str = env_or_default!("RUX_IP");
 const GATEWAY: &str = env_or_default!("RUX_GW");
-const DNS_SEVER: &str = "8.8.8.8";
 const IP_PREFIX: u8 = 24;
+const IPV6_LINK_LOCAL_PREFIX: u8 = 64;
 
 const STANDARD_MTU: usize = 1500;
 
@@ -57,6 +72,10 @@ const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+const RAW_RX_BUF_LEN: usize = 64 * 1024;
+const RAW_TX_BUF_LEN: usize = 64 * 1024;
+const ICMP_RX_BUF_LEN: usize = 16 * 1024;
+const ICMP_TX_BUF_LEN: usize = 16 * 1024;
 const LISTEN_QUEUE_SIZE: usize = 512;
 
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
@@ -82,12 +101,14 @@ impl<'a> SocketSetWrapper<'a> {
     }
 
     pub fn new_tcp_socket() -> socket::tcp::Socket<'a> {
+        axalloc::charge(MemTag::NetBuffer, TCP_RX_BUF_LEN + TCP_TX_BUF_LEN);
         let tcp_rx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_RX_BUF_LEN]);
         let tcp_tx_buffer = socket::tcp::SocketBuffer::new(vec![0; TCP_TX_BUF_LEN]);
         socket::tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
     }
 
     pub fn new_udp_socket() -> socket::udp::Socket<'a> {
+        axalloc::charge(MemTag::NetBuffer, UDP_RX_BUF_LEN + UDP_TX_BUF_LEN);
         let udp_rx_buffer = socket::udp::PacketBuffer::new(
             vec![socket::udp::PacketMetadata::EMPTY; 8],
             vec![0; UDP_RX_BUF_LEN],
@@ -99,9 +120,35 @@ impl<'a> SocketSetWrapper<'a> {
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
 
-    pub fn new_dns_socket() -> socket::dns::Socket<'a> {
-        let server_addr = DNS_SEVER.parse().expect("invalid DNS server address");
-        socket::dns::Socket::new(&[server_addr], vec![])
+    pub fn new_raw_socket(version: IpVersion, protocol: u8) -> socket::raw::Socket<'a> {
+        axalloc::charge(MemTag::NetBuffer, RAW_RX_BUF_LEN + RAW_TX_BUF_LEN);
+        let raw_rx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 8],
+            vec![0; RAW_RX_BUF_LEN],
+        );
+        let raw_tx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 8],
+            vec![0; RAW_TX_BUF_LEN],
+        );
+        socket::raw::Socket::new(
+            version,
+            IpProtocol::from(protocol),
+            raw_rx_buffer,
+            raw_tx_buffer,
+        )
+    }
+
+    pub fn new_icmp_socket() -> socket::icmp::Socket<'a> {
+        axalloc::charge(MemTag::NetBuffer, ICMP_RX_BUF_LEN + ICMP_TX_BUF_LEN);
+        let icmp_rx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_RX_BUF_LEN],
+        );
+        let icmp_tx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_TX_BUF_LEN],
+        );
+        socket::icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
     }
 
     pub fn add<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
@@ -130,14 +177,31 @@ impl<'a> SocketSetWrapper<'a> {
 
     pub fn poll_interfaces(&self) {
         ETH0.poll(&self.0);
+        #[cfg(not(feature = "multitask"))]
+        packet::flush_capture();
     }
 
     pub fn remove(&self, handle: SocketHandle) {
-        self.0.lock().remove(handle);
+        let socket = self.0.lock().remove(handle);
+        axalloc::uncharge(MemTag::NetBuffer, buffer_len(&socket));
+        waiter::SocketWaiter::remove(handle);
         debug!("socket {}: destroyed", handle);
     }
 }
 
+/// Size of the buffers of a socket created by `SocketSetWrapper::new_*_socket`,
+/// which are charged to [`MemTag::NetBuffer`].
+fn buffer_len(socket: &socket::Socket) -> usize {
+    match socket {
+        socket::Socket::Tcp(_) => TCP_RX_BUF_LEN + TCP_TX_BUF_LEN,
+        socket::Socket::Udp(_) => UDP_RX_BUF_LEN + UDP_TX_BUF_LEN,
+        socket::Socket::Raw(_) => RAW_RX_BUF_LEN + RAW_TX_BUF_LEN,
+        socket::Socket::Icmp(_) => ICMP_RX_BUF_LEN + ICMP_TX_BUF_LEN,
+        #[allow(unreachable_patterns)]
+        _ => 0,
+    }
+}
+
 impl InterfaceWrapper {
     fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
         let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
@@ -165,6 +229,39 @@ impl InterfaceWrapper {
         self.ether_addr
     }
 
+    /// Returns the first IPv4 address of the interface.
+    pub fn ipv4_address(&self) -> Option<Ipv4Address> {
+        self.iface.lock().ipv4_addr()
+    }
+
+    /// Returns the first IPv6 address of the interface.
+    pub fn ipv6_address(&self) -> Option<Ipv6Address> {
+        self.iface
+            .lock()
+            .ip_addrs()
+            .iter()
+            .find_map(|cidr| match cidr.address() {
+                IpAddress::Ipv6(addr) => Some(addr),
+                _ => None,
+            })
+    }
+
+    /// Returns the link-local IPv6 address derived from the MAC address
+    /// (modified EUI-64).
+    fn link_local_ipv6_address(&self) -> Ipv6Address {
+        let mac = self.ether_addr.0;
+        Ipv6Address::new(
+            0xfe80,
+            0,
+            0,
+            0,
+            u16::from_be_bytes([mac[0] ^ 0x02, mac[1]]),
+            u16::from_be_bytes([mac[2], 0xff]),
+            u16::from_be_bytes([0xfe, mac[3]]),
+            u16::from_be_bytes([mac[4], mac[5]]),
+        )
+    }
+
     pub fn setup_ip_addr(&self, ip: IpAddress, prefix_len: u8) {
         let mut iface = self.iface.lock();
         iface.update_ip_addrs(|ip_addrs| {
@@ -176,6 +273,7 @@ impl InterfaceWrapper {
         let mut iface = self.iface.lock();
         match gateway {
             IpAddress::Ipv4(v4) => iface.routes_mut().add_default_ipv4_route(v4).unwrap(),
+            IpAddress::Ipv6(v6) => iface.routes_mut().add_default_ipv6_route(v6).unwrap(),
         };
     }
 
@@ -186,6 +284,28 @@ impl InterfaceWrapper {
         let timestamp = Self::current_time();
         iface.poll(timestamp, dev.deref_mut(), &mut sockets);
     }
+
+    /// Transmits a frame of `len` bytes filled by `f`, bypassing the network
+    /// stack.
+    pub fn transmit_frame<F>(&self, len: usize, f: F) -> AxResult
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let dev = self.dev.lock();
+        let mut dev = dev.inner.borrow_mut();
+        if let Err(e) = dev.recycle_tx_buffers() {
+            warn!("recycle_tx_buffers failed: {:?}", e);
+            return Err(AxError::Io);
+        }
+        if !dev.can_transmit() {
+            return Err(AxError::WouldBlock);
+        }
+        let mut tx_buf = dev.alloc_tx_buffer(len).map_err(|_| AxError::NoMemory)?;
+        f(tx_buf.packet_mut());
+        trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+        packet::tap_frame(tx_buf.packet(), true);
+        dev.transmit(tx_buf).map_err(|_| AxError::Io)
+    }
 }
 
 impl DeviceWrapper {
@@ -262,6 +382,7 @@ impl<'a> RxToken for AxNetRxToken<'a> {
             rx_buf.packet_len(),
             rx_buf.packet()
         );
+        packet::tap_frame(rx_buf.packet(), false);
         let result = f(rx_buf.packet_mut());
         self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
         result
@@ -277,6 +398,7 @@ impl<'a> TxToken for AxNetTxToken<'a> {
         let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+        packet::tap_frame(tx_buf.packet(), true);
         dev.transmit(tx_buf).unwrap();
         ret
     }
@@ -327,6 +449,8 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     let gateway = GATEWAY.parse().expect("invalid gateway IP address");
     eth0.setup_ip_addr(ip, IP_PREFIX);
     eth0.setup_gateway(gateway);
+    let ipv6 = eth0.link_local_ipv6_address();
+    eth0.setup_ip_addr(IpAddress::Ipv6(ipv6), IPV6_LINK_LOCAL_PREFIX);
 
     ETH0.init_by(eth0);
     SOCKET_SET.init_by(SocketSetWrapper::new());
@@ -336,4 +460,8 @@ pub(crate) fn init(net_dev: AxNetDevice) {
     info!("  ether:    {}", ETH0.ethernet_address());
     info!("  ip:       {}/{}", ip, IP_PREFIX);
     info!("  gateway:  {}", gateway);
+    info!("  ipv6:     {}/{}", ipv6, IPV6_LINK_LOCAL_PREFIX);
+
+    #[cfg(all(feature = "irq", feature = "multitask"))]
+    worker::init();
 }
@@ -0,0 +1,444 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Link layer (`AF_PACKET`) sockets and traffic capture.
+//!
+//! Every frame received from or transmitted to the NIC is copied to the
+//! packet sockets that are interested in its protocol, and to the capture
+//! queue if capturing is started. The captured frames are written to the
+//! sink later, without holding the NIC or socket locks: by a writer task with
+//! the `multitask` feature, or after polling the interface otherwise.
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use axerrno::{ax_err, AxError, AxResult};
+use axio::{PollState, Write};
+use axsync::Mutex;
+use ruxhal::time::{current_time_nanos, NANOS_PER_MICROS, NANOS_PER_SEC};
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol};
+
+use super::waiter::{self, SocketWaiter};
+use super::{ETH0, SOCKET_SET};
+use alloc::task::Wake;
+
+/// The protocol that matches frames of all protocols, including outgoing
+/// ones (`ETH_P_ALL`).
+pub const ETH_P_ALL: u16 = 0x0003;
+
+/// Index of the only interface, `eth0`.
+pub const ETH0_IFINDEX: i32 = 1;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const MAX_FRAME_LEN: usize = 1514;
+
+/// Number of frames a packet socket queues before dropping new ones.
+const PACKET_QUEUE_LEN: usize = 256;
+/// Number of captured frames queued before dropping new ones.
+const CAPTURE_QUEUE_LEN: usize = 1024;
+#[cfg(feature = "multitask")]
+const CAPTURE_WRITER_STACK_SIZE: usize = 0x4000;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_SNAPLEN: u32 = 65535;
+const PCAP_LINKTYPE_ETHERNET: u32 = 1;
+
+static PACKET_QUEUES: Mutex<Vec<Arc<PacketQueue>>> = Mutex::new(Vec::new());
+/// The capture sink, which is locked only to write the records.
+static CAPTURE: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+/// The pcap records of the captured frames, waiting to be written.
+static CAPTURE_QUEUE: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+static TAPPED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "multitask")]
+static CAPTURE_WQ: ruxtask::WaitQueue = ruxtask::WaitQueue::new();
+
+/// The type of a packet socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    /// Frames are sent and received with their link layer header
+    /// (`SOCK_RAW`).
+    Raw,
+    /// The link layer header is removed from received frames and built for
+    /// sent ones (`SOCK_DGRAM`).
+    Dgram,
+}
+
+/// The direction a frame goes, i.e., the `sll_pkttype` of `sockaddr_ll`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PacketType {
+    /// To us (`PACKET_HOST`).
+    Host = 0,
+    /// To all (`PACKET_BROADCAST`).
+    Broadcast = 1,
+    /// To a group (`PACKET_MULTICAST`).
+    Multicast = 2,
+    /// To someone else (`PACKET_OTHERHOST`).
+    OtherHost = 3,
+    /// Sent by us (`PACKET_OUTGOING`).
+    Outgoing = 4,
+}
+
+/// A link layer address, as `sockaddr_ll` in Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketAddr {
+    /// The ethernet protocol of the frame, in host byte order.
+    pub protocol: u16,
+    /// The interface index, 0 for any interface.
+    pub ifindex: i32,
+    /// The direction of the frame.
+    pub pkttype: PacketType,
+    /// The source address of received frames, or the destination address of
+    /// sent frames.
+    pub addr: [u8; 6],
+}
+
+struct PacketQueue {
+    /// Ethernet protocol to receive, in host byte order, 0 for none.
+    protocol: AtomicU16,
+    frames: Mutex<VecDeque<(Vec<u8>, PacketAddr)>>,
+    waiter: Arc<SocketWaiter>,
+}
+
+impl PacketQueue {
+    fn matches(&self, protocol: u16, pkttype: PacketType) -> bool {
+        match self.protocol.load(Ordering::Acquire) {
+            0 => false,
+            ETH_P_ALL => true,
+            p => p == protocol && pkttype != PacketType::Outgoing,
+        }
+    }
+
+    fn push(&self, frame: &[u8], addr: PacketAddr) {
+        let mut frames = self.frames.lock();
+        if frames.len() >= PACKET_QUEUE_LEN {
+            return;
+        }
+        frames.push_back((frame.to_vec(), addr));
+        drop(frames);
+        self.waiter.wake_by_ref();
+    }
+}
+
+/// A link layer (`AF_PACKET`) socket that provides POSIX-like APIs.
+///
+/// It receives copies of frames on the interface, and injects frames into it
+/// bypassing the network stack.
+pub struct PacketSocket {
+    kind: PacketKind,
+    queue: Arc<PacketQueue>,
+    nonblock: AtomicBool,
+}
+
+impl PacketSocket {
+    /// Creates a new packet socket receiving frames of the given ethernet
+    /// protocol, in host byte order. No frames are received if it's 0 until
+    /// the socket is bound to a protocol.
+    pub fn new(kind: PacketKind, protocol: u16) -> Self {
+        let queue = Arc::new(PacketQueue {
+            protocol: AtomicU16::new(protocol),
+            frames: Mutex::new(VecDeque::new()),
+            waiter: SocketWaiter::new(),
+        });
+        let mut queues = PACKET_QUEUES.lock();
+        queues.push(queue.clone());
+        TAPPED.store(true, Ordering::Release);
+        Self {
+            kind,
+            queue,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the type of this socket.
+    pub fn kind(&self) -> PacketKind {
+        self.kind
+    }
+
+    /// Returns the bound address.
+    pub fn local_addr(&self) -> AxResult<PacketAddr> {
+        Ok(PacketAddr {
+            protocol: self.queue.protocol.load(Ordering::Acquire),
+            ifindex: ETH0_IFINDEX,
+            pkttype: PacketType::Host,
+            addr: ETH0.ethernet_address().0,
+        })
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this packet socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds the socket to the protocol and the interface in `addr`.
+    ///
+    /// The protocol is kept if `addr.protocol` is 0.
+    pub fn bind(&self, addr: PacketAddr) -> AxResult {
+        if addr.ifindex != 0 && addr.ifindex != ETH0_IFINDEX {
+            return ax_err!(NotFound, "socket bind() failed: no such device");
+        }
+        if addr.protocol != 0 {
+            self.queue.protocol.store(addr.protocol, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Sends a frame. On success, returns the number of bytes written.
+    ///
+    /// For [`PacketKind::Dgram`] sockets, `addr` gives the destination and
+    /// the protocol of the frame, otherwise `buf` contains the whole frame.
+    pub fn send_to(&self, buf: &[u8], addr: Option<PacketAddr>) -> AxResult<usize> {
+        match self.kind {
+            PacketKind::Raw => {
+                if buf.len() < ETHERNET_HEADER_LEN {
+                    return ax_err!(InvalidInput, "socket send() failed: frame too short");
+                }
+                self.transmit(buf.len(), |frame| frame.copy_from_slice(buf))?;
+            }
+            PacketKind::Dgram => {
+                let Some(addr) = addr else {
+                    return ax_err!(NotConnected, "socket send() failed: no destination");
+                };
+                let protocol = match addr.protocol {
+                    0 => self.queue.protocol.load(Ordering::Acquire),
+                    protocol => protocol,
+                };
+                self.transmit(ETHERNET_HEADER_LEN + buf.len(), |frame| {
+                    let mut frame = EthernetFrame::new_unchecked(frame);
+                    frame.set_dst_addr(EthernetAddress(addr.addr));
+                    frame.set_src_addr(ETH0.ethernet_address());
+                    frame.set_ethertype(EthernetProtocol::from(protocol));
+                    frame.payload_mut().copy_from_slice(buf);
+                })?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Receives a frame. On success, returns the number of bytes read and
+    /// the address of the frame.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, PacketAddr)> {
+        self.block_on(|| {
+            let (frame, addr) = self
+                .queue
+                .frames
+                .lock()
+                .pop_front()
+                .ok_or(AxError::WouldBlock)?;
+            let data = match self.kind {
+                PacketKind::Raw => &frame[..],
+                PacketKind::Dgram => &frame[ETHERNET_HEADER_LEN..],
+            };
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok((len, addr))
+        })
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        SOCKET_SET.poll_interfaces();
+        Ok(PollState {
+            readable: !self.queue.frames.lock().is_empty(),
+            writable: true,
+        })
+    }
+
+    fn transmit<F>(&self, len: usize, f: F) -> AxResult
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        if len > MAX_FRAME_LEN {
+            return ax_err!(InvalidInput, "socket send() failed: frame too long");
+        }
+        let mut f = Some(f);
+        self.block_on(|| ETH0.transmit_frame(len, |frame| (f.take().unwrap())(frame)))
+    }
+
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            waiter::block_on(|| self.queue.waiter.clone(), None, f)
+        }
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        let mut queues = PACKET_QUEUES.lock();
+        queues.retain(|queue| !Arc::ptr_eq(queue, &self.queue));
+        if queues.is_empty() && !CAPTURING.load(Ordering::Acquire) {
+            TAPPED.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// Starts writing all traffic of the interface to `sink` in the pcap format.
+///
+/// Frames are queued and written asynchronously. If the sink is slower than
+/// the traffic, the frames that do not fit in the queue are dropped.
+pub fn start_capture(mut sink: Box<dyn Write + Send>) -> AxResult {
+    let mut capture = CAPTURE.lock();
+    if capture.is_some() {
+        return ax_err!(ResourceBusy, "capture already started");
+    }
+    CAPTURE_QUEUE.lock().clear();
+
+    let mut header = [0u8; 24];
+    header[0..4].copy_from_slice(&PCAP_MAGIC.to_le_bytes());
+    header[4..6].copy_from_slice(&2u16.to_le_bytes()); // version 2.4
+    header[6..8].copy_from_slice(&4u16.to_le_bytes());
+    header[16..20].copy_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+    header[20..24].copy_from_slice(&PCAP_LINKTYPE_ETHERNET.to_le_bytes());
+    sink.write_all(&header)?;
+    *capture = Some(sink);
+    CAPTURING.store(true, Ordering::Release);
+    TAPPED.store(true, Ordering::Release);
+    drop(capture);
+
+    #[cfg(feature = "multitask")]
+    ruxtask::spawn_raw(
+        capture_writer,
+        "pcap-writer".into(),
+        CAPTURE_WRITER_STACK_SIZE,
+    );
+    Ok(())
+}
+
+/// Stops the capture started by [`start_capture`], writes the queued frames
+/// and flushes the sink.
+pub fn stop_capture() -> AxResult {
+    if !CAPTURING.swap(false, Ordering::AcqRel) {
+        return ax_err!(BadState, "capture not started");
+    }
+    if PACKET_QUEUES.lock().is_empty() {
+        TAPPED.store(false, Ordering::Release);
+    }
+    #[cfg(feature = "multitask")]
+    CAPTURE_WQ.notify_one(false);
+
+    let mut capture = CAPTURE.lock();
+    write_capture_records(&mut capture);
+    match capture.take() {
+        Some(mut sink) => sink.flush(),
+        None => ax_err!(BadState, "capture not started"),
+    }
+}
+
+/// Writes the queued records to the sink.
+///
+/// The sink is locked by the caller, so that the records are written in
+/// order by a single writer.
+fn write_capture_records(capture: &mut Option<Box<dyn Write + Send>>) {
+    let Some(sink) = capture.as_mut() else {
+        return;
+    };
+    while let Some(record) = CAPTURE_QUEUE.lock().pop_front() {
+        if let Err(e) = sink.write_all(&record) {
+            warn!("failed to capture frame: {:?}", e);
+        }
+    }
+}
+
+/// Writes the captured frames queued by [`tap_frame`], if any. It must be
+/// called without the NIC or socket locks held.
+#[cfg(not(feature = "multitask"))]
+pub(crate) fn flush_capture() {
+    if CAPTURING.load(Ordering::Acquire) {
+        write_capture_records(&mut CAPTURE.lock());
+    }
+}
+
+/// The task that writes the captured frames until the capture is stopped.
+#[cfg(feature = "multitask")]
+fn capture_writer() {
+    loop {
+        CAPTURE_WQ
+            .wait_until(|| !CAPTURING.load(Ordering::Acquire) || !CAPTURE_QUEUE.lock().is_empty());
+        if !CAPTURING.load(Ordering::Acquire) {
+            // the remaining frames are written by `stop_capture`
+            break;
+        }
+        write_capture_records(&mut CAPTURE.lock());
+    }
+}
+
+/// Queues a pcap record of the frame, to be written by the capture writer.
+fn capture_frame(frame: &[u8]) {
+    let mut queue = CAPTURE_QUEUE.lock();
+    if queue.len() >= CAPTURE_QUEUE_LEN {
+        return;
+    }
+    let nanos = current_time_nanos();
+    let len = frame.len().min(PCAP_SNAPLEN as usize);
+    let mut record = Vec::with_capacity(16 + len);
+    record.extend_from_slice(&((nanos / NANOS_PER_SEC) as u32).to_le_bytes());
+    record.extend_from_slice(&((nanos % NANOS_PER_SEC / NANOS_PER_MICROS) as u32).to_le_bytes());
+    record.extend_from_slice(&(len as u32).to_le_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&frame[..len]);
+    queue.push_back(record);
+    drop(queue);
+    #[cfg(feature = "multitask")]
+    CAPTURE_WQ.notify_one(false);
+}
+
+/// Copies a frame on the interface to the packet sockets and the capture.
+pub(crate) fn tap_frame(frame: &[u8], outgoing: bool) {
+    if !TAPPED.load(Ordering::Acquire) {
+        return;
+    }
+
+    if CAPTURING.load(Ordering::Acquire) {
+        capture_frame(frame);
+    }
+
+    let Ok(ether_frame) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let (peer, pkttype) = if outgoing {
+        (ether_frame.dst_addr(), PacketType::Outgoing)
+    } else {
+        let dst = ether_frame.dst_addr();
+        let pkttype = if dst.is_broadcast() {
+            PacketType::Broadcast
+        } else if dst.is_multicast() {
+            PacketType::Multicast
+        } else if dst == ETH0.ethernet_address() {
+            PacketType::Host
+        } else {
+            PacketType::OtherHost
+        };
+        (ether_frame.src_addr(), pkttype)
+    };
+    let protocol = u16::from(ether_frame.ethertype());
+    let addr = PacketAddr {
+        protocol,
+        ifindex: ETH0_IFINDEX,
+        pkttype,
+        addr: peer.0,
+    };
+    for queue in PACKET_QUEUES.lock().iter() {
+        if queue.matches(protocol, pkttype) {
+            queue.push(frame, addr);
+        }
+    }
+}
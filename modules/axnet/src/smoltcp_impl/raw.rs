@@ -0,0 +1,375 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+use alloc::collections::BTreeMap;
+use core::net::SocketAddr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, ax_err_type, AxError, AxResult};
+use axio::PollState;
+use axsync::Mutex;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+use smoltcp::wire::{
+    Icmpv6Packet, IpAddress, IpProtocol, IpVersion, Ipv4Packet, Ipv4Repr, Ipv6Packet, Ipv6Repr,
+};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr, is_unspecified};
+use super::waiter::{self, SocketWaiter};
+use super::{SocketSetWrapper, ETH0, SOCKET_SET};
+use alloc::sync::Arc;
+
+/// The protocol number of `IPPROTO_RAW`, whose sockets send packets with the
+/// IP header included and never receive.
+const IPPROTO_RAW: u8 = 255;
+
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// Length of the ICMPv6 header up to the checksum (type, code, checksum).
+const ICMPV6_CHECKSUM_END: usize = 4;
+
+/// A raw IPv4 or IPv6 socket that provides POSIX-like APIs.
+///
+/// As on Linux, received IPv4 packets include the IP header, while received
+/// IPv6 packets do not. Packets to send include the IP header only if the
+/// socket is opened with `IPPROTO_RAW`, otherwise the header is filled in by
+/// the socket, as well as the checksum of ICMPv6 messages.
+pub struct RawSocket {
+    handle: SocketHandle,
+    version: IpVersion,
+    protocol: u8,
+    /// Sockets to send packets of other protocols on an `IPPROTO_RAW` socket,
+    /// as smoltcp drops packets that do not match the protocol of the socket.
+    tx_handles: Mutex<BTreeMap<u8, SocketHandle>>,
+    local_addr: RwLock<Option<IpAddress>>,
+    peer_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+}
+
+impl RawSocket {
+    /// Creates a new raw IPv4 socket of the given IP protocol.
+    pub fn new(protocol: u8) -> Self {
+        Self::with_version(IpVersion::Ipv4, protocol)
+    }
+
+    /// Creates a new raw IPv6 socket of the given IP protocol.
+    pub fn new_v6(protocol: u8) -> Self {
+        Self::with_version(IpVersion::Ipv6, protocol)
+    }
+
+    fn with_version(version: IpVersion, protocol: u8) -> Self {
+        let socket = SocketSetWrapper::new_raw_socket(version, protocol);
+        let handle = SOCKET_SET.add(socket);
+        Self {
+            handle,
+            version,
+            protocol,
+            tx_handles: Mutex::new(BTreeMap::new()),
+            local_addr: RwLock::new(None),
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the IP protocol of this socket.
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Returns the local address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not bound.
+    pub fn local_addr(&self) -> AxResult<SocketAddr> {
+        match *self.local_addr.read() {
+            Some(addr) => Ok(SocketAddr::new(into_core_ipaddr(addr), 0)),
+            None => Err(AxError::NotConnected),
+        }
+    }
+
+    /// Returns the remote address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<SocketAddr> {
+        match *self.peer_addr.read() {
+            Some(addr) => Ok(SocketAddr::new(into_core_ipaddr(addr), 0)),
+            None => Err(AxError::NotConnected),
+        }
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this raw socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds the socket to the given local address, which is used as the
+    /// source address of sent packets, and filters the received ones by their
+    /// destination address. The port is ignored.
+    pub fn bind(&self, local_addr: SocketAddr) -> AxResult {
+        let local_ip = self.check_family(local_addr)?;
+        let mut self_local_addr = self.local_addr.write();
+        if self_local_addr.is_some() {
+            return ax_err!(InvalidInput, "socket bind() failed: already bound");
+        }
+        *self_local_addr = Some(local_ip);
+        debug!("raw socket {}: bound on {}", self.handle, local_addr.ip());
+        Ok(())
+    }
+
+    /// Connects the socket to a remote address, so that [`send`](Self::send)
+    /// can be used and only packets from that address are received. The port
+    /// is ignored.
+    pub fn connect(&self, addr: SocketAddr) -> AxResult {
+        *self.peer_addr.write() = Some(self.check_family(addr)?);
+        debug!("raw socket {}: connected to {}", self.handle, addr.ip());
+        Ok(())
+    }
+
+    /// Sends a packet to the given address. On success, returns the number of
+    /// bytes written.
+    pub fn send_to(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
+        if remote_addr.ip().is_unspecified() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        let remote_ip = self.check_family(remote_addr)?;
+        self.send_impl(buf, remote_ip)
+    }
+
+    /// Sends a packet to the remote address to which it is connected.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let remote_addr = self.peer_addr.read().ok_or(AxError::NotConnected)?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives a packet, with its IP header if it is an IPv4 one. On success,
+    /// returns the number of bytes read and the origin.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
+        let local_addr = *self.local_addr.read();
+        let peer_addr = *self.peer_addr.read();
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                loop {
+                    let packet = match socket.recv() {
+                        Ok(packet) => packet,
+                        // no more data
+                        Err(_) => return Err(AxError::WouldBlock),
+                    };
+                    let (src_addr, dst_addr, data) = match self.version {
+                        IpVersion::Ipv4 => match Ipv4Packet::new_checked(packet) {
+                            Ok(header) => (
+                                IpAddress::Ipv4(header.src_addr()),
+                                IpAddress::Ipv4(header.dst_addr()),
+                                packet,
+                            ),
+                            Err(_) => continue,
+                        },
+                        IpVersion::Ipv6 => match Ipv6Packet::new_checked(packet) {
+                            Ok(header) => (
+                                IpAddress::Ipv6(header.src_addr()),
+                                IpAddress::Ipv6(header.dst_addr()),
+                                header.payload(),
+                            ),
+                            Err(_) => continue,
+                        },
+                    };
+                    if local_addr.is_some_and(|addr| !is_unspecified(addr) && addr != dst_addr)
+                        || peer_addr.is_some_and(|addr| addr != src_addr)
+                    {
+                        continue;
+                    }
+                    let len = data.len().min(buf.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                    return Ok((len, SocketAddr::new(into_core_ipaddr(src_addr), 0)));
+                }
+            })
+        })
+    }
+
+    /// Receives a packet from the remote address to which it is connected.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        self.recv_from(buf).map(|(len, _)| len)
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        SOCKET_SET.poll_interfaces();
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+            Ok(PollState {
+                readable: socket.can_recv(),
+                writable: socket.can_send(),
+            })
+        })
+    }
+}
+
+/// Private methods
+impl RawSocket {
+    fn send_impl(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<usize> {
+        if self.protocol == IPPROTO_RAW {
+            return self.send_with_header(buf);
+        }
+
+        let src_addr = match *self.local_addr.read() {
+            Some(addr) if !is_unspecified(addr) => Some(addr),
+            _ => match self.version {
+                IpVersion::Ipv4 => ETH0.ipv4_address().map(IpAddress::Ipv4),
+                IpVersion::Ipv6 => ETH0.ipv6_address().map(IpAddress::Ipv6),
+            },
+        }
+        .ok_or_else(|| ax_err_type!(BadState, "socket send() failed: no address"))?;
+        let next_header = IpProtocol::from(self.protocol);
+
+        match (src_addr, remote_addr) {
+            (IpAddress::Ipv4(src_addr), IpAddress::Ipv4(dst_addr)) => {
+                let repr = Ipv4Repr {
+                    src_addr,
+                    dst_addr,
+                    next_header,
+                    payload_len: buf.len(),
+                    hop_limit: DEFAULT_HOP_LIMIT,
+                };
+                self.send_on(self.handle, repr.buffer_len() + buf.len(), |packet| {
+                    let (header, payload) = packet.split_at_mut(repr.buffer_len());
+                    repr.emit(
+                        &mut Ipv4Packet::new_unchecked(header),
+                        &ChecksumCapabilities::default(),
+                    );
+                    payload.copy_from_slice(buf);
+                })?;
+            }
+            (IpAddress::Ipv6(src_addr), IpAddress::Ipv6(dst_addr)) => {
+                let is_icmpv6 = next_header == IpProtocol::Icmpv6;
+                if is_icmpv6 && buf.len() < ICMPV6_CHECKSUM_END {
+                    return ax_err!(InvalidInput, "socket send() failed: message too short");
+                }
+                let repr = Ipv6Repr {
+                    src_addr,
+                    dst_addr,
+                    next_header,
+                    payload_len: buf.len(),
+                    hop_limit: DEFAULT_HOP_LIMIT,
+                };
+                self.send_on(self.handle, repr.buffer_len() + buf.len(), |packet| {
+                    let (header, payload) = packet.split_at_mut(repr.buffer_len());
+                    repr.emit(&mut Ipv6Packet::new_unchecked(header));
+                    payload.copy_from_slice(buf);
+                    if is_icmpv6 {
+                        Icmpv6Packet::new_unchecked(payload)
+                            .fill_checksum(&src_addr.into(), &dst_addr.into());
+                    }
+                })?;
+            }
+            _ => {
+                return ax_err!(
+                    InvalidInput,
+                    "socket send() failed: address family mismatch"
+                )
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Sends a packet whose IP header is provided by the user.
+    fn send_with_header(&self, buf: &[u8]) -> AxResult<usize> {
+        let bad_header = |_| ax_err_type!(InvalidInput, "socket send() failed: bad IP header");
+        let next_header = match self.version {
+            IpVersion::Ipv4 => Ipv4Packet::new_checked(buf)
+                .map_err(bad_header)?
+                .next_header(),
+            IpVersion::Ipv6 => Ipv6Packet::new_checked(buf)
+                .map_err(bad_header)?
+                .next_header(),
+        };
+        let protocol = u8::from(next_header);
+        let handle = *self.tx_handles.lock().entry(protocol).or_insert_with(|| {
+            SOCKET_SET.add(SocketSetWrapper::new_raw_socket(self.version, protocol))
+        });
+        self.send_on(handle, buf.len(), |packet| packet.copy_from_slice(buf))?;
+        Ok(buf.len())
+    }
+
+    /// Checks that the address is of the family of this socket.
+    fn check_family(&self, addr: SocketAddr) -> AxResult<IpAddress> {
+        let ip = from_core_ipaddr(addr.ip());
+        if ip.version() != self.version {
+            return ax_err!(InvalidInput, "socket address family mismatch");
+        }
+        Ok(ip)
+    }
+
+    fn send_on<F>(&self, handle: SocketHandle, len: usize, mut fill: F) -> AxResult
+    where
+        F: FnMut(&mut [u8]),
+    {
+        self.block_on(|| {
+            SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(handle, |socket| {
+                if len > socket.payload_send_capacity() {
+                    return ax_err!(InvalidInput, "socket send() failed: message too long");
+                }
+                match socket.send(len) {
+                    Ok(packet) => fill(packet),
+                    // tx buffer is full
+                    Err(raw::SendError::BufferFull) => return Err(AxError::WouldBlock),
+                }
+                waiter::wake_worker();
+                Ok(())
+            })
+        })
+    }
+
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        if self.is_nonblocking() {
+            f()
+        } else {
+            waiter::block_on(|| self.register_waiter(), None, f)
+        }
+    }
+
+    /// Registers the waiter of this socket to smoltcp, so that it is woken up
+    /// when the socket becomes ready.
+    fn register_waiter(&self) -> Arc<SocketWaiter> {
+        let waiter = SocketWaiter::of(self.handle);
+        let waker = waiter.waker();
+        SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+        for &handle in self.tx_handles.lock().values() {
+            SOCKET_SET.with_socket_mut::<raw::Socket, _, _>(handle, |socket| {
+                socket.register_send_waker(&waker);
+            });
+        }
+        waiter
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        self.shutdown().ok();
+        SOCKET_SET.remove(self.handle);
+        for &handle in self.tx_handles.lock().values() {
+            SOCKET_SET.remove(handle);
+        }
+    }
+}
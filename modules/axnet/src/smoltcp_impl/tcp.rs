@@ -10,6 +10,7 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::time::Duration;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -20,7 +21,9 @@ use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
+use super::waiter::{self, SocketWaiter};
 use super::{SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET};
+use alloc::sync::Arc;
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -349,6 +352,7 @@ impl TcpSocket {
                     let len = socket
                         .send_slice(buf)
                         .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                    waiter::wake_worker();
                     Ok(len)
                 } else {
                     // tx buffer is full
@@ -500,22 +504,45 @@ impl TcpSocket {
     /// If the socket is non-blocking, it calls the function once and returns
     /// immediately. Otherwise, it may call the function multiple times if it
     /// returns [`Err(WouldBlock)`](AxError::WouldBlock).
-    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
     {
         if self.is_nonblocking() {
             f()
         } else {
-            loop {
-                SOCKET_SET.poll_interfaces();
-                match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => ruxtask::yield_now(),
-                    Err(e) => return Err(e),
-                }
-            }
+            self.block_until(None, f)
+        }
+    }
+
+    /// Retries `f` until it does not block, sleeping until the socket becomes
+    /// ready in between, regardless of the nonblocking mode. Returns
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) after `deadline`, if given.
+    pub(crate) fn block_until<F, T>(&self, deadline: Option<Duration>, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        waiter::block_on(|| self.register_waiter(), deadline, f)
+    }
+
+    /// Registers the waiter of this socket to smoltcp, so that it is woken up
+    /// when the socket becomes ready.
+    fn register_waiter(&self) -> Arc<SocketWaiter> {
+        if self.is_listening() {
+            // SAFETY: `self.local_addr` should be initialized in a listening socket.
+            let local_port = unsafe { self.local_addr.get().read().port };
+            return LISTEN_TABLE.register_waiter(local_port);
         }
+        // SAFETY: `self.handle` should be initialized in a connecting or
+        // connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        let waiter = SocketWaiter::of(handle);
+        let waker = waiter.waker();
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+        waiter
     }
 }
 
@@ -9,6 +9,7 @@
 
 use core::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -17,10 +18,13 @@ use spin::RwLock;
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::udp::{self, BindError, SendError};
+use smoltcp::socket::AnySocket;
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{from_core_sockaddr, into_core_sockaddr, is_unspecified, UNSPECIFIED_ENDPOINT};
+use super::waiter::{self, SocketWaiter};
 use super::{SocketSetWrapper, SOCKET_SET};
+use alloc::sync::Arc;
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
@@ -109,6 +113,13 @@ impl UdpSocket {
         Ok(())
     }
 
+    /// Whether any UDP socket is bound to `port`.
+    pub(crate) fn is_port_bound(port: u16) -> bool {
+        SOCKET_SET.0.lock().iter().any(|(_, socket)| {
+            udp::Socket::downcast(socket).is_some_and(|socket| socket.endpoint().port == port)
+        })
+    }
+
     /// Sends data on the socket to the given address. On success, returns the
     /// number of bytes written.
     pub fn send_to(&self, buf: &[u8], remote_addr: SocketAddr) -> AxResult<usize> {
@@ -232,6 +243,7 @@ impl UdpSocket {
                                 ax_err_type!(ConnectionRefused, "socket send() failed")
                             }
                         })?;
+                    waiter::wake_worker();
                     Ok(buf.len())
                 } else {
                     // tx buffer is full
@@ -262,23 +274,38 @@ impl UdpSocket {
         })
     }
 
-    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    fn block_on<F, T>(&self, f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
     {
         if self.is_nonblocking() {
             f()
         } else {
-            loop {
-                SOCKET_SET.poll_interfaces();
-                match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => ruxtask::yield_now(),
-                    Err(e) => return Err(e),
-                }
-            }
+            self.block_until(None, f)
         }
     }
+
+    /// Retries `f` until it does not block, sleeping until the socket becomes
+    /// ready in between, regardless of the nonblocking mode. Returns
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) after `deadline`, if given.
+    pub(crate) fn block_until<F, T>(&self, deadline: Option<Duration>, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        waiter::block_on(|| self.register_waiter(), deadline, f)
+    }
+
+    /// Registers the waiter of this socket to smoltcp, so that it is woken up
+    /// when the socket becomes ready.
+    fn register_waiter(&self) -> Arc<SocketWaiter> {
+        let waiter = SocketWaiter::of(self.handle);
+        let waker = waiter.waker();
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            socket.register_recv_waker(&waker);
+            socket.register_send_waker(&waker);
+        });
+        waiter
+    }
 }
 
 impl Drop for UdpSocket {
@@ -0,0 +1,229 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Blocking of the socket operations.
+//!
+//! With both `irq` and `multitask` enabled, a blocked operation sleeps on the
+//! [`SocketWaiter`] of its socket, which is registered to smoltcp as a waker,
+//! and the network worker drives the interface. Otherwise it yields and polls
+//! the interface again. The sockets only use this module, so that they need
+//! not care about the features.
+
+use alloc::sync::Arc;
+use axerrno::{AxError, AxResult};
+use core::time::Duration;
+use ruxhal::time::current_time;
+
+use super::SOCKET_SET;
+
+#[cfg(not(all(feature = "irq", feature = "multitask")))]
+pub(crate) use self::poll::*;
+#[cfg(all(feature = "irq", feature = "multitask"))]
+pub(crate) use self::sleep::*;
+
+/// Retries `f` until it does not block, waiting on the waiter returned by
+/// `register` until the socket becomes ready in between.
+///
+/// Returns [`Err(WouldBlock)`](AxError::WouldBlock) after `deadline`, if
+/// given, and [`Err(Interrupted)`](AxError::Interrupted) if woken up by
+/// `ruxtask::interrupt`.
+pub(crate) fn block_on<R, F, T>(register: R, deadline: Option<Duration>, mut f: F) -> AxResult<T>
+where
+    R: Fn() -> Arc<SocketWaiter>,
+    F: FnMut() -> AxResult<T>,
+{
+    loop {
+        // The waiter must be registered before checking, otherwise an event
+        // between the check and the wait could be lost.
+        let waiter = register_if_sleeping(&register);
+        let events = waiter.as_ref().map_or(0, |waiter| waiter.events());
+        SOCKET_SET.poll_interfaces();
+        match f() {
+            Err(AxError::WouldBlock) => {
+                if interrupted() {
+                    return Err(AxError::Interrupted);
+                }
+                let timeout = match deadline {
+                    Some(deadline) => match deadline.checked_sub(current_time()) {
+                        Some(timeout) if !timeout.is_zero() => Some(timeout),
+                        _ => return Err(AxError::WouldBlock),
+                    },
+                    None => None,
+                };
+                wait(waiter, events, timeout);
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(all(feature = "irq", feature = "multitask"))]
+mod sleep {
+    use alloc::collections::BTreeMap;
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::Waker;
+    use core::time::Duration;
+
+    use axsync::Mutex;
+    use ruxtask::WaitQueue;
+    use smoltcp::iface::SocketHandle;
+
+    static SOCKET_WAITERS: Mutex<BTreeMap<SocketHandle, Arc<SocketWaiter>>> =
+        Mutex::new(BTreeMap::new());
+
+    /// A wait queue that blocked operations on one socket sleep on.
+    ///
+    /// It is registered to smoltcp sockets as a [`Waker`], which is woken up
+    /// whenever the socket becomes readable, writable, or changes its state.
+    pub(crate) struct SocketWaiter {
+        events: AtomicUsize,
+        wq: WaitQueue,
+    }
+
+    impl SocketWaiter {
+        pub fn new() -> Arc<Self> {
+            Arc::new(Self {
+                events: AtomicUsize::new(0),
+                wq: WaitQueue::new(),
+            })
+        }
+
+        /// Returns the waiter of the socket with the given handle.
+        pub fn of(handle: SocketHandle) -> Arc<Self> {
+            SOCKET_WAITERS
+                .lock()
+                .entry(handle)
+                .or_insert_with(Self::new)
+                .clone()
+        }
+
+        /// Drops the waiter of the socket with the given handle.
+        pub fn remove(handle: SocketHandle) {
+            SOCKET_WAITERS.lock().remove(&handle);
+        }
+
+        pub fn waker(self: &Arc<Self>) -> Waker {
+            Waker::from(self.clone())
+        }
+
+        /// Returns the number of wake-ups so far.
+        pub fn events(&self) -> usize {
+            self.events.load(Ordering::Acquire)
+        }
+
+        /// Blocks the current task until the waiter is woken up after `events`
+        /// was read.
+        pub fn wait(&self, events: usize) {
+            self.wq.wait_until(|| self.events() != events);
+        }
+
+        /// Like [`wait`](Self::wait), but gives up after `dur`.
+        pub fn wait_timeout(&self, events: usize, dur: Duration) {
+            self.wq.wait_timeout_until(dur, || self.events() != events);
+        }
+    }
+
+    impl Wake for SocketWaiter {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.events.fetch_add(1, Ordering::Release);
+            self.wq.notify_all(false);
+        }
+    }
+
+    pub(crate) use super::super::worker::wake as wake_worker;
+    pub(crate) use ruxtask::interrupted;
+
+    /// Registers the waiter to sleep on.
+    pub(super) fn register_if_sleeping<R>(register: &R) -> Option<Arc<SocketWaiter>>
+    where
+        R: Fn() -> Arc<SocketWaiter>,
+    {
+        Some(register())
+    }
+
+    /// Sleeps until the waiter is woken up after `events` was read.
+    pub(super) fn wait(
+        waiter: Option<Arc<SocketWaiter>>,
+        events: usize,
+        timeout: Option<Duration>,
+    ) {
+        let waiter = waiter.expect("the waiter should be registered");
+        match timeout {
+            Some(timeout) => waiter.wait_timeout(events, timeout),
+            None => waiter.wait(events),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "irq", feature = "multitask")))]
+mod poll {
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::task::Waker;
+    use core::time::Duration;
+
+    use smoltcp::iface::SocketHandle;
+
+    /// The waiter that never sleeps, as blocked operations poll instead.
+    pub(crate) struct SocketWaiter;
+
+    impl SocketWaiter {
+        pub fn new() -> Arc<Self> {
+            Arc::new(Self)
+        }
+
+        pub fn of(_handle: SocketHandle) -> Arc<Self> {
+            Self::new()
+        }
+
+        pub fn remove(_handle: SocketHandle) {}
+
+        pub fn waker(self: &Arc<Self>) -> Waker {
+            Waker::from(self.clone())
+        }
+
+        pub fn events(&self) -> usize {
+            0
+        }
+    }
+
+    impl Wake for SocketWaiter {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// There is no worker, the sockets poll the interface themselves.
+    pub(crate) fn wake_worker() {}
+
+    pub(crate) fn interrupted() -> bool {
+        false
+    }
+
+    /// Polling needs no waiter.
+    pub(super) fn register_if_sleeping<R>(_register: &R) -> Option<Arc<SocketWaiter>>
+    where
+        R: Fn() -> Arc<SocketWaiter>,
+    {
+        None
+    }
+
+    /// Yields, and polls again.
+    pub(super) fn wait(
+        _waiter: Option<Arc<SocketWaiter>>,
+        _events: usize,
+        _timeout: Option<Duration>,
+    ) {
+        ruxtask::yield_now();
+    }
+}
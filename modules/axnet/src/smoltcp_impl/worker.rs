@@ -0,0 +1,103 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! The network worker task, which drives the interface when packets arrive
+//! or smoltcp timers fire, instead of relying on sockets to busy poll.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axsync::Mutex;
+use lazy_init::LazyInit;
+use ruxdriver::prelude::NetDriverOps;
+use ruxtask::WaitQueue;
+use smoltcp::iface::SocketSet;
+
+use super::{InterfaceWrapper, ETH0, SOCKET_SET};
+
+/// Poll interval of NICs that cannot raise interrupts.
+const NIC_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+const NET_WORKER_STACK_SIZE: usize = 0x4_0000;
+
+static POLL_PENDING: AtomicBool = AtomicBool::new(false);
+static WORKER_WQ: WaitQueue = WaitQueue::new();
+
+/// The IRQ of the NIC, if its handler has been registered.
+static NIC_IRQ: LazyInit<Option<usize>> = LazyInit::new();
+
+/// Asks the worker to poll the interface as soon as possible.
+pub(crate) fn wake() {
+    POLL_PENDING.store(true, Ordering::Release);
+    WORKER_WQ.notify_one(false);
+}
+
+fn nic_irq_handler() {
+    // The line is re-enabled after the worker acknowledges the NIC, so that
+    // a level-triggered interrupt does not fire again right away.
+    if let Some(&Some(irq)) = NIC_IRQ.try_get() {
+        ruxhal::irq::set_enable(irq, false);
+    }
+    wake();
+}
+
+fn worker_entry() {
+    let irq = *NIC_IRQ;
+    loop {
+        if let Some(irq) = irq {
+            ETH0.ack_interrupt();
+            ruxhal::irq::set_enable(irq, true);
+        }
+        SOCKET_SET.poll_interfaces();
+
+        let condition = || POLL_PENDING.swap(false, Ordering::AcqRel);
+        match ETH0.poll_delay(&SOCKET_SET.0) {
+            Some(delay) => {
+                WORKER_WQ.wait_timeout_until(delay, condition);
+            }
+            None if irq.is_none() => {
+                WORKER_WQ.wait_timeout_until(NIC_POLL_INTERVAL, condition);
+            }
+            None => WORKER_WQ.wait_until(condition),
+        }
+    }
+}
+
+pub(crate) fn init() {
+    let irq = ETH0
+        .irq_num()
+        .filter(|&irq| ruxhal::irq::register_handler(irq, nic_irq_handler));
+    match irq {
+        Some(irq) => info!("  irq:      {}", irq),
+        None => info!("  irq:      none, polling every {:?}", NIC_POLL_INTERVAL),
+    }
+    NIC_IRQ.init_by(irq);
+    ruxtask::spawn_raw(worker_entry, "net-worker".into(), NET_WORKER_STACK_SIZE);
+}
+
+impl InterfaceWrapper {
+    /// Returns how long to wait before the next [`poll`](Self::poll) is due
+    /// for smoltcp timers, or [`None`] if only new packets require one.
+    fn poll_delay(&self, sockets: &Mutex<SocketSet>) -> Option<Duration> {
+        let mut iface = self.iface.lock();
+        let sockets = sockets.lock();
+        let timestamp = Self::current_time();
+        iface
+            .poll_delay(timestamp, &sockets)
+            .map(|delay| Duration::from_micros(delay.total_micros()))
+    }
+
+    fn irq_num(&self) -> Option<usize> {
+        self.dev.lock().inner.borrow().irq_num()
+    }
+
+    fn ack_interrupt(&self) -> bool {
+        self.dev.lock().inner.borrow_mut().ack_interrupt()
+    }
+}
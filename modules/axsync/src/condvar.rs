@@ -0,0 +1,185 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! A condition variable working with [`Mutex`].
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use ruxtask::WaitQueue;
+
+use crate::{Mutex, MutexGuard};
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable, similar to
+/// [`std::sync::Condvar`](https://doc.rust-lang.org/std/sync/struct.Condvar.html).
+///
+/// Condition variables represent the ability to block a task such that it
+/// consumes no CPU time while waiting for an event to occur. It is used with
+/// a [`Mutex`] that protects the condition. Spurious wakeups are possible,
+/// so the condition should be checked again after waking up.
+pub struct Condvar {
+    wq: WaitQueue,
+    /// Increased on every notification, so that a notification between
+    /// releasing the mutex and sleeping is not lost.
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Releases the mutex of `guard`, and returns it with the mutex locked
+    /// again after `wait` returns.
+    fn unlock_and<'a, T: ?Sized, R>(
+        guard: MutexGuard<'a, T>,
+        wait: impl FnOnce() -> R,
+    ) -> (MutexGuard<'a, T>, R) {
+        let mutex: &'a Mutex<T> = guard.lock;
+        drop(guard);
+        let res = wait();
+        (mutex.lock(), res)
+    }
+
+    /// Blocks the current task until this condition variable receives a
+    /// notification.
+    ///
+    /// The mutex of `guard` is released while blocking, and is locked again
+    /// before returning.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let seq = self.seq.load(Ordering::Acquire);
+        Self::unlock_and(guard, || {
+            self.wq
+                .wait_until(|| self.seq.load(Ordering::Acquire) != seq)
+        })
+        .0
+    }
+
+    /// Blocks the current task until `condition` returns `false`, checking it
+    /// every time this condition variable receives a notification.
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: core::time::Duration,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult) {
+        let seq = self.seq.load(Ordering::Acquire);
+        let (guard, timed_out) = Self::unlock_and(guard, || {
+            self.wq
+                .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq)
+        });
+        (guard, WaitTimeoutResult(timed_out))
+    }
+
+    /// Waits on this condition variable until `condition` returns `false`,
+    /// timing out after the specified duration.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: core::time::Duration,
+        mut condition: F,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = ruxhal::time::current_time() + dur;
+        while condition(&mut *guard) {
+            let now = ruxhal::time::current_time();
+            if now >= deadline {
+                return (guard, WaitTimeoutResult(true));
+            }
+            guard = self.wait_timeout(guard, deadline - now).0;
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    /// Wakes up one task blocked on this condition variable.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Wakes up all tasks blocked on this condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Condvar, Mutex};
+    use ruxtask as thread;
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+
+    #[test]
+    fn producer_consumer() {
+        INIT.call_once(thread::init_scheduler);
+
+        const NUM_ITEMS: usize = 1_000;
+        static QUEUE: Mutex<(usize, bool)> = Mutex::new((0, false));
+        static CVAR: Condvar = Condvar::new();
+
+        thread::spawn(|| {
+            for _ in 0..NUM_ITEMS {
+                let mut queue = CVAR.wait_while(QUEUE.lock(), |q| q.1);
+                queue.0 += 1;
+                queue.1 = true;
+                CVAR.notify_all();
+            }
+        });
+
+        for i in 0..NUM_ITEMS {
+            let mut queue = CVAR.wait_while(QUEUE.lock(), |q| !q.1);
+            assert_eq!(queue.0, i + 1);
+            queue.1 = false;
+            CVAR.notify_all();
+        }
+    }
+}
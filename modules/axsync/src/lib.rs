@@ -12,26 +12,56 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`RwLock`]: A writer-preferring reader-writer lock, with upgradable reads.
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Condvar`]: A condition variable working with [`Mutex`].
+//! - [`Barrier`]: A barrier to synchronize a group of tasks.
+//! - [`Once`] and [`LazyLock`]: One-time initialization.
 //! - mod [`spin`](spinlock): spin-locks.
 //!
+//! All primitives except the spin-locks block the current task by putting it
+//! into a [`WaitQueue`](ruxtask::WaitQueue), they are only available with the
+//! `multitask` feature.
+//!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
 //!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
 //!   feature is enabled by default.
+//! - `irq`: Enables the waiting operations with timeouts.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
 pub use spinlock as spin;
 
+#[cfg(feature = "multitask")]
+mod barrier;
+#[cfg(feature = "multitask")]
+mod condvar;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(feature = "multitask")]
+mod once;
+#[cfg(feature = "multitask")]
+mod rwlock;
+#[cfg(feature = "multitask")]
+mod semaphore;
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
 pub use self::mutex::{Mutex, MutexGuard};
 
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::{
+    barrier::{Barrier, BarrierWaitResult},
+    condvar::{Condvar, WaitTimeoutResult},
+    once::{LazyLock, Once},
+    rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard},
+    semaphore::{Semaphore, SemaphoreGuard},
+};
+
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
 pub use spinlock::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
@@ -32,7 +32,7 @@ pub struct Mutex<T: ?Sized> {
 ///
 /// When the guard falls out of scope it will release the lock.
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    lock: &'a Mutex<T>,
+    pub(crate) lock: &'a Mutex<T>,
     data: *mut T,
 }
 
@@ -101,6 +101,7 @@ impl<T: ?Sized> Mutex<T> {
                 }
             }
         }
+        current().lock_acquired();
         MutexGuard {
             lock: self,
             data: unsafe { &mut *self.data.get() },
@@ -118,6 +119,7 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            current().lock_acquired();
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -142,6 +144,7 @@ impl<T: ?Sized> Mutex<T> {
             "{} tried to release mutex it doesn't own",
             current().id_name()
         );
+        current().lock_released();
         self.wq.notify_one(true);
     }
 
@@ -0,0 +1,419 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! A sleeping reader-writer lock.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ruxtask::{current, WaitQueue};
+
+const WRITER: usize = 1;
+const UPGRADABLE: usize = 1 << 1;
+const READER: usize = 1 << 2;
+
+/// A reader-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// The lock prefers writers: new readers are blocked while some task is
+/// waiting for the write lock, so that writers can not be starved.
+///
+/// Besides shared and exclusive access, it provides an upgradable read
+/// access, which is shared with other readers but exclusive with other
+/// upgradable readers and writers, and can be atomically upgraded to the
+/// write access.
+pub struct RwLock<T: ?Sized> {
+    wq: WaitQueue,
+    state: AtomicUsize,
+    writers_waiting: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will decrement the read count,
+/// potentially releasing the lock.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+/// A guard that provides immutable data access but can be upgraded to
+/// [`RwLockWriteGuard`].
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct RwLockUpgradableGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicUsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    fn acquire_reader(&self) -> bool {
+        self.acquire_shared(WRITER, READER)
+    }
+
+    fn acquire_upgradable(&self) -> bool {
+        self.acquire_shared(WRITER | UPGRADABLE, UPGRADABLE)
+    }
+
+    /// Adds `inc` to the state if none of the `exclusive` bits are set and no
+    /// writers are waiting.
+    fn acquire_shared(&self, exclusive: usize, inc: usize) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & exclusive != 0 || self.writers_waiting.load(Ordering::Relaxed) > 0 {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + inc,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn acquire_writer(&self, from: usize) -> bool {
+        self.state
+            .compare_exchange(from, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Waits until the state changes from `from` to [`WRITER`], blocking new
+    /// readers in the meantime.
+    fn wait_writer(&self, from: usize) {
+        if self.acquire_writer(from) {
+            return;
+        }
+        self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+        self.wq.wait_until(|| self.acquire_writer(from));
+        // Readers blocked by us will be woken up when the lock is released.
+        self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        if !self.acquire_reader() {
+            self.wq.wait_until(|| self.acquire_reader());
+        }
+        current().lock_acquired();
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with shared read access, returning
+    /// a guard if successful.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        self.acquire_reader().then(|| {
+            current().lock_acquired();
+            RwLockReadGuard { lock: self }
+        })
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the current
+    /// task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        self.wait_writer(0);
+        current().lock_acquired();
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// Attempts to lock this [`RwLock`] with exclusive write access, returning
+    /// a guard if successful.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        self.acquire_writer(0).then(|| {
+            current().lock_acquired();
+            RwLockWriteGuard { lock: self }
+        })
+    }
+
+    /// Locks this [`RwLock`] with upgradable read access, blocking the
+    /// current task until it can be acquired.
+    pub fn upgradable_read(&self) -> RwLockUpgradableGuard<T> {
+        if !self.acquire_upgradable() {
+            self.wq.wait_until(|| self.acquire_upgradable());
+        }
+        current().lock_acquired();
+        RwLockUpgradableGuard { lock: self }
+    }
+
+    /// Attempts to acquire this [`RwLock`] with upgradable read access,
+    /// returning a guard if successful.
+    pub fn try_upgradable_read(&self) -> Option<RwLockUpgradableGuard<T>> {
+        self.acquire_upgradable().then(|| {
+            current().lock_acquired();
+            RwLockUpgradableGuard { lock: self }
+        })
+    }
+
+    /// Returns the number of readers that currently hold the lock, including
+    /// the upgradable one.
+    ///
+    /// This function provides no synchronization guarantees and so its result
+    /// should be considered 'out of date' the instant it is called.
+    pub fn reader_count(&self) -> usize {
+        let state = self.state.load(Ordering::Relaxed);
+        state / READER + (state & UPGRADABLE != 0) as usize
+    }
+
+    /// Returns `true` if the lock is currently held with write access.
+    ///
+    /// This function provides no synchronization guarantees and so its result
+    /// should be considered 'out of date' the instant it is called.
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER != 0
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking needs
+    /// to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockWriteGuard<'a, T> {
+    /// Atomically downgrades the write lock into a read lock, without
+    /// allowing any writers to take the lock in the meantime.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        lock.state.store(READER, Ordering::Release);
+        lock.wq.notify_all(false);
+        RwLockReadGuard { lock }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockUpgradableGuard<'a, T> {
+    /// Upgrades the upgradable read lock to the write lock, blocking the
+    /// current task until all other readers release the lock.
+    pub fn upgrade(self) -> RwLockWriteGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        lock.wait_writer(UPGRADABLE);
+        RwLockWriteGuard { lock }
+    }
+
+    /// Tries to upgrade the upgradable read lock to the write lock, returns
+    /// the guard back if there are other readers.
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        if self.lock.acquire_writer(UPGRADABLE) {
+            let lock = ManuallyDrop::new(self).lock;
+            Ok(RwLockWriteGuard { lock })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Downgrades the upgradable read lock to a normal read lock, allowing
+    /// another task to take the upgradable read lock.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = ManuallyDrop::new(self).lock;
+        // UPGRADABLE is set, adding it turns into one more READER.
+        lock.state.fetch_add(UPGRADABLE, Ordering::Release);
+        lock.wq.notify_all(false);
+        RwLockReadGuard { lock }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockUpgradableGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockUpgradableGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        let state = self.lock.state.fetch_sub(READER, Ordering::Release);
+        // Wake up the writer or the upgrader waiting for the last reader.
+        if state & !UPGRADABLE == READER {
+            self.lock.wq.notify_all(true);
+        }
+        current().lock_released();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockUpgradableGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_and(!UPGRADABLE, Ordering::Release);
+        self.lock.wq.notify_all(true);
+        current().lock_released();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+        self.lock.wq.notify_all(true);
+        current().lock_released();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RwLock;
+    use ruxtask as thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+
+    #[test]
+    fn readers_and_writers() {
+        INIT.call_once(thread::init_scheduler);
+
+        const NUM_TASKS: usize = 10;
+        const NUM_ITERS: usize = 1_000;
+        static LOCK: RwLock<(usize, usize)> = RwLock::new((0, 0));
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for i in 0..NUM_TASKS {
+            thread::spawn(move || {
+                for _ in 0..NUM_ITERS {
+                    if i % 2 == 0 {
+                        let mut val = LOCK.write();
+                        val.0 += 1;
+                        thread::yield_now();
+                        val.1 += 1;
+                    } else {
+                        let val = LOCK.read();
+                        thread::yield_now();
+                        assert_eq!(val.0, val.1);
+                    }
+                }
+                FINISHED.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        while FINISHED.load(Ordering::Relaxed) < NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(*LOCK.read(), (NUM_ITERS * 5, NUM_ITERS * 5));
+    }
+
+    #[test]
+    fn upgrade_and_downgrade() {
+        INIT.call_once(thread::init_scheduler);
+
+        let lock = RwLock::new(0);
+        let upgradable = lock.upgradable_read();
+        assert!(lock.try_upgradable_read().is_none());
+        let reader = lock.read();
+        assert_eq!(lock.reader_count(), 2);
+
+        let upgradable = upgradable.try_upgrade().unwrap_err();
+        drop(reader);
+        let mut writer = upgradable.upgrade();
+        assert!(lock.try_read().is_none());
+        *writer += 1;
+
+        let reader = writer.downgrade();
+        assert_eq!(*reader, 1);
+        assert!(lock.try_upgradable_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(reader);
+        assert!(lock.try_write().is_some());
+    }
+}
@@ -0,0 +1,138 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ruxtask::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It maintains a count of available resources. [`acquire`](Semaphore::acquire)
+/// blocks the current task until the count is positive and then decrements
+/// it, [`release`](Semaphore::release) increments the count and wakes up a
+/// waiting task.
+pub struct Semaphore {
+    wq: WaitQueue,
+    count: AtomicUsize,
+}
+
+/// An RAII guard which will release a resource acquired from a semaphore
+/// when dropped.
+pub struct SemaphoreGuard<'a> {
+    sem: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the initial count.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            count: AtomicUsize::new(count),
+        }
+    }
+
+    /// Returns the number of the available resources.
+    ///
+    /// This function provides no synchronization guarantees and so its result
+    /// should be considered 'out of date' the instant it is called.
+    pub fn available(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Tries to acquire a resource without blocking, returns `true` if
+    /// successful.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |c| c.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Acquires a resource, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        if !self.try_acquire() {
+            self.wq.wait_until(|| self.try_acquire());
+        }
+    }
+
+    /// Acquires a resource, blocking the current task until one is available
+    /// or the given duration has elapsed.
+    ///
+    /// Returns `true` if the resource is acquired.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: core::time::Duration) -> bool {
+        self.try_acquire() || !self.wq.wait_timeout_until(dur, || self.try_acquire())
+    }
+
+    /// Releases a resource, and wakes up a task waiting for it.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Acquires a resource, and returns a guard which releases it when
+    /// dropped.
+    pub fn access(&self) -> SemaphoreGuard {
+        self.acquire();
+        SemaphoreGuard { sem: self }
+    }
+
+    /// Tries to acquire a resource without blocking, and returns a guard
+    /// which releases it when dropped if successful.
+    pub fn try_access(&self) -> Option<SemaphoreGuard> {
+        self.try_acquire().then_some(SemaphoreGuard { sem: self })
+    }
+}
+
+impl Drop for SemaphoreGuard<'_> {
+    fn drop(&mut self) {
+        self.sem.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Semaphore;
+    use ruxtask as thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+
+    #[test]
+    fn bounded_concurrency() {
+        INIT.call_once(thread::init_scheduler);
+
+        const NUM_TASKS: usize = 10;
+        const MAX_CONCURRENT: usize = 3;
+        static SEM: Semaphore = Semaphore::new(MAX_CONCURRENT);
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                for _ in 0..100 {
+                    let _guard = SEM.access();
+                    let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                    assert!(running <= MAX_CONCURRENT);
+                    thread::yield_now();
+                    RUNNING.fetch_sub(1, Ordering::SeqCst);
+                }
+                FINISHED.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        while FINISHED.load(Ordering::SeqCst) < NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(SEM.available(), MAX_CONCURRENT);
+        assert!(SEM.try_access().is_some());
+    }
+}
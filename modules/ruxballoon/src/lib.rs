@@ -0,0 +1,317 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! [Ruxos](https://github.com/syswonder/ruxos) memory balloon module.
+//!
+//! It lets the host reclaim the unused guest memory:
+//!
+//! - The balloon is inflated with free pages taken from the page allocator,
+//!   or deflated by giving them back, to follow the target set by the host.
+//!   The pages go back to the page allocator they are taken from, instead of
+//!   [`axalloc::global_add_memory`], which only grows the heap.
+//! - If the host allows, the balloon is deflated when the guest runs out of
+//!   memory, by a shrinker of [`axalloc`].
+//! - The memory statistics are reported when the host asks for them.
+//! - Free memory is reported to the host in chunks of 2 MiB, which are taken
+//!   from the page allocator for the time of the report. As the page
+//!   allocator hands out the lowest free pages first, the same chunks may be
+//!   reported again until they are used. Some free pages are left above the
+//!   high watermark of reclaim, so that the report doesn't make the guest
+//!   reclaim its memory.
+//!
+//! With the `multitask` and `irq` features, [`update`] is called periodically
+//! by a work queue. Otherwise, it is only called at initialization, and
+//! should be called by the application.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use axalloc::{global_allocator, MemTag};
+use axsync::Mutex;
+use driver_balloon::{BalloonStat, StatTag, BALLOON_PAGE_SIZE};
+use lazy_init::LazyInit;
+use ruxdriver::{prelude::*, AxDeviceContainer};
+use ruxhal::mem::{direct_virt_to_phys, VirtAddr};
+
+const PAGE_SIZE: usize = 0x1000;
+const _: () = assert!(PAGE_SIZE == BALLOON_PAGE_SIZE);
+
+/// The pages never given to the host, so the guest can still run.
+const RESERVED_PAGES: usize = 1024;
+/// The maximum number of pages per inflate or deflate request.
+const MAX_BATCH_PAGES: usize = 256;
+/// The size of the chunks of free memory reported to the host.
+const REPORT_CHUNK_SIZE: usize = 0x20_0000;
+const REPORT_CHUNK_PAGES: usize = REPORT_CHUNK_SIZE / PAGE_SIZE;
+/// The maximum number of chunks per free page report.
+const MAX_REPORT_CHUNKS: usize = 16;
+/// The free pages left to the guest during a free page report, above the
+/// high watermark of reclaim.
+const REPORT_HEADROOM_PAGES: usize = 4 * REPORT_CHUNK_PAGES;
+
+struct Balloon {
+    dev: AxBalloonDevice,
+    /// Virtual addresses of the pages in the balloon.
+    pages: Vec<usize>,
+    /// The available pages after the last free page report.
+    reported_available: usize,
+}
+
+static BALLOON: LazyInit<Mutex<Balloon>> = LazyInit::new();
+
+fn pfn_of(vaddr: usize) -> u32 {
+    (direct_virt_to_phys(VirtAddr::from(vaddr)).as_usize() / BALLOON_PAGE_SIZE) as u32
+}
+
+impl Balloon {
+    /// Inflates the balloon by at most `num_pages` pages, returns the number
+    /// of pages given to the host.
+    fn inflate(&mut self, num_pages: usize) -> usize {
+        let allocator = global_allocator();
+        let max_batch = self.dev.max_pages_per_request().min(MAX_BATCH_PAGES);
+        let mut pfns = [0u32; MAX_BATCH_PAGES];
+        let mut inflated = 0;
+        while inflated < num_pages && max_batch > 0 {
+            let batch = (num_pages - inflated).min(max_batch);
+            if self.pages.try_reserve(batch).is_err() {
+                break;
+            }
+            let mut num = 0;
+            while num < batch && allocator.available_pages() > RESERVED_PAGES {
+                let Ok(vaddr) = allocator.alloc_pages(1, PAGE_SIZE) else {
+                    break;
+                };
+                pfns[num] = pfn_of(vaddr);
+                self.pages.push(vaddr);
+                num += 1;
+            }
+            if num == 0 {
+                break;
+            }
+            let start = self.pages.len() - num;
+            if let Err(e) = self.dev.inflate(&pfns[..num]) {
+                warn!("balloon: failed to inflate {} pages: {:?}", num, e);
+                for vaddr in self.pages.drain(start..) {
+                    allocator.dealloc_pages(vaddr, 1);
+                }
+                break;
+            }
+            axalloc::charge(MemTag::Balloon, num * PAGE_SIZE);
+            inflated += num;
+            if num < batch {
+                break;
+            }
+        }
+        inflated
+    }
+
+    /// Deflates the balloon by at most `num_pages` pages, returns the number
+    /// of pages taken back from the host.
+    ///
+    /// It doesn't allocate memory, so it can be called by the shrinker.
+    fn deflate(&mut self, num_pages: usize) -> usize {
+        let allocator = global_allocator();
+        let max_batch = self.dev.max_pages_per_request().min(MAX_BATCH_PAGES);
+        let mut pfns = [0u32; MAX_BATCH_PAGES];
+        let mut deflated = 0;
+        while deflated < num_pages {
+            let num = (num_pages - deflated).min(max_batch).min(self.pages.len());
+            if num == 0 {
+                break;
+            }
+            let start = self.pages.len() - num;
+            for (pfn, vaddr) in pfns.iter_mut().zip(&self.pages[start..]) {
+                *pfn = pfn_of(*vaddr);
+            }
+            // Tell the host before touching the pages.
+            if let Err(e) = self.dev.deflate(&pfns[..num]) {
+                warn!("balloon: failed to deflate {} pages: {:?}", num, e);
+                break;
+            }
+            for vaddr in self.pages.drain(start..) {
+                allocator.dealloc_pages(vaddr, 1);
+            }
+            axalloc::uncharge(MemTag::Balloon, num * PAGE_SIZE);
+            deflated += num;
+        }
+        deflated
+    }
+
+    /// Inflates or deflates the balloon to the target of the host.
+    fn adjust(&mut self) {
+        let target = self.dev.target_pages();
+        let size = self.pages.len();
+        if target > size {
+            let inflated = self.inflate(target - size);
+            debug!("balloon: inflated {} pages", inflated);
+        } else if target < size {
+            let deflated = self.deflate(size - target);
+            debug!("balloon: deflated {} pages", deflated);
+        }
+        if self.dev.actual_pages() != self.pages.len() {
+            self.dev.set_actual_pages(self.pages.len());
+        }
+    }
+
+    fn update_stats(&mut self) {
+        if !self.dev.stats_requested() {
+            return;
+        }
+        let allocator = global_allocator();
+        let (used_pages, available_pages) = (allocator.used_pages(), allocator.available_pages());
+        let total = (used_pages + available_pages - self.pages.len()) * PAGE_SIZE;
+        let free = available_pages * PAGE_SIZE + allocator.available_bytes();
+        let caches = axalloc::usage(MemTag::FsCache).current;
+        let stats = [
+            BalloonStat::new(StatTag::FreeMemory, free as u64),
+            BalloonStat::new(StatTag::TotalMemory, total as u64),
+            BalloonStat::new(StatTag::AvailableMemory, (free + caches) as u64),
+            BalloonStat::new(StatTag::DiskCaches, caches as u64),
+        ];
+        match self.dev.update_stats(&stats) {
+            Ok(()) | Err(DevError::Again) => {}
+            Err(e) => warn!("balloon: failed to update stats: {:?}", e),
+        }
+    }
+
+    /// Reports the free memory to the host, if some pages are freed since the
+    /// last report.
+    fn report_free_pages(&mut self) {
+        let max_chunks = self.dev.max_reported_ranges().min(MAX_REPORT_CHUNKS);
+        if max_chunks == 0 {
+            return;
+        }
+        let allocator = global_allocator();
+        let available = allocator.available_pages();
+        if available < self.reported_available + REPORT_CHUNK_PAGES {
+            self.reported_available = self.reported_available.min(available);
+            return;
+        }
+
+        let reserved =
+            RESERVED_PAGES.max(ruxconfig::RECLAIM_HIGH_WATERMARK) + REPORT_HEADROOM_PAGES;
+        let mut chunks = [0usize; MAX_REPORT_CHUNKS];
+        let mut num = 0;
+        while num < max_chunks && allocator.available_pages() > reserved + REPORT_CHUNK_PAGES {
+            match allocator.alloc_pages(REPORT_CHUNK_PAGES, REPORT_CHUNK_SIZE) {
+                Ok(vaddr) => chunks[num] = vaddr,
+                Err(_) => break,
+            }
+            num += 1;
+        }
+        if num > 0 {
+            let mut ranges: Vec<&mut [u8]> = chunks[..num]
+                .iter()
+                // Safe because the chunks are allocated above, and not used
+                // by anyone else.
+                .map(|&vaddr| unsafe {
+                    core::slice::from_raw_parts_mut(vaddr as *mut u8, REPORT_CHUNK_SIZE)
+                })
+                .collect();
+            match self.dev.report_free_pages(&mut ranges) {
+                Ok(()) => debug!("balloon: reported {} free chunks", num),
+                Err(e) => warn!("balloon: failed to report free pages: {:?}", e),
+            }
+            drop(ranges);
+            for &vaddr in &chunks[..num] {
+                allocator.dealloc_pages(vaddr, REPORT_CHUNK_PAGES);
+            }
+        }
+        self.reported_available = allocator.available_pages();
+    }
+}
+
+/// Deflates the balloon when the guest runs out of memory, if the host
+/// allows it.
+fn deflate_on_oom(num_pages: usize) -> usize {
+    let Some(balloon) = BALLOON.try_get() else {
+        return 0;
+    };
+    let Some(mut balloon) = balloon.try_lock() else {
+        return 0;
+    };
+    let deflated = balloon.deflate(num_pages);
+    if deflated > 0 {
+        let size = balloon.pages.len();
+        balloon.dev.set_actual_pages(size);
+    }
+    deflated
+}
+
+/// Initializes the memory balloon by underlayer devices.
+pub fn init_balloon(mut balloon_devs: AxDeviceContainer<AxBalloonDevice>) {
+    info!("Initialize memory balloon...");
+
+    let Some(dev) = balloon_devs.take_one() else {
+        warn!("No balloon device found!");
+        return;
+    };
+    info!("  use balloon device 0: {:?}", dev.device_name());
+    let deflate_on_oom_allowed = dev.deflate_on_oom();
+    BALLOON.init_by(Mutex::new(Balloon {
+        dev,
+        pages: Vec::new(),
+        reported_available: 0,
+    }));
+    if deflate_on_oom_allowed {
+        axalloc::register_shrinker(deflate_on_oom);
+    }
+    update();
+
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    poll::start();
+}
+
+/// Follows the target of the host, answers the statistics request, and
+/// reports the free memory.
+pub fn update() {
+    let Some(balloon) = BALLOON.try_get() else {
+        return;
+    };
+    let mut balloon = balloon.lock();
+    balloon.dev.ack_interrupt();
+    balloon.adjust();
+    balloon.update_stats();
+    balloon.report_free_pages();
+}
+
+/// Returns the number of pages in the balloon.
+pub fn balloon_pages() -> usize {
+    BALLOON
+        .try_get()
+        .map_or(0, |balloon| balloon.lock().pages.len())
+}
+
+#[cfg(all(feature = "multitask", feature = "irq"))]
+mod poll {
+    use alloc::sync::Arc;
+    use core::time::Duration;
+    use lazy_init::LazyInit;
+    use ruxtask::{Work, WorkQueue};
+
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    static BALLOON_WQ: LazyInit<Arc<WorkQueue>> = LazyInit::new();
+    static BALLOON_WORK: LazyInit<Arc<Work>> = LazyInit::new();
+
+    /// Calls [`update`](super::update) every [`POLL_INTERVAL`], in its own
+    /// work queue as inflating may take long.
+    pub(super) fn start() {
+        BALLOON_WQ.init_by(WorkQueue::new("balloon", 1));
+        BALLOON_WORK.init_by(Work::new(|| {
+            super::update();
+            BALLOON_WQ.queue_delayed_work(&BALLOON_WORK, POLL_INTERVAL);
+        }));
+        BALLOON_WQ.queue_delayed_work(&BALLOON_WORK, POLL_INTERVAL);
+    }
+}
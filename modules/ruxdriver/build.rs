@@ -11,6 +11,7 @@ const NET_DEV_FEATURES: &[&str] = &["ixgbe", "virtio-net"];
 const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "bcm2835-sdhci", "virtio-blk"];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
 const _9P_DEV_FEATURES: &[&str] = &["virtio-9p"];
+const BALLOON_DEV_FEATURES: &[&str] = &["virtio-balloon"];
 
 fn has_feature(feature: &str) -> bool {
     let ret = std::env::var(format!(
@@ -45,6 +46,7 @@ fn main() {
         ("block", BLOCK_DEV_FEATURES),
         ("display", DISPLAY_DEV_FEATURES),
         ("_9p", _9P_DEV_FEATURES),
+        ("balloon", BALLOON_DEV_FEATURES),
     ] {
         if !has_feature(dev_kind) {
             continue;
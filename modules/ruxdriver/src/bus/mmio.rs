@@ -10,13 +10,28 @@
 #[allow(unused_imports)]
 use crate::{prelude::*, AllDevices};
 
+/// Returns the IRQ number of the VirtIO MMIO slot at `mmio_base`.
+///
+/// Slots are wired to consecutive IRQs starting from
+/// [`ruxconfig::VIRTIO_MMIO_IRQ_BASE`], which is 0 if the platform does not
+/// route them.
+#[cfg(feature = "virtio")]
+fn virtio_mmio_irq(mmio_base: usize, mmio_size: usize) -> Option<usize> {
+    let first_base = ruxconfig::VIRTIO_MMIO_REGIONS.first()?.0;
+    if ruxconfig::VIRTIO_MMIO_IRQ_BASE == 0 || mmio_size == 0 || mmio_base < first_base {
+        return None;
+    }
+    Some(ruxconfig::VIRTIO_MMIO_IRQ_BASE + (mmio_base - first_base) / mmio_size)
+}
+
 impl AllDevices {
     pub(crate) fn probe_bus_devices(&mut self) {
         // TODO: parse device tree
         #[cfg(feature = "virtio")]
         for reg in ruxconfig::VIRTIO_MMIO_REGIONS {
+            let irq = virtio_mmio_irq(reg.0, reg.1);
             for_each_drivers!(type Driver, {
-                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1) {
+                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1, irq) {
                     info!(
                         "registered a new {:?} device at [PA:{:#x}, PA:{:#x}): {:?}",
                         dev.device_type(),
@@ -14,6 +14,30 @@ use driver_pci::{
 use ruxhal::mem::phys_to_virt;
 
 const PCI_BAR_NUM: u8 = 6;
+const PCI_INTERRUPT_PIN_OFFSET: usize = 0x3d;
+
+/// Returns the IRQ number of the legacy interrupt line of the device.
+///
+/// The INTA#..INTD# lines of all slots are wired to 4 consecutive IRQs
+/// starting from [`ruxconfig::PCI_IRQ_BASE`], swizzled by the slot number.
+/// It returns [`None`] if the platform does not route them, or the device
+/// does not use a line.
+fn pci_intx_irq(bdf: DeviceFunction) -> Option<usize> {
+    if ruxconfig::PCI_IRQ_BASE == 0 {
+        return None;
+    }
+    let offset = (bdf.bus as usize) << 20
+        | (bdf.device as usize) << 15
+        | (bdf.function as usize) << 12
+        | PCI_INTERRUPT_PIN_OFFSET;
+    let ecam_base = phys_to_virt(ruxconfig::PCI_ECAM_BASE.into()).as_usize();
+    // Safe because the ECAM space is mapped, and the device exists.
+    let pin = unsafe { ((ecam_base + offset) as *const u8).read_volatile() } as usize;
+    if pin == 0 || pin > 4 {
+        return None;
+    }
+    Some(ruxconfig::PCI_IRQ_BASE + (pin - 1 + bdf.device as usize) % 4)
+}
 
 fn config_pci_device(
     root: &mut PciRoot,
@@ -107,9 +131,10 @@ impl AllDevices {
                 if dev_info.header_type != HeaderType::Standard {
                     continue;
                 }
+                let irq = pci_intx_irq(bdf);
                 match config_pci_device(&mut root, bdf, &mut allocator) {
                     Ok(_) => for_each_drivers!(type Driver, {
-                        if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
+                        if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info, irq) {
                             info!(
                                 "registered a new {:?} device at {}: {:?}",
                                 dev.device_type(),
@@ -28,7 +28,11 @@ pub trait DriverProbe {
     }
 
     #[cfg(bus = "mmio")]
-    fn probe_mmio(_mmio_base: usize, _mmio_size: usize) -> Option<AxDeviceEnum> {
+    fn probe_mmio(
+        _mmio_base: usize,
+        _mmio_size: usize,
+        _irq: Option<usize>,
+    ) -> Option<AxDeviceEnum> {
         None
     }
 
@@ -37,6 +41,7 @@ pub trait DriverProbe {
         _root: &mut PciRoot,
         _bdf: DeviceFunction,
         _dev_info: &DeviceFunctionInfo,
+        _irq: Option<usize>,
     ) -> Option<AxDeviceEnum> {
         None
     }
@@ -66,6 +71,12 @@ register_9p_driver!(
     <virtio::VirtIo9p as VirtIoDevMeta>::Device
 );
 
+#[cfg(balloon_dev = "virtio-balloon")]
+register_balloon_driver!(
+    <virtio::VirtIoBalloon as VirtIoDevMeta>::Driver,
+    <virtio::VirtIoBalloon as VirtIoDevMeta>::Device
+);
+
 cfg_if::cfg_if! {
     if #[cfg(block_dev = "ramdisk")] {
         pub struct RamDiskDriver;
@@ -107,6 +118,7 @@ cfg_if::cfg_if! {
                     root: &mut driver_pci::PciRoot,
                     bdf: driver_pci::DeviceFunction,
                     dev_info: &driver_pci::DeviceFunctionInfo,
+                    irq: Option<usize>,
                 ) -> Option<crate::AxDeviceEnum> {
                     use crate::ixgbe::IxgbeHalImpl;
                     use driver_net::ixgbe::{INTEL_82599, INTEL_VEND, IxgbeNic};
@@ -129,7 +141,8 @@ cfg_if::cfg_if! {
                                     phys_to_virt((address as usize).into()).into(),
                                     size as usize
                                 )
-                                .expect("failed to initialize ixgbe device");
+                                .expect("failed to initialize ixgbe device")
+                                .with_irq(irq);
                                 return Some(AxDeviceEnum::from_net(ixgbe_nic));
                             }
                             driver_pci::BarInfo::IO { .. } => {
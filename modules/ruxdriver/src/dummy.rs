@@ -110,6 +110,60 @@ cfg_if! {
     }
 }
 
+cfg_if! {
+    if #[cfg(balloon_dev = "dummy")] {
+        pub struct DummyBalloonDev;
+        pub struct DummyBalloonDriver;
+        register_balloon_driver!(DummyBalloonDriver, DummyBalloonDev);
+
+        impl BaseDriverOps for DummyBalloonDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Balloon
+            }
+            fn device_name(&self) -> &str {
+                "dummy-balloon"
+            }
+        }
+
+        impl BalloonDriverOps for DummyBalloonDev {
+            fn target_pages(&self) -> usize {
+                0
+            }
+            fn actual_pages(&self) -> usize {
+                0
+            }
+            fn set_actual_pages(&mut self, _: usize) {}
+            fn max_pages_per_request(&self) -> usize {
+                0
+            }
+            fn inflate(&mut self, _: &[u32]) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn deflate(&mut self, _: &[u32]) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn deflate_on_oom(&self) -> bool {
+                false
+            }
+            fn stats_requested(&mut self) -> bool {
+                false
+            }
+            fn update_stats(&mut self, _: &[driver_balloon::BalloonStat]) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn max_reported_ranges(&self) -> usize {
+                0
+            }
+            fn report_free_pages(&mut self, _: &mut [&mut [u8]]) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn ack_interrupt(&mut self) -> bool {
+                false
+            }
+        }
+    }
+}
+
 cfg_if! {
     if #[cfg(_9p_dev = "dummy")] {
         pub struct Dummy9pDev;
@@ -44,6 +44,7 @@
 //! | Network | `virtio-net` | VirtIO network device |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
 //! | 9p | `virtio-9p`、`net-9p` | VirtIO/Net 9pfs device |
+//! | Balloon | `virtio-balloon` | VirtIO memory balloon device |
 //!
 //! # Other Cargo Features
 //!
@@ -59,6 +60,7 @@
 //! - `block`: use block storage devices. Similar to the `net` feature.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
 //! - `_9p`: use 9pfs devices. Similar to the `net` feature.
+//! - `balloon`: use memory balloon devices. Similar to the `net` feature.
 //!
 //! [`VirtioNetDev`]: driver_virtio::VirtIoNetDev
 //! [`Box<dyn NetDriverOps>`]: driver_net::NetDriverOps
@@ -97,6 +99,8 @@ pub use self::structs::{AxDeviceContainer, AxDeviceEnum};
 
 #[cfg(feature = "_9p")]
 pub use self::structs::Ax9pDevice;
+#[cfg(feature = "balloon")]
+pub use self::structs::AxBalloonDevice;
 #[cfg(feature = "block")]
 pub use self::structs::AxBlockDevice;
 #[cfg(feature = "display")]
@@ -119,6 +123,9 @@ pub struct AllDevices {
     /// All 9p device drivers.
     #[cfg(feature = "_9p")]
     pub _9p: AxDeviceContainer<Ax9pDevice>,
+    /// All memory balloon device drivers.
+    #[cfg(feature = "balloon")]
+    pub balloon: AxDeviceContainer<AxBalloonDevice>,
 }
 
 impl AllDevices {
@@ -161,6 +168,8 @@ impl AllDevices {
             AxDeviceEnum::Display(dev) => self.display.push(dev),
             #[cfg(feature = "_9p")]
             AxDeviceEnum::_9P(dev) => self._9p.push(dev),
+            #[cfg(feature = "balloon")]
+            AxDeviceEnum::Balloon(dev) => self.balloon.push(dev),
         }
     }
 }
@@ -205,6 +214,14 @@ pub fn init_drivers() -> AllDevices {
             debug!("  9p device {}: {:?}", i, dev.device_name());
         }
     }
+    #[cfg(feature = "balloon")]
+    {
+        debug!("number of balloon devices: {}", all_devs.balloon.len());
+        for (i, dev) in all_devs.balloon.iter().enumerate() {
+            assert_eq!(dev.device_type(), DeviceType::Balloon);
+            debug!("  balloon device {}: {:?}", i, dev.device_name());
+        }
+    }
 
     all_devs
 }
@@ -43,6 +43,14 @@ macro_rules! register_9p_driver {
     };
 }
 
+macro_rules! register_balloon_driver {
+    ($driver_type:ty, $device_type:ty) => {
+        /// The unified type of the memory balloon devices.
+        #[cfg(not(feature = "dyn"))]
+        pub type AxBalloonDevice = $device_type;
+    };
+}
+
 macro_rules! for_each_drivers {
     (type $drv_type:ident, $code:block) => {{
         #[allow(unused_imports)]
@@ -71,6 +79,11 @@ macro_rules! for_each_drivers {
             type $drv_type = <virtio::VirtIo9p as VirtIoDevMeta>::Driver;
             $code
         }
+        #[cfg(balloon_dev = "virtio-balloon")]
+        {
+            type $drv_type = <virtio::VirtIoBalloon as VirtIoDevMeta>::Driver;
+            $code
+        }
         #[cfg(block_dev = "ramdisk")]
         {
             type $drv_type = crate::drivers::RamDiskDriver;
@@ -24,6 +24,9 @@ pub type AxDisplayDevice = Box<dyn DisplayDriverOps>;
 /// The unified type of the 9p devices.
 #[cfg(feature = "_9p")]
 pub type Ax9pDevice = Box<dyn _9pDriverOps>;
+/// The unified type of the memory balloon devices.
+#[cfg(feature = "balloon")]
+pub type AxBalloonDevice = Box<dyn BalloonDriverOps>;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -49,6 +52,12 @@ impl super::AxDeviceEnum {
     pub fn from_9p(dev: impl _9pDriverOps + 'static) -> Self {
         Self::_9P(Box::new(dev))
     }
+
+    /// Constructs a memory balloon device.
+    #[cfg(feature = "balloon")]
+    pub fn from_balloon(dev: impl BalloonDriverOps + 'static) -> Self {
+        Self::Balloon(Box::new(dev))
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
@@ -30,6 +30,9 @@ pub enum AxDeviceEnum {
     /// Plan-9 protocol device.
     #[cfg(feature = "_9p")]
     _9P(Ax9pDevice),
+    /// Memory balloon device.
+    #[cfg(feature = "balloon")]
+    Balloon(AxBalloonDevice),
 }
 
 impl BaseDriverOps for AxDeviceEnum {
@@ -45,6 +48,8 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Display(_) => DeviceType::Display,
             #[cfg(feature = "_9p")]
             Self::_9P(_) => DeviceType::_9P,
+            #[cfg(feature = "balloon")]
+            Self::Balloon(_) => DeviceType::Balloon,
             _ => unreachable!(),
         }
     }
@@ -61,6 +66,8 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Display(dev) => dev.device_name(),
             #[cfg(feature = "_9p")]
             Self::_9P(dev) => dev.device_name(),
+            #[cfg(feature = "balloon")]
+            Self::Balloon(dev) => dev.device_name(),
             _ => unreachable!(),
         }
     }
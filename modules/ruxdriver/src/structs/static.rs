@@ -9,6 +9,8 @@
 
 #[cfg(feature = "_9p")]
 pub use crate::drivers::Ax9pDevice;
+#[cfg(feature = "balloon")]
+pub use crate::drivers::AxBalloonDevice;
 #[cfg(feature = "block")]
 pub use crate::drivers::AxBlockDevice;
 #[cfg(feature = "display")]
@@ -40,6 +42,12 @@ impl super::AxDeviceEnum {
     pub const fn from_9p(dev: Ax9pDevice) -> Self {
         Self::_9P(dev)
     }
+
+    /// Constructs a memory balloon device.
+    #[cfg(feature = "balloon")]
+    pub const fn from_balloon(dev: AxBalloonDevice) -> Self {
+        Self::Balloon(dev)
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
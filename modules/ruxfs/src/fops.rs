@@ -145,6 +145,7 @@ impl File {
             // just open the existing
             node_option?
         };
+        let node = crate::root::share_opened_node(dir, path, node)?;
 
         let attr = node.get_attr()?;
         if attr.is_dir()
@@ -249,6 +250,12 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.node.access(Cap::empty())?.get_attr()
     }
+
+    /// Gets the underlying VFS node, which is shared by all the files opened
+    /// on the same path.
+    pub fn get_node(&self) -> AxResult<VfsNodeRef> {
+        Ok(self.node.access(Cap::empty())?.clone())
+    }
 }
 
 impl Directory {
@@ -17,8 +17,8 @@
 //!    is **enabled** by default.
 //! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
 //!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
-//!    **enabled** by default.
+//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp` and `/dev/shm`. This
+//!    feature is **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -98,6 +98,11 @@ pub fn prepare_commonfs(mount_points: &mut Vec<self::root::MountPoint>) {
     let mount_point = MountPoint::new("/tmp", mounts::ramfs());
     mount_points.push(mount_point);
 
+    // Mount another ramfs as the tmpfs of POSIX shared memory, after devfs
+    #[cfg(feature = "ramfs")]
+    let mount_point = MountPoint::new("/dev/shm", mounts::ramfs());
+    mount_points.push(mount_point);
+
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     let mount_point = MountPoint::new("/proc", mounts::procfs().unwrap());
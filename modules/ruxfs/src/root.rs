@@ -11,7 +11,13 @@
 //!
 //! TODO: it doesn't work very well if the mount points have containment relationships.
 
-use alloc::{format, string::String, sync::Arc, vec::Vec};
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use axerrno::{ax_err, AxError, AxResult};
 use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
@@ -22,6 +28,12 @@ use crate::api::FileType;
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
+/// Nodes of the opened files by absolute path. Some filesystems (e.g. fatfs)
+/// create a new node on each lookup, files opened on the same path share the
+/// node recorded here, so that the state attached to the node (e.g. the page
+/// cache) is shared as well.
+static OPENED_NODES: Mutex<BTreeMap<String, Weak<dyn VfsNodeOps>>> = Mutex::new(BTreeMap::new());
+
 /// mount point information
 pub struct MountPoint {
     path: &'static str,
@@ -211,6 +223,36 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     }
 }
 
+/// Returns the node shared by the files opened on `path`, `node` is the one
+/// just looked up and is recorded if the path is not opened yet.
+pub(crate) fn share_opened_node(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    node: VfsNodeRef,
+) -> AxResult<VfsNodeRef> {
+    // The path relative to a directory node can't be resolved to an absolute one.
+    if dir.is_some() && !path.starts_with('/') {
+        return Ok(node);
+    }
+    let abs_path = absolute_path(path)?;
+    let mut opened = OPENED_NODES.lock();
+    if let Some(shared) = opened.get(&abs_path).and_then(Weak::upgrade) {
+        return Ok(shared);
+    }
+    opened.retain(|_, node| node.strong_count() > 0);
+    opened.insert(abs_path, Arc::downgrade(&node));
+    Ok(node)
+}
+
+/// Stops sharing the node opened on `path`, called after the path is removed
+/// or renamed, the opened files keep working on the old node.
+fn forget_opened_node(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
+    if dir.is_none() || path.starts_with('/') {
+        OPENED_NODES.lock().remove(&absolute_path(path)?);
+    }
+    Ok(())
+}
+
 pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
@@ -248,7 +290,8 @@ pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        parent_node_of(dir, path).remove(path)?;
+        forget_opened_node(dir, path)
     }
 }
 
@@ -314,5 +357,6 @@ pub(crate) fn rename(old: &str, new: &str) -> AxResult {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    parent_node_of(None, old).rename(old, new)?;
+    forget_opened_node(None, old)
 }
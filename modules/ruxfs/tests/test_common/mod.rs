@@ -9,6 +9,8 @@
 
 use axio as io;
 use ruxfs::api as fs;
+use ruxfs::fops;
+use std::sync::Arc;
 
 use fs::{File, FileType, OpenOptions};
 use io::{prelude::*, Error, Result};
@@ -57,6 +59,23 @@ fn test_read_write_file() -> Result<()> {
     Ok(())
 }
 
+fn test_shared_node() -> Result<()> {
+    let fname = "/very/long/path/test.txt";
+    println!("test shared node of {:?}:", fname);
+
+    // files opened on the same path share the node
+    let mut opts = fops::OpenOptions::new();
+    opts.read(true);
+    let file1 = fops::File::open(fname, &opts)?;
+    let file2 = fops::File::open("///very/long/../long/path/./test.txt", &opts)?;
+    assert!(Arc::ptr_eq(&file1.get_node()?, &file2.get_node()?));
+    drop(file1);
+    drop(file2);
+
+    println!("test_shared_node() OK!");
+    Ok(())
+}
+
 fn test_read_dir() -> Result<()> {
     let dir = "/././//./";
     println!("list directory {:?}:", dir);
@@ -254,6 +273,7 @@ fn test_devfs_ramfs() -> Result<()> {
 
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
+    test_shared_node().expect("test_shared_node() failed");
     test_read_dir().expect("test_read_dir() failed");
     test_file_permission().expect("test_file_permission() failed");
     test_create_file_dir().expect("test_create_file_dir() failed");
@@ -13,7 +13,7 @@ use axerrno::{AxError, AxResult};
 use log::{debug, trace};
 
 use super::{
-    types::{FutexBucket, FutexKey},
+    types::{FutexBucket, FutexKey, FutexWakeOp},
     FUTEX_BUCKETS,
 };
 
@@ -210,3 +210,94 @@ pub fn futex_wake_bitset(futex_addr: *const i32, max_count: usize, bitset: u32)
     });
     Ok(task_count)
 }
+
+/// This operation wakes at most `max_wake` of the waiters that are waiting on
+/// the futex at `futex_addr`. If there are more than `max_wake` waiters, then
+/// the remaining waiters are removed from the wait queue of the futex at
+/// `futex_addr` and added to the wait queue of the target futex at
+/// `futex_addr2`. The `max_requeue` argument specifies an upper limit on the
+/// number of waiters that are requeued to the futex at `futex_addr2`.
+///
+/// If `expected` is not [`None`] (the `FUTEX_CMP_REQUEUE` operation), the
+/// value of the futex word at `futex_addr` is checked first, and the call
+/// fails with [`AxError::WouldBlock`] if it does not equal `expected`.
+///
+/// Returns the total number of waiters that were woken up or requeued.
+pub fn futex_requeue(
+    futex_addr: *const i32,
+    max_wake: usize,
+    futex_addr2: *const i32,
+    max_requeue: usize,
+    expected: Option<i32>,
+) -> AxResult<usize> {
+    debug!(
+        "futex_requeue addr: {:#x}, max_wake: {}, addr2: {:#x}, max_requeue: {}, expected: {:?}",
+        futex_addr as usize, max_wake, futex_addr2 as usize, max_requeue, expected
+    );
+
+    let futex_key = FutexKey::new(futex_addr, FUTEX_BITSET_MATCH_ANY);
+    if let Some(expected) = expected {
+        let actual_val = futex_key.load_val();
+        if actual_val != expected {
+            trace!("futex_requeue: expected {}, found {}", expected, actual_val);
+            return Err(AxError::WouldBlock);
+        }
+    }
+
+    let woken = futex_wake(futex_addr, max_wake)?;
+
+    let futex_key2 = FutexKey::new(futex_addr2, FUTEX_BITSET_MATCH_ANY);
+    let (_, futex_bucket) = FUTEX_BUCKETS.get_bucket(futex_key);
+    let (_, futex_bucket2) = FUTEX_BUCKETS.get_bucket(futex_key2);
+
+    let mut count = 0;
+    let requeued = futex_bucket.requeue_tasks_if(futex_bucket2, |_, key| {
+        if count >= max_requeue || futex_key != *key {
+            false
+        } else {
+            key.set_addr(futex_addr2);
+            count += 1;
+            true
+        }
+    });
+    Ok(woken + requeued)
+}
+
+/// This operation modifies the futex word at `futex_addr2` as encoded in
+/// `op`, wakes up at most `max_count` waiters on the futex at `futex_addr`,
+/// and then, if the old value of the futex word at `futex_addr2` meets the
+/// condition encoded in `op`, wakes up at most `max_count2` waiters on the
+/// futex at `futex_addr2`.
+///
+/// `op` has the same encoding as the `val3` argument of the `FUTEX_WAKE_OP`
+/// operation of Linux:
+///
+/// ```text
+/// +---+---+-----------+-----------+
+/// |op |cmp|   oparg   |  cmparg   |
+/// +---+---+-----------+-----------+
+///   4   4       12          12    <== # of bits
+/// ```
+///
+/// Returns the total number of waiters that were woken up.
+pub fn futex_wake_op(
+    futex_addr: *const i32,
+    max_count: usize,
+    futex_addr2: *const i32,
+    max_count2: usize,
+    op: u32,
+) -> AxResult<usize> {
+    debug!(
+        "futex_wake_op addr: {:#x}, max_count: {}, addr2: {:#x}, max_count2: {}, op: {:#x}",
+        futex_addr as usize, max_count, futex_addr2 as usize, max_count2, op
+    );
+
+    let wake_op = FutexWakeOp::decode(op)?;
+    let old_val = wake_op.apply(FutexKey::new(futex_addr2, FUTEX_BITSET_MATCH_ANY).word());
+
+    let mut count = futex_wake(futex_addr, max_count)?;
+    if wake_op.test(old_val) {
+        count += futex_wake(futex_addr2, max_count2)?;
+    }
+    Ok(count)
+}
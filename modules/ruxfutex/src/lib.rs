@@ -23,10 +23,16 @@ extern crate alloc;
 extern crate log;
 
 mod api;
+mod pi;
 mod types;
 
 pub use api::{
-    futex_wait, futex_wait_bitset, futex_wake, futex_wake_bitset, FUTEX_BITSET_MATCH_ANY,
+    futex_requeue, futex_wait, futex_wait_bitset, futex_wake, futex_wake_bitset, futex_wake_op,
+    FUTEX_BITSET_MATCH_ANY,
+};
+pub use pi::{
+    exit_pi_state_list, futex_lock_pi, futex_owner_died, futex_trylock_pi, futex_unlock_pi,
+    FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS,
 };
 
 use types::FutexVec;
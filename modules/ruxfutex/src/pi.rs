@@ -0,0 +1,418 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Priority-inheritance futexes and robust futex cleanup.
+//!
+//! The futex word of a PI futex holds the thread ID of its owner, or 0 if it
+//! is unlocked. The kernel keeps the tasks waiting for a PI futex, and boosts
+//! the owner to the most urgent scheduling policy of them, transitively
+//! through the chain of PI futexes the owner itself is waiting for.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{sync::atomic::Ordering, time::Duration};
+
+use axerrno::{LinuxError, LinuxResult};
+use log::debug;
+use ruxtask::{AxTaskRef, SchedPolicy};
+use spinlock::SpinNoIrq;
+
+use crate::{api::FUTEX_BITSET_MATCH_ANY, types::FutexKey, FUTEX_BUCKETS};
+
+/// The bits of the futex word holding the owner thread ID.
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+/// Set in the futex word if the owner died without unlocking it.
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// Set in the futex word if there are tasks waiting for it.
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+
+/// Maximum length of the chain of owners that priority is propagated along.
+const MAX_PI_CHAIN: usize = 1024;
+
+struct PiState {
+    owner: Option<AxTaskRef>,
+    waiters: Vec<AxTaskRef>,
+}
+
+struct PiTable {
+    /// The PI futexes with waiters, keyed by address.
+    states: BTreeMap<usize, PiState>,
+    /// The PI futex each waiting task is waiting for, keyed by task ID.
+    blocked_on: BTreeMap<u64, usize>,
+}
+
+enum LockAttempt {
+    Acquired,
+    Wait,
+}
+
+static PI_TABLE: SpinNoIrq<PiTable> = SpinNoIrq::new(PiTable {
+    states: BTreeMap::new(),
+    blocked_on: BTreeMap::new(),
+});
+
+fn tid_of(task: &AxTaskRef) -> u32 {
+    task.id().as_u64() as u32
+}
+
+impl PiTable {
+    /// Returns the owner of the PI futex `task` is waiting for.
+    fn blocking_owner(&self, task: &AxTaskRef) -> Option<AxTaskRef> {
+        let addr = self.blocked_on.get(&task.id().as_u64())?;
+        self.states.get(addr)?.owner.clone()
+    }
+
+    /// Recomputes the inherited policy of `task` from the waiters of the PI
+    /// futexes it owns, and propagates it along the chain of owners.
+    fn update_boost(&self, task: &AxTaskRef) {
+        let mut task = task.clone();
+        for _ in 0..MAX_PI_CHAIN {
+            let top = self
+                .states
+                .values()
+                .filter(|s| s.owner.as_ref().is_some_and(|o| Arc::ptr_eq(o, &task)))
+                .flat_map(|s| s.waiters.iter())
+                .map(ruxtask::effective_sched_policy)
+                .fold(None, |top: Option<SchedPolicy>, p| match top {
+                    Some(top) if !p.precedes(&top) => Some(top),
+                    _ => Some(p),
+                });
+            ruxtask::set_inherited_sched_policy(&task, top);
+            match self.blocking_owner(&task) {
+                Some(owner) => task = owner,
+                None => break,
+            }
+        }
+    }
+
+    /// Tries to acquire the PI futex for `curr`, or puts `curr` into its
+    /// waiters if `find_task` is given.
+    fn try_acquire<F>(
+        &mut self,
+        key: FutexKey,
+        curr: &AxTaskRef,
+        find_task: Option<&F>,
+    ) -> LinuxResult<LockAttempt>
+    where
+        F: Fn(u32) -> Option<AxTaskRef>,
+    {
+        let addr = key.addr();
+        let word = key.word();
+        let tid = tid_of(curr);
+        let owner_tid = loop {
+            let val = word.load(Ordering::SeqCst);
+            let owner_tid = val & FUTEX_TID_MASK;
+            if owner_tid == 0 {
+                // The futex is free, or its owner died.
+                let has_waiters = self
+                    .states
+                    .get(&addr)
+                    .is_some_and(|s| !s.waiters.is_empty());
+                let mut new_val = tid | (val & FUTEX_OWNER_DIED);
+                if has_waiters {
+                    new_val |= FUTEX_WAITERS;
+                }
+                if word
+                    .compare_exchange(val, new_val, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+                {
+                    continue;
+                }
+                if let Some(state) = self.states.get_mut(&addr) {
+                    state.owner = Some(curr.clone());
+                    self.update_boost(curr);
+                }
+                return Ok(LockAttempt::Acquired);
+            }
+            if owner_tid == tid {
+                return Err(LinuxError::EDEADLK);
+            }
+            if find_task.is_none() {
+                return Err(LinuxError::EAGAIN);
+            }
+            if val & FUTEX_WAITERS == 0
+                && word
+                    .compare_exchange(val, val | FUTEX_WAITERS, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+            {
+                continue;
+            }
+            break owner_tid;
+        };
+
+        let owner = match self.states.get(&addr).and_then(|s| s.owner.clone()) {
+            Some(owner) if tid_of(&owner) == owner_tid => owner,
+            _ => find_task
+                .and_then(|f| f(owner_tid))
+                .ok_or(LinuxError::ESRCH)?,
+        };
+        // Waiting for a task that is waiting for us, directly or not.
+        let mut task = owner.clone();
+        for _ in 0..MAX_PI_CHAIN {
+            if Arc::ptr_eq(&task, curr) {
+                return Err(LinuxError::EDEADLK);
+            }
+            match self.blocking_owner(&task) {
+                Some(next) => task = next,
+                None => break,
+            }
+        }
+
+        let state = self.states.entry(addr).or_insert_with(|| PiState {
+            owner: None,
+            waiters: Vec::new(),
+        });
+        state.owner = Some(owner.clone());
+        state.waiters.push(curr.clone());
+        self.blocked_on.insert(curr.id().as_u64(), addr);
+        self.update_boost(&owner);
+        Ok(LockAttempt::Wait)
+    }
+
+    /// Removes `curr` from the waiters of the PI futex at `addr`, after it
+    /// gives up waiting.
+    fn remove_waiter(&mut self, addr: usize, curr: &AxTaskRef) {
+        self.blocked_on.remove(&curr.id().as_u64());
+        let Some(state) = self.states.get_mut(&addr) else {
+            return;
+        };
+        state.waiters.retain(|t| !Arc::ptr_eq(t, curr));
+        let owner = state.owner.clone();
+        if state.waiters.is_empty() {
+            self.states.remove(&addr);
+        }
+        if let Some(owner) = owner {
+            self.update_boost(&owner);
+        }
+    }
+
+    /// Hands the PI futex over to its most urgent waiter, or releases it if
+    /// there are no waiters. Returns the new owner to wake up.
+    fn hand_over(
+        &mut self,
+        key: FutexKey,
+        curr: &AxTaskRef,
+        owner_died: bool,
+    ) -> Option<AxTaskRef> {
+        let addr = key.addr();
+        let died = if owner_died { FUTEX_OWNER_DIED } else { 0 };
+        let next = self.states.get_mut(&addr).and_then(|state| {
+            let (idx, _) = state
+                .waiters
+                .iter()
+                .map(ruxtask::effective_sched_policy)
+                .enumerate()
+                .fold(
+                    None,
+                    |top: Option<(usize, SchedPolicy)>, (i, p)| match top {
+                        Some((_, top_p)) if !p.precedes(&top_p) => top,
+                        _ => Some((i, p)),
+                    },
+                )?;
+            // Keep the order of the rest waiters.
+            let next = state.waiters.remove(idx);
+            let mut new_val = tid_of(&next) | died;
+            if !state.waiters.is_empty() {
+                new_val |= FUTEX_WAITERS;
+            }
+            key.word().store(new_val, Ordering::SeqCst);
+            state.owner = Some(next.clone());
+            Some(next)
+        });
+
+        match &next {
+            Some(next) => {
+                self.blocked_on.remove(&next.id().as_u64());
+                if self.states.get(&addr).is_some_and(|s| s.waiters.is_empty()) {
+                    self.states.remove(&addr);
+                }
+                self.update_boost(next);
+            }
+            None => {
+                self.states.remove(&addr);
+                key.word().store(died, Ordering::SeqCst);
+            }
+        }
+        self.update_boost(curr);
+        next
+    }
+}
+
+fn futex_lock_pi_common<F>(
+    futex_addr: *const i32,
+    timeout: Option<Duration>,
+    find_task: Option<&F>,
+) -> LinuxResult<()>
+where
+    F: Fn(u32) -> Option<AxTaskRef>,
+{
+    let curr = ruxtask::current();
+    let curr = curr.as_task_ref();
+    let futex_key = FutexKey::new(futex_addr, FUTEX_BITSET_MATCH_ANY);
+    let (_, futex_bucket) = FUTEX_BUCKETS.get_bucket(futex_key);
+
+    loop {
+        // Check the futex word with the bucket locked, so that the unlocker
+        // can not miss us.
+        let condition = || match PI_TABLE.lock().try_acquire(futex_key, curr, find_task) {
+            Ok(LockAttempt::Wait) => Ok(()),
+            Ok(LockAttempt::Acquired) => Err(Ok(())),
+            Err(e) => Err(Err(e)),
+        };
+        let timed_out = match timeout {
+            Some(deadline) => {
+                futex_bucket.wait_timeout_absolutely_meta_if(deadline, futex_key, condition)
+            }
+            None => futex_bucket
+                .wait_meta_if(futex_key, condition)
+                .map(|_| false),
+        };
+        let timed_out = match timed_out {
+            Ok(timed_out) => timed_out,
+            Err(res) => return res,
+        };
+
+        let mut table = PI_TABLE.lock();
+        // The futex has been handed over to us.
+        if futex_key.word().load(Ordering::SeqCst) & FUTEX_TID_MASK == tid_of(curr) {
+            return Ok(());
+        }
+        table.remove_waiter(futex_key.addr(), curr);
+        if timed_out {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+    }
+}
+
+/// This operation is used after an attempt to acquire the lock via an atomic
+/// user-mode instruction failed because the futex word at `futex_addr` has a
+/// nonzero value, i.e. the thread ID of the lock owner.
+///
+/// If the lock is still owned, the calling task is blocked until the owner
+/// unlocks it with [`futex_unlock_pi`], and the owner inherits the scheduling
+/// policy of the calling task if it is more urgent. `find_task` is used to
+/// find the owner by its thread ID.
+///
+/// If timeout is not [`None`], it specifies an absolute timeout for the wait
+/// operation.
+pub fn futex_lock_pi<F>(
+    futex_addr: *const i32,
+    timeout: Option<Duration>,
+    find_task: F,
+) -> LinuxResult<()>
+where
+    F: Fn(u32) -> Option<AxTaskRef>,
+{
+    debug!(
+        "futex_lock_pi addr: {:#x}, timeout: {:?}",
+        futex_addr as usize, timeout
+    );
+    futex_lock_pi_common(futex_addr, timeout, Some(&find_task))
+}
+
+/// This operation tries to acquire the lock at `futex_addr` like
+/// [`futex_lock_pi`], but fails with [`LinuxError::EAGAIN`] instead of
+/// blocking if the lock is owned.
+pub fn futex_trylock_pi(futex_addr: *const i32) -> LinuxResult<()> {
+    debug!("futex_trylock_pi addr: {:#x}", futex_addr as usize);
+    futex_lock_pi_common::<fn(u32) -> Option<AxTaskRef>>(futex_addr, None, None)
+}
+
+/// This operation wakes up the most urgent waiter of the lock at
+/// `futex_addr`, and hands the lock over to it. The calling task must own
+/// the lock, and gives up the scheduling policy inherited from the waiters.
+pub fn futex_unlock_pi(futex_addr: *const i32) -> LinuxResult<()> {
+    debug!("futex_unlock_pi addr: {:#x}", futex_addr as usize);
+    let curr = ruxtask::current();
+    let futex_key = FutexKey::new(futex_addr, FUTEX_BITSET_MATCH_ANY);
+    if futex_key.word().load(Ordering::SeqCst) & FUTEX_TID_MASK != tid_of(curr.as_task_ref()) {
+        return Err(LinuxError::EPERM);
+    }
+    futex_release(futex_key, curr.as_task_ref(), true, false);
+    Ok(())
+}
+
+fn futex_release(futex_key: FutexKey, curr: &AxTaskRef, pi: bool, owner_died: bool) {
+    if !pi {
+        let word = futex_key.word();
+        let _ = word.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
+            Some((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED)
+        });
+        let _ = crate::futex_wake(futex_key.addr() as *const i32, 1);
+        return;
+    }
+    let next = PI_TABLE.lock().hand_over(futex_key, curr, owner_died);
+    if let Some(next) = next {
+        let (_, futex_bucket) = FUTEX_BUCKETS.get_bucket(futex_key);
+        futex_bucket.notify_task(false, &next);
+    }
+}
+
+/// Releases the lock at `futex_addr` owned by the exiting current task, as
+/// required by the robust futex list.
+///
+/// The [`FUTEX_OWNER_DIED`] bit is set in the futex word. A waiter is woken
+/// up for a normal futex, and the lock is handed over to the most urgent
+/// waiter for a PI futex (`pi` is true). Nothing is done if the current task
+/// does not own the lock.
+pub fn futex_owner_died(futex_addr: *const i32, pi: bool) {
+    let curr = ruxtask::current();
+    let futex_key = FutexKey::new(futex_addr, FUTEX_BITSET_MATCH_ANY);
+    if futex_key.word().load(Ordering::SeqCst) & FUTEX_TID_MASK != tid_of(curr.as_task_ref()) {
+        return;
+    }
+    debug!(
+        "futex_owner_died addr: {:#x}, pi: {}",
+        futex_addr as usize, pi
+    );
+    futex_release(futex_key, curr.as_task_ref(), pi, true);
+}
+
+/// Hands over or releases the PI futexes still owned by the exiting current
+/// task, like `exit_pi_state_list` of Linux. It's called after the robust
+/// futex list is released, for the PI futexes not in the list.
+///
+/// Each lock is handed over to its most urgent waiter with
+/// [`FUTEX_OWNER_DIED`] set in the futex word. The current task is also
+/// removed from the waiters, so that the kernel holds no reference to it.
+pub fn exit_pi_state_list() {
+    let curr = ruxtask::current();
+    let curr = curr.as_task_ref();
+    let tid = tid_of(curr);
+    loop {
+        let mut table = PI_TABLE.lock();
+        if let Some(&addr) = table.blocked_on.get(&curr.id().as_u64()) {
+            table.remove_waiter(addr, curr);
+        }
+        let Some(addr) = table
+            .states
+            .iter()
+            .find(|(_, s)| s.owner.as_ref().is_some_and(|o| Arc::ptr_eq(o, curr)))
+            .map(|(&addr, _)| addr)
+        else {
+            break;
+        };
+        let futex_key = FutexKey::new(addr as *const i32, FUTEX_BITSET_MATCH_ANY);
+        if futex_key.word().load(Ordering::SeqCst) & FUTEX_TID_MASK != tid {
+            // The futex word is not ours any more, only drop the reference,
+            // the waiters look up the real owner when they retry.
+            if let Some(state) = table.states.get_mut(&addr) {
+                state.owner = None;
+            }
+            table.update_boost(curr);
+            continue;
+        }
+        debug!("exit_pi_state_list addr: {:#x}", addr);
+        let next = table.hand_over(futex_key, curr, true);
+        drop(table);
+        if let Some(next) = next {
+            let (_, futex_bucket) = FUTEX_BUCKETS.get_bucket(futex_key);
+            futex_bucket.notify_task(false, &next);
+        }
+    }
+}
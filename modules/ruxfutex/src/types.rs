@@ -10,11 +10,12 @@
 use core::{
     fmt::Debug,
     hash::{Hash, Hasher},
-    sync::atomic::{self, AtomicI32},
+    sync::atomic::{self, AtomicI32, AtomicU32},
 };
 
 use ahash::AHasher;
 use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
 
 use ruxtask::WaitQueueWithMetadata;
 
@@ -60,6 +61,18 @@ impl FutexKey {
         unsafe { (*ptr).load(atomic::Ordering::SeqCst) }
     }
 
+    /// Returns the futex word as an [`AtomicU32`].
+    #[inline]
+    pub fn word(&self) -> &AtomicU32 {
+        unsafe { &*(self.key as *const AtomicU32) }
+    }
+
+    /// Makes the key reference another address, keeping the bitset.
+    #[inline]
+    pub fn set_addr(&mut self, addr: *const i32) {
+        self.key = addr as usize;
+    }
+
     /// Return the address that this futex key references.
     #[inline]
     pub fn addr(&self) -> usize {
@@ -99,3 +112,80 @@ impl FutexVec {
         (idx, &self.buckets[idx])
     }
 }
+
+/// The operation and comparison encoded in the argument of
+/// [`futex_wake_op`](crate::futex_wake_op).
+pub(crate) struct FutexWakeOp {
+    op: u32,
+    oparg: u32,
+    cmp: u32,
+    cmparg: i32,
+}
+
+impl FutexWakeOp {
+    const OP_SET: u32 = 0;
+    const OP_ADD: u32 = 1;
+    const OP_OR: u32 = 2;
+    const OP_ANDN: u32 = 3;
+    const OP_XOR: u32 = 4;
+    /// Use `1 << oparg` as the operand.
+    const OP_ARG_SHIFT: u32 = 8;
+
+    const CMP_EQ: u32 = 0;
+    const CMP_NE: u32 = 1;
+    const CMP_LT: u32 = 2;
+    const CMP_LE: u32 = 3;
+    const CMP_GT: u32 = 4;
+    const CMP_GE: u32 = 5;
+
+    pub fn decode(bits: u32) -> AxResult<Self> {
+        let op = bits >> 28;
+        let cmp = (bits >> 24) & 0xf;
+        // both arguments are 12-bit signed integers
+        let oparg = (((bits << 8) as i32) >> 20) as u32;
+        let cmparg = ((bits << 20) as i32) >> 20;
+
+        let (op, oparg) = if op & Self::OP_ARG_SHIFT != 0 {
+            if oparg > 31 {
+                return ax_err!(InvalidInput, "invalid futex wake op shift: {}", oparg);
+            }
+            (op & !Self::OP_ARG_SHIFT, 1 << oparg)
+        } else {
+            (op, oparg)
+        };
+        if op > Self::OP_XOR || cmp > Self::CMP_GE {
+            return ax_err!(InvalidInput, "invalid futex wake op: {:#x}", bits);
+        }
+        Ok(Self {
+            op,
+            oparg,
+            cmp,
+            cmparg,
+        })
+    }
+
+    /// Applies the operation to the futex word atomically, returns its old value.
+    pub fn apply(&self, word: &AtomicU32) -> i32 {
+        let ord = atomic::Ordering::SeqCst;
+        let old = match self.op {
+            Self::OP_SET => word.swap(self.oparg, ord),
+            Self::OP_ADD => word.fetch_add(self.oparg, ord),
+            Self::OP_OR => word.fetch_or(self.oparg, ord),
+            Self::OP_ANDN => word.fetch_and(!self.oparg, ord),
+            _ => word.fetch_xor(self.oparg, ord),
+        };
+        old as i32
+    }
+
+    /// Tests the old value of the futex word against the comparison.
+    pub fn test(&self, old_val: i32) -> bool {
+        match self.cmp {
+            Self::CMP_EQ => old_val == self.cmparg,
+            Self::CMP_NE => old_val != self.cmparg,
+            Self::CMP_LT => old_val < self.cmparg,
+            Self::CMP_LE => old_val <= self.cmparg,
+            Self::CMP_GT => old_val > self.cmparg,
+            _ => old_val >= self.cmparg,
+        }
+    }
+}
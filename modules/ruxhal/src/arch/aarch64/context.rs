@@ -116,6 +116,14 @@ impl TaskContext {
         self.tpidr_el0 = tls_area.as_usize() as u64;
     }
 
+    /// Returns the instruction pointer and frame pointer saved when the task
+    /// was switched out, used to unwind its stack.
+    ///
+    /// The result is meaningless for the currently running task.
+    pub fn saved_frame(&self) -> (usize, usize) {
+        (self.lr as usize, self.r29 as usize)
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -45,6 +45,18 @@ pub fn wait_for_irqs() {
     aarch64_cpu::asm::wfi();
 }
 
+/// Enables interrupts and waits for interrupts, called with interrupts
+/// disabled.
+///
+/// An interrupt arrived after disabling interrupts also wakes up the CPU, so
+/// no interrupt is missed between checking a condition and waiting.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // WFI is woken up by pending interrupts even if they are masked.
+    aarch64_cpu::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -145,3 +157,24 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
     TPIDR_EL0.set(tpidr_el0 as _)
 }
+
+/// Returns a random number from the `RNDR` register of `FEAT_RNG`, or
+/// [`None`] if the CPU does not support it or fails to generate one.
+pub fn hw_random() -> Option<u64> {
+    let isar0: u64;
+    unsafe { asm!("mrs {}, ID_AA64ISAR0_EL1", out(reg) isar0) };
+    if (isar0 >> 60) & 0xf == 0 {
+        return None;
+    }
+    let (val, ok): (u64, u64);
+    // `NZCV.Z` is set if no random number is available in a reasonable time.
+    unsafe {
+        asm!(
+            "mrs {val}, s3_3_c2_c4_0",
+            "cset {ok}, ne",
+            val = out(reg) val,
+            ok = out(reg) ok,
+        )
+    };
+    (ok != 0).then_some(val)
+}
@@ -18,7 +18,14 @@ use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
 
-global_asm!(include_str!("trap.S"));
+/// Bytes pushed to the stack by the entry of synchronous exceptions: the trap
+/// frame and the NEON registers.
+const SYNC_FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>() + 50 * 8;
+
+global_asm!(
+    include_str!("trap.S"),
+    sync_frame_size = const SYNC_FRAME_SIZE,
+);
 
 #[repr(u8)]
 #[derive(Debug)]
@@ -48,6 +55,22 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
     );
 }
 
+/// Reports a synchronous exception from the kernel whose trap frame does not
+/// fit below `sp`, running on the overflow stack of this CPU.
+#[no_mangle]
+fn handle_kernel_stack_overflow(tf: &TrapFrame, sp: usize) -> ! {
+    let vaddr = FAR_EL1.get() as usize;
+    #[cfg(feature = "paging")]
+    {
+        crate::trap::check_stack_overflow(vaddr, tf.elr as usize);
+        crate::trap::check_stack_overflow(sp.wrapping_sub(SYNC_FRAME_SIZE), tf.elr as usize);
+    }
+    panic!(
+        "EL1 Page Fault without room for the trap frame @ {:#x}, FAR={:#x}, SP={:#x}:\n{:#x?}",
+        tf.elr, vaddr, sp, tf,
+    );
+}
+
 #[no_mangle]
 fn handle_sync_exception(tf: &mut TrapFrame) {
     let esr = ESR_EL1.extract();
@@ -93,6 +116,10 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
             #[cfg(feature = "paging")]
             {
                 let vaddr = FAR_EL1.get() as usize;
+                // Faults leaving no room for the trap frame are reported on
+                // entry, an overflow reaching here accessed the guard area
+                // far below SP.
+                crate::trap::check_stack_overflow(vaddr, tf.elr as usize);
 
                 // this cause is coded like linux.
                 let cause: PageFaultCause = match esr.read_as_enum(ESR_EL1::EC) {
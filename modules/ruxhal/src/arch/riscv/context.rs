@@ -112,6 +112,14 @@ impl TaskContext {
         self.tp = tls_area.as_usize();
     }
 
+    /// Returns the instruction pointer and frame pointer saved when the task
+    /// was switched out, used to unwind its stack.
+    ///
+    /// The result is meaningless for the currently running task.
+    pub fn saved_frame(&self) -> (usize, usize) {
+        (self.ra, self.s0)
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
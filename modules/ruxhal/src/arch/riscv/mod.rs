@@ -45,6 +45,18 @@ pub fn wait_for_irqs() {
     unsafe { riscv::asm::wfi() }
 }
 
+/// Enables interrupts and waits for interrupts, called with interrupts
+/// disabled.
+///
+/// An interrupt arrived after disabling interrupts also wakes up the CPU, so
+/// no interrupt is missed between checking a condition and waiting.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // WFI is woken up by pending interrupts even if `sstatus.SIE` is clear.
+    unsafe { riscv::asm::wfi() };
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -116,3 +128,9 @@ pub fn read_thread_pointer() -> usize {
 pub unsafe fn write_thread_pointer(tp: usize) {
     core::arch::asm!("mv tp, {}", in(reg) tp)
 }
+
+/// Returns a random number generated by the hardware, which is not supported
+/// on riscv yet, so it always returns [`None`].
+pub fn hw_random() -> Option<u64> {
+    None
+}
@@ -8,6 +8,7 @@
  */
 
 use riscv::register::scause::{self, Exception as E, Trap};
+use riscv::register::stval;
 
 use super::TrapFrame;
 
@@ -23,6 +24,22 @@ fn handle_breakpoint(sepc: &mut usize) {
     *sepc += 2
 }
 
+/// Handles page faults from S mode, running on the overflow stack of this CPU
+/// as they may be caused by a kernel stack overflow.
+#[no_mangle]
+fn riscv_kernel_page_fault(tf: &TrapFrame) -> ! {
+    let vaddr = stval::read();
+    #[cfg(feature = "paging")]
+    crate::trap::check_stack_overflow(vaddr, tf.sepc);
+    panic!(
+        "Unhandled trap {:?} @ {:#x}, stval={:#x}:\n{:#x?}",
+        scause::read().cause(),
+        tf.sepc,
+        vaddr,
+        tf
+    );
+}
+
 #[no_mangle]
 fn riscv_trap_handler(tf: &mut TrapFrame, _from_user: bool) {
     let scause = scause::read();
@@ -186,6 +186,18 @@ impl TaskContext {
         self.fs_base = tls_area.as_usize();
     }
 
+    /// Returns the instruction pointer and frame pointer saved when the task
+    /// was switched out, used to unwind its stack.
+    ///
+    /// The result is meaningless for the currently running task.
+    pub fn saved_frame(&self) -> (usize, usize) {
+        if self.rsp == 0 {
+            return (0, 0);
+        }
+        let frame = unsafe { &*(self.rsp as *const ContextSwitchFrame) };
+        (frame.rip as usize, frame.rbp as usize)
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
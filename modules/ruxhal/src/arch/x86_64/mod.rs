@@ -38,7 +38,7 @@ use {
 
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
 pub use self::gdt::GdtStruct;
-pub use self::idt::IdtStruct;
+pub use self::idt::{IdtStruct, DOUBLE_FAULT_IST_INDEX};
 pub use x86_64::structures::tss::TaskStateSegment;
 
 /// Allows the current CPU to respond to interrupts.
@@ -73,6 +73,22 @@ pub fn wait_for_irqs() {
     }
 }
 
+/// Enables interrupts and waits for interrupts, called with interrupts
+/// disabled.
+///
+/// An interrupt arrived after disabling interrupts also wakes up the CPU, so
+/// no interrupt is missed between checking a condition and waiting.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    if cfg!(target_os = "none") {
+        // Interrupts are not taken until the instruction after `sti` is done.
+        unsafe { asm!("sti; hlt") }
+    } else {
+        enable_irqs();
+        core::hint::spin_loop()
+    }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -248,3 +264,19 @@ pub unsafe fn init_syscall_entry() {
         msr::wrmsr(msr::IA32_FMASK, 0x47700);
     }
 }
+
+/// Returns a random number from the `rdrand` instruction, or [`None`] if the
+/// CPU does not support it or fails to generate one.
+pub fn hw_random() -> Option<u64> {
+    use core::arch::x86_64::{__cpuid, _rdrand64_step};
+    // Safe because `cpuid` is always available on x86_64.
+    if unsafe { __cpuid(1) }.ecx & (1 << 30) == 0 {
+        return None;
+    }
+    let mut val = 0;
+    // Intel recommends retrying 10 times if the entropy is exhausted.
+    (0..10).find_map(|_| match unsafe { _rdrand64_step(&mut val) } {
+        1 => Some(val),
+        _ => None,
+    })
+}
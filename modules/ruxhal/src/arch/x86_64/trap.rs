@@ -36,6 +36,8 @@ fn x86_trap_handler(tf: &TrapFrame) {
                 );
             } else {
                 let vaddr = unsafe { cr2() };
+                #[cfg(feature = "paging")]
+                crate::trap::check_stack_overflow(vaddr, tf.rip as usize);
                 #[cfg(any(
                     all(feature = "paging", feature = "irq", feature = "smp"),
                     all(feature = "paging", not(feature = "smp"))
@@ -57,6 +59,14 @@ fn x86_trap_handler(tf: &TrapFrame) {
                 );
             }
         }
+        DOUBLE_FAULT_VECTOR => {
+            // The CPU fails to push the trap frame of a page fault if the
+            // stack overflows, it runs on its own stack, see `IdtStruct`.
+            let vaddr = unsafe { cr2() };
+            #[cfg(feature = "paging")]
+            crate::trap::check_stack_overflow(vaddr, tf.rip as usize);
+            panic!("#DF @ {:#x}, fault_vaddr={:#x}:\n{:#x?}", tf.rip, vaddr, tf);
+        }
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
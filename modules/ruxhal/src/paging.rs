@@ -9,6 +9,8 @@
 
 //! Page table manipulation.
 extern crate alloc;
+use alloc::collections::BTreeMap;
+
 use crate::arch::flush_tlb;
 use spinlock::SpinNoIrq;
 
@@ -16,7 +18,7 @@ use crate::mem::{
     direct_virt_to_phys, memory_regions, phys_to_virt, MemRegionFlags, PhysAddr, VirtAddr,
     PAGE_SIZE_4K,
 };
-use axalloc::global_allocator;
+use axalloc::{global_allocator, GlobalPage, MemTag};
 use lazy_init::LazyInit;
 
 #[doc(no_inline)]
@@ -125,11 +127,43 @@ pub fn alloc_page_preload() -> Result<VirtAddr, PagingError> {
         return Err(PagingError::NoMemory);
     };
     match global_allocator().alloc_pages(1, PAGE_SIZE_4K) {
-        Ok(fake_vaddr) => Ok(VirtAddr::from(fake_vaddr)),
+        Ok(fake_vaddr) => {
+            axalloc::charge(MemTag::MmapPage, PAGE_SIZE_4K);
+            Ok(VirtAddr::from(fake_vaddr))
+        }
         Err(_) => Err(PagingError::NoMemory),
     }
 }
 
+/// Obtain a fake VirtAddr of a 2M huge page, like `alloc_page_preload`.
+///
+/// The physically contiguous frames are allocated aligned to 2M, and they
+/// are freed by `pte_unmap_page` or one by one after the page is split.
+/// use `do_pte_map_huge` to do actually page mapping after call this function.
+pub fn alloc_huge_page_preload() -> Result<VirtAddr, PagingError> {
+    let num_pages = PageSize::Size2M as usize / PAGE_SIZE_4K;
+    if global_allocator().available_pages() < PAGE_NUM_MIN + num_pages {
+        return Err(PagingError::NoMemory);
+    };
+    match GlobalPage::alloc_contiguous(num_pages, PageSize::Size2M as usize) {
+        Ok(page) => {
+            let fake_vaddr = page.start_vaddr();
+            // The frames are owned by the PTE after mapping.
+            core::mem::forget(page);
+            axalloc::charge(MemTag::MmapPage, num_pages * PAGE_SIZE_4K);
+            Ok(fake_vaddr)
+        }
+        Err(_) => Err(PagingError::NoMemory),
+    }
+}
+
+/// Free the pages obtained by `alloc_page_preload`, `alloc_huge_page_preload`
+/// or `pte_swap_preload` which are not mapped.
+pub fn free_page_preload(fake_vaddr: VirtAddr, num_pages: usize) {
+    axalloc::uncharge(MemTag::MmapPage, num_pages * PAGE_SIZE_4K);
+    global_allocator().dealloc_pages(fake_vaddr.as_usize(), num_pages);
+}
+
 /// Unmap memory for an mmap-induced PageFault and updating PTE entries.
 /// After call the function. the page is alloced in allocator but its virtual
 /// address is still on linear mapping region.
@@ -154,6 +188,28 @@ pub fn do_pte_map(vaddr: VirtAddr, fake_vaddr: VirtAddr, flags: MappingFlags) ->
     )
 }
 
+/// Map a 2M huge page for an mmap-induced PageFault, like `do_pte_map`.
+///
+/// This function must be called after `alloc_huge_page_preload`.
+pub fn do_pte_map_huge(vaddr: VirtAddr, fake_vaddr: VirtAddr, flags: MappingFlags) -> PagingResult {
+    KERNEL_PAGE_TABLE.lock().map(
+        vaddr,
+        direct_virt_to_phys(fake_vaddr),
+        PageSize::Size2M,
+        flags,
+    )
+}
+
+/// Split the huge page containing the virtual address into smaller pages.
+///
+/// the pages keep mapping the same memory, return the page size after splitting
+pub fn pte_split_huge_page(vaddr: VirtAddr) -> PagingResult<PageSize> {
+    trace!("splitting huge page of vaddr: 0x{:x?}", vaddr);
+    let size = KERNEL_PAGE_TABLE.lock().split_huge(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok(size)
+}
+
 /// Query PTE entries of the virtual address.
 ///
 /// get the physical address information corresponding to the virtual address from the page table
@@ -181,13 +237,163 @@ pub fn pte_update_page(
     Ok(())
 }
 
+/// Clear the dirty state of an PTE entry.
+///
+/// return whether the page has been written since the last clearing
+pub fn pte_clear_dirty(vaddr: VirtAddr) -> PagingResult<bool> {
+    let dirty = KERNEL_PAGE_TABLE.lock().clear_dirty(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok(dirty)
+}
+
+/// Set the dirty state of an PTE entry after a write fault on a clean page.
+///
+/// return whether the page was clean, i.e. the fault is caused by dirty tracking
+pub fn pte_set_dirty(vaddr: VirtAddr) -> PagingResult<bool> {
+    let clean = KERNEL_PAGE_TABLE.lock().set_dirty(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok(clean)
+}
+
+/// Clear the accessed state of an PTE entry.
+///
+/// return whether the page has been accessed since the last clearing
+pub fn pte_clear_accessed(vaddr: VirtAddr) -> PagingResult<bool> {
+    let accessed = KERNEL_PAGE_TABLE.lock().clear_accessed(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok(accessed)
+}
+
+/// Set the accessed state of an PTE entry after a fault on a page whose
+/// accessed state is cleared.
+///
+/// return whether the page was not accessed, i.e. the fault is caused by access tracking
+pub fn pte_set_accessed(vaddr: VirtAddr) -> PagingResult<bool> {
+    let unaccessed = KERNEL_PAGE_TABLE.lock().set_accessed(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok(unaccessed)
+}
+
+/// Unmapping an page shared with other mappings, and keep its memory.
+///
+/// return the page on the linear mapping region and whether it is dirty
+pub fn pte_unmap_shared_page(vaddr: VirtAddr) -> PagingResult<(VirtAddr, bool)> {
+    trace!("unmapping shared vaddr: 0x{:x?}", vaddr);
+    let mut kernel_page_table = KERNEL_PAGE_TABLE.lock();
+    let dirty = kernel_page_table.clear_dirty(vaddr)?;
+    let (paddr, _) = kernel_page_table.unmap(vaddr)?;
+    flush_tlb(Some(vaddr));
+    Ok((phys_to_virt(paddr), dirty))
+}
+
 /// Unmapping and decalloc memory for an page in page table.
 ///
-/// release the corresponding memory at the same time
+/// release the corresponding memory at the same time, all of the frames for a huge page
 pub fn pte_unmap_page(vaddr: VirtAddr) -> PagingResult {
     trace!("unmapping vaddr: 0x{:x?}", vaddr);
-    let (paddr, _) = KERNEL_PAGE_TABLE.lock().unmap(vaddr)?;
-    global_allocator().dealloc_pages(phys_to_virt(paddr).as_usize(), 1);
+    let (paddr, size) = KERNEL_PAGE_TABLE.lock().unmap(vaddr)?;
+    free_page_preload(phys_to_virt(paddr), size as usize / PAGE_SIZE_4K);
     flush_tlb(Some(vaddr));
     Ok(())
 }
+
+/// A kernel stack mapped in the stack area.
+#[derive(Clone, Copy)]
+struct KernelStack {
+    size: usize,
+    guard_size: usize,
+    /// ID of the task owning the stack.
+    owner: u64,
+}
+
+/// Kernel stacks mapped in the stack area, indexed by their bottom addresses.
+///
+/// Each stack has an unmapped guard area right below it, so that an overflow
+/// causes a page fault instead of corrupting other memory.
+static KERNEL_STACKS: SpinNoIrq<BTreeMap<usize, KernelStack>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Finds a free range in the stack area for a stack of `size` bytes and its
+/// guard area of `guard_size` bytes, returns the bottom address of the stack.
+fn find_free_stack_range(
+    stacks: &BTreeMap<usize, KernelStack>,
+    size: usize,
+    guard_size: usize,
+) -> Option<usize> {
+    let mut cursor = ruxconfig::TASK_STACK_START_VADDR;
+    for (&bottom, stack) in stacks.iter() {
+        if cursor + guard_size + size <= bottom - stack.guard_size {
+            return Some(cursor + guard_size);
+        }
+        cursor = bottom + stack.size;
+    }
+    (cursor + guard_size + size <= ruxconfig::TASK_STACK_END_VADDR).then_some(cursor + guard_size)
+}
+
+/// Allocates a kernel stack of `size` bytes for the task `owner`, and maps it
+/// in the stack area with an unmapped guard area of `guard_size` bytes below
+/// it. Both sizes are rounded up to multiples of the page size.
+///
+/// Returns the bottom address of the stack.
+pub fn alloc_kernel_stack(size: usize, guard_size: usize, owner: u64) -> PagingResult<VirtAddr> {
+    let size = memory_addr::align_up_4k(size);
+    let guard_size = memory_addr::align_up_4k(guard_size);
+    let num_pages = size / PAGE_SIZE_4K;
+    let frames = global_allocator()
+        .alloc_pages(num_pages, PAGE_SIZE_4K)
+        .map_err(|_| PagingError::NoMemory)?;
+    let mut stacks = KERNEL_STACKS.lock();
+    let Some(bottom) = find_free_stack_range(&stacks, size, guard_size) else {
+        global_allocator().dealloc_pages(frames, num_pages);
+        return Err(PagingError::NoMemory);
+    };
+    let res = KERNEL_PAGE_TABLE.lock().map_region(
+        VirtAddr::from(bottom),
+        direct_virt_to_phys(VirtAddr::from(frames)),
+        size,
+        MappingFlags::READ | MappingFlags::WRITE,
+        false,
+    );
+    if let Err(e) = res {
+        global_allocator().dealloc_pages(frames, num_pages);
+        return Err(e);
+    }
+    stacks.insert(
+        bottom,
+        KernelStack {
+            size,
+            guard_size,
+            owner,
+        },
+    );
+    Ok(VirtAddr::from(bottom))
+}
+
+/// Unmaps and deallocates the kernel stack allocated by
+/// [`alloc_kernel_stack`] at `bottom`.
+pub fn dealloc_kernel_stack(bottom: VirtAddr) {
+    let Some(KernelStack { size, .. }) = KERNEL_STACKS.lock().remove(&bottom.as_usize()) else {
+        warn!("dealloc_kernel_stack: no stack at {:#x}", bottom);
+        return;
+    };
+    let (paddr, _) = KERNEL_PAGE_TABLE
+        .lock()
+        .query(bottom)
+        .expect("kernel stack not mapped");
+    KERNEL_PAGE_TABLE
+        .lock()
+        .unmap_region(bottom, size)
+        .expect("failed to unmap kernel stack");
+    flush_tlb(None);
+    global_allocator().dealloc_pages(phys_to_virt(paddr).as_usize(), size / PAGE_SIZE_4K);
+}
+
+/// Returns the ID of the task owning the kernel stack whose guard area
+/// contains `vaddr`, i.e., the task overflowed its stack if it faults at
+/// `vaddr`.
+///
+/// It gives up if the stacks are locked, as it is called on page faults.
+pub(crate) fn stack_guard_owner(vaddr: usize) -> Option<u64> {
+    let stacks = KERNEL_STACKS.try_lock()?;
+    let (&bottom, stack) = stacks.range(vaddr..).next()?;
+    (bottom - stack.guard_size <= vaddr).then_some(stack.owner)
+}
@@ -7,17 +7,23 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-//! TODO: PLIC
+//! Interrupts of the local interrupt controller (timer, IPI) and the PLIC.
+//!
+//! The timer and IPI are identified by their `scause`, the external IRQs by
+//! their PLIC source numbers, which are all routed to the supervisor context
+//! of the boot CPU.
 
 use crate::irq::IrqHandler;
+use crate::mem::phys_to_virt;
 use lazy_init::LazyInit;
-use riscv::register::sie;
+use memory_addr::PhysAddr;
+use riscv::register::{sie, sip};
+use spinlock::SpinNoIrq;
 
 /// `Interrupt` bit in `scause`
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -28,26 +34,82 @@ pub(super) const S_EXT: usize = INTC_IRQ_BASE + 9;
 
 static TIMER_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
 
+static IPI_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
+
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 1024;
 
 /// The timer IRQ number (supervisor timer interrupt in `scause`).
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
-macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
-        match $cause {
-            S_TIMER => $timer_op,
-            S_EXT => $ext_op,
-            _ => panic!("invalid trap cause: {:#x}", $cause),
+/// The IRQ number of inter-processor interrupts (supervisor software
+/// interrupt in `scause`).
+pub const IPI_IRQ_NUM: usize = S_SOFT;
+
+mod plic {
+    use super::*;
+
+    const PLIC_BASE: PhysAddr = PhysAddr::from(ruxconfig::PLIC_PADDR);
+    const PRIORITY_OFFSET: usize = 0;
+    const ENABLE_OFFSET: usize = 0x2000;
+    const ENABLE_STRIDE: usize = 0x80;
+    const CONTEXT_OFFSET: usize = 0x20_0000;
+    const CONTEXT_STRIDE: usize = 0x1000;
+
+    /// The hart that handles the external IRQs.
+    const TARGET_HART: usize = 0;
+
+    /// Serializes the read-modify-write of the enable bits.
+    static ENABLE_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+    fn reg(offset: usize) -> *mut u32 {
+        (phys_to_virt(PLIC_BASE).as_usize() + offset) as *mut u32
+    }
+
+    /// The supervisor context of the given hart (the machine context is
+    /// `2 * hart`).
+    fn s_context(hart: usize) -> usize {
+        2 * hart + 1
+    }
+
+    pub fn set_enable(irq: usize, enabled: bool) {
+        let ctx = s_context(TARGET_HART);
+        let enable = reg(ENABLE_OFFSET + ctx * ENABLE_STRIDE + irq / 32 * 4);
+        let _guard = ENABLE_LOCK.lock();
+        unsafe {
+            if enabled {
+                reg(PRIORITY_OFFSET + irq * 4).write_volatile(1);
+                enable.write_volatile(enable.read_volatile() | 1 << (irq % 32));
+            } else {
+                enable.write_volatile(enable.read_volatile() & !(1 << (irq % 32)));
+            }
         }
-    };
+    }
+
+    /// Claims the pending IRQ with the highest priority, 0 if none.
+    pub fn claim() -> usize {
+        let ctx = s_context(crate::cpu::this_cpu_id());
+        unsafe { reg(CONTEXT_OFFSET + ctx * CONTEXT_STRIDE + 4).read_volatile() as usize }
+    }
+
+    pub fn complete(irq: usize) {
+        let ctx = s_context(crate::cpu::this_cpu_id());
+        unsafe { reg(CONTEXT_OFFSET + ctx * CONTEXT_STRIDE + 4).write_volatile(irq as u32) }
+    }
+
+    /// Accepts the IRQs of any priority on this CPU.
+    pub fn init_percpu() {
+        let ctx = s_context(crate::cpu::this_cpu_id());
+        unsafe { reg(CONTEXT_OFFSET + ctx * CONTEXT_STRIDE).write_volatile(0) }
+    }
 }
 
 /// Enables or disables the given IRQ.
-pub fn set_enable(scause: usize, _enabled: bool) {
-    if scause == S_EXT {
-        // TODO: set enable in PLIC
+///
+/// Only the external IRQs (PLIC sources) can be disabled individually.
+pub fn set_enable(irq_num: usize, enabled: bool) {
+    if irq_num > 0 && irq_num < MAX_IRQ_COUNT {
+        plic::set_enable(irq_num, enabled);
     }
 }
 
@@ -55,17 +117,26 @@ pub fn set_enable(scause: usize, _enabled: bool) {
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
-pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
-    with_cause!(
-        scause,
-        @TIMER => if !TIMER_HANDLER.is_init() {
-            TIMER_HANDLER.init_by(handler);
-            true
-        } else {
-            false
-        },
-        @EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
-    )
+pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
+    match irq_num {
+        S_TIMER => {
+            if !TIMER_HANDLER.is_init() {
+                TIMER_HANDLER.init_by(handler);
+                true
+            } else {
+                false
+            }
+        }
+        S_SOFT => {
+            if !IPI_HANDLER.is_init() {
+                IPI_HANDLER.init_by(handler);
+                true
+            } else {
+                false
+            }
+        }
+        _ => crate::irq::register_handler_common(irq_num, handler),
+    }
 }
 
 /// Dispatches the IRQ.
@@ -74,17 +145,37 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
 /// up in the IRQ handler table and calls the corresponding handler. If
 /// necessary, it also acknowledges the interrupt controller after handling.
 pub fn dispatch_irq(scause: usize) {
-    with_cause!(
-        scause,
-        @TIMER => {
+    match scause {
+        S_TIMER => {
             trace!("IRQ: timer");
             TIMER_HANDLER();
+        }
+        S_SOFT => {
+            trace!("IRQ: IPI");
+            unsafe { sip::clear_ssoft() };
+            if IPI_HANDLER.is_init() {
+                IPI_HANDLER();
+            }
+        }
+        S_EXT => loop {
+            let irq = plic::claim();
+            if irq == 0 {
+                break;
+            }
+            crate::irq::dispatch_irq_common(irq);
+            plic::complete(irq);
         },
-        @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
-    );
+        _ => panic!("invalid trap cause: {:#x}", scause),
+    }
+}
+
+/// Sends an inter-processor interrupt to the given CPU.
+pub fn send_ipi(cpu_id: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(1 << cpu_id, 0));
 }
 
 pub(super) fn init_percpu() {
+    plic::init_percpu();
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
         sie::set_ssoft();
@@ -9,8 +9,20 @@
 
 //! Description tables (per-CPU GDT, per-CPU ISS, IDT)
 
-use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment};
+use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment, DOUBLE_FAULT_IST_INDEX};
 use lazy_init::LazyInit;
+use x86_64::VirtAddr;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+#[repr(align(16))]
+struct DoubleFaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+const EMPTY_DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+/// Stacks of the double fault handler, one per CPU.
+static mut DOUBLE_FAULT_STACKS: [DoubleFaultStack; ruxconfig::SMP] =
+    [EMPTY_DOUBLE_FAULT_STACK; ruxconfig::SMP];
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
@@ -25,7 +37,11 @@ fn init_percpu() {
         IDT.load();
         let tss = TSS.current_ref_mut_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.init_by(TaskStateSegment::new());
+        let mut new_tss = TaskStateSegment::new();
+        let stack = core::ptr::addr_of!(DOUBLE_FAULT_STACKS[crate::cpu::this_cpu_id()]);
+        new_tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::new((stack as usize + DOUBLE_FAULT_STACK_SIZE) as u64);
+        tss.init_by(new_tss);
         gdt.init_by(GdtStruct::new(tss));
         gdt.load();
         gdt.load_tss();
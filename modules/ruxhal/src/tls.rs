@@ -24,7 +24,7 @@
 //!             | / PADDING / / / / / / / | /
 //!             +-------------------------+
 //!    tls_ptr -+-> self pointer (void *) | \
-//! (tp_offset) |                         |  |
+//! (tp_offset) | DTV pointer             |  |
 //!             | Custom TCB format       |   > Thread Control Block (TCB)
 //!             | (might be used          |  |  (length: TCB_SIZE)
 //!             |  by a libC)             |  |
@@ -43,6 +43,7 @@
 //!             |                         | /
 //!    tls_ptr -+-------------------------+
 //! (tp_offset) | GAP_ABOVE_TP            |
+//!             | (DTV pointer on AArch64)|
 //!             +-------------------------+- static_tls_offset
 //!             |                         | \
 //!             | .tdata                  |  |
@@ -54,29 +55,58 @@
 //!             +-------------------------+- (total length: tls_area_size)
 //! ```
 //!
+//! The static TLS block has the same layout as the one computed by the linker
+//! for the kernel image, so both the local-exec and initial-exec TLS models
+//! work without relocation.
+//!
+//! ## Dynamic TLS
+//!
+//! Modules loaded at runtime (e.g., by `dlopen`) register their TLS templates
+//! with [`register_tls_module`]. Their TLS blocks are allocated lazily for
+//! each thread when first accessed by [`tls_get_addr`] (or
+//! `__tls_get_addr()` in the general-dynamic model), and recorded in the
+//! dynamic thread vector (DTV) of the thread, which is pointed to by the TCB.
+//! Module 1 is the kernel image itself, whose block is the static TLS block.
+//!
 //! Reference:
 //! 1. <https://github.com/unikraft/unikraft/blob/staging/arch/x86/x86_64/tls.c>
 //! 2. <https://github.com/unikraft/unikraft/blob/staging/arch/arm/arm64/tls.c>
 
+// Dynamic TLS is managed by the libc with musl.
+#![cfg_attr(feature = "musl", allow(dead_code))]
+
 extern crate alloc;
 
+use alloc::{boxed::Box, vec::Vec};
 use memory_addr::align_up;
+use spinlock::SpinNoIrq;
 
 use core::alloc::Layout;
 use core::ptr::NonNull;
 
-const TLS_ALIGN: usize = 8;
+/// Alignment of the static TLS block, which must be the same as the alignment
+/// of the `.tdata` and `.tbss` sections in the linker script, as the linker
+/// computes the TLS offsets with it on x86_64.
+const TLS_ALIGN: usize = 16;
 
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
-        const TCB_SIZE: usize = 8; // to store TLS self pointer
+        const TCB_SIZE: usize = 16; // to store TLS self pointer and DTV pointer
         const GAP_ABOVE_TP: usize = 0;
+        /// Offset of the DTV pointer from the thread pointer.
+        const DTV_PTR_OFFSET: isize = 8;
+        /// Bias of the offsets in the general-dynamic model.
+        const TLS_DTV_OFFSET: usize = 0;
     } else if #[cfg(target_arch = "aarch64")] {
         const TCB_SIZE: usize = 0;
         const GAP_ABOVE_TP: usize = 16;
+        const DTV_PTR_OFFSET: isize = 0;
+        const TLS_DTV_OFFSET: usize = 0;
     } else if #[cfg(target_arch = "riscv64")] {
-        const TCB_SIZE: usize = 0;
+        const TCB_SIZE: usize = 16; // to store DTV pointer
         const GAP_ABOVE_TP: usize = 0;
+        const DTV_PTR_OFFSET: isize = -16;
+        const TLS_DTV_OFFSET: usize = 0x800;
     }
 }
 
@@ -97,6 +127,10 @@ impl Drop for TlsArea {
     fn drop(&mut self) {
         #[cfg(not(feature = "musl"))]
         unsafe {
+            let dtv = dtv_ptr(self.tls_ptr()).read();
+            if !dtv.is_null() {
+                drop(Box::from_raw(dtv));
+            }
             alloc::alloc::dealloc(self.base.as_ptr(), self.layout);
         }
     }
@@ -124,9 +158,10 @@ impl TlsArea {
         let area_base = unsafe { alloc::alloc::alloc_zeroed(layout) };
 
         let tls_load_base = _stdata as *mut u8;
-        let tls_load_size = _etbss as usize - _stdata as usize;
+        // `.tbss` is not loaded, and is zeroed by `alloc_zeroed`.
+        let tls_load_size = _etdata as usize - _stdata as usize;
         unsafe {
-            // copy data from .tbdata section
+            // copy data from .tdata section
             core::ptr::copy_nonoverlapping(
                 tls_load_base,
                 area_base.add(static_tls_offset()),
@@ -182,4 +217,172 @@ unsafe fn init_tcb(tls_area: *mut u8) {
         let tp_addr = tls_area.add(tp_offset()).cast::<usize>();
         tp_addr.write(tp_addr as usize); // write self pointer
     }
+    // The DTV is allocated on the first access to dynamic TLS.
+    dtv_ptr(tls_area.add(tp_offset())).write(core::ptr::null_mut());
+}
+
+/// Returns the address of the DTV pointer in the TCB.
+unsafe fn dtv_ptr(tls_ptr: *mut u8) -> *mut *mut Dtv {
+    tls_ptr.offset(DTV_PTR_OFFSET).cast()
+}
+
+/// The TLS template of a module loaded at runtime.
+#[derive(Debug, Clone, Copy)]
+pub struct TlsTemplate {
+    /// The initialization image (i.e., `.tdata`).
+    pub image: &'static [u8],
+    /// Size of the TLS block, including the zero-initialized part (i.e.,
+    /// `.tbss`).
+    pub mem_size: usize,
+    /// Alignment of the TLS block.
+    pub align: usize,
+}
+
+struct TlsModule {
+    template: TlsTemplate,
+    /// Distinguishes modules reusing the same ID.
+    generation: u64,
+}
+
+/// TLS modules indexed by their IDs. ID 0 is unused and ID 1 is the kernel
+/// image.
+static TLS_MODULES: SpinNoIrq<Vec<Option<TlsModule>>> = SpinNoIrq::new(Vec::new());
+
+static TLS_GENERATION: SpinNoIrq<u64> = SpinNoIrq::new(0);
+
+/// Module ID of the static TLS block of the kernel image.
+const STATIC_TLS_MODULE: usize = 1;
+
+/// Registers the TLS template of a module loaded at runtime, returns the
+/// module ID to be used with [`tls_get_addr`].
+#[cfg(not(feature = "musl"))]
+pub fn register_tls_module(template: TlsTemplate) -> usize {
+    assert!(template.image.len() <= template.mem_size);
+    let generation = {
+        let mut gen = TLS_GENERATION.lock();
+        *gen += 1;
+        *gen
+    };
+    let module = Some(TlsModule {
+        template,
+        generation,
+    });
+    let mut modules = TLS_MODULES.lock();
+    if modules.len() <= STATIC_TLS_MODULE {
+        modules.resize_with(STATIC_TLS_MODULE + 1, || None);
+    }
+    if let Some(id) = (STATIC_TLS_MODULE + 1..modules.len()).find(|&i| modules[i].is_none()) {
+        modules[id] = module;
+        id
+    } else {
+        modules.push(module);
+        modules.len() - 1
+    }
+}
+
+/// Unregisters a TLS module, when the module is unloaded.
+///
+/// The TLS blocks of the module are freed when the threads exit, or the ID is
+/// reused by another module.
+#[cfg(not(feature = "musl"))]
+pub fn unregister_tls_module(id: usize) {
+    if id > STATIC_TLS_MODULE {
+        if let Some(module) = TLS_MODULES.lock().get_mut(id) {
+            *module = None;
+        }
+    }
+}
+
+/// A TLS block of a module loaded at runtime.
+struct DynamicTlsBlock {
+    base: NonNull<u8>,
+    layout: Layout,
+    generation: u64,
+}
+
+impl Drop for DynamicTlsBlock {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.base.as_ptr(), self.layout) };
+    }
+}
+
+/// The dynamic thread vector, indexed by module IDs.
+#[derive(Default)]
+struct Dtv {
+    blocks: Vec<Option<DynamicTlsBlock>>,
+}
+
+/// Returns the address of the thread-local variable at `offset` in the TLS
+/// block of the module `module`, for the current thread.
+///
+/// The TLS block is allocated and initialized on the first access.
+///
+/// # Panics
+///
+/// Panics if the module is not registered.
+#[cfg(not(feature = "musl"))]
+pub fn tls_get_addr(module: usize, offset: usize) -> *mut u8 {
+    let tls_ptr = crate::arch::read_thread_pointer() as *mut u8;
+    if module == STATIC_TLS_MODULE {
+        let base = tls_ptr.wrapping_sub(tp_offset());
+        return base.wrapping_add(static_tls_offset() + offset);
+    }
+    let (template, generation) = match TLS_MODULES.lock().get(module) {
+        Some(Some(m)) => (m.template, m.generation),
+        _ => panic!("invalid TLS module ID: {}", module),
+    };
+    // Safety: the TCB is initialized by `TlsArea::alloc`, and the DTV is only
+    // accessed by its own thread.
+    let dtv = unsafe {
+        let dtv_ptr = dtv_ptr(tls_ptr);
+        if dtv_ptr.read().is_null() {
+            dtv_ptr.write(Box::into_raw(Box::default()));
+        }
+        &mut *dtv_ptr.read()
+    };
+    if dtv.blocks.len() <= module {
+        dtv.blocks.resize_with(module + 1, || None);
+    }
+    let block = &mut dtv.blocks[module];
+    if block.as_ref().map(|b| b.generation) != Some(generation) {
+        let layout = Layout::from_size_align(template.mem_size.max(1), template.align.max(1))
+            .expect("invalid TLS template");
+        let base = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        let base = NonNull::new(base).expect("failed to allocate the TLS block");
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                template.image.as_ptr(),
+                base.as_ptr(),
+                template.image.len(),
+            )
+        };
+        *block = Some(DynamicTlsBlock {
+            base,
+            layout,
+            generation,
+        });
+    }
+    unsafe { block.as_ref().unwrap().base.as_ptr().add(offset) }
+}
+
+/// The argument of `__tls_get_addr()`.
+#[repr(C)]
+pub struct TlsIndex {
+    /// Module ID.
+    pub module: usize,
+    /// Offset in the TLS block, biased by `TLS_DTV_OFFSET` on RISC-V.
+    pub offset: usize,
+}
+
+/// Resolves the address of a thread-local variable in the general-dynamic
+/// and local-dynamic TLS models.
+///
+/// # Safety
+///
+/// `index` must point to a valid [`TlsIndex`].
+#[cfg(not(feature = "musl"))]
+#[no_mangle]
+pub unsafe extern "C" fn __tls_get_addr(index: *const TlsIndex) -> *mut u8 {
+    let index = &*index;
+    tls_get_addr(index.module, index.offset.wrapping_add(TLS_DTV_OFFSET))
 }
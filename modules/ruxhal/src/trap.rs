@@ -46,6 +46,60 @@ pub trait TrapHandler {
     }
 }
 
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+const OVERFLOW_STACK_SIZE: usize = 0x4000;
+
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+#[repr(align(16))]
+struct OverflowStack([u8; OVERFLOW_STACK_SIZE]);
+
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+const EMPTY_OVERFLOW_STACK: OverflowStack = OverflowStack([0; OVERFLOW_STACK_SIZE]);
+
+/// Stacks the kernel stack overflows are reported on, one per CPU.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+static mut OVERFLOW_STACKS: [OverflowStack; ruxconfig::SMP] =
+    [EMPTY_OVERFLOW_STACK; ruxconfig::SMP];
+
+/// Top of the overflow stack of this CPU, loaded by the trap entry.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+#[no_mangle]
+#[percpu::def_percpu]
+static OVERFLOW_STACK_TOP: usize = 0;
+
+/// Sets up the overflow stack of the current CPU.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+))]
+pub(crate) fn init_overflow_stack(cpu_id: usize) {
+    unsafe {
+        let stack = core::ptr::addr_of!(OVERFLOW_STACKS[cpu_id]);
+        OVERFLOW_STACK_TOP.write_current_raw(stack as usize + OVERFLOW_STACK_SIZE);
+    }
+}
+
 /// Call the external IRQ handler.
 #[allow(dead_code)]
 pub(crate) fn handle_irq_extern(irq_num: usize) {
@@ -59,6 +113,20 @@ pub(crate) fn handle_syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     call_interface!(TrapHandler::handle_syscall, syscall_id, args)
 }
 
+/// Panics with a clear message if the page fault at `vaddr` is caused by a
+/// kernel stack overflow, i.e., `vaddr` is in the guard area of a stack
+/// allocated by [`alloc_kernel_stack`](crate::paging::alloc_kernel_stack).
+#[allow(dead_code)]
+#[cfg(feature = "paging")]
+pub(crate) fn check_stack_overflow(vaddr: usize, pc: usize) {
+    if let Some(task_id) = crate::paging::stack_guard_owner(vaddr) {
+        panic!(
+            "Kernel stack overflow in task {} @ {:#x}, fault_vaddr={:#x}",
+            task_id, pc, vaddr
+        );
+    }
+}
+
 /// Call the external IRQ handler.
 #[allow(dead_code)]
 #[cfg(feature = "paging")]
@@ -12,5 +12,14 @@ use core::panic::PanicInfo;
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
+    #[cfg(feature = "multitask")]
+    {
+        use core::sync::atomic::{AtomicBool, Ordering};
+        // Do not dump again if dumping itself panics.
+        static DUMPING: AtomicBool = AtomicBool::new(false);
+        if !DUMPING.swap(true, Ordering::AcqRel) {
+            ruxtask::dump_tasks();
+        }
+    }
     ruxhal::misc::terminate()
 }
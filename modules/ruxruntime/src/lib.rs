@@ -25,6 +25,7 @@
 //! - `signal`: Enable signal support
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
+//! - `balloon`: Enable memory balloon support.
 //! - `virtio-9p`: Enable virtio-based 9pfs support.
 //! - `net-9p`: Enable net-based 9pfs support.
 //! - `musl`: Enable musl libc support.
@@ -205,7 +206,12 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         ruxfutex::init_futex();
     }
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "balloon"
+    ))]
     {
         #[allow(unused_variables)]
         let all_devices = ruxdriver::init_drivers();
@@ -247,8 +253,14 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
             ruxfs::init_filesystems(mount_points);
         }
 
+        #[cfg(all(feature = "fs", feature = "net"))]
+        init_net_capture();
+
         #[cfg(feature = "display")]
         ruxdisplay::init_display(all_devices.display);
+
+        #[cfg(feature = "balloon")]
+        ruxballoon::init_balloon(all_devices.balloon);
     }
 
     #[cfg(feature = "smp")]
@@ -387,6 +399,26 @@ fn init_allocator() {
     }
 }
 
+#[cfg(all(feature = "fs", feature = "net"))]
+fn init_net_capture() {
+    extern crate alloc;
+    use alloc::boxed::Box;
+
+    let path = option_env!("RUX_PCAP").unwrap_or("");
+    if path.is_empty() {
+        return;
+    }
+    info!("Capture network traffic to {:?}...", path);
+    match ruxfs::api::File::create(path) {
+        Ok(file) => {
+            if let Err(e) = axnet::start_capture(Box::new(file)) {
+                warn!("failed to start capture: {:?}", e);
+            }
+        }
+        Err(e) => warn!("failed to create {:?}: {:?}", path, e),
+    }
+}
+
 #[cfg(feature = "paging")]
 use ruxhal::paging::remap_kernel_memory;
 
@@ -395,12 +427,17 @@ fn init_interrupt() {
     use ruxhal::time::TIMER_IRQ_NUM;
 
     // Setup timer interrupt handler
+    #[cfg(not(feature = "multitask"))]
     const PERIODIC_INTERVAL_NANOS: u64 =
         ruxhal::time::NANOS_PER_SEC / ruxconfig::TICKS_PER_SEC as u64;
 
+    #[cfg(not(feature = "multitask"))]
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
+    // With multitasking, the timer is programmed by the task manager to the
+    // next deadline instead, see `ruxtask::on_timer_tick()`.
+    #[cfg(not(feature = "multitask"))]
     fn update_timer() {
         let now_ns = ruxhal::time::current_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
@@ -414,6 +451,12 @@ fn init_interrupt() {
 
     #[cfg(feature = "signal")]
     fn do_signal() {
+        // Timer interrupts may occur on several CPUs at the same time.
+        static IN_SIGNAL: core::sync::atomic::AtomicBool =
+            core::sync::atomic::AtomicBool::new(false);
+        if IN_SIGNAL.swap(true, core::sync::atomic::Ordering::Acquire) {
+            return;
+        }
         let now_ns = ruxhal::time::current_time_nanos();
         // timer signal num
         let timers = [14, 26, 27];
@@ -439,18 +482,26 @@ fn init_interrupt() {
                 Signal::signal(signum as i8, false);
             }
         }
+        IN_SIGNAL.store(false, core::sync::atomic::Ordering::Release);
     }
 
     ruxhal::irq::register_handler(TIMER_IRQ_NUM, || {
+        #[cfg(not(feature = "multitask"))]
         update_timer();
-        #[cfg(feature = "signal")]
+        #[cfg(feature = "multitask")]
+        ruxtask::on_timer_tick();
+        #[cfg(all(feature = "signal", not(feature = "multitask")))]
         if ruxhal::cpu::this_cpu_is_bsp() {
             do_signal();
         }
-        #[cfg(feature = "multitask")]
-        ruxtask::on_timer_tick();
+        // Any CPU may handle the interval timers, as idle CPUs have no ticks.
+        #[cfg(all(feature = "signal", feature = "multitask"))]
+        do_signal();
     });
 
+    // IPIs only wake up idle CPUs to run the tasks pushed to them.
+    ruxhal::irq::register_handler(ruxhal::irq::IPI_IRQ_NUM, || {});
+
     // Enable IRQs before starting app
     ruxhal::arch::enable_irqs();
 }
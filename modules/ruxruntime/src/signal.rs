@@ -134,6 +134,11 @@ impl Signal {
             unsafe {
                 SIGNAL_IF.timer_value[which] = Duration::from_nanos(s);
             }
+            // There are no periodic timer interrupts to check the deadline.
+            #[cfg(all(feature = "multitask", feature = "irq"))]
+            if s != 0 {
+                ruxtask::set_timer_deadline(Duration::from_nanos(s));
+            }
         }
         Some(old.as_nanos() as u64)
     }
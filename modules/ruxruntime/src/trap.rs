@@ -16,7 +16,11 @@ impl ruxhal::trap::TrapHandler for TrapHandlerImpl {
         {
             let guard = kernel_guard::NoPreempt::new();
             ruxhal::irq::dispatch_irq(_irq_num);
+            #[cfg(feature = "multitask")]
+            ruxtask::run_tasklets();
             drop(guard); // rescheduling may occur when preemption is re-enabled.
+            #[cfg(feature = "multitask")]
+            ruxtask::exit_if_killed();
         }
     }
 }
@@ -9,22 +9,39 @@
 
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+};
+#[cfg(feature = "irq")]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use axalloc::OomKill;
+use axerrno::AxResult;
+use spinlock::SpinNoIrq;
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::{current_run_queue, AxRunQueue};
+use crate::task::TaskState;
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
-#[cfg(not(feature = "musl"))]
-use crate::tsd;
+pub use crate::cpumask::CpuMask;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{CurrentTask, StackAttr, TaskId, TaskInner, TaskStats};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::{WaitQueue, WaitQueueWithMetadata};
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
+#[doc(cfg(feature = "multitask"))]
+pub use scheduler::{SchedPolicy, RT_PRIO_MAX, RT_PRIO_MIN};
+
 cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
+    if #[cfg(feature = "sched_rt")] {
+        const MAX_TIME_SLICE: usize = 5;
+        pub(crate) type AxTask = scheduler::RTTask<TaskInner, MAX_TIME_SLICE>;
+        pub(crate) type Scheduler = scheduler::RTScheduler<TaskInner, MAX_TIME_SLICE>;
+    } else if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
         pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
@@ -79,8 +96,9 @@ pub fn init_scheduler() {
     crate::run_queue::init();
     #[cfg(feature = "irq")]
     crate::timers::init();
-    #[cfg(not(feature = "musl"))]
-    tsd::init();
+    crate::workqueue::init();
+    axalloc::set_task_account(current_mem_owner);
+    axalloc::set_oom_killer(oom_kill, oom_wait);
 
     info!("  use {} scheduler.", Scheduler::scheduler_name());
 }
@@ -88,16 +106,38 @@ pub fn init_scheduler() {
 /// Initializes the task scheduler for secondary CPUs.
 pub fn init_scheduler_secondary() {
     crate::run_queue::init_secondary();
+    #[cfg(feature = "irq")]
+    crate::timers::init();
+    crate::workqueue::init_secondary();
 }
 
-/// Handles periodic timer ticks for the task manager.
+/// Handles timer interrupts for the task manager.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
+/// For example, advance scheduler states, checks timed events, etc. It also
+/// programs the next timer interrupt, which is the next timed event, or the
+/// next scheduler tick if the current CPU is not idle.
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    interrupt_oom_victim();
+    notify_deferred();
+    let rq = current_run_queue();
+    let ticks = crate::timers::elapsed_ticks();
+    if ticks > 0 {
+        rq.scheduler_timer_tick(ticks);
+    }
+    crate::timers::program_next_timer(crate::current().is_idle());
+}
+
+/// Makes sure that a timer interrupt occurs on some CPU at `deadline`.
+///
+/// It is used by timers managed outside the task manager (e.g., interval
+/// timers of signals), as there are no periodic timer interrupts.
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub fn set_timer_deadline(deadline: ruxhal::time::TimeValue) {
+    crate::timers::set_timer_deadline(deadline);
 }
 
 /// Spawns a new task with the given parameters.
@@ -108,10 +148,25 @@ where
     F: FnOnce() + Send + 'static,
 {
     let task = TaskInner::new(f, name, stack_size);
-    RUN_QUEUE.lock().add_task(task.clone());
+    current_run_queue().add_task(task.clone());
     task
 }
 
+/// Creates a new task with the given name and stack, which does not run
+/// until it is put into a run queue by [`put_task`].
+///
+/// The caller can set things up for the task (e.g., record it where the task
+/// looks itself up) before it runs.
+///
+/// Returns [`AxError::NoMemory`](axerrno::AxError::NoMemory) if the stack
+/// can't be allocated.
+pub fn new_task_with_stack<F>(f: F, name: String, stack: StackAttr) -> AxResult<AxTaskRef>
+where
+    F: FnOnce() + Send + 'static,
+{
+    TaskInner::new_with_stack(f, name, stack)
+}
+
 /// Used by musl
 #[cfg(feature = "musl")]
 pub fn pspawn_raw<F>(
@@ -121,7 +176,7 @@ pub fn pspawn_raw<F>(
     tls: usize,
     set_tid: core::sync::atomic::AtomicU64,
     tl: core::sync::atomic::AtomicU64,
-) -> AxTaskRef
+) -> AxResult<AxTaskRef>
 where
     F: FnOnce() + Send + 'static,
 {
@@ -148,18 +203,17 @@ pub fn pspawn<F>(
     tls: usize,
     set_tid: core::sync::atomic::AtomicU64,
     tl: core::sync::atomic::AtomicU64,
-) -> AxTaskRef
+) -> AxResult<AxTaskRef>
 where
     F: FnOnce() + Send + 'static,
 {
     pspawn_raw(f, "".into(), ruxconfig::TASK_STACK_SIZE, tls, set_tid, tl)
 }
 
-/// Used by musl
-///
-/// Put new thread into run_queue
+/// Put new thread into run_queue, which is created by
+/// [`new_task_with_stack`] or `pspawn` (used by musl).
 pub fn put_task(task: AxTaskRef) {
-    RUN_QUEUE.lock().add_task(task);
+    current_run_queue().add_task(task);
 }
 
 /// Set the priority for current task.
@@ -172,13 +226,339 @@ pub fn put_task(task: AxTaskRef) {
 ///
 /// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+    current_run_queue().set_current_priority(prio)
+}
+
+/// Sets the scheduling policy of the given task.
+///
+/// Real-time policies are only supported by the real-time scheduler (the
+/// `sched_rt` feature), other schedulers only accept [`SchedPolicy::Normal`].
+///
+/// Returns `true` if the policy is set successfully.
+pub fn set_sched_policy(task: &AxTaskRef, policy: SchedPolicy) -> bool {
+    #[cfg(feature = "sched_rt")]
+    {
+        crate::run_queue::set_sched_policy(task, policy)
+    }
+    #[cfg(not(feature = "sched_rt"))]
+    {
+        let _ = task;
+        policy == SchedPolicy::Normal
+    }
+}
+
+/// Returns the scheduling policy of the given task, which is set by
+/// [`set_sched_policy`].
+pub fn sched_policy(task: &AxTaskRef) -> SchedPolicy {
+    #[cfg(feature = "sched_rt")]
+    {
+        task.base_policy()
+    }
+    #[cfg(not(feature = "sched_rt"))]
+    {
+        let _ = task;
+        SchedPolicy::Normal
+    }
+}
+
+/// Returns the scheduling policy the given task actually runs with, which
+/// may be inherited from other tasks by [`set_inherited_sched_policy`].
+pub fn effective_sched_policy(task: &AxTaskRef) -> SchedPolicy {
+    #[cfg(feature = "sched_rt")]
+    {
+        crate::run_queue::effective_sched_policy(task)
+    }
+    #[cfg(not(feature = "sched_rt"))]
+    {
+        let _ = task;
+        SchedPolicy::Normal
+    }
+}
+
+/// Lets the given task inherit a scheduling policy, for priority inheritance
+/// of locks. The task is boosted to run with `policy` if it precedes its own
+/// one, until the inherited policy is reset by passing `None`.
+///
+/// It does nothing if the real-time scheduler (the `sched_rt` feature) is not
+/// used.
+pub fn set_inherited_sched_policy(task: &AxTaskRef, policy: Option<SchedPolicy>) {
+    #[cfg(feature = "sched_rt")]
+    crate::run_queue::set_inherited_sched_policy(task, policy);
+    #[cfg(not(feature = "sched_rt"))]
+    let _ = (task, policy);
+}
+
+/// Sets the CPU affinity of the given task, i.e., the set of CPUs it is
+/// allowed to run on.
+///
+/// The current task is migrated at once if the current CPU is not in `mask`.
+/// Other tasks are migrated the next time they are scheduled.
+///
+/// Returns `false` if `mask` does not contain any online CPU.
+pub fn set_cpu_affinity(task: &AxTaskRef, mask: CpuMask) -> bool {
+    if !mask.iter().any(crate::run_queue::is_cpu_online) {
+        return false;
+    }
+    task.set_cpumask(mask);
+    let curr = current();
+    if curr.ptr_eq(task) && !mask.get(curr.cpu_id()) {
+        current_run_queue().yield_current();
+    }
+    true
+}
+
+/// Returns the set of CPUs which can run tasks.
+pub fn online_cpus() -> CpuMask {
+    let mut mask = CpuMask::new();
+    for cpu_id in (0..CpuMask::MAX_CPUS).filter(|&c| crate::run_queue::is_cpu_online(c)) {
+        mask.set(cpu_id, true);
+    }
+    mask
+}
+
+/// Runs `f`, in which the current task can be woken up from blocking waits
+/// by [`interrupt`].
+///
+/// An interrupted wait returns early as if it had been notified, so `f` must
+/// be ready for spurious wakeups. Once the task is interrupted, all waits in
+/// `f` return at once, including [`WaitQueue::wait_until`] whose condition
+/// does not hold, so the loops waiting in `f` should give up when
+/// [`interrupted`] returns `true`, otherwise they would spin.
+pub fn interruptible<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let curr = current();
+    curr.set_interrupt_pending(false);
+    curr.set_interruptible(true);
+    let ret = f();
+    curr.set_interruptible(false);
+    ret
+}
+
+/// Whether the current task has been [interrupted](interrupt) in
+/// [`interruptible`].
+pub fn interrupted() -> bool {
+    current().is_interrupted()
+}
+
+/// Interrupts the given task, so that it stops waiting if it is blocked in
+/// (or going to block in) [`interruptible`] waits.
+///
+/// It has no effect on the waits outside `interruptible`. If the task is not
+/// in `interruptible` at all, the interrupt is lost, so the caller usually
+/// sets a flag checked by the task in `interruptible` before the call.
+pub fn interrupt(task: &AxTaskRef) {
+    task.set_interrupt_pending(true);
+    // Paired with `TaskInner::cancel_interrupted_block()`: either the task
+    // sees the pending interrupt before blocking, or we see it blocked.
+    if task.is_interruptible() && task.is_blocked() {
+        current_run_queue().unblock_task(task.clone(), false);
+    }
+}
+
+/// Kills the given task, like `SIGKILL`.
+///
+/// The task is marked as [killed](TaskInner::is_killed) and interrupted. It
+/// exits at its next cancellation point, e.g., the POSIX API checks it as a
+/// pending thread cancellation which cannot be disabled.
+pub fn kill(task: &AxTaskRef) {
+    task.set_killed();
+    interrupt(task);
+}
+
+/// Returns the owner of the memory allocated by the current task, called by
+/// the allocator.
+fn current_mem_owner() -> Option<core::ptr::NonNull<axalloc::MemOwner>> {
+    current_may_uninit()?
+        .mem_owner()
+        .map(axalloc::MemOwnerRef::as_ptr)
+}
+
+/// The task killed by the OOM killer until it's dropped, and when it's killed.
+static OOM_VICTIM: SpinNoIrq<Option<(Weak<AxTask>, core::time::Duration)>> = SpinNoIrq::new(None);
+/// Whether the OOM victim is to be interrupted on the next timer tick.
+#[cfg(feature = "irq")]
+static OOM_INTERRUPT_PENDING: AtomicBool = AtomicBool::new(false);
+/// How long the allocator waits for the OOM victim to exit.
+#[cfg(feature = "irq")]
+const OOM_KILL_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(1);
+/// How long the OOM victim is waited for, before another task is killed
+/// instead, e.g., it's blocked on a lock held by the task allocating.
+const OOM_VICTIM_GRACE: core::time::Duration = core::time::Duration::from_secs(5);
+
+fn oom_victim() -> Option<AxTaskRef> {
+    OOM_VICTIM
+        .lock()
+        .as_ref()
+        .and_then(|(victim, _)| victim.upgrade())
+}
+
+/// Kills the task using the most memory, called by the allocator when the
+/// memory runs out.
+///
+/// Interrupting the task may take the run queue lock, which may be held by
+/// the current task here, so it's deferred to the next timer tick. A task
+/// killed before is waited for instead, if it's still exiting and not given
+/// up yet.
+fn oom_kill() -> OomKill {
+    let now = ruxhal::time::current_time();
+    let prev = OOM_VICTIM
+        .lock()
+        .as_ref()
+        .map(|(victim, killed_at)| (victim.upgrade(), *killed_at));
+    if let Some((Some(victim), killed_at)) = prev {
+        if victim.state() != TaskState::Exited && now < killed_at + OOM_VICTIM_GRACE {
+            return OomKill::Exiting;
+        }
+    }
+    let Some(victim) = crate::registry::try_find_largest() else {
+        return OomKill::NoVictim;
+    };
+    victim.set_killed();
+    warn!(
+        "out of memory: killed Task({}, {:?}) using {} bytes",
+        victim.id().as_u64(),
+        victim.name(),
+        victim.mem_usage()
+    );
+    *OOM_VICTIM.lock() = Some((Arc::downgrade(&victim), now));
+    #[cfg(feature = "irq")]
+    OOM_INTERRUPT_PENDING.store(true, Ordering::Release);
+    OomKill::Killed
+}
+
+/// Waits for the task killed by [`oom_kill`] to exit, called by the
+/// allocator after the killer.
+///
+/// The current task doesn't wait if it's the victim, or it can't block here,
+/// e.g., it holds a sleeping lock which the victim may need to exit.
+fn oom_wait() -> bool {
+    let Some(victim) = oom_victim() else {
+        return true;
+    };
+    #[cfg(feature = "irq")]
+    {
+        let curr = current();
+        let may_block =
+            ruxhal::arch::irqs_enabled() && !curr.ptr_eq(&victim) && !curr.holds_locks();
+        #[cfg(feature = "preempt")]
+        let may_block = may_block && curr.can_preempt(0);
+        if may_block {
+            return victim.join_timeout(OOM_KILL_TIMEOUT);
+        }
+    }
+    victim.state() == TaskState::Exited
+}
+
+/// Interrupts the task killed by the OOM killer, on the timer tick after it's
+/// killed.
+#[cfg(feature = "irq")]
+fn interrupt_oom_victim() {
+    if OOM_INTERRUPT_PENDING.swap(false, Ordering::AcqRel) {
+        if let Some(victim) = oom_victim() {
+            interrupt(&victim);
+        }
+    }
+}
+
+/// A section where the current task runs kernel code on behalf of the
+/// application, e.g., a system call. A killed task is not forced to exit in
+/// it by [`exit_if_killed`], since it may hold locks not tracked by the task.
+pub struct KernelSection(());
+
+impl KernelSection {
+    /// Enters a kernel section until the returned value is dropped.
+    pub fn enter() -> Self {
+        current().enter_kernel();
+        Self(())
+    }
+}
+
+impl Drop for KernelSection {
+    fn drop(&mut self) {
+        current().leave_kernel();
+    }
+}
+
+/// Exits the current task if it has been [killed](kill) and can exit here,
+/// called on the return from interrupts.
+///
+/// A killed task exits at its next cancellation point usually, but a
+/// compute-bound one never reaches any. So it's forced to exit when
+/// interrupted, if it has preemption enabled, holds no sleeping lock and is
+/// out of [`KernelSection`]s. Like `SIGKILL`, nothing is run before it exits.
+pub fn exit_if_killed() {
+    #[cfg(feature = "preempt")]
+    {
+        let curr = current();
+        if curr.is_killed()
+            && !curr.is_idle()
+            && !curr.is_init()
+            && curr.can_preempt(0)
+            && !curr.holds_locks()
+            && !curr.in_kernel()
+        {
+            debug!("task {} killed on interrupt", curr.id_name());
+            current_run_queue().exit_current(0);
+        }
+    }
+}
+
+/// Wait queues to be notified on the next timer tick, see [`notify_on_tick`].
+#[cfg(feature = "irq")]
+static DEFERRED_NOTIFY: [AtomicPtr<WaitQueue>; 4] = [
+    AtomicPtr::new(core::ptr::null_mut()),
+    AtomicPtr::new(core::ptr::null_mut()),
+    AtomicPtr::new(core::ptr::null_mut()),
+    AtomicPtr::new(core::ptr::null_mut()),
+];
+
+/// Wakes up one task waiting on `wq` on the next timer tick, for the code
+/// which can't take the locks of the wait queue and the run queue, e.g., the
+/// shrinkers called in the allocator.
+///
+/// If the `irq` feature is not enabled, it's woken up at once.
+pub fn notify_on_tick(wq: &'static WaitQueue) {
+    #[cfg(feature = "irq")]
+    {
+        let ptr = wq as *const WaitQueue as *mut WaitQueue;
+        for slot in DEFERRED_NOTIFY.iter() {
+            match slot.compare_exchange(
+                core::ptr::null_mut(),
+                ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(other) if other == ptr => return,
+                Err(_) => {}
+            }
+        }
+        warn!(
+            "too many deferred notifications, {:#x} is lost",
+            ptr as usize
+        );
+    }
+    #[cfg(not(feature = "irq"))]
+    wq.notify_one(false);
+}
+
+#[cfg(feature = "irq")]
+fn notify_deferred() {
+    for slot in DEFERRED_NOTIFY.iter() {
+        let ptr = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+        // Safe because it's a static reference set by `notify_on_tick`.
+        if let Some(wq) = unsafe { ptr.as_ref() } {
+            wq.notify_one(false);
+        }
+    }
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
@@ -193,7 +573,7 @@ pub fn sleep(dur: core::time::Duration) {
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: ruxhal::time::TimeValue) {
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    current_run_queue().sleep_until(deadline);
     #[cfg(not(feature = "irq"))]
     ruxhal::time::busy_wait_until(deadline);
 }
@@ -202,7 +582,7 @@ pub fn sleep_until(deadline: ruxhal::time::TimeValue) {
 pub fn exit(exit_code: i32) -> ! {
     #[cfg(not(feature = "musl"))]
     current().destroy_keys();
-    RUN_QUEUE.lock().exit_current(exit_code)
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
@@ -216,6 +596,15 @@ pub fn run_idle() -> ! {
             current().id().as_u64()
         );
         #[cfg(feature = "irq")]
-        ruxhal::arch::wait_for_irqs();
+        {
+            // A task may be pushed here (with an IPI) after `yield_now()`,
+            // check it with IRQs disabled so that the IPI is not lost.
+            ruxhal::arch::disable_irqs();
+            if current_run_queue().has_ready_tasks() {
+                ruxhal::arch::enable_irqs();
+            } else {
+                ruxhal::arch::enable_irqs_and_wait();
+            }
+        }
     }
 }
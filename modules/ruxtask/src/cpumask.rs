@@ -0,0 +1,101 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! CPU sets, used as the CPU affinity of tasks.
+
+use core::fmt;
+
+const BITS_PER_WORD: usize = usize::BITS as usize;
+const NR_WORDS: usize = (ruxconfig::SMP + BITS_PER_WORD - 1) / BITS_PER_WORD;
+
+/// A set of CPUs, indexed by CPU IDs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuMask([usize; NR_WORDS]);
+
+impl CpuMask {
+    /// The maximum number of CPUs, same as [`ruxconfig::SMP`].
+    pub const MAX_CPUS: usize = ruxconfig::SMP;
+
+    /// Creates an empty CPU set.
+    pub const fn new() -> Self {
+        Self([0; NR_WORDS])
+    }
+
+    /// Creates a CPU set containing all CPUs.
+    pub fn full() -> Self {
+        let mut mask = Self::new();
+        for cpu in 0..Self::MAX_CPUS {
+            mask.set(cpu, true);
+        }
+        mask
+    }
+
+    /// Creates a CPU set containing only the given CPU.
+    pub fn one(cpu_id: usize) -> Self {
+        let mut mask = Self::new();
+        mask.set(cpu_id, true);
+        mask
+    }
+
+    /// Whether the given CPU is in the set.
+    pub fn get(&self, cpu_id: usize) -> bool {
+        cpu_id < Self::MAX_CPUS
+            && self.0[cpu_id / BITS_PER_WORD] & (1 << (cpu_id % BITS_PER_WORD)) != 0
+    }
+
+    /// Adds the given CPU to the set if `value` is `true`, or removes it
+    /// otherwise. CPU IDs not less than [`CpuMask::MAX_CPUS`] are ignored.
+    pub fn set(&mut self, cpu_id: usize, value: bool) {
+        if cpu_id >= Self::MAX_CPUS {
+            return;
+        }
+        let bit = 1 << (cpu_id % BITS_PER_WORD);
+        if value {
+            self.0[cpu_id / BITS_PER_WORD] |= bit;
+        } else {
+            self.0[cpu_id / BITS_PER_WORD] &= !bit;
+        }
+    }
+
+    /// Whether the set contains no CPU.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&w| w == 0)
+    }
+
+    /// Returns the number of CPUs in the set.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns the intersection of two sets.
+    pub fn and(&self, other: &Self) -> Self {
+        let mut mask = *self;
+        for (w, o) in mask.0.iter_mut().zip(other.0.iter()) {
+            *w &= *o;
+        }
+        mask
+    }
+
+    /// Iterates over the IDs of CPUs in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..Self::MAX_CPUS).filter(|&cpu| self.get(cpu))
+    }
+}
+
+impl Default for CpuMask {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+impl fmt::Debug for CpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,167 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Dumping the states and backtraces of all tasks, for debugging.
+//!
+//! Backtraces are unwound by following the frame pointers, so they are only
+//! meaningful if the kernel is built with frame pointers enabled (e.g.,
+//! `-C force-frame-pointers=yes`).
+
+use core::fmt::{self, Write};
+
+use crate::{AxTaskRef, TaskState};
+
+/// At most this many frames are printed for each task.
+const MAX_FRAMES: usize = 32;
+
+/// Writes to the console directly, without any lock.
+struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        ruxhal::console::write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Prints the state, statistics and backtrace of every task to the console.
+///
+/// It does not wait for any lock, so it can be used in the panic handler.
+pub fn dump_tasks() {
+    let _ = dump_tasks_to(&mut ConsoleWriter);
+}
+
+/// Writes the state, statistics and backtrace of every task to `out`.
+pub fn dump_tasks_to(out: &mut dyn Write) -> fmt::Result {
+    let Some(tasks) = crate::registry::try_all_tasks() else {
+        return writeln!(out, "task registry is locked, cannot dump tasks");
+    };
+    writeln!(out, "---- {} tasks ----", tasks.len())?;
+    for task in tasks.iter() {
+        dump_task(out, task)?;
+    }
+    writeln!(out, "----")
+}
+
+fn dump_task(out: &mut dyn Write, task: &AxTaskRef) -> fmt::Result {
+    let stats = task.stats();
+    let curr = crate::current_may_uninit();
+    let is_current = curr.as_ref().is_some_and(|curr| curr.ptr_eq(task));
+    writeln!(
+        out,
+        "{}{} state={:?} cpu={}",
+        if is_current { "*" } else { " " },
+        task.id_name(),
+        task.state(),
+        task.cpu_id()
+    )?;
+    writeln!(
+        out,
+        "    cpu_time={:?} nvcsw={} nivcsw={} wakeups={} stack={}/{}",
+        stats.cpu_time,
+        stats.voluntary_switches,
+        stats.involuntary_switches,
+        stats.wakeups,
+        stats.stack_high_water,
+        stats.stack_size
+    )?;
+    writeln!(
+        out,
+        "    mem={} peak={}{}",
+        stats.mem_usage,
+        stats.mem_peak,
+        if task.is_killed() { " killed" } else { "" }
+    )?;
+    if task.state() == TaskState::Blocked && task.wait_channel() != 0 {
+        writeln!(out, "    wchan={:#x}", task.wait_channel())?;
+    }
+
+    let (pc, fp) = if is_current {
+        (0, current_frame_pointer())
+    } else if task.on_cpu() {
+        return writeln!(out, "    <running on another CPU>");
+    } else {
+        task.saved_frame()
+    };
+    let Some(stack) = task.stack_range() else {
+        return writeln!(out, "    <no backtrace on the boot stack>");
+    };
+    if pc != 0 {
+        writeln!(out, "    #0  {:#018x}", pc)?;
+    }
+    let mut depth = (pc != 0) as usize;
+    walk_frames(fp, stack, |ra| {
+        let res = writeln!(out, "    #{:<2} {:#018x}", depth, ra);
+        depth += 1;
+        res
+    })
+}
+
+/// Gets the frame pointer of the caller.
+#[inline(always)]
+fn current_frame_pointer() -> usize {
+    let fp: usize;
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+        } else {
+            fp = 0;
+        }
+    }
+    fp
+}
+
+/// Returns the addresses of the saved frame pointer and the return address
+/// in the frame record that `fp` points to.
+#[inline]
+fn frame_record(fp: usize) -> (usize, usize) {
+    const WORD: usize = core::mem::size_of::<usize>();
+    if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        // `s0` points to the top of the frame, with the record right below.
+        (fp.wrapping_sub(2 * WORD), fp.wrapping_sub(WORD))
+    } else {
+        (fp, fp.wrapping_add(WORD))
+    }
+}
+
+/// Calls `f` with the return address of every frame, starting from the one
+/// that `fp` points to. Frame records outside `stack` are not accessed.
+fn walk_frames<F>(mut fp: usize, stack: (usize, usize), mut f: F) -> fmt::Result
+where
+    F: FnMut(usize) -> fmt::Result,
+{
+    const WORD: usize = core::mem::size_of::<usize>();
+    let (bottom, top) = stack;
+    for _ in 0..MAX_FRAMES {
+        let (prev_fp_addr, ra_addr) = frame_record(fp);
+        if fp % WORD != 0 || prev_fp_addr < bottom || ra_addr + WORD > top {
+            break;
+        }
+        let (prev_fp, ra) = unsafe {
+            (
+                (prev_fp_addr as *const usize).read_volatile(),
+                (ra_addr as *const usize).read_volatile(),
+            )
+        };
+        if ra == 0 {
+            break;
+        }
+        f(ra)?;
+        // The stack grows downwards, callers' frames must be above.
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+    Ok(())
+}
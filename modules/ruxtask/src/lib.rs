@@ -10,8 +10,8 @@
 //! [Ruxos](https://github.com/syswonder/ruxos) task management module.
 //!
 //! This module provides primitives for task management, including task
-//! creation, scheduling, sleeping, termination, etc. The scheduler algorithm
-//! is configurable by cargo features.
+//! creation, scheduling, sleeping, termination, deferred work, etc. The
+//! scheduler algorithm is configurable by cargo features.
 //!
 //! # Cargo Features
 //!
@@ -19,9 +19,11 @@
 //!   management and scheduling is used, as well as more task-related APIs.
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
-//!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    APIs can be used, such as [`sleep`], [`sleep_until`],
+//!    [`WaitQueue::wait_timeout`] and [`queue_delayed_work`].
 //! - `preempt`: Enable preemptive scheduling.
+//! - `paging`: Map task stacks with guard pages below them, so that stack
+//!   overflows are caught by the page fault handler.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default,
 //!   and it can be overriden by other scheduler features.
@@ -29,10 +31,14 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_rt`: Use the [real-time scheduler][4], which supports the
+//!   `SCHED_DEADLINE`, `SCHED_FIFO` and `SCHED_RR` policies besides CFS. It
+//!   also enables the `multitask` and `preempt` features if it is enabled.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
 //! [3]: scheduler::CFScheduler
+//! [4]: scheduler::RTScheduler
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
@@ -44,10 +50,14 @@ cfg_if::cfg_if! {
         extern crate log;
         extern crate alloc;
 
+        mod cpumask;
         mod run_queue;
         mod task;
         mod api;
         mod wait_queue;
+        mod registry;
+        mod dump;
+        mod workqueue;
         #[cfg(feature = "irq")]
         /// load average
         pub mod loadavg;
@@ -75,6 +85,13 @@ cfg_if::cfg_if! {
         pub use self::api::*;
         pub use self::api::{sleep, sleep_until, yield_now};
         pub use task::TaskState;
+        pub use self::registry::{all_tasks, for_each_task, task_by_id, task_count};
+        pub use self::dump::{dump_tasks, dump_tasks_to};
+        pub use self::workqueue::{
+            queue_work, run_tasklets, system_wq, Tasklet, Work, WorkQueue,
+        };
+        #[cfg(feature = "irq")]
+        pub use self::workqueue::{cancel_delayed_work, queue_delayed_work};
     } else {
         mod api_s;
         pub use self::api_s::{sleep, sleep_until, yield_now};
@@ -44,16 +44,17 @@ fn calc_load(load: u64, exp: u64, active: u64) -> u64 {
 /*
  * calc_load_tick - update the avenrun load
  *
- * Called from the scheduler_timer_tick.
+ * Called from the scheduler_timer_tick, with the number of ticks elapsed
+ * since the last call, as there are no ticks while the CPU is idle.
  */
-pub(crate) fn calc_load_tick(is_idle: bool) {
+pub(crate) fn calc_load_tick(is_idle: bool, ticks: u64) {
     if is_idle {
         unsafe {
-            IDLE_CNT.fetch_add(1, Ordering::Relaxed);
+            IDLE_CNT.fetch_add(ticks, Ordering::Relaxed);
         }
     }
     unsafe {
-        ALL_CNT.fetch_add(1, Ordering::Relaxed);
+        ALL_CNT.fetch_add(ticks, Ordering::Relaxed);
     }
 
     let curr = ruxhal::time::current_time_nanos();
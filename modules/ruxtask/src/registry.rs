@@ -0,0 +1,98 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! A global registry of all alive tasks, for introspection.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spinlock::SpinNoIrq;
+
+use crate::task::{TaskId, TaskState};
+use crate::{AxTask, AxTaskRef};
+
+/// Tasks indexed by their IDs. It does not keep the tasks alive, a task is
+/// removed when it is dropped.
+static TASKS: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
+pub(crate) fn register(task: &AxTaskRef) {
+    TASKS
+        .lock()
+        .insert(task.id().as_u64(), Arc::downgrade(task));
+}
+
+pub(crate) fn unregister(id: TaskId) {
+    TASKS.lock().remove(&id.as_u64());
+}
+
+/// Like [`all_tasks`], but returns `None` instead of spinning if the registry
+/// is locked, e.g., by the panicked task.
+pub(crate) fn try_all_tasks() -> Option<Vec<AxTaskRef>> {
+    let tasks = TASKS.try_lock()?;
+    Some(tasks.values().filter_map(Weak::upgrade).collect())
+}
+
+/// Finds the task using the most memory which can be killed, i.e., not an
+/// idle task, the init task, a task of the kernel, or a killed or exited
+/// task.
+///
+/// It's used by the OOM killer in the allocator, so it neither allocates
+/// memory nor spins on the registry lock.
+pub(crate) fn try_find_largest() -> Option<AxTaskRef> {
+    let tasks = TASKS.try_lock()?;
+    let victim = tasks
+        .values()
+        .filter(|task| task.strong_count() > 0)
+        // Safe because the memory of the task is kept by the weak reference,
+        // and the task is not freed with the registry locked.
+        .map(|task| (task, unsafe { &*task.as_ptr() }))
+        .filter(|(_, t)| {
+            !t.is_idle()
+                && !t.is_init()
+                && !t.is_kernel()
+                && !t.is_killed()
+                && t.state() != TaskState::Exited
+        })
+        .max_by_key(|(_, t)| t.mem_usage())
+        .and_then(|(task, _)| task.upgrade());
+    // The task must not be dropped with the registry locked.
+    drop(tasks);
+    victim
+}
+
+/// Returns all alive tasks, in the ascending order of their IDs.
+///
+/// Exited tasks are included until they are dropped.
+pub fn all_tasks() -> Vec<AxTaskRef> {
+    // The returned tasks must not be dropped with the registry locked, as
+    // dropping the last reference unregisters the task.
+    let tasks = TASKS.lock();
+    tasks.values().filter_map(Weak::upgrade).collect()
+}
+
+/// Calls `f` on every alive task, in the ascending order of their IDs.
+///
+/// `f` is called on a snapshot of the registry, so it can spawn or drop
+/// tasks.
+pub fn for_each_task<F>(f: F)
+where
+    F: FnMut(&AxTaskRef),
+{
+    all_tasks().iter().for_each(f);
+}
+
+/// Finds the alive task with the given ID.
+pub fn task_by_id(id: u64) -> Option<AxTaskRef> {
+    TASKS.lock().get(&id).and_then(Weak::upgrade)
+}
+
+/// Returns the number of alive tasks.
+pub fn task_count() -> usize {
+    TASKS.lock().len()
+}
@@ -10,16 +10,30 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use kernel_guard::NoPreemptIrqSave;
 use lazy_init::LazyInit;
 use ruxfdtable::{FD_TABLE, RUX_FILE_LIMIT};
 use scheduler::BaseScheduler;
-use spinlock::SpinNoIrq;
+use spinlock::{SpinNoIrq, SpinRaw, SpinRawGuard};
 
 use crate::task::{CurrentTask, TaskState};
 use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
 
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// Every CPU tries to pull a task from the busiest CPU once per this many
+/// timer ticks.
+#[cfg(feature = "irq")]
+const BALANCE_INTERVAL_TICKS: usize = 16;
+
+#[percpu::def_percpu]
+static RUN_QUEUE: LazyInit<AxRunQueue> = LazyInit::new();
+
+#[allow(clippy::declare_interior_mutable_const)]
+const CPU_OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the run queue of each CPU has been initialized.
+static CPU_ONLINE: [AtomicBool; ruxconfig::SMP] = [CPU_OFFLINE; ruxconfig::SMP];
 
 // TODO: per-CPU
 static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
@@ -29,57 +43,255 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task switched out by the last context switch on this CPU, until the
+/// next task finishes the switch.
+#[percpu::def_percpu]
+static PREV_TASK: Option<AxTaskRef> = None;
+
+/// Whether [`PREV_TASK`] should be moved to another CPU, as its affinity no
+/// longer contains this CPU.
+#[percpu::def_percpu]
+static MIGRATE_PREV_TASK: bool = false;
+
+/// Total CPU bandwidth reserved by `SCHED_DEADLINE` tasks, in units of
+/// `1 / (1 << 20)` of a CPU.
+#[cfg(feature = "sched_rt")]
+static DL_BANDWIDTH: SpinNoIrq<u64> = SpinNoIrq::new(0);
+
+/// `SCHED_DEADLINE` tasks can reserve at most 95% of all CPUs, like the
+/// default `sched_rt_runtime_us / sched_rt_period_us` in Linux.
+#[cfg(feature = "sched_rt")]
+const DL_BANDWIDTH_LIMIT: u64 = ((ruxconfig::SMP as u64) << 20) * 95 / 100;
+
+/// The run queue of a CPU.
+///
+/// Each CPU schedules the tasks in its own run queue. A task is put into
+/// the run queue of one of the CPUs in its affinity when it is spawned or
+/// woken up, and idle CPUs steal ready tasks from others.
 pub(crate) struct AxRunQueue {
-    scheduler: Scheduler,
+    cpu_id: usize,
+    // IRQs are always disabled when it is locked, see `current_run_queue()`.
+    scheduler: SpinRaw<Scheduler>,
+    /// Number of tasks in `scheduler`, which can be read without locking.
+    nr_ready: AtomicUsize,
+    /// Whether the CPU is running its idle task.
+    idle: AtomicBool,
+    #[cfg(feature = "irq")]
+    ticks: AtomicUsize,
+}
+
+/// A reference to the run queue of the current CPU, which keeps IRQs and
+/// preemption disabled on the current CPU until it is dropped, so that the
+/// current task cannot be migrated to another CPU.
+pub(crate) struct CurrentRunQueueRef {
+    inner: &'static AxRunQueue,
+    _guard: NoPreemptIrqSave,
+}
+
+impl Deref for CurrentRunQueueRef {
+    type Target = AxRunQueue;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+/// Gets the run queue of the current CPU.
+pub(crate) fn current_run_queue() -> CurrentRunQueueRef {
+    let guard = NoPreemptIrqSave::new();
+    CurrentRunQueueRef {
+        inner: this_run_queue(),
+        _guard: guard,
+    }
+}
+
+/// Whether the given CPU can run tasks.
+pub(crate) fn is_cpu_online(cpu_id: usize) -> bool {
+    cpu_id < ruxconfig::SMP && CPU_ONLINE[cpu_id].load(Ordering::Acquire)
+}
+
+/// Gets the run queue of the current CPU, IRQs and preemption must be
+/// disabled.
+fn this_run_queue() -> &'static AxRunQueue {
+    // Safety: the run queue is initialized before the CPU runs any task, and
+    // is never dropped.
+    unsafe { RUN_QUEUE.current_ref_raw() }
+}
+
+/// Gets the run queue of the given CPU, or `None` if the CPU is offline.
+fn run_queue_of(cpu_id: usize) -> Option<&'static AxRunQueue> {
+    // Safety: the run queue of an online CPU is initialized, and never dropped.
+    is_cpu_online(cpu_id).then(|| unsafe { &**RUN_QUEUE.remote_ref_raw(cpu_id) })
+}
+
+/// Sets the scheduling policy of `task`, and moves it to the right place in
+/// the ready queue if it is ready.
+///
+/// Returns `false` if the parameters are invalid, or there is not enough CPU
+/// bandwidth for a `SCHED_DEADLINE` task.
+#[cfg(feature = "sched_rt")]
+pub(crate) fn set_sched_policy(task: &AxTaskRef, policy: scheduler::SchedPolicy) -> bool {
+    if !policy.is_valid() {
+        return false;
+    }
+    // Also serializes policy changes of tasks, and keeps IRQs disabled while
+    // locking the run queue.
+    let mut dl_bandwidth = DL_BANDWIDTH.lock();
+    let old_bw = task.base_policy().bandwidth();
+    let new_bw = policy.bandwidth();
+    let total = *dl_bandwidth - old_bw + new_bw;
+    if new_bw > old_bw && total > DL_BANDWIDTH_LIMIT {
+        return false;
+    }
+    *dl_bandwidth = total;
+    task.set_base_policy(policy);
+    apply_sched_policy(task);
+    true
+}
+
+/// Sets the scheduling policy `task` inherits from the tasks waiting for the
+/// locks it holds. The task runs with it if it precedes the task's own policy.
+#[cfg(feature = "sched_rt")]
+pub(crate) fn set_inherited_sched_policy(task: &AxTaskRef, policy: Option<scheduler::SchedPolicy>) {
+    let _lock = DL_BANDWIDTH.lock();
+    task.set_pi_policy(policy);
+    apply_sched_policy(task);
+}
+
+/// Returns the scheduling policy `task` actually runs with.
+#[cfg(feature = "sched_rt")]
+pub(crate) fn effective_sched_policy(task: &AxTaskRef) -> scheduler::SchedPolicy {
+    let base = task.base_policy();
+    match task.pi_policy() {
+        Some(inherited) if inherited.precedes(&base) => inherited,
+        _ => base,
+    }
+}
+
+/// Puts the effective policy of `task` into effect, `DL_BANDWIDTH` must be
+/// locked.
+#[cfg(feature = "sched_rt")]
+fn apply_sched_policy(task: &AxTaskRef) {
+    let policy = effective_sched_policy(task);
+    if task.policy() == policy {
+        return;
+    }
+    loop {
+        let cpu_id = task.cpu_id();
+        let rq = run_queue_of(cpu_id).unwrap_or_else(this_run_queue);
+        let mut scheduler = rq.lock_scheduler();
+        // The task has been moved to another run queue before we lock this
+        // one, try again.
+        if task.cpu_id() != cpu_id {
+            continue;
+        }
+        let queued = scheduler.remove_task(task);
+        scheduler.set_policy(task, policy);
+        if let Some(task) = queued {
+            scheduler.add_task(task);
+        }
+        break;
+    }
+    // Let the current task be rescheduled, in case it should no longer run.
+    #[cfg(feature = "preempt")]
+    crate::current().set_preempt_pending(true);
+}
+
+/// Releases the CPU bandwidth reserved by an exited task.
+#[cfg(feature = "sched_rt")]
+fn release_bandwidth(task: &AxTaskRef) {
+    *DL_BANDWIDTH.lock() -= task.base_policy().bandwidth();
+}
+
+/// Selects the run queue to put a ready task into.
+///
+/// If `affine` is true, the CPU the task was last running on is preferred for
+/// better cache usage. Otherwise, or if that CPU is not allowed, the least
+/// loaded CPU in the task's affinity is selected.
+fn select_run_queue(task: &AxTaskRef, affine: bool) -> &'static AxRunQueue {
+    let mask = task.cpumask();
+    if affine && mask.get(task.cpu_id()) {
+        if let Some(rq) = run_queue_of(task.cpu_id()) {
+            return rq;
+        }
+    }
+    mask.iter()
+        .filter_map(run_queue_of)
+        .min_by_key(|rq| rq.load())
+        .unwrap_or_else(this_run_queue)
 }
 
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), ruxconfig::TASK_STACK_SIZE);
-        let mut scheduler = Scheduler::new();
-        scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+    fn new(cpu_id: usize, idle: bool) -> Self {
+        Self {
+            cpu_id,
+            scheduler: SpinRaw::new(Scheduler::new()),
+            nr_ready: AtomicUsize::new(0),
+            idle: AtomicBool::new(idle),
+            #[cfg(feature = "irq")]
+            ticks: AtomicUsize::new(0),
+        }
+    }
+
+    /// Locks the scheduler, and advances its clock if it needs one.
+    fn lock_scheduler(&self) -> SpinRawGuard<'_, Scheduler> {
+        #[allow(unused_mut)]
+        let mut scheduler = self.scheduler.lock();
+        #[cfg(feature = "sched_rt")]
+        scheduler.update_clock(ruxhal::time::current_time_nanos());
+        scheduler
     }
 
-    pub fn add_task(&mut self, task: AxTaskRef) {
+    /// The number of ready and running tasks on this CPU.
+    fn load(&self) -> usize {
+        self.nr_ready.load(Ordering::Relaxed) + !self.idle.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn add_task(&self, task: AxTaskRef) {
         debug!("task spawn: {}", task.id_name());
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        select_run_queue(&task, false).push_task(task);
     }
 
+    /// Handles `ticks` scheduler ticks elapsed since the last call.
     #[cfg(feature = "irq")]
-    pub fn scheduler_timer_tick(&mut self) {
+    pub fn scheduler_timer_tick(&self, ticks: u64) {
         use crate::loadavg;
         let curr = crate::current();
-        loadavg::calc_load_tick(curr.is_idle());
-        if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
+        loadavg::calc_load_tick(curr.is_idle(), ticks);
+        if !curr.is_idle() && self.lock_scheduler().task_tick(curr.as_task_ref()) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+        let old_ticks = self.ticks.fetch_add(ticks as usize, Ordering::Relaxed);
+        if old_ticks / BALANCE_INTERVAL_TICKS
+            != (old_ticks + ticks as usize) / BALANCE_INTERVAL_TICKS
+        {
+            self.balance();
+        }
     }
 
-    pub fn yield_current(&mut self) {
+    pub fn yield_current(&self) {
         let curr = crate::current();
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
         self.resched(false);
     }
 
-    pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.scheduler
+    pub fn set_current_priority(&self, prio: isize) -> bool {
+        self.lock_scheduler()
             .set_priority(crate::current().as_task_ref(), prio)
     }
 
     #[cfg(feature = "preempt")]
-    pub fn preempt_resched(&mut self) {
+    pub fn preempt_resched(&self) {
         let curr = crate::current();
         assert!(curr.is_running());
 
-        // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
-        // disabled. So we need to set `current_disable_count` to 1 in
-        // `can_preempt()` to obtain the preemption permission before
-        //  locking the run queue.
+        // When we get the reference of the current run queue, we must have
+        // both IRQs and preemption disabled. So we need to set
+        // `current_disable_count` to 1 in `can_preempt()` to obtain the
+        // preemption permission.
         let can_preempt = curr.can_preempt(1);
 
         debug!(
@@ -94,7 +306,7 @@ impl AxRunQueue {
         }
     }
 
-    pub fn exit_current(&mut self, exit_code: i32) -> ! {
+    pub fn exit_current(&self, exit_code: i32) -> ! {
         let curr = crate::current();
         debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
         assert!(curr.is_running());
@@ -104,15 +316,21 @@ impl AxRunQueue {
             ruxhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
+            #[cfg(feature = "sched_rt")]
+            release_bandwidth(curr.as_task_ref());
             curr.notify_exit(exit_code, self);
-            EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
+            // A detached task is dropped in `finish_task_switch()` by the
+            // next task instead, unless someone else still holds it.
+            if !curr.is_detached() {
+                EXITED_TASKS.lock().push_back(curr.clone());
+                WAIT_FOR_EXIT.notify_one_locked(false, self);
+            }
             self.resched(false);
         }
         unreachable!("task exited!");
     }
 
-    pub fn block_current<F>(&mut self, wait_queue_push: F)
+    pub fn block_current<F>(&self, wait_queue_push: F)
     where
         F: FnOnce(AxTaskRef),
     {
@@ -126,16 +344,38 @@ impl AxRunQueue {
         assert!(curr.can_preempt(1));
 
         curr.set_state(TaskState::Blocked);
+        if curr.cancel_interrupted_block() {
+            return;
+        }
         wait_queue_push(curr.clone());
         self.resched(false);
     }
 
-    pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
+    pub fn unblock_task(&self, task: AxTaskRef, resched: bool) {
         debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
-            if resched {
+        // A task may be woken up by several wakers (e.g., the timer and the
+        // wait queue) at the same time, only one of them can succeed.
+        if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+            task.account_wakeup();
+            // The task may be still being switched out on another CPU.
+            while task.on_cpu() {
+                core::hint::spin_loop();
+            }
+            let rq = select_run_queue(&task, true);
+            let local = core::ptr::eq(rq, self);
+            // Real-time tasks preempt the current task once woken up. Tasks
+            // put on other busy CPUs are picked at their next timer ticks.
+            #[cfg(feature = "sched_rt")]
+            let resched = resched
+                || local && {
+                    let curr = crate::current();
+                    !curr.is_idle()
+                        && self
+                            .lock_scheduler()
+                            .task_preempts(curr.as_task_ref(), &task)
+                };
+            rq.push_task(task);
+            if resched && local {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
             }
@@ -143,7 +383,7 @@ impl AxRunQueue {
     }
 
     #[cfg(feature = "irq")]
-    pub fn sleep_until(&mut self, deadline: ruxhal::time::TimeValue) {
+    pub fn sleep_until(&self, deadline: ruxhal::time::TimeValue) {
         let curr = crate::current();
         debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
         assert!(curr.is_running());
@@ -151,32 +391,182 @@ impl AxRunQueue {
 
         let now = ruxhal::time::current_time();
         if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+            // Block it before setting the alarm, as the timer may be expired
+            // on another CPU at once.
             curr.set_state(TaskState::Blocked);
+            if curr.cancel_interrupted_block() {
+                return;
+            }
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
             self.resched(false);
+            if curr.in_timer_list() {
+                // Woken up by `interrupt()` before the deadline.
+                crate::timers::cancel_alarm(curr.as_task_ref());
+            }
         }
     }
 }
 
 impl AxRunQueue {
+    /// Puts a ready task into this run queue.
+    fn push_task(&self, task: AxTaskRef) {
+        self.enqueue_task(task, false);
+    }
+
+    /// Puts a task stolen from another run queue into this run queue, keeping
+    /// its scheduling state.
+    #[cfg(feature = "irq")]
+    fn push_migrated_task(&self, task: AxTaskRef) {
+        self.enqueue_task(task, true);
+    }
+
+    fn enqueue_task(&self, task: AxTaskRef, migrated: bool) {
+        task.set_cpu_id(self.cpu_id);
+        if migrated {
+            self.lock_scheduler().migrate_task(task);
+        } else {
+            self.lock_scheduler().add_task(task);
+        }
+        self.nr_ready.fetch_add(1, Ordering::Relaxed);
+        // An idle CPU has no timer ticks, wake it up to run the task.
+        #[cfg(feature = "irq")]
+        if self.idle.load(Ordering::Relaxed) && self.cpu_id != ruxhal::cpu::this_cpu_id() {
+            ruxhal::irq::send_ipi(self.cpu_id);
+        }
+    }
+
+    /// Whether there are ready tasks in this run queue.
+    #[cfg(feature = "irq")]
+    pub fn has_ready_tasks(&self) -> bool {
+        self.nr_ready.load(Ordering::Relaxed) > 0
+    }
+
+    /// Picks the next task to run on this CPU from this run queue, tasks
+    /// whose affinity has been changed are moved to other CPUs.
+    fn pick_next_task(&self) -> Option<AxTaskRef> {
+        loop {
+            let task = {
+                let mut scheduler = self.lock_scheduler();
+                let task = scheduler.pick_next_task()?;
+                self.nr_ready.fetch_sub(1, Ordering::Relaxed);
+                task
+            };
+            if task.cpumask().get(self.cpu_id) {
+                return Some(task);
+            }
+            select_run_queue(&task, false).push_task(task);
+        }
+    }
+
+    /// Takes a ready task which is allowed to run on this CPU from `victim`,
+    /// without touching the scheduling state of the tasks there.
+    ///
+    /// It gives up if `victim` is locked, as waiting for it while holding
+    /// another run queue may cause deadlocks.
+    fn steal_from(&self, victim: &AxRunQueue) -> Option<AxTaskRef> {
+        // A task being switched out on `victim` can not be run here.
+        let task = victim
+            .scheduler
+            .try_lock()?
+            .steal_task(|task| task.cpumask().get(self.cpu_id) && !task.on_cpu())?;
+        victim.nr_ready.fetch_sub(1, Ordering::Relaxed);
+        debug!(
+            "task migrate: {}, CPU {} -> {}",
+            task.id_name(),
+            victim.cpu_id,
+            self.cpu_id
+        );
+        Some(task)
+    }
+
+    /// Steals a ready task from other CPUs, when this CPU is going to be idle.
+    fn steal_task(&self) -> Option<AxTaskRef> {
+        (1..ruxconfig::SMP)
+            .map(|i| (self.cpu_id + i) % ruxconfig::SMP)
+            .filter_map(run_queue_of)
+            .filter(|rq| rq.nr_ready.load(Ordering::Relaxed) > 0)
+            .find_map(|rq| self.steal_from(rq))
+    }
+
+    /// Pulls a task from the busiest CPU, if it is busier than this CPU by at
+    /// least two tasks.
+    #[cfg(feature = "irq")]
+    fn balance(&self) {
+        let busiest = (0..ruxconfig::SMP)
+            .filter(|&cpu_id| cpu_id != self.cpu_id)
+            .filter_map(run_queue_of)
+            .max_by_key(|rq| rq.load());
+        if let Some(busiest) = busiest {
+            if busiest.load() > self.load() + 1 {
+                if let Some(task) = self.steal_from(busiest) {
+                    self.push_migrated_task(task);
+                }
+            }
+        }
+    }
+
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
-    fn resched(&mut self, preempt: bool) {
+    fn resched(&self, preempt: bool) {
         let prev = crate::current();
+        #[allow(unused_mut)]
+        let mut throttled = false;
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
+            #[cfg(feature = "sched_rt")]
             if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+                throttled = self.throttle_prev_task(&prev);
+            }
+            if !prev.is_idle() && !throttled {
+                if prev.cpumask().get(self.cpu_id) {
+                    self.lock_scheduler().put_prev_task(prev.clone(), preempt);
+                    self.nr_ready.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    // It can be run by other CPUs only after it is switched
+                    // out, see `finish_task_switch()`.
+                    unsafe { MIGRATE_PREV_TASK.write_current_raw(true) };
+                }
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
+        let next = self
+            .pick_next_task()
+            .or_else(|| self.steal_task())
+            .unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
         self.switch_to(prev, next);
+        if throttled {
+            let curr = crate::current();
+            if curr.in_timer_list() {
+                // Woken up by `interrupt()` before the next period.
+                crate::timers::cancel_alarm(curr.as_task_ref());
+            }
+        }
+    }
+
+    /// Throttles `prev` until its next period if it is a `SCHED_DEADLINE`
+    /// task which has used up its runtime budget. It is blocked, and woken
+    /// up by a timer then.
+    #[cfg(feature = "sched_rt")]
+    fn throttle_prev_task(&self, prev: &CurrentTask) -> bool {
+        let Some(next_period) = self.lock_scheduler().throttle_prev_task(prev.as_task_ref()) else {
+            return false;
+        };
+        debug!(
+            "task throttled: {}, until={}ns",
+            prev.id_name(),
+            next_period
+        );
+        prev.set_state(TaskState::Blocked);
+        crate::timers::set_alarm_wakeup(
+            ruxhal::time::TimeValue::from_nanos(next_period),
+            prev.clone(),
+        );
+        true
     }
 
-    fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
+    fn switch_to(&self, prev_task: CurrentTask, next_task: AxTaskRef) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
@@ -188,6 +578,25 @@ impl AxRunQueue {
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        next_task.set_cpu_id(self.cpu_id);
+        next_task.set_on_cpu(true);
+        self.idle.store(next_task.is_idle(), Ordering::Relaxed);
+        #[cfg(feature = "irq")]
+        if prev_task.is_idle() != next_task.is_idle() {
+            if prev_task.is_idle() {
+                // Account the ticks skipped while idle, and restart ticking.
+                crate::loadavg::calc_load_tick(true, crate::timers::elapsed_ticks());
+            }
+            crate::timers::program_next_timer(next_task.is_idle());
+        }
+
+        if prev_task.stack_overflowed() {
+            panic!("stack overflow in task {}", prev_task.id_name());
+        }
+
+        let now_ns = ruxhal::time::current_time_nanos();
+        prev_task.account_switch_out(now_ns);
+        next_task.account_switch_in(now_ns);
 
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
@@ -198,9 +607,28 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            *PREV_TASK.current_ref_mut_raw() = Some(prev_task.clone());
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
         }
+        // Now we are back, maybe on another CPU.
+        finish_task_switch();
+    }
+}
+
+/// Finishes the context switch on the current CPU, called by the task just
+/// switched to, with IRQs disabled.
+///
+/// The previous task is marked as switched out, so that other CPUs can run
+/// it from now on.
+pub(crate) fn finish_task_switch() {
+    let prev = unsafe { PREV_TASK.current_ref_mut_raw().take() };
+    if let Some(prev) = prev {
+        prev.set_on_cpu(false);
+        if unsafe { MIGRATE_PREV_TASK.read_current_raw() } {
+            unsafe { MIGRATE_PREV_TASK.write_current_raw(false) };
+            select_run_queue(&prev, false).push_task(prev);
+        }
     }
 }
 
@@ -243,20 +671,36 @@ fn gc_entry() {
 }
 
 pub(crate) fn init() {
+    let cpu_id = ruxhal::cpu::this_cpu_id();
+
     const IDLE_TASK_STACK_SIZE: usize = 4096;
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
 
     let main_task = TaskInner::new_init("main".into());
     main_task.set_state(TaskState::Running);
+    main_task.set_cpu_id(cpu_id);
+    main_task.set_on_cpu(true);
 
-    RUN_QUEUE.init_by(AxRunQueue::new());
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id, false)));
+    CPU_ONLINE[cpu_id].store(true, Ordering::Release);
     unsafe { CurrentTask::init_current(main_task) }
+
+    let gc_task = TaskInner::new(gc_entry, "gc".into(), ruxconfig::TASK_STACK_SIZE);
+    gc_task.set_kernel();
+    current_run_queue().add_task(gc_task);
 }
 
 pub(crate) fn init_secondary() {
+    let cpu_id = ruxhal::cpu::this_cpu_id();
+
     let idle_task = TaskInner::new_init("idle".into());
     idle_task.set_state(TaskState::Running);
+    idle_task.set_cpu_id(cpu_id);
+    idle_task.set_on_cpu(true);
     IDLE_TASK.with_current(|i| i.init_by(idle_task.clone()));
+
+    RUN_QUEUE.with_current(|rq| rq.init_by(AxRunQueue::new(cpu_id, true)));
+    CPU_ONLINE[cpu_id].store(true, Ordering::Release);
     unsafe { CurrentTask::init_current(idle_task) }
 }
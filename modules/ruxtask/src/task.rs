@@ -8,13 +8,12 @@
  */
 
 use alloc::{boxed::Box, string::String, sync::Arc};
+use axerrno::{AxError, AxResult};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use core::time::Duration;
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 #[cfg(feature = "tls")]
 use ruxhal::tls::TlsArea;
 
@@ -22,8 +21,10 @@ use memory_addr::{align_up_4k, VirtAddr};
 use ruxhal::arch::TaskContext;
 
 #[cfg(not(feature = "musl"))]
-use crate::tsd::{DestrFunction, KEYS, TSD};
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+use crate::tsd::{self, DestrFunction, TSD};
+#[cfg(feature = "sched_rt")]
+use crate::SchedPolicy;
+use crate::{AxRunQueue, AxTask, AxTaskRef, CpuMask, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -43,20 +44,118 @@ pub enum TaskState {
     Exited = 4,
 }
 
+/// Scheduling statistics of a task, see [`TaskInner::stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskStats {
+    /// Total time the task has been running on CPUs.
+    pub cpu_time: Duration,
+    /// Number of times the task gave up the CPU by blocking or exiting.
+    pub voluntary_switches: u64,
+    /// Number of times the task was switched out while still ready to run,
+    /// i.e., it yielded or was preempted.
+    pub involuntary_switches: u64,
+    /// Number of times the task was woken up.
+    pub wakeups: u64,
+    /// Size of the task's stack in bytes, or 0 if the task runs on the boot
+    /// stack.
+    pub stack_size: usize,
+    /// Maximum number of bytes of the stack ever used.
+    pub stack_high_water: usize,
+    /// Number of bytes of heap memory allocated by the task and not freed
+    /// yet, by whichever task.
+    pub mem_usage: usize,
+    /// Maximum of [`mem_usage`](Self::mem_usage) ever reached.
+    pub mem_peak: usize,
+}
+
+/// Where the stack of a new task comes from, see
+/// [`new_task_with_stack`](crate::new_task_with_stack).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StackAttr {
+    /// Allocates a stack of `size` bytes. If the `paging` feature is enabled,
+    /// it is mapped with an unmapped guard area of `guard_size` bytes below
+    /// it, both rounded up to multiples of the page size.
+    Alloc {
+        /// Size of the stack in bytes.
+        size: usize,
+        /// Size of the guard area in bytes, 0 for no guard area.
+        guard_size: usize,
+    },
+    /// Runs on the memory `[bottom, bottom + size)` provided by the caller,
+    /// which must outlive the task, and is not freed when the task is
+    /// dropped. There is no guard area.
+    User {
+        /// The lowest address of the stack.
+        bottom: usize,
+        /// Size of the stack in bytes.
+        size: usize,
+    },
+}
+
+impl StackAttr {
+    /// Allocates a stack of `size` bytes, with a guard page below it.
+    pub const fn with_size(size: usize) -> Self {
+        Self::Alloc {
+            size,
+            guard_size: memory_addr::PAGE_SIZE_4K,
+        }
+    }
+}
+
+/// Counters updated by the scheduler, for [`TaskStats`].
+#[derive(Default)]
+struct TaskCounters {
+    cpu_time_ns: AtomicU64,
+    /// Time the task was last switched in, in nanoseconds.
+    switched_in_at: AtomicU64,
+    voluntary_switches: AtomicU64,
+    involuntary_switches: AtomicU64,
+    wakeups: AtomicU64,
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
     name: String,
     is_idle: bool,
     is_init: bool,
+    /// Whether the task works for the kernel, e.g., a worker of a work
+    /// queue, which is never killed by the OOM killer.
+    is_kernel: AtomicBool,
 
     entry: Option<*mut dyn FnOnce()>,
     state: AtomicU8,
 
+    /// CPUs the task is allowed to run on.
+    cpumask: spinlock::SpinNoIrq<CpuMask>,
+    /// The CPU the task is running on, or was last queued on.
+    cpu_id: AtomicUsize,
+    /// Whether the task is running on a CPU, including the moments during
+    /// it is being switched out.
+    on_cpu: AtomicBool,
+
     in_wait_queue: AtomicBool,
+    /// Address of the wait queue the task has been moved to by
+    /// [`WaitQueueWithMetadata::requeue_tasks_if`], or 0 if not moved.
+    ///
+    /// [`WaitQueueWithMetadata::requeue_tasks_if`]: crate::WaitQueueWithMetadata::requeue_tasks_if
+    requeued_to: AtomicUsize,
+    /// Address of the wait queue the task is blocked on, or 0 if not in any.
+    wait_channel: AtomicUsize,
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// The scheduling policy set by the user.
+    #[cfg(feature = "sched_rt")]
+    base_policy: spinlock::SpinNoIrq<SchedPolicy>,
+    /// The scheduling policy inherited from the tasks waiting for the locks
+    /// held by this task.
+    #[cfg(feature = "sched_rt")]
+    pi_policy: spinlock::SpinNoIrq<Option<SchedPolicy>>,
+
+    /// Address of the head of the robust futex list, or 0 if not set.
+    robust_list: AtomicUsize,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
@@ -64,6 +163,25 @@ pub struct TaskInner {
 
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
+    /// Whether nobody is going to join the task, so that it is dropped as
+    /// soon as it exits.
+    detached: AtomicBool,
+
+    /// Whether the task is in a wait which can be interrupted, see
+    /// [`interruptible`](crate::interruptible).
+    interruptible: AtomicBool,
+    /// Whether the task has been [interrupted](crate::interrupt).
+    interrupt_pending: AtomicBool,
+    /// Whether the task has been [killed](crate::kill).
+    killed: AtomicBool,
+    /// Number of sleeping locks held by the task, see [`lock_acquired`](Self::lock_acquired).
+    locks_held: AtomicUsize,
+    /// Depth of the [`KernelSection`](crate::KernelSection)s the task is in.
+    kernel_depth: AtomicUsize,
+
+    counters: TaskCounters,
+    /// The heap memory charged to the task, `None` if it can't be allocated.
+    mem_owner: Option<axalloc::MemOwnerRef>,
 
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
@@ -132,6 +250,54 @@ impl TaskInner {
         alloc::format!("Task({}, {:?})", self.id.as_u64(), self.name)
     }
 
+    /// Gets the set of CPUs the task is allowed to run on.
+    pub fn cpumask(&self) -> CpuMask {
+        *self.cpumask.lock()
+    }
+
+    /// Gets the ID of the CPU the task is running on, or was last queued on.
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    /// Gets the address of the head of the task's robust futex list, or 0 if
+    /// it is not set.
+    pub fn robust_list(&self) -> usize {
+        self.robust_list.load(Ordering::Acquire)
+    }
+
+    /// Sets the address of the head of the task's robust futex list, which
+    /// is walked when the task exits.
+    pub fn set_robust_list(&self, head: usize) {
+        self.robust_list.store(head, Ordering::Release);
+    }
+
+    /// Gets the address of the wait queue the task is blocked on, or 0 if it
+    /// is not waiting in any wait queue.
+    pub fn wait_channel(&self) -> usize {
+        self.wait_channel.load(Ordering::Acquire)
+    }
+
+    /// Gets the scheduling statistics of the task.
+    pub fn stats(&self) -> TaskStats {
+        let c = &self.counters;
+        let mut cpu_time_ns = c.cpu_time_ns.load(Ordering::Relaxed);
+        if self.is_running() {
+            let now = ruxhal::time::current_time_nanos();
+            cpu_time_ns += now.saturating_sub(c.switched_in_at.load(Ordering::Relaxed));
+        }
+        TaskStats {
+            cpu_time: Duration::from_nanos(cpu_time_ns),
+            voluntary_switches: c.voluntary_switches.load(Ordering::Relaxed),
+            involuntary_switches: c.involuntary_switches.load(Ordering::Relaxed),
+            wakeups: c.wakeups.load(Ordering::Relaxed),
+            stack_size: self.kstack.as_ref().map_or(0, |s| s.size()),
+            stack_high_water: self.kstack.as_ref().map_or(0, |s| s.high_water_mark()),
+            mem_usage: self.mem_usage(),
+            mem_peak: self.mem_owner().map_or(0, |owner| owner.usage().peak),
+        }
+    }
+
     /// Wait for the task to exit, and return the exit code.
     ///
     /// It will return immediately if the task has already exited (but not dropped).
@@ -141,6 +307,27 @@ impl TaskInner {
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Waits for the task to exit for at most `dur`, and returns whether it
+    /// has exited.
+    #[cfg(feature = "irq")]
+    pub(crate) fn join_timeout(&self, dur: Duration) -> bool {
+        self.wait_for_exit
+            .wait_timeout_until(dur, || self.state() == TaskState::Exited);
+        self.state() == TaskState::Exited
+    }
+
+    /// Marks the task as detached: nobody is going to join it, so it is
+    /// dropped as soon as it exits and no one else holds it, instead of being
+    /// collected by the gc task later.
+    pub fn detach(&self) {
+        self.detached.store(true, Ordering::Release);
+    }
+
+    /// Whether the task has been [detached](TaskInner::detach).
+    pub fn is_detached(&self) -> bool {
+        self.detached.load(Ordering::Acquire)
+    }
+
     /// set 0 to thread_list_lock
     #[cfg(feature = "musl")]
     pub fn free_thread_list_lock(&self) {
@@ -160,23 +347,42 @@ impl TaskInner {
             name,
             is_idle: false,
             is_init: false,
+            is_kernel: AtomicBool::new(false),
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            cpumask: spinlock::SpinNoIrq::new(CpuMask::full()),
+            cpu_id: AtomicUsize::new(0),
+            on_cpu: AtomicBool::new(false),
             in_wait_queue: AtomicBool::new(false),
+            requeued_to: AtomicUsize::new(0),
+            wait_channel: AtomicUsize::new(0),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            #[cfg(feature = "sched_rt")]
+            base_policy: spinlock::SpinNoIrq::new(SchedPolicy::Normal),
+            #[cfg(feature = "sched_rt")]
+            pi_policy: spinlock::SpinNoIrq::new(None),
+            robust_list: AtomicUsize::new(0),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            detached: AtomicBool::new(false),
+            interruptible: AtomicBool::new(false),
+            interrupt_pending: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+            locks_held: AtomicUsize::new(0),
+            kernel_depth: AtomicUsize::new(0),
+            counters: TaskCounters::default(),
+            mem_owner: axalloc::MemOwnerRef::new().ok(),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             #[cfg(feature = "tls")]
             tls: TlsArea::alloc(),
             #[cfg(not(feature = "musl"))]
-            tsd: spinlock::SpinNoIrq::new([core::ptr::null_mut(); ruxconfig::PTHREAD_KEY_MAX]),
+            tsd: spinlock::SpinNoIrq::new(alloc::vec::Vec::new()),
             #[cfg(feature = "musl")]
             set_tid: AtomicU64::new(0),
             #[cfg(feature = "musl")]
@@ -197,17 +403,36 @@ impl TaskInner {
             name,
             is_idle: false,
             is_init: false,
+            is_kernel: AtomicBool::new(false),
             entry: None,
             state: AtomicU8::new(TaskState::Ready as u8),
+            cpumask: spinlock::SpinNoIrq::new(CpuMask::full()),
+            cpu_id: AtomicUsize::new(0),
+            on_cpu: AtomicBool::new(false),
             in_wait_queue: AtomicBool::new(false),
+            requeued_to: AtomicUsize::new(0),
+            wait_channel: AtomicUsize::new(0),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            #[cfg(feature = "sched_rt")]
+            base_policy: spinlock::SpinNoIrq::new(SchedPolicy::Normal),
+            #[cfg(feature = "sched_rt")]
+            pi_policy: spinlock::SpinNoIrq::new(None),
+            robust_list: AtomicUsize::new(0),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            detached: AtomicBool::new(false),
+            interruptible: AtomicBool::new(false),
+            interrupt_pending: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+            locks_held: AtomicUsize::new(0),
+            kernel_depth: AtomicUsize::new(0),
+            counters: TaskCounters::default(),
+            mem_owner: axalloc::MemOwnerRef::new().ok(),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             #[cfg(feature = "tls")]
@@ -234,13 +459,13 @@ impl TaskInner {
         set_tid: AtomicU64,
         // clear child tid
         tl: AtomicU64,
-    ) -> AxTaskRef
+    ) -> AxResult<AxTaskRef>
     where
         F: FnOnce() + Send + 'static,
     {
         let mut t = Self::new_common_tls(TaskId::new(), name, tls, set_tid, tl);
         debug!("new task: {}", t.id_name());
-        let kstack = TaskStack::alloc(align_up_4k(stack_size));
+        let kstack = TaskStack::alloc(align_up_4k(stack_size), memory_addr::PAGE_SIZE_4K, t.id)?;
 
         #[cfg(feature = "tls")]
         let tls = VirtAddr::from(t.tls.tls_ptr() as usize);
@@ -253,17 +478,37 @@ impl TaskInner {
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        let task = Arc::new(AxTask::new(t));
+        crate::registry::register(&task);
+        Ok(task)
     }
 
     /// Create a new task with the given entry function and stack size.
+    ///
+    /// It panics if the stack can't be allocated, for the tasks of the kernel.
     pub(crate) fn new<F>(entry: F, name: String, stack_size: usize) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Self::new_with_stack(entry, name, StackAttr::with_size(stack_size))
+            .expect("failed to allocate task stack")
+    }
+
+    /// Create a new task with the given entry function and stack.
+    ///
+    /// Returns [`AxError::NoMemory`] if the stack can't be allocated.
+    pub(crate) fn new_with_stack<F>(entry: F, name: String, stack: StackAttr) -> AxResult<AxTaskRef>
     where
         F: FnOnce() + Send + 'static,
     {
         let mut t = Self::new_common(TaskId::new(), name);
         debug!("new task: {}", t.id_name());
-        let kstack = TaskStack::alloc(align_up_4k(stack_size));
+        let kstack = match stack {
+            StackAttr::Alloc { size, guard_size } => {
+                TaskStack::alloc(align_up_4k(size), guard_size, t.id)?
+            }
+            StackAttr::User { bottom, size } => TaskStack::from_user(bottom, size),
+        };
 
         #[cfg(feature = "tls")]
         let tls = VirtAddr::from(t.tls.tls_ptr() as usize);
@@ -276,7 +521,9 @@ impl TaskInner {
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        let task = Arc::new(AxTask::new(t));
+        crate::registry::register(&task);
+        Ok(task)
     }
 
     /// Creates an "init task" using the current CPU states, to use as the
@@ -293,7 +540,9 @@ impl TaskInner {
         if t.name == "idle" {
             t.is_idle = true;
         }
-        Arc::new(AxTask::new(t))
+        let task = Arc::new(AxTask::new(t));
+        crate::registry::register(&task);
+        task
     }
 
     /// Get task state
@@ -308,6 +557,20 @@ impl TaskInner {
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Changes the task state from `current` to `new`, returns whether it
+    /// succeeded.
+    #[inline]
+    pub(crate) fn transition_state(&self, current: TaskState, new: TaskState) -> bool {
+        self.state
+            .compare_exchange(
+                current as u8,
+                new as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -334,6 +597,39 @@ impl TaskInner {
         self.is_idle
     }
 
+    /// Marks the task as working for the kernel, e.g., reclaiming memory, so
+    /// that it's never killed by the OOM killer.
+    #[inline]
+    pub fn set_kernel(&self) {
+        self.is_kernel.store(true, Ordering::Release);
+    }
+
+    /// Whether the task works for the kernel, see [`set_kernel`](Self::set_kernel).
+    #[inline]
+    pub fn is_kernel(&self) -> bool {
+        self.is_kernel.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_cpumask(&self, mask: CpuMask) {
+        *self.cpumask.lock() = mask;
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
     #[inline]
     pub(crate) fn in_wait_queue(&self) -> bool {
         self.in_wait_queue.load(Ordering::Acquire)
@@ -344,6 +640,173 @@ impl TaskInner {
         self.in_wait_queue.store(in_wait_queue, Ordering::Release);
     }
 
+    #[inline]
+    pub(crate) fn requeued_to(&self) -> usize {
+        self.requeued_to.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_requeued_to(&self, wait_queue: usize) {
+        self.requeued_to.store(wait_queue, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_wait_channel(&self, wait_queue: usize) {
+        self.wait_channel.store(wait_queue, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_interruptible(&self, interruptible: bool) {
+        self.interruptible.store(interruptible, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(crate) fn is_interruptible(&self) -> bool {
+        self.interruptible.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn set_interrupt_pending(&self, pending: bool) {
+        self.interrupt_pending.store(pending, Ordering::SeqCst);
+    }
+
+    /// Whether the task has been interrupted in an interruptible wait.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.is_interruptible() && self.interrupt_pending.load(Ordering::SeqCst)
+    }
+
+    /// Whether the task is about to block in an interruptible wait, but has
+    /// been interrupted, so it should not block. The state must have been set
+    /// to [`TaskState::Blocked`], it is set back to running in that case.
+    pub(crate) fn cancel_interrupted_block(&self) -> bool {
+        self.is_interrupted() && self.transition_state(TaskState::Blocked, TaskState::Running)
+    }
+
+    /// Updates the statistics when the task is switched out at `now_ns`.
+    pub(crate) fn account_switch_out(&self, now_ns: u64) {
+        let c = &self.counters;
+        let ran = now_ns.saturating_sub(c.switched_in_at.load(Ordering::Relaxed));
+        c.cpu_time_ns.fetch_add(ran, Ordering::Relaxed);
+        if self.is_ready() {
+            c.involuntary_switches.fetch_add(1, Ordering::Relaxed);
+        } else {
+            c.voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Updates the statistics when the task is switched in at `now_ns`.
+    pub(crate) fn account_switch_in(&self, now_ns: u64) {
+        self.counters
+            .switched_in_at
+            .store(now_ns, Ordering::Relaxed);
+    }
+
+    pub(crate) fn account_wakeup(&self) {
+        self.counters.wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the owner of the heap memory allocated by the task.
+    pub(crate) fn mem_owner(&self) -> Option<&axalloc::MemOwnerRef> {
+        self.mem_owner.as_ref()
+    }
+
+    /// Returns the number of bytes allocated by the task and not freed yet.
+    pub(crate) fn mem_usage(&self) -> usize {
+        self.mem_owner().map_or(0, |owner| owner.usage().current)
+    }
+
+    #[inline]
+    pub(crate) fn set_killed(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the task has been [killed](crate::kill), so it should exit as
+    /// soon as possible.
+    #[inline]
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    /// Records that the task has acquired a sleeping lock, e.g., an
+    /// `axsync::Mutex`. A task holding any is neither forced to exit when
+    /// killed, nor blocked by the OOM killer, as others may wait for the lock.
+    #[inline]
+    pub fn lock_acquired(&self) {
+        self.locks_held.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the task has released a sleeping lock acquired before.
+    #[inline]
+    pub fn lock_released(&self) {
+        self.locks_held.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Whether the task holds any sleeping lock.
+    #[inline]
+    pub(crate) fn holds_locks(&self) -> bool {
+        self.locks_held.load(Ordering::Relaxed) > 0
+    }
+
+    #[inline]
+    pub(crate) fn enter_kernel(&self) {
+        self.kernel_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn leave_kernel(&self) {
+        self.kernel_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Whether the task is in a [`KernelSection`](crate::KernelSection).
+    #[inline]
+    pub(crate) fn in_kernel(&self) -> bool {
+        self.kernel_depth.load(Ordering::Relaxed) > 0
+    }
+
+    /// Gets the bottom and top addresses of the task's stack, or `None` if
+    /// it runs on the boot stack.
+    pub(crate) fn stack_range(&self) -> Option<(usize, usize)> {
+        self.kstack
+            .as_ref()
+            .map(|s| (s.bottom().as_usize(), s.top().as_usize()))
+    }
+
+    /// Whether the task has overflowed its stack, which is detected by the
+    /// canary at the bottom of the stack.
+    pub(crate) fn stack_overflowed(&self) -> bool {
+        self.kstack.as_ref().is_some_and(|s| s.overflowed())
+    }
+
+    /// Gets the instruction pointer and frame pointer saved in the task
+    /// context, only valid when the task is not running.
+    pub(crate) fn saved_frame(&self) -> (usize, usize) {
+        unsafe { (*self.ctx.get()).saved_frame() }
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_rt")]
+    pub(crate) fn base_policy(&self) -> SchedPolicy {
+        *self.base_policy.lock()
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_rt")]
+    pub(crate) fn set_base_policy(&self, policy: SchedPolicy) {
+        *self.base_policy.lock() = policy;
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_rt")]
+    pub(crate) fn pi_policy(&self) -> Option<SchedPolicy> {
+        *self.pi_policy.lock()
+    }
+
+    #[inline]
+    #[cfg(feature = "sched_rt")]
+    pub(crate) fn set_pi_policy(&self, policy: Option<SchedPolicy>) {
+        *self.pi_policy.lock() = policy;
+    }
+
     #[inline]
     #[cfg(feature = "irq")]
     pub(crate) fn in_timer_list(&self) -> bool {
@@ -387,14 +850,14 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let rq = crate::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
         }
     }
 
-    pub(crate) fn notify_exit(&self, exit_code: i32, rq: &mut AxRunQueue) {
+    pub(crate) fn notify_exit(&self, exit_code: i32, rq: &AxRunQueue) {
         self.exit_code.store(exit_code, Ordering::Release);
         self.wait_for_exit.notify_all_locked(false, rq);
     }
@@ -407,34 +870,29 @@ impl TaskInner {
 
 #[cfg(not(feature = "musl"))]
 impl TaskInner {
-    /// Allocate a key
+    /// Allocate a key, shared by all tasks.
+    ///
+    /// Returns `None` if there are already `ruxconfig::PTHREAD_KEY_MAX` keys.
     pub fn alloc_key(&self, destr_function: Option<DestrFunction>) -> Option<usize> {
-        unsafe { KEYS.lock() }.alloc(destr_function)
+        tsd::alloc_key(destr_function)
     }
-    /// Get the destructor function of a key
+    /// Delete a key, returns `None` if the key is not in use.
     pub fn free_key(&self, key: usize) -> Option<()> {
-        unsafe { KEYS.lock() }.free(key)
+        tsd::free_key(key)
     }
-    /// Get the destructor function of a key
+    /// Set the value of a key for this task, returns `None` if the key is not
+    /// in use.
     pub fn set_tsd(&self, key: usize, value: *mut core::ffi::c_void) -> Option<()> {
-        if key < self.tsd.lock().len() {
-            self.tsd.lock()[key] = value;
-            Some(())
-        } else {
-            None
-        }
+        tsd::set_tsd(&self.tsd, key, value)
     }
-    /// Get the destructor function of a key
+    /// Get the value of a key for this task, returns `None` if the key is not
+    /// in use.
     pub fn get_tsd(&self, key: usize) -> Option<*mut core::ffi::c_void> {
-        if key < self.tsd.lock().len() {
-            Some(self.tsd.lock()[key])
-        } else {
-            None
-        }
+        tsd::get_tsd(&self.tsd, key)
     }
-    /// Get the destructor function of a key
+    /// Call the destructors of the values of all keys, when the task exits.
     pub fn destroy_keys(&self) {
-        unsafe { KEYS.lock() }.destr_used_keys(&self.tsd)
+        tsd::destroy_tsd(&self.tsd)
     }
 }
 
@@ -451,32 +909,125 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        crate::registry::unregister(self.id);
     }
 }
 
+/// The pattern a new stack is filled with, to find out how much of it has
+/// been used.
+const STACK_FILL_PATTERN: u64 = 0x5a5a_5a5a_5a5a_5a5a;
+
+/// The value at the bottom of a stack, which is overwritten if the stack
+/// overflows.
+const STACK_CANARY: u64 = 0xdead_c0de_cafe_f00d;
+
+/// The stack of a task.
+///
+/// If the `paging` feature is enabled, it is mapped with an unmapped guard
+/// area below it, so that an overflow is reported by the page fault handler.
+/// Otherwise it is allocated from the heap, and an overflow can only be
+/// detected by the canary at the bottom when the task is switched out.
+///
+/// It may also be provided by the user, see [`StackAttr::User`].
 struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
+    /// Whether the memory is allocated by us and freed on drop.
+    owned: bool,
 }
 
 impl TaskStack {
-    pub fn alloc(size: usize) -> Self {
+    /// Allocates a stack of `size` bytes, returns [`AxError::NoMemory`] if
+    /// the memory runs out.
+    pub fn alloc(size: usize, guard_size: usize, owner: TaskId) -> AxResult<Self> {
         let layout = Layout::from_size_align(size, 8).unwrap();
         debug!("taskStack::layout = {:?}", layout);
-        Self {
-            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
+        #[cfg(feature = "paging")]
+        let ptr = ruxhal::paging::alloc_kernel_stack(size, guard_size, owner.as_u64())
+            .map_err(|_| AxError::NoMemory)?
+            .as_mut_ptr();
+        #[cfg(not(feature = "paging"))]
+        let ptr = {
+            let _ = (guard_size, owner);
+            unsafe { alloc::alloc::alloc(layout) }
+        };
+        let ptr = NonNull::new(ptr).ok_or(AxError::NoMemory)?;
+        axalloc::charge(axalloc::MemTag::TaskStack, size);
+        let stack = Self {
+            ptr,
             layout,
-        }
+            owned: true,
+        };
+        stack.init();
+        Ok(stack)
+    }
+
+    /// Uses the memory `[bottom, bottom + size)` as the stack, whose top is
+    /// aligned down to 16 bytes.
+    pub fn from_user(bottom: usize, size: usize) -> Self {
+        let start = (bottom + 7) & !7;
+        let end = (bottom + size) & !15;
+        assert!(end > start, "invalid user stack");
+        let stack = Self {
+            ptr: NonNull::new(start as *mut u8).unwrap(),
+            layout: Layout::from_size_align(end - start, 8).unwrap(),
+            owned: false,
+        };
+        stack.init();
+        stack
+    }
+
+    fn init(&self) {
+        let words = self.size() / core::mem::size_of::<u64>();
+        let stack =
+            unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr() as *mut u64, words) };
+        stack.fill(STACK_FILL_PATTERN);
+        stack[0] = STACK_CANARY;
+    }
+
+    /// Whether the canary at the bottom of the stack has been overwritten.
+    pub fn overflowed(&self) -> bool {
+        unsafe { (self.ptr.as_ptr() as *const u64).read_volatile() != STACK_CANARY }
     }
 
     pub const fn top(&self) -> VirtAddr {
         unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
     }
+
+    pub fn bottom(&self) -> VirtAddr {
+        VirtAddr::from(self.ptr.as_ptr() as usize)
+    }
+
+    pub const fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// Returns the maximum number of bytes of the stack ever used, by finding
+    /// the lowest word which no longer holds the fill pattern.
+    pub fn high_water_mark(&self) -> usize {
+        let base = self.ptr.as_ptr() as *const u64;
+        let words = self.size() / core::mem::size_of::<u64>();
+        // The stack may be being written by its task, read it volatilely.
+        // Skip the canary, which is the first word.
+        let untouched = (1..words)
+            .take_while(|&i| unsafe { base.add(i).read_volatile() } == STACK_FILL_PATTERN)
+            .count();
+        self.size() - (untouched + 1) * core::mem::size_of::<u64>()
+    }
 }
 
 impl Drop for TaskStack {
     fn drop(&mut self) {
-        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+        if !self.owned {
+            return;
+        }
+        axalloc::uncharge(axalloc::MemTag::TaskStack, self.size());
+        #[cfg(feature = "paging")]
+        ruxhal::paging::dealloc_kernel_stack(self.bottom());
+        #[cfg(not(feature = "paging"))]
+        unsafe {
+            alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout)
+        }
     }
 }
 
@@ -535,8 +1086,8 @@ impl Deref for CurrentTask {
 }
 
 extern "C" fn task_entry() -> ! {
-    // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    // finish the reschedule that switched to this task for the first time
+    crate::run_queue::finish_task_switch();
     #[cfg(feature = "irq")]
     ruxhal::arch::enable_irqs();
     let task = crate::current();
@@ -8,7 +8,7 @@
  */
 
 use core::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, Once};
+use std::sync::{Arc, Mutex, Once};
 
 use crate::{self as ruxtask, current, WaitQueue};
 
@@ -137,3 +137,156 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_task_registry() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ruxtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static READY: AtomicUsize = AtomicUsize::new(0);
+
+    let task = ruxtask::spawn_raw(
+        || {
+            READY.store(1, Ordering::Release);
+            WQ.wait();
+        },
+        "registry".into(),
+        0x4000,
+    );
+    let id = task.id().as_u64();
+    assert!(ruxtask::task_by_id(id).is_some_and(|t| Arc::ptr_eq(&t, &task)));
+    assert!(ruxtask::all_tasks().iter().any(|t| t.name() == "registry"));
+
+    while READY.load(Ordering::Acquire) == 0 {
+        ruxtask::yield_now();
+    }
+    let stats = task.stats();
+    assert!(task.is_blocked());
+    assert_eq!(task.wait_channel(), &WQ as *const _ as usize);
+    assert!(stats.voluntary_switches >= 1);
+    assert!(stats.stack_high_water > 0 && stats.stack_high_water <= stats.stack_size);
+
+    let mut dump = String::new();
+    ruxtask::dump_tasks_to(&mut dump).unwrap();
+    assert!(dump.contains("\"registry\""));
+
+    WQ.notify_one(false);
+    task.join();
+    assert_eq!(task.stats().wakeups, 1);
+}
+
+#[test]
+fn test_workqueue() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ruxtask::init_scheduler);
+
+    const NUM_WORKS: usize = 16;
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let wq = ruxtask::WorkQueue::new("test_wq", 2);
+    let works: Vec<_> = (0..NUM_WORKS)
+        .map(|_| {
+            ruxtask::Work::new(|| {
+                COUNTER.fetch_add(1, Ordering::Relaxed);
+                ruxtask::yield_now();
+            })
+        })
+        .collect();
+    for work in works.iter() {
+        assert!(wq.queue_work(work));
+    }
+    // Pending works are not queued twice.
+    let queued_again = works.iter().filter(|w| wq.queue_work(w)).count();
+    wq.flush();
+    assert!(works.iter().all(|w| !w.is_pending()));
+    assert_eq!(COUNTER.load(Ordering::Relaxed), NUM_WORKS + queued_again);
+
+    wq.destroy();
+    assert!(ruxtask::all_tasks()
+        .iter()
+        .filter(|t| t.name().starts_with("test_wq/"))
+        .all(|t| t.state() == ruxtask::TaskState::Exited));
+}
+
+#[test]
+fn test_pthread_keys() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ruxtask::init_scheduler);
+
+    static DESTR_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static KEY: AtomicUsize = AtomicUsize::new(0);
+
+    // Sets the value again, so it is called in every round.
+    unsafe extern "C" fn destr(value: *mut core::ffi::c_void) {
+        DESTR_CALLS.fetch_add(1, Ordering::SeqCst);
+        current().set_tsd(KEY.load(Ordering::SeqCst), value);
+    }
+
+    let curr = current();
+    // More keys than a single chunk of the key table.
+    let keys: Vec<_> = (0..200).map(|_| curr.alloc_key(None).unwrap()).collect();
+    for (i, &key) in keys.iter().enumerate() {
+        curr.set_tsd(key, (i + 1) as *mut _).unwrap();
+    }
+    for (i, &key) in keys.iter().enumerate() {
+        assert_eq!(curr.get_tsd(key), Some((i + 1) as *mut _));
+    }
+
+    // Values are not visible through a new key reusing the slot.
+    let key = keys[100];
+    curr.free_key(key).unwrap();
+    assert_eq!(curr.get_tsd(key), None);
+    assert!(curr.set_tsd(key, 1 as *mut _).is_none());
+    let new_key = curr.alloc_key(Some(destr)).unwrap();
+    assert_eq!(new_key, key);
+    assert_eq!(curr.get_tsd(new_key), Some(core::ptr::null_mut()));
+    KEY.store(new_key, Ordering::SeqCst);
+
+    ruxtask::spawn(move || {
+        current().set_tsd(new_key, 1 as *mut _).unwrap();
+    })
+    .join();
+    assert_eq!(
+        DESTR_CALLS.load(Ordering::SeqCst),
+        ruxtask::tsd::PTHREAD_DESTRUCTOR_ITERATIONS
+    );
+
+    for key in keys {
+        curr.free_key(key);
+    }
+}
+
+#[test]
+fn test_detach_and_interrupt() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(ruxtask::init_scheduler);
+
+    // A detached task is dropped when it exits.
+    let task = ruxtask::new_task_with_stack(
+        || {},
+        "detached".into(),
+        ruxtask::StackAttr::with_size(0x4000),
+    )
+    .unwrap();
+    task.detach();
+    let weak = Arc::downgrade(&task);
+    ruxtask::put_task(task);
+    while weak.strong_count() > 0 {
+        ruxtask::yield_now();
+    }
+
+    // An interrupted wait returns early.
+    static WQ: WaitQueue = WaitQueue::new();
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+    let task = ruxtask::spawn(|| {
+        ruxtask::interruptible(|| WQ.wait());
+        WOKEN.fetch_add(1, Ordering::SeqCst);
+    });
+    while !task.is_blocked() {
+        ruxtask::yield_now();
+    }
+    ruxtask::interrupt(&task);
+    task.join();
+    assert_eq!(WOKEN.load(Ordering::SeqCst), 1);
+}
@@ -7,43 +7,128 @@
  *   See the Mulan PSL v2 for more details.
  */
 
+//! Timed events and the programming of the one-shot timer.
+//!
+//! There is no periodic timer interrupt. The next interrupt of each CPU is
+//! programmed to the earliest of the next timed event, and the next
+//! scheduler tick if the CPU is busy, so an idle CPU is not woken up until
+//! there is something to do.
+
 use alloc::sync::Arc;
 use lazy_init::LazyInit;
-use ruxhal::time::current_time;
+use ruxhal::time::{current_time, current_time_nanos, set_oneshot_timer, NANOS_PER_SEC};
 use spinlock::SpinNoIrq;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use crate::{AxTaskRef, RUN_QUEUE};
+use crate::workqueue::{Work, WorkQueue};
+use crate::{current_run_queue, AxTaskRef};
+
+/// The interval between scheduler ticks, while the CPU is busy.
+const TICK_INTERVAL_NANOS: u64 = NANOS_PER_SEC / ruxconfig::TICKS_PER_SEC as u64;
+
+/// An idle CPU is woken up at least once per this interval, as the
+/// hardware timers can not be programmed arbitrarily far ahead (e.g., the
+/// down counters are 32 bits on x86_64 and aarch64).
+const MAX_IDLE_NANOS: u64 = NANOS_PER_SEC;
+
+/// The timed events of this CPU, which are checked by its timer interrupts.
+///
+/// An event is added to the list of the CPU setting it, but can be canceled
+/// from any CPU.
+#[percpu::def_percpu]
+static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TimerEventKind>>> = LazyInit::new();
 
-// TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+/// The time of the next scheduler tick of this CPU.
+#[percpu::def_percpu]
+static NEXT_TICK: u64 = 0;
 
-struct TaskWakeupEvent(AxTaskRef);
+/// The deadline that the timer of this CPU is programmed to.
+#[percpu::def_percpu]
+static TIMER_DEADLINE: u64 = 0;
 
-impl TimerEvent for TaskWakeupEvent {
+enum TimerEventKind {
+    /// Wakes up the blocked task.
+    Wakeup(AxTaskRef),
+    /// Queues the delayed work.
+    Work(Arc<WorkQueue>, Arc<Work>),
+    /// Only raises a timer interrupt, for timers outside this crate.
+    Interrupt,
+}
+
+impl TimerEvent for TimerEventKind {
     fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+        match self {
+            Self::Wakeup(task) => {
+                let rq = current_run_queue();
+                task.set_in_timer_list(false);
+                rq.unblock_task(task, true);
+            }
+            Self::Work(wq, work) => wq.delayed_work_timeout(work),
+            Self::Interrupt => {}
+        }
+    }
+}
+
+/// Gets the timer list of the current CPU, preemption must be disabled.
+fn this_timer_list() -> &'static SpinNoIrq<TimerList<TimerEventKind>> {
+    // Safety: the timer list is initialized before the CPU runs any task, and
+    // is never dropped.
+    unsafe { TIMER_LIST.current_ref_raw() }
+}
+
+/// Cancels the events matching `cond` on all CPUs.
+fn cancel_events<F>(cond: F)
+where
+    F: Fn(&TimerEventKind) -> bool,
+{
+    for cpu_id in 0..ruxconfig::SMP {
+        // Safety: the timer lists are never dropped, and `LazyInit` tells
+        // whether the CPU has initialized its own.
+        if let Some(timers) = unsafe { TIMER_LIST.remote_ref_raw(cpu_id) }.try_get() {
+            timers.lock().cancel(&cond);
+        }
+    }
+}
+
+fn add_event(deadline: TimeValue, event: TimerEventKind) {
+    // Interrupt earlier if the timer is programmed after the new event.
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    this_timer_list().lock().set(deadline, event);
+    let deadline_ns = deadline.as_nanos() as u64;
+    if deadline_ns < unsafe { TIMER_DEADLINE.read_current_raw() } {
+        program_timer(deadline_ns);
     }
 }
 
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    add_event(deadline, TimerEventKind::Wakeup(task));
 }
 
 pub fn cancel_alarm(task: &AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    cancel_events(|t| matches!(t, TimerEventKind::Wakeup(t) if Arc::ptr_eq(t, task)));
+}
+
+pub fn set_work_timer(deadline: TimeValue, wq: Arc<WorkQueue>, work: Arc<Work>) {
+    add_event(deadline, TimerEventKind::Work(wq, work));
+}
+
+pub fn cancel_work_timer(work: &Arc<Work>) {
+    cancel_events(|t| matches!(t, TimerEventKind::Work(_, w) if Arc::ptr_eq(w, work)));
+}
+
+/// Makes sure that a timer interrupt occurs at `deadline`, for timers
+/// managed outside the task manager (e.g., `setitimer`).
+pub fn set_timer_deadline(deadline: TimeValue) {
+    add_event(deadline, TimerEventKind::Interrupt);
 }
 
+/// Runs the expired events of this CPU. IRQs must be disabled.
 pub fn check_events() {
     loop {
         let now = current_time();
-        let event = TIMER_LIST.lock().expire_one(now);
+        let event = this_timer_list().lock().expire_one(now);
         if let Some((_deadline, event)) = event {
             event.callback(now);
         } else {
@@ -52,6 +137,44 @@ pub fn check_events() {
     }
 }
 
+/// Returns the number of scheduler ticks of this CPU elapsed till now, and
+/// advances the next tick after now. IRQs must be disabled.
+pub(crate) fn elapsed_ticks() -> u64 {
+    let now_ns = current_time_nanos();
+    let next_tick = unsafe { NEXT_TICK.read_current_raw() };
+    if next_tick == 0 {
+        unsafe { NEXT_TICK.write_current_raw(now_ns + TICK_INTERVAL_NANOS) };
+        return 1;
+    }
+    if now_ns < next_tick {
+        return 0;
+    }
+    let ticks = (now_ns - next_tick) / TICK_INTERVAL_NANOS + 1;
+    unsafe { NEXT_TICK.write_current_raw(next_tick + ticks * TICK_INTERVAL_NANOS) };
+    ticks
+}
+
+/// Programs the timer of this CPU to the next timed event, or the next
+/// scheduler tick if the CPU is not `idle`. IRQs must be disabled.
+pub(crate) fn program_next_timer(idle: bool) {
+    let limit = if idle {
+        current_time_nanos() + MAX_IDLE_NANOS
+    } else {
+        unsafe { NEXT_TICK.read_current_raw() }
+    };
+    let deadline = this_timer_list()
+        .lock()
+        .next_deadline()
+        .map_or(limit, |d| limit.min(d.as_nanos() as u64));
+    program_timer(deadline);
+}
+
+fn program_timer(deadline_ns: u64) {
+    unsafe { TIMER_DEADLINE.write_current_raw(deadline_ns) };
+    set_oneshot_timer(deadline_ns);
+}
+
+/// Initializes the timer list of the current CPU.
 pub fn init() {
-    TIMER_LIST.init_by(SpinNoIrq::new(TimerList::new()));
+    TIMER_LIST.with_current(|timers| timers.init_by(SpinNoIrq::new(TimerList::new())));
 }
@@ -7,116 +7,184 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-use core::{
-    ffi::c_void,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicBool, AtomicPtr},
-};
-use lazy_init::LazyInit;
+//! Thread-specific data (`pthread_key_*`).
+//!
+//! Keys live in a two-level table which grows by chunks when more keys are
+//! created, up to `ruxconfig::PTHREAD_KEY_MAX` keys. Each key has a sequence
+//! number, which is odd while the key is in use and is increased when the
+//! key is created or deleted. A task stores the sequence number along with
+//! each value, so that values set before the key is deleted are not visible
+//! through a new key reusing the slot.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::ffi::c_void;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
 use spinlock::SpinNoIrq;
 
 /// Destroy a specific key when a thread exits.
 pub type DestrFunction = unsafe extern "C" fn(*mut c_void);
-/// Thread-specific data set.
-pub(crate) type TSD = SpinNoIrq<[*mut c_void; ruxconfig::PTHREAD_KEY_MAX]>;
+
+/// Maximum number of rounds of calling the destructors of thread-specific
+/// data when a thread exits, as values may be set again by the destructors.
+pub const PTHREAD_DESTRUCTOR_ITERATIONS: usize = 4;
+
+const KEYS_PER_CHUNK: usize = 64;
+const MAX_CHUNKS: usize = ruxconfig::PTHREAD_KEY_MAX.div_ceil(KEYS_PER_CHUNK);
 
 /// A key for a process.
-#[derive(Default)]
-#[allow(dead_code)]
-pub(crate) struct PthreadKey {
-    in_use: AtomicBool,
-    // AtomicPtr<c_void> means *mut c_void. It should be convert to DestrFunction when use.
-    destr_function: AtomicPtr<c_void>,
+struct PthreadKey {
+    seq: AtomicU64,
+    // `DestrFunction` as `usize`, 0 for no destructor.
+    destr_function: AtomicUsize,
 }
 
 impl PthreadKey {
-    /// Create a new key.
-    pub fn new() -> Self {
+    const fn new() -> Self {
         Self {
-            in_use: AtomicBool::new(false),
-            destr_function: AtomicPtr::new(core::ptr::null_mut()),
+            seq: AtomicU64::new(0),
+            destr_function: AtomicUsize::new(0),
         }
     }
 }
 
-/// A set of keys for a process.
-pub(crate) struct PthreadKeys {
-    keys: [PthreadKey; ruxconfig::PTHREAD_KEY_MAX],
+type KeyChunk = [PthreadKey; KEYS_PER_CHUNK];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CHUNK: AtomicPtr<KeyChunk> = AtomicPtr::new(null_mut());
+
+/// Chunks of keys, allocated on demand and never freed, so that they can be
+/// read without locking.
+static KEY_CHUNKS: [AtomicPtr<KeyChunk>; MAX_CHUNKS] = [NO_CHUNK; MAX_CHUNKS];
+
+/// Serializes creating and deleting keys.
+static KEYS_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+fn get_key(key: usize) -> Option<&'static PthreadKey> {
+    let chunk = KEY_CHUNKS
+        .get(key / KEYS_PER_CHUNK)?
+        .load(Ordering::Acquire);
+    // Safety: chunks are never freed.
+    unsafe { chunk.as_ref() }.map(|chunk| &chunk[key % KEYS_PER_CHUNK])
 }
 
-impl PthreadKeys {
-    /// Create a new key set.
-    pub fn new() -> Self {
-        let mut arr: [MaybeUninit<PthreadKey>; ruxconfig::PTHREAD_KEY_MAX] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-        for a in arr.iter_mut() {
-            *a = MaybeUninit::new(PthreadKey::new());
-        }
-        Self {
-            keys: unsafe {
-                core::mem::transmute::<_, [PthreadKey; ruxconfig::PTHREAD_KEY_MAX]>(arr)
-            },
-        }
-    }
+/// Returns the sequence number of `key` if it is in use.
+fn key_seq(key: usize) -> Option<u64> {
+    let seq = get_key(key)?.seq.load(Ordering::Acquire);
+    (seq % 2 == 1).then_some(seq)
+}
 
-    /// Allocate a key
-    pub fn alloc(&self, destr_function: Option<DestrFunction>) -> Option<usize> {
-        for (i, key) in self.keys.iter().enumerate() {
-            if !key.in_use.load(core::sync::atomic::Ordering::Relaxed) {
-                key.in_use
-                    .store(true, core::sync::atomic::Ordering::Relaxed);
-                if let Some(destr_function) = destr_function {
-                    key.destr_function.store(
-                        destr_function as *mut c_void,
-                        core::sync::atomic::Ordering::Relaxed,
-                    );
-                } else {
-                    key.destr_function
-                        .store(core::ptr::null_mut(), core::sync::atomic::Ordering::Relaxed);
-                }
-                return Some(i);
+/// Allocates a key, returns `None` if there are already
+/// `ruxconfig::PTHREAD_KEY_MAX` keys.
+pub(crate) fn alloc_key(destr_function: Option<DestrFunction>) -> Option<usize> {
+    let _lock = KEYS_LOCK.lock();
+    for (i, chunk_ptr) in KEY_CHUNKS.iter().enumerate() {
+        let mut chunk = chunk_ptr.load(Ordering::Acquire);
+        if chunk.is_null() {
+            const UNUSED_KEY: PthreadKey = PthreadKey::new();
+            chunk = Box::into_raw(Box::new([UNUSED_KEY; KEYS_PER_CHUNK]));
+            chunk_ptr.store(chunk, Ordering::Release);
+        }
+        // Safety: chunks are never freed.
+        let chunk = unsafe { &*chunk };
+        for (j, key) in chunk.iter().enumerate() {
+            let id = i * KEYS_PER_CHUNK + j;
+            if id >= ruxconfig::PTHREAD_KEY_MAX {
+                return None;
+            }
+            let seq = key.seq.load(Ordering::Relaxed);
+            if seq % 2 == 0 {
+                key.destr_function
+                    .store(destr_function.map_or(0, |f| f as usize), Ordering::Relaxed);
+                key.seq.store(seq.wrapping_add(1), Ordering::Release);
+                return Some(id);
             }
         }
-        None
     }
+    None
+}
 
-    /// Free a key
-    pub fn free(&self, key: usize) -> Option<()> {
-        if key < self.keys.len() {
-            self.keys[key]
-                .in_use
-                .store(false, core::sync::atomic::Ordering::Relaxed);
-            Some(())
-        } else {
-            None
-        }
-    }
+/// Deletes a key. The destructor is not called for the existing values.
+pub(crate) fn free_key(key: usize) -> Option<()> {
+    let _lock = KEYS_LOCK.lock();
+    let seq = key_seq(key)?;
+    get_key(key)?
+        .seq
+        .store(seq.wrapping_add(1), Ordering::Release);
+    Some(())
+}
 
-    /// Get all keys used
-    pub fn destr_used_keys(&self, tsd: &TSD) {
-        for (i, key) in self.keys.iter().enumerate() {
-            if key.in_use.load(core::sync::atomic::Ordering::Relaxed) {
-                let destr_function = key
-                    .destr_function
-                    .load(core::sync::atomic::Ordering::Relaxed);
-                if !destr_function.is_null() {
-                    unsafe {
-                        let destr_function =
-                            core::mem::transmute::<*mut c_void, DestrFunction>(destr_function);
-                        destr_function(tsd.lock()[i]);
-                    }
-                }
-            }
-        }
+/// A value of thread-specific data, valid if `seq` is the sequence number of
+/// the key in use.
+#[derive(Clone, Copy)]
+pub(crate) struct TsdEntry {
+    seq: u64,
+    value: *mut c_void,
+}
+
+/// Thread-specific data set, indexed by keys. It grows on demand.
+pub(crate) type TSD = SpinNoIrq<Vec<TsdEntry>>;
+
+pub(crate) fn set_tsd(tsd: &TSD, key: usize, value: *mut c_void) -> Option<()> {
+    let seq = key_seq(key)?;
+    let mut tsd = tsd.lock();
+    if key >= tsd.len() {
+        tsd.resize(
+            key + 1,
+            TsdEntry {
+                seq: 0,
+                value: null_mut(),
+            },
+        );
     }
+    tsd[key] = TsdEntry { seq, value };
+    Some(())
 }
 
-/// Instance of a thread-shared key set.
-pub(crate) static mut KEYS: LazyInit<SpinNoIrq<PthreadKeys>> = LazyInit::new();
+pub(crate) fn get_tsd(tsd: &TSD, key: usize) -> Option<*mut c_void> {
+    let seq = key_seq(key)?;
+    let value = match tsd.lock().get(key) {
+        Some(entry) if entry.seq == seq => entry.value,
+        _ => null_mut(),
+    };
+    Some(value)
+}
 
-/// Initialize the thread-shared key set.
-pub(crate) fn init() {
-    unsafe {
-        KEYS.init_by(SpinNoIrq::new(PthreadKeys::new()));
+/// Calls the destructors of non-null values in `tsd`, as required by POSIX
+/// when a thread exits.
+///
+/// The value is set to null before its destructor is called. Destructors may
+/// set new values, so it is repeated until there are no non-null values, or
+/// for at most [`PTHREAD_DESTRUCTOR_ITERATIONS`] rounds.
+pub(crate) fn destroy_tsd(tsd: &TSD) {
+    for _ in 0..PTHREAD_DESTRUCTOR_ITERATIONS {
+        let mut called = false;
+        let len = tsd.lock().len();
+        for key in 0..len {
+            let Some(k) = get_key(key) else {
+                continue;
+            };
+            let (value, destr_function) = {
+                let mut tsd = tsd.lock();
+                let entry = &mut tsd[key];
+                if entry.value.is_null() || Some(entry.seq) != key_seq(key) {
+                    continue;
+                }
+                let value = core::mem::replace(&mut entry.value, null_mut());
+                (value, k.destr_function.load(Ordering::Relaxed))
+            };
+            // Called without the lock, as it may set values.
+            if destr_function != 0 {
+                unsafe {
+                    let destr_function =
+                        core::mem::transmute::<usize, DestrFunction>(destr_function);
+                    destr_function(value);
+                }
+                called = true;
+            }
+        }
+        if !called {
+            break;
+        }
     }
 }
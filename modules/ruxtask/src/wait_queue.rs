@@ -11,7 +11,7 @@ use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use spinlock::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::{current_run_queue, AxRunQueue, AxTaskRef, CurrentTask};
 
 type ItemType<Meta> = (AxTaskRef, Meta);
 type QueueType<Meta> = VecDeque<ItemType<Meta>>;
@@ -39,7 +39,7 @@ type QueueType<Meta> = VecDeque<ItemType<Meta>>;
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueueWithMetadata<Meta> {
-    queue: SpinRaw<QueueType<Meta>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinRaw<QueueType<Meta>>, // we already disabled IRQs when get the current run queue
 }
 
 /// A wait queue with no metadata.
@@ -61,19 +61,33 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     }
 
     fn cancel_events(&self, curr: CurrentTask) {
-        Self::cancel_events_locked(&mut self.queue.lock(), curr);
-    }
-
-    fn cancel_events_locked(queue: &mut QueueType<Meta>, curr: CurrentTask) {
         // A task can be wake up only one events (timer or `notify()`), remove
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
+            // The current run queue is not held here, so disable IRQs.
             let _guard = kernel_guard::IrqSave::new();
-            queue.retain(|(t, _)| !curr.ptr_eq(t));
-            curr.set_in_wait_queue(false);
+            // The task may have been moved to another queue, which is only
+            // changed with the queue it is in locked.
+            loop {
+                let requeued_to = curr.requeued_to();
+                let queue = if requeued_to == 0 {
+                    self
+                } else {
+                    // Safety: tasks are only requeued between queues of the
+                    // same type, which outlive the tasks in them.
+                    unsafe { &*(requeued_to as *const Self) }
+                };
+                let mut wq = queue.queue.lock();
+                if curr.requeued_to() == requeued_to {
+                    wq.retain(|(t, _)| !curr.ptr_eq(t));
+                    curr.set_in_wait_queue(false);
+                    break;
+                }
+            }
         }
+        curr.set_requeued_to(0);
+        curr.set_wait_channel(0);
         #[cfg(feature = "irq")]
         if curr.in_timer_list() {
             // timeout was set but not triggered (wake up by `WaitQueue::notify()`)
@@ -84,8 +98,9 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
     pub fn wait_meta(&self, meta: Meta) {
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
+            task.set_wait_channel(self as *const Self as usize);
             self.queue.lock().push_back((task, meta))
         });
         self.cancel_events(crate::current());
@@ -97,12 +112,13 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     where
         F: FnMut() -> Result<(), R>,
     {
-        let mut rq = RUN_QUEUE.lock();
+        let rq = current_run_queue();
         let mut wq = self.queue.lock();
         condition()?;
 
         rq.block_current(|task| {
             task.set_in_wait_queue(true);
+            task.set_wait_channel(self as *const Self as usize);
             wq.push_back((task, meta));
             drop(wq);
         });
@@ -144,12 +160,12 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
             curr.id_name(),
             deadline
         );
-        #[cfg(feature = "irq")]
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
-            self.queue.lock().push_back((task, meta))
+            task.set_wait_channel(self as *const Self as usize);
+            self.queue.lock().push_back((task.clone(), meta));
+            #[cfg(feature = "irq")]
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
         self.cancel_events(curr);
@@ -168,7 +184,7 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
         F: FnMut() -> Result<(), R>,
     {
         let curr = crate::current();
-        let mut rq = RUN_QUEUE.lock();
+        let rq = current_run_queue();
         let mut wq = self.queue.lock();
         condition()?;
 
@@ -177,13 +193,13 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
             curr.id_name(),
             deadline
         );
-        #[cfg(feature = "irq")]
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
         rq.block_current(|task| {
             task.set_in_wait_queue(true);
-            wq.push_back((task, meta));
+            task.set_wait_channel(self as *const Self as usize);
+            wq.push_back((task.clone(), meta));
             drop(wq);
+            #[cfg(feature = "irq")]
+            crate::timers::set_alarm_wakeup(deadline, task);
         });
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
         self.cancel_events(curr);
@@ -195,9 +211,9 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let rq = current_run_queue();
         if !self.queue.lock().is_empty() {
-            self.notify_one_locked(resched, &mut rq)
+            self.notify_one_locked(resched, &rq)
         } else {
             false
         }
@@ -209,14 +225,14 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let rq = current_run_queue();
             if let Some((task, _)) = self.queue.lock().pop_front() {
                 task.set_in_wait_queue(false);
                 rq.unblock_task(task, resched);
             } else {
                 break;
             }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
+            drop(rq); // we must enable IRQs after unlocking `self.queue`.
         }
     }
 
@@ -225,7 +241,7 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let rq = current_run_queue();
         let mut wq = self.queue.lock();
         if let Some(index) = wq.iter().position(|(t, _)| Arc::ptr_eq(t, task)) {
             task.set_in_wait_queue(false);
@@ -246,7 +262,7 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
     where
         F: FnMut(&AxTaskRef, &Meta) -> bool,
     {
-        let mut rq = RUN_QUEUE.lock();
+        let rq = current_run_queue();
         let mut wq = self.queue.lock();
         let len_before = wq.len();
 
@@ -263,7 +279,7 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
         len_before - wq.len()
     }
 
-    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &mut AxRunQueue) -> bool {
+    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &AxRunQueue) -> bool {
         if let Some((task, _)) = self.queue.lock().pop_front() {
             task.set_in_wait_queue(false);
             rq.unblock_task(task, resched);
@@ -273,13 +289,54 @@ impl<Meta> WaitQueueWithMetadata<Meta> {
         }
     }
 
-    pub(crate) fn notify_all_locked(&self, resched: bool, rq: &mut AxRunQueue) {
+    pub(crate) fn notify_all_locked(&self, resched: bool, rq: &AxRunQueue) {
         while let Some((task, _)) = self.queue.lock().pop_front() {
             task.set_in_wait_queue(false);
             rq.unblock_task(task, resched);
         }
     }
 
+    /// Moves all tasks that `filter` returns true to the `target` wait queue,
+    /// without waking them up. `filter` may also update the metadata of the
+    /// tasks to be moved.
+    ///
+    /// Returns number of tasks moved.
+    pub fn requeue_tasks_if<F>(&self, target: &Self, mut filter: F) -> usize
+    where
+        F: FnMut(&AxTaskRef, &mut Meta) -> bool,
+    {
+        let _guard = kernel_guard::IrqSave::new();
+        if core::ptr::eq(self, target) {
+            let mut wq = self.queue.lock();
+            return wq.iter_mut().filter(|(t, m)| filter(t, m)).count();
+        }
+
+        // Lock the two queues in the order of their addresses, to avoid
+        // deadlocks with requeueing in the opposite direction.
+        let (mut src, mut dst) = if (self as *const Self) < (target as *const Self) {
+            let src = self.queue.lock();
+            (src, target.queue.lock())
+        } else {
+            let dst = target.queue.lock();
+            (self.queue.lock(), dst)
+        };
+        let mut count = 0;
+        let mut i = 0;
+        while i < src.len() {
+            let (task, meta) = &mut src[i];
+            if filter(task, meta) {
+                let item = src.remove(i).unwrap();
+                item.0.set_requeued_to(target as *const Self as usize);
+                item.0.set_wait_channel(target as *const Self as usize);
+                dst.push_back(item);
+                count += 1;
+            } else {
+                i += 1;
+            }
+        }
+        count
+    }
+
     /// Queue a given task with its metadata given.
     ///
     /// It is marked as unsafe as it does nothing other than queueing the task,
@@ -340,29 +397,39 @@ impl<Meta: Clone> WaitQueueWithMetadata<Meta> {
     /// `condition` becomes true.
     ///
     /// Note that even other tasks notify this task, it will not wake up until
-    /// the condition becomes true.
+    /// the condition becomes true, or it is [interrupted](crate::interrupt) in
+    /// [`interruptible`](crate::interruptible).
     pub fn wait_until_meta<F>(&self, mut condition: F, meta: Meta)
     where
         F: FnMut() -> bool,
     {
+        let curr = crate::current();
         loop {
-            let mut rq = RUN_QUEUE.lock();
-            if condition() {
+            let rq = current_run_queue();
+            // Check the condition with the wait queue locked, so that the
+            // notifier can not miss us after it changes the condition.
+            let mut wq = self.queue.lock();
+            if condition() || curr.is_interrupted() {
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back((task, meta.clone()));
+                task.set_wait_channel(self as *const Self as usize);
+                // may have been requeued in the previous round
+                task.set_requeued_to(0);
+                wq.push_back((task, meta.clone()));
+                drop(wq);
             });
         }
-        self.cancel_events(crate::current());
+        self.cancel_events(curr);
     }
 
     /// Blocks the current task and put it into the wait queue, until the given
     /// `condition` becomes true, or the given duration has elapsed.
     ///
     /// Note that even other tasks notify this task, it will not wake up until
-    /// the above conditions are met.
+    /// the above conditions are met, or it is [interrupted](crate::interrupt)
+    /// in [`interruptible`](crate::interruptible).
     #[cfg(feature = "irq")]
     pub fn wait_timeout_until_meta<F>(
         &self,
@@ -380,18 +447,27 @@ impl<Meta: Clone> WaitQueueWithMetadata<Meta> {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
 
         let mut timeout = true;
         while ruxhal::time::current_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
-            if condition() {
+            let rq = current_run_queue();
+            let mut wq = self.queue.lock();
+            if condition() || curr.is_interrupted() {
                 timeout = false;
                 break;
             }
             rq.block_current(|task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back((task, meta.clone()));
+                task.set_wait_channel(self as *const Self as usize);
+                // may have been requeued in the previous round
+                task.set_requeued_to(0);
+                wq.push_back((task.clone(), meta.clone()));
+                drop(wq);
+                // Set the alarm after the task is blocked, or the wakeup may
+                // be lost if the timer is expired on another CPU at once.
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task);
+                }
             });
         }
         self.cancel_events(curr);
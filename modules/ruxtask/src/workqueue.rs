@@ -0,0 +1,397 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+//! Deferring work out of interrupt context.
+//!
+//! - [`WorkQueue`]s run [`Work`] items in kernel worker tasks, where the work
+//!   can sleep. They can be queued from any context, including IRQ handlers.
+//! - [`Tasklet`]s run on the CPU they are scheduled on, right after the IRQ
+//!   handlers return, and must not sleep.
+
+use alloc::{boxed::Box, collections::VecDeque, format, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lazy_init::LazyInit;
+use spinlock::SpinNoIrq;
+
+use crate::{AxTaskRef, CpuMask, TaskInner, WaitQueue};
+
+/// A work item, which can be queued to a [`WorkQueue`] again and again.
+pub struct Work {
+    func: Box<dyn Fn() + Send + Sync>,
+    /// Whether it is in a queue (or waiting for its delay), and not running
+    /// yet.
+    pending: AtomicBool,
+    /// Whether it is waiting for its delay, see [`WorkQueue::queue_delayed_work`].
+    delayed: AtomicBool,
+}
+
+impl Work {
+    /// Creates a new work item that calls `f` each time it runs.
+    pub fn new<F>(f: F) -> Arc<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            func: Box::new(f),
+            pending: AtomicBool::new(false),
+            delayed: AtomicBool::new(false),
+        })
+    }
+
+    /// Whether the work is queued and has not started running yet.
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for Work {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Work")
+            .field("pending", &self.is_pending())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A queue of [`Work`] items, served by a pool of named worker tasks.
+pub struct WorkQueue {
+    name: String,
+    works: SpinNoIrq<VecDeque<Arc<Work>>>,
+    /// Idle workers wait here for new works.
+    worker_wq: WaitQueue,
+    /// Tasks flushing the queue wait here for the works to finish.
+    flush_wq: WaitQueue,
+    /// Number of works that are running.
+    running: AtomicUsize,
+    stopping: AtomicBool,
+    workers: SpinNoIrq<Vec<AxTaskRef>>,
+}
+
+impl WorkQueue {
+    /// Creates a new work queue served by `num_workers` worker tasks, which
+    /// are named `"{name}/{i}"`.
+    pub fn new(name: &str, num_workers: usize) -> Arc<Self> {
+        let wq = Self::new_empty(name);
+        for i in 0..num_workers.max(1) {
+            wq.add_worker(i, None);
+        }
+        wq
+    }
+
+    fn new_empty(name: &str) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            works: SpinNoIrq::new(VecDeque::new()),
+            worker_wq: WaitQueue::new(),
+            flush_wq: WaitQueue::new(),
+            running: AtomicUsize::new(0),
+            stopping: AtomicBool::new(false),
+            workers: SpinNoIrq::new(Vec::new()),
+        })
+    }
+
+    /// Spawns the `i`-th worker, which only runs on `cpu_id` if it is given.
+    fn add_worker(self: &Arc<Self>, i: usize, cpu_id: Option<usize>) {
+        let wq = self.clone();
+        let worker = TaskInner::new(
+            move || wq.worker_entry(),
+            format!("{}/{}", self.name, i),
+            ruxconfig::TASK_STACK_SIZE,
+        );
+        worker.set_kernel();
+        if let Some(cpu_id) = cpu_id {
+            worker.set_cpumask(CpuMask::one(cpu_id));
+        }
+        self.workers.lock().push(worker.clone());
+        crate::put_task(worker);
+    }
+
+    /// The name of the work queue.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Queues `work` to run on one of the workers.
+    ///
+    /// Returns `false` if the work is already pending, in which case it runs
+    /// only once.
+    pub fn queue_work(&self, work: &Arc<Work>) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.push(work.clone());
+        true
+    }
+
+    /// Queues `work` to run on one of the workers after `delay`.
+    ///
+    /// Returns `false` if the work is already pending.
+    #[cfg(feature = "irq")]
+    pub fn queue_delayed_work(
+        self: &Arc<Self>,
+        work: &Arc<Work>,
+        delay: core::time::Duration,
+    ) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        work.delayed.store(true, Ordering::Release);
+        let deadline = ruxhal::time::current_time() + delay;
+        crate::timers::set_work_timer(deadline, self.clone(), work.clone());
+        true
+    }
+
+    /// Called by the timer when the delay of `work` has passed.
+    #[cfg(feature = "irq")]
+    pub(crate) fn delayed_work_timeout(&self, work: Arc<Work>) {
+        // It may be cancelled at the same time.
+        if work.delayed.swap(false, Ordering::AcqRel) {
+            self.push(work);
+        }
+    }
+
+    /// Blocks the current task until all works queued before have finished.
+    ///
+    /// Delayed works that are still waiting for their delays are not waited
+    /// for.
+    pub fn flush(&self) {
+        self.flush_wq.wait_until(|| {
+            let works = self.works.lock();
+            works.is_empty() && self.running.load(Ordering::Acquire) == 0
+        });
+    }
+
+    /// Finishes all queued works, then stops the workers and waits for them
+    /// to exit.
+    ///
+    /// Works queued after that are never run.
+    pub fn destroy(&self) {
+        self.flush();
+        self.stopping.store(true, Ordering::Release);
+        self.worker_wq.notify_all(false);
+        let workers = core::mem::take(&mut *self.workers.lock());
+        for worker in workers {
+            worker.join();
+        }
+    }
+
+    fn push(&self, work: Arc<Work>) {
+        self.works.lock().push_back(work);
+        self.worker_wq.notify_one(true);
+    }
+
+    fn worker_entry(&self) {
+        loop {
+            let mut next = None;
+            self.worker_wq.wait_until(|| {
+                let mut works = self.works.lock();
+                next = works.pop_front();
+                if next.is_some() {
+                    // Counted as running with the queue locked, so that
+                    // `flush` does not miss it.
+                    self.running.fetch_add(1, Ordering::AcqRel);
+                }
+                next.is_some() || self.stopping.load(Ordering::Acquire)
+            });
+            let Some(work) = next else {
+                break;
+            };
+            work.pending.store(false, Ordering::Release);
+            (work.func)();
+            drop(work);
+            if self.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+                self.flush_wq.notify_all(false);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for WorkQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkQueue")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+static SYSTEM_WQ: LazyInit<Arc<WorkQueue>> = LazyInit::new();
+
+/// The system work queue, which has one worker bound to each CPU.
+///
+/// Works that may run for a long time should use their own queues instead.
+pub fn system_wq() -> &'static Arc<WorkQueue> {
+    &SYSTEM_WQ
+}
+
+/// Queues `work` to the [system work queue](system_wq).
+///
+/// Returns `false` if the work is already pending.
+pub fn queue_work(work: &Arc<Work>) -> bool {
+    system_wq().queue_work(work)
+}
+
+/// Queues `work` to the [system work queue](system_wq) after `delay`.
+///
+/// Returns `false` if the work is already pending.
+#[cfg(feature = "irq")]
+pub fn queue_delayed_work(work: &Arc<Work>, delay: core::time::Duration) -> bool {
+    system_wq().queue_delayed_work(work, delay)
+}
+
+/// Cancels `work` if it is still waiting for its delay.
+///
+/// Returns `false` if the work was not waiting (e.g., it has been queued to
+/// run already).
+#[cfg(feature = "irq")]
+pub fn cancel_delayed_work(work: &Arc<Work>) -> bool {
+    if work.delayed.swap(false, Ordering::AcqRel) {
+        crate::timers::cancel_work_timer(work);
+        work.pending.store(false, Ordering::Release);
+        true
+    } else {
+        false
+    }
+}
+
+/// A short deferred function, which runs on the CPU it is scheduled on,
+/// after the IRQ handlers return.
+///
+/// It runs in interrupt context with IRQs disabled, so it must not sleep. A
+/// tasklet never runs on several CPUs at the same time.
+pub struct Tasklet {
+    func: Box<dyn Fn() + Send + Sync>,
+    scheduled: AtomicBool,
+    /// The CPU whose list the tasklet is in, while it is scheduled.
+    cpu_id: AtomicUsize,
+    running: AtomicBool,
+    disabled: AtomicUsize,
+}
+
+impl Tasklet {
+    /// Creates a new tasklet that calls `f` each time it runs.
+    pub fn new<F>(f: F) -> Arc<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            func: Box::new(f),
+            scheduled: AtomicBool::new(false),
+            cpu_id: AtomicUsize::new(0),
+            running: AtomicBool::new(false),
+            disabled: AtomicUsize::new(0),
+        })
+    }
+
+    /// Schedules the tasklet to run on the current CPU.
+    ///
+    /// Returns `false` if it is already scheduled and has not started
+    /// running, in which case it runs only once.
+    pub fn schedule(self: &Arc<Self>) -> bool {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        self.cpu_id
+            .store(ruxhal::cpu::this_cpu_id(), Ordering::Release);
+        // Safety: IRQs and preemption are disabled.
+        unsafe { TASKLETS.current_ref_mut_raw().push_back(self.clone()) };
+        true
+    }
+
+    /// Prevents the tasklet from running until [`enable`](Tasklet::enable)
+    /// is called, and waits for it to finish if it is running.
+    ///
+    /// It can be nested, the tasklet is enabled again after the same number
+    /// of `enable` calls. The tasklet is still scheduled, but delayed.
+    pub fn disable(&self) {
+        self.disabled.fetch_add(1, Ordering::AcqRel);
+        while self.running.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Reverts a [`disable`](Tasklet::disable) call.
+    ///
+    /// If the tasklet was deferred while disabled, its CPU is interrupted to
+    /// run it, instead of waiting for the next IRQ there.
+    pub fn enable(&self) {
+        if self.disabled.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.raise();
+        }
+    }
+
+    /// Runs the tasklet, returns `false` if it can not run now.
+    fn try_run(&self) -> bool {
+        if self.disabled.load(Ordering::Acquire) > 0 || self.running.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.scheduled.store(false, Ordering::Release);
+        (self.func)();
+        self.running.store(false, Ordering::Release);
+        // It may have been deferred on another CPU while running.
+        if self.cpu_id.load(Ordering::Acquire) != ruxhal::cpu::this_cpu_id() {
+            self.raise();
+        }
+        true
+    }
+
+    /// Sends an IPI to the CPU the tasklet is scheduled on, so that it runs
+    /// after the IPI handler returns.
+    fn raise(&self) {
+        #[cfg(feature = "irq")]
+        if self.scheduled.load(Ordering::Acquire) && self.disabled.load(Ordering::Acquire) == 0 {
+            ruxhal::irq::send_ipi(self.cpu_id.load(Ordering::Acquire));
+        }
+    }
+}
+
+impl fmt::Debug for Tasklet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tasklet")
+            .field("scheduled", &self.scheduled.load(Ordering::Relaxed))
+            .field("disabled", &(self.disabled.load(Ordering::Relaxed) > 0))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Tasklets scheduled on this CPU.
+#[percpu::def_percpu]
+static TASKLETS: VecDeque<Arc<Tasklet>> = VecDeque::new();
+
+/// Runs the tasklets scheduled on the current CPU.
+///
+/// It is called after the IRQ handlers return, with IRQs disabled. Tasklets
+/// that are disabled or running on other CPUs are left for the next time.
+pub fn run_tasklets() {
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    // Safety: IRQs and preemption are disabled, and the tasklets scheduled
+    // during the run are pushed to the emptied list.
+    let tasklets = unsafe { core::mem::take(TASKLETS.current_ref_mut_raw()) };
+    let mut deferred = VecDeque::new();
+    for tasklet in tasklets {
+        if !tasklet.try_run() {
+            deferred.push_back(tasklet);
+        }
+    }
+    if !deferred.is_empty() {
+        unsafe { TASKLETS.current_ref_mut_raw().append(&mut deferred) };
+    }
+}
+
+/// Initializes the system work queue, with the worker of the primary CPU.
+pub(crate) fn init() {
+    SYSTEM_WQ.init_by(WorkQueue::new_empty("kworker"));
+    init_secondary();
+}
+
+/// Adds the system worker of the current CPU, once it can run tasks.
+pub(crate) fn init_secondary() {
+    let cpu_id = ruxhal::cpu::this_cpu_id();
+    SYSTEM_WQ.add_worker(cpu_id, Some(cpu_id));
+}
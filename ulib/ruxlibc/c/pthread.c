@@ -15,66 +15,93 @@
 #include <stdio.h>
 #include <unistd.h>
 
-int pthread_setcancelstate(int new, int *old)
+// TODO
+int pthread_setname_np(pthread_t thread, const char *name)
 {
     unimplemented();
     return 0;
 }
 
-int pthread_setcanceltype(int new, int *old)
+#define DEFAULT_STACK_SIZE 131072
+#define DEFAULT_GUARD_SIZE 8192
+
+// TODO
+int pthread_attr_init(pthread_attr_t *a)
 {
-    unimplemented();
+    *a = (pthread_attr_t){0};
+    // __acquire_ptc();
+    a->_a_stacksize = DEFAULT_STACK_SIZE;
+    a->_a_guardsize = DEFAULT_GUARD_SIZE;
+    // __release_ptc();
     return 0;
 }
 
-// TODO
-void pthread_testcancel(void)
+int pthread_attr_getstacksize(const pthread_attr_t *restrict a, size_t *restrict size)
 {
-    unimplemented();
-    return;
+    *size = a->_a_stacksize;
+    return 0;
 }
 
-// TODO
-int pthread_cancel(pthread_t t)
+int pthread_attr_setstacksize(pthread_attr_t *a, size_t size)
 {
-    unimplemented();
+    if (size - PTHREAD_STACK_MIN > SIZE_MAX / 4)
+        return EINVAL;
+    a->_a_stackaddr = 0;
+    a->_a_stacksize = size;
     return 0;
 }
 
-// TODO
-int pthread_setname_np(pthread_t thread, const char *name)
+int pthread_attr_destroy(pthread_attr_t *a)
 {
-    unimplemented();
     return 0;
 }
 
-#define DEFAULT_STACK_SIZE 131072
-#define DEFAULT_GUARD_SIZE 8192
+int pthread_attr_getguardsize(const pthread_attr_t *restrict a, size_t *restrict size)
+{
+    *size = a->_a_guardsize;
+    return 0;
+}
 
-// TODO
-int pthread_attr_init(pthread_attr_t *a)
+int pthread_attr_setguardsize(pthread_attr_t *a, size_t size)
 {
-    *a = (pthread_attr_t){0};
-    // __acquire_ptc();
-    a->_a_stacksize = DEFAULT_STACK_SIZE;
-    a->_a_guardsize = DEFAULT_GUARD_SIZE;
-    // __release_ptc();
+    if (size > SIZE_MAX / 8)
+        return EINVAL;
+    a->_a_guardsize = size;
     return 0;
 }
 
-int pthread_attr_getstacksize(const pthread_attr_t *restrict a, size_t *restrict size)
+// `_a_stackaddr` is the top of the stack, as in musl.
+int pthread_attr_getstack(const pthread_attr_t *restrict a, void **restrict addr,
+                          size_t *restrict size)
 {
+    if (!a->_a_stackaddr)
+        return EINVAL;
     *size = a->_a_stacksize;
+    *addr = (void *)(a->_a_stackaddr - *size);
     return 0;
 }
 
-int pthread_attr_setstacksize(pthread_attr_t *a, size_t size)
+int pthread_attr_setstack(pthread_attr_t *a, void *addr, size_t size)
 {
     if (size - PTHREAD_STACK_MIN > SIZE_MAX / 4)
         return EINVAL;
-    a->_a_stackaddr = 0;
+    a->_a_stackaddr = (size_t)addr + size;
     a->_a_stacksize = size;
     return 0;
 }
 
+int pthread_attr_getdetachstate(const pthread_attr_t *a, int *state)
+{
+    *state = a->_a_detach;
+    return 0;
+}
+
+int pthread_attr_setdetachstate(pthread_attr_t *a, int state)
+{
+    if (state > 1U)
+        return EINVAL;
+    a->_a_detach = state;
+    return 0;
+}
+
 #endif // RUX_CONFIG_MULTITASK
@@ -31,7 +31,13 @@ void srandom(unsigned int s)
 
 void *calloc(size_t m, size_t n)
 {
+    if (n && m > SIZE_MAX / n) {
+        errno = ENOMEM;
+        return NULL;
+    }
     void *mem = malloc(m * n);
+    if (!mem)
+        return NULL;
 
     return memset(mem, 0, n * m);
 }
@@ -43,7 +49,10 @@ void *realloc(void *memblock, size_t size)
 
     size_t o_size = *(size_t *)(memblock - 8);
 
+    /* The original block is left untouched on failure. */
     void *mem = malloc(size);
+    if (!mem)
+        return NULL;
 
     for (int i = 0; i < (o_size < size ? o_size : size); i++)
         ((char *)mem)[i] = ((char *)memblock)[i];
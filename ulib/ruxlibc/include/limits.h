@@ -41,6 +41,8 @@
 #define SSIZE_MAX LONG_MAX
 #define CHAR_MAX  127
 
-#define PTHREAD_KEYS_MAX 1024
+/* The same as `pthread-key-max` in the config */
+#define PTHREAD_KEYS_MAX              65536
+#define PTHREAD_DESTRUCTOR_ITERATIONS 4
 
 #endif
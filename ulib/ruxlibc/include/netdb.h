@@ -87,6 +87,8 @@ const char *hstrerror(int ecode);
 
 int getaddrinfo(const char *, const char *, const struct addrinfo *, struct addrinfo **);
 void freeaddrinfo(struct addrinfo *);
+int getnameinfo(const struct sockaddr *__restrict, socklen_t, char *__restrict, socklen_t,
+                char *__restrict, socklen_t, int);
 const char *gai_strerror(int __ecode);
 
 /* Legacy functions follow (marked OBsolete in SUS) */
@@ -12,6 +12,7 @@
 #define _PTHREAD_H
 
 #include <features.h>
+#include <sched.h>
 
 typedef void *pthread_t;
 #include <time.h>
@@ -40,9 +41,18 @@ typedef struct {
         unsigned long __s[sizeof(long) == 8 ? 7 : 9];
     } __u;
 } pthread_attr_t;
+#define __SU         (sizeof(size_t) / sizeof(int))
 #define _a_stacksize __u.__s[0]
 #define _a_guardsize __u.__s[1]
 #define _a_stackaddr __u.__s[2]
+#define _a_detach    __u.__i[3 * __SU + 0]
+
+#define PTHREAD_CREATE_JOINABLE 0
+#define PTHREAD_CREATE_DETACHED 1
+
+#define PTHREAD_PRIO_NONE    0
+#define PTHREAD_PRIO_INHERIT 1
+#define PTHREAD_PRIO_PROTECT 2
 
 
 #define PTHREAD_CANCELED ((void *)-1)
@@ -51,6 +61,12 @@ typedef struct {
 /* Keys for thread-specific data */
 typedef unsigned int pthread_key_t;
 
+struct __ptcb {
+    void (*__f)(void *);
+    void *__x;
+    struct __ptcb *__next;
+};
+
 #ifdef RUX_CONFIG_MULTITASK
 
 _Noreturn void pthread_exit(void *);
@@ -60,18 +76,42 @@ int pthread_create(pthread_t *__restrict, const pthread_attr_t *__restrict, void
                    void *__restrict);
 int pthread_join(pthread_t t, void **res);
 
+int pthread_detach(pthread_t);
+
 int pthread_setcancelstate(int, int *);
 int pthread_setcanceltype(int, int *);
 void pthread_testcancel(void);
 int pthread_cancel(pthread_t);
 
+void _pthread_cleanup_push(struct __ptcb *, void (*)(void *), void *);
+void _pthread_cleanup_pop(struct __ptcb *, int);
+
+#define pthread_cleanup_push(f, x) \
+    do {                           \
+        struct __ptcb __cb;        \
+        _pthread_cleanup_push(&__cb, f, x);
+#define pthread_cleanup_pop(r)            \
+        _pthread_cleanup_pop(&__cb, (r)); \
+    }                                     \
+    while (0)
+
 int pthread_mutex_init(pthread_mutex_t *__restrict, const pthread_mutexattr_t *__restrict);
 int pthread_mutex_destroy(pthread_mutex_t *);
 int pthread_mutex_lock(pthread_mutex_t *);
 int pthread_mutex_unlock(pthread_mutex_t *);
 int pthread_mutex_trylock(pthread_mutex_t *);
 
+int pthread_mutexattr_init(pthread_mutexattr_t *);
+int pthread_mutexattr_destroy(pthread_mutexattr_t *);
+int pthread_mutexattr_setprotocol(pthread_mutexattr_t *, int);
+int pthread_mutexattr_getprotocol(const pthread_mutexattr_t *__restrict, int *__restrict);
+
 int pthread_setname_np(pthread_t, const char *);
+int pthread_setaffinity_np(pthread_t, size_t, const cpu_set_t *);
+int pthread_getaffinity_np(pthread_t, size_t, cpu_set_t *);
+int pthread_setschedparam(pthread_t, int, const struct sched_param *);
+int pthread_getschedparam(pthread_t, int *__restrict, struct sched_param *__restrict);
+int pthread_setschedprio(pthread_t, int);
 
 int pthread_cond_init(pthread_cond_t *__restrict__ __cond,
                       const pthread_condattr_t *__restrict__ __cond_attr);
@@ -87,6 +127,15 @@ int pthread_attr_init(pthread_attr_t *__attr);
 int pthread_attr_getstacksize(const pthread_attr_t *__restrict__ __attr,
                               size_t *__restrict__ __stacksize);
 int pthread_attr_setstacksize(pthread_attr_t *__attr, size_t __stacksize);
+int pthread_attr_destroy(pthread_attr_t *__attr);
+int pthread_attr_getguardsize(const pthread_attr_t *__restrict__ __attr,
+                              size_t *__restrict__ __guardsize);
+int pthread_attr_setguardsize(pthread_attr_t *__attr, size_t __guardsize);
+int pthread_attr_getstack(const pthread_attr_t *__restrict__ __attr, void **__restrict__ __stackaddr,
+                          size_t *__restrict__ __stacksize);
+int pthread_attr_setstack(pthread_attr_t *__attr, void *__stackaddr, size_t __stacksize);
+int pthread_attr_getdetachstate(const pthread_attr_t *__attr, int *__detachstate);
+int pthread_attr_setdetachstate(pthread_attr_t *__attr, int __detachstate);
 
 /* Create a key value identifying a location in the thread-specific
    data area.  Each thread maintains a distinct thread-specific data
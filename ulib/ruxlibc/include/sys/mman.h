@@ -39,25 +39,62 @@
 #else
 #define MAP_ANONYMOUS 0x20 /* Don't use a file.  */
 #endif
-#define MAP_ANON MAP_ANONYMOUS
+#define MAP_ANON    MAP_ANONYMOUS
+#define MAP_HUGETLB 0x40000 /* Create huge page mapping.  */
 /* When MAP_HUGETLB is set bits [26:31] encode the log2 of the huge page size.  */
 #define MAP_HUGE_SHIFT 26
 #define MAP_HUGE_MASK  0x3f
+#define MAP_HUGE_2MB   (21 << MAP_HUGE_SHIFT)
+#define MAP_HUGE_1GB   (30 << MAP_HUGE_SHIFT)
 
 #define MS_SYNC 0
 
 #define MAP_FAILED ((void *)-1)
 
+/* Advice to madvise.  */
+#define MADV_NORMAL     0  /* No further special treatment.  */
+#define MADV_RANDOM     1  /* Expect random page references.  */
+#define MADV_SEQUENTIAL 2  /* Expect sequential page references.  */
+#define MADV_WILLNEED   3  /* Will need these pages.  */
+#define MADV_DONTNEED   4  /* Don't need these pages.  */
+#define MADV_FREE       8  /* Free pages only if memory pressure.  */
+#define MADV_DONTFORK   10 /* Do not inherit across fork.  */
+#define MADV_DOFORK     11 /* Do inherit across fork.  */
+#define MADV_HUGEPAGE   14 /* Worth backing with hugepages.  */
+#define MADV_NOHUGEPAGE 15 /* Not worth backing with hugepages.  */
+
+/* Flags for mlockall.  */
+#define MCL_CURRENT 1 /* Lock all currently mapped pages.  */
+#define MCL_FUTURE  2 /* Lock all additions to address space.  */
+#define MCL_ONFAULT 4 /* Lock all pages that are faulted in.  */
+
+/* Flags for mlock2.  */
+#define MLOCK_ONFAULT 1 /* Lock pages in range after they are faulted in.  */
+
 /* Flags for mremap.  */
 #define MREMAP_MAYMOVE   1
 #define MREMAP_FIXED     2
 #define MREMAP_DONTUNMAP 4
 
+#define MFD_CLOEXEC       0x0001U
+#define MFD_ALLOW_SEALING 0x0002U
+#define MFD_HUGETLB       0x0004U
+
 void *mmap(void *addr, size_t len, int prot, int flags, int fildes, off_t off);
 int munmap(void *addr, size_t length);
 void *mremap(void *old_address, size_t old_size, size_t new_size, int flags,
              ... /* void *new_address */);
 int mprotect(void *addr, size_t len, int prot);
 int madvise(void *addr, size_t length, int advice);
+int mincore(void *addr, size_t length, unsigned char *vec);
+int mlock(const void *addr, size_t len);
+int mlock2(const void *addr, size_t len, unsigned flags);
+int munlock(const void *addr, size_t len);
+int mlockall(int flags);
+int munlockall(void);
+
+int memfd_create(const char *name, unsigned flags);
+int shm_open(const char *name, int flag, mode_t mode);
+int shm_unlink(const char *name);
 
 #endif
@@ -37,6 +37,7 @@ typedef unsigned id_t;
 typedef int pid_t;
 typedef unsigned uid_t;
 typedef unsigned gid_t;
+typedef int key_t;
 
 #include <sys/select.h>
 
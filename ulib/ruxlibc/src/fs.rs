@@ -7,11 +7,11 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_uint};
 
 use ruxos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_mkdir, sys_open, sys_rename, sys_rmdir,
-    sys_stat, sys_unlink,
+    sys_copy_file_range, sys_fstat, sys_ftruncate, sys_getcwd, sys_lseek, sys_lstat, sys_mkdir,
+    sys_open, sys_rename, sys_rmdir, sys_sendfile, sys_stat, sys_unlink,
 };
 
 use crate::{ctypes, utils::e};
@@ -37,6 +37,12 @@ pub unsafe extern "C" fn lseek(fd: c_int, offset: ctypes::off_t, whence: c_int)
     e(sys_lseek(fd, offset, whence) as _) as _
 }
 
+/// Truncate or extend the file indicated by `fd` to `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ftruncate(fd: c_int, length: ctypes::off_t) -> c_int {
+    e(sys_ftruncate(fd, length))
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -107,3 +113,31 @@ pub unsafe extern "C" fn unlink(pathname: *const c_char) -> c_int {
 pub unsafe extern "C" fn mkdir(pathname: *const c_char, mode: ctypes::mode_t) -> c_int {
     e(sys_mkdir(pathname, mode))
 }
+
+/// Transfer data from the file `in_fd` to the file descriptor `out_fd`.
+///
+/// Return the number of bytes transferred.
+#[no_mangle]
+pub unsafe extern "C" fn sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: usize,
+) -> ctypes::ssize_t {
+    e(sys_sendfile(out_fd, in_fd, offset, count) as _) as _
+}
+
+/// Copy a range of data from the file `fd_in` to the file `fd_out`.
+///
+/// Return the number of bytes copied.
+#[no_mangle]
+pub unsafe extern "C" fn copy_file_range(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    e(sys_copy_file_range(fd_in, off_in, fd_out, off_out, len, flags) as _) as _
+}
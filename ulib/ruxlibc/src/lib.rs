@@ -76,6 +76,8 @@ mod net;
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "multitask")]
+mod sched;
 #[cfg(feature = "alloc")]
 mod strftime;
 #[cfg(feature = "fp_simd")]
@@ -112,7 +114,10 @@ pub use self::env::{getenv, setenv, unsetenv};
 #[cfg(feature = "fd")]
 pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, mkdir, rename, rmdir, stat, unlink};
+pub use self::fs::{
+    ax_open, copy_file_range, fstat, ftruncate, getcwd, lseek, lstat, mkdir, rename, rmdir,
+    sendfile, stat, unlink,
+};
 #[cfg(feature = "fd")]
 pub use self::io::rux_ioctl;
 #[cfg(feature = "poll")]
@@ -123,15 +128,22 @@ pub use self::io_mpx::select;
 pub use self::io_mpx::{epoll_create, epoll_ctl, epoll_wait};
 #[cfg(feature = "alloc")]
 pub use self::malloc::{free, malloc};
+#[cfg(feature = "fs")]
+pub use self::mmap::{memfd_create, shm_open, shm_unlink, shmat, shmctl, shmdt, shmget};
 #[cfg(feature = "alloc")]
 pub use self::mmap::{mmap, munmap};
 #[cfg(feature = "net")]
 pub use self::net::{
-    accept, ax_sendmsg, bind, connect, freeaddrinfo, getaddrinfo, getpeername, getsockname, listen,
-    recv, recvfrom, send, sendto, shutdown, socket,
+    accept, ax_sendmsg, bind, connect, freeaddrinfo, getaddrinfo, getnameinfo, getpeername,
+    getsockname, listen, recv, recvfrom, send, sendto, shutdown, socket,
 };
 #[cfg(feature = "pipe")]
-pub use self::pipe::pipe;
+pub use self::pipe::{pipe, splice};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    _pthread_cleanup_pop, _pthread_cleanup_push, pthread_cancel, pthread_detach,
+    pthread_setcancelstate, pthread_setcanceltype, pthread_testcancel,
+};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{
     pthread_cond_broadcast, pthread_cond_init, pthread_cond_signal, pthread_cond_wait,
@@ -139,9 +151,25 @@ pub use self::pthread::{
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
 #[cfg(feature = "multitask")]
+pub use self::pthread::{pthread_getaffinity_np, pthread_setaffinity_np};
+#[cfg(feature = "multitask")]
+pub use self::pthread::{pthread_getschedparam, pthread_setschedparam, pthread_setschedprio};
+#[cfg(feature = "multitask")]
 pub use self::pthread::{
     pthread_mutex_init, pthread_mutex_lock, pthread_mutex_trylock, pthread_mutex_unlock,
 };
+#[cfg(feature = "multitask")]
+pub use self::pthread::{
+    pthread_mutexattr_destroy, pthread_mutexattr_getprotocol, pthread_mutexattr_init,
+    pthread_mutexattr_setprotocol,
+};
+#[cfg(feature = "multitask")]
+pub use self::sched::{
+    sched_get_priority_max, sched_get_priority_min, sched_getattr, sched_getparam,
+    sched_getscheduler, sched_setattr, sched_setparam, sched_setscheduler,
+};
+#[cfg(feature = "multitask")]
+pub use self::sched::{sched_getaffinity, sched_getcpu, sched_setaffinity};
 #[cfg(feature = "alloc")]
 pub use self::strftime::strftime;
 #[cfg(feature = "fp_simd")]
@@ -29,16 +29,26 @@ const CTRL_BLK_SIZE: usize = core::mem::size_of::<MemoryControlBlock>();
 
 /// Allocate memory and return the memory address.
 ///
-/// Returns 0 on failure (the current implementation does not trigger an exception)
+/// Returns 0 and sets `errno` to `ENOMEM` on failure, after the OOM policy of
+/// the allocator is applied.
 #[no_mangle]
 pub unsafe extern "C" fn malloc(size: ctypes::size_t) -> *mut c_void {
     // Allocate `(actual length) + 8`. The lowest 8 Bytes are stored in the actual allocated space size.
     // This is because free(uintptr_t) has only one parameter representing the address,
     // So we need to save in advance to know the size of the memory space that needs to be released
-    let layout = Layout::from_size_align(size + CTRL_BLK_SIZE, 8).unwrap();
+    let Some(layout) = size
+        .checked_add(CTRL_BLK_SIZE)
+        .and_then(|total| Layout::from_size_align(total, 8).ok())
+    else {
+        crate::errno::set_errno(axerrno::LinuxError::ENOMEM as _);
+        return core::ptr::null_mut();
+    };
     unsafe {
         let ptr = alloc(layout).cast::<MemoryControlBlock>();
-        assert!(!ptr.is_null(), "malloc failed");
+        if ptr.is_null() {
+            crate::errno::set_errno(axerrno::LinuxError::ENOMEM as _);
+            return core::ptr::null_mut();
+        }
         ptr.write(MemoryControlBlock { size });
         ptr.add(1).cast()
     }
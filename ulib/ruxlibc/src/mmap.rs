@@ -7,10 +7,21 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-use crate::ctypes;
-use core::ffi::{c_int, c_void};
+use crate::{ctypes, utils::e};
+use core::ffi::{c_int, c_uchar, c_uint, c_void};
 
-use ruxos_posix_api::{sys_madvise, sys_mmap, sys_mprotect, sys_mremap, sys_msync, sys_munmap};
+use ruxos_posix_api::{
+    sys_madvise, sys_mincore, sys_mlock, sys_mlock2, sys_mlockall, sys_mmap, sys_mprotect,
+    sys_mremap, sys_msync, sys_munlock, sys_munlockall, sys_munmap,
+};
+#[cfg(feature = "fs")]
+use {
+    core::ffi::c_char,
+    ruxos_posix_api::{
+        sys_memfd_create, sys_shm_open, sys_shm_unlink, sys_shmat, sys_shmctl, sys_shmdt,
+        sys_shmget, sys_swapoff, sys_swapon,
+    },
+};
 
 /// Map a file or device into virtual memory.
 #[no_mangle]
@@ -62,3 +73,111 @@ pub unsafe extern "C" fn mprotect(addr: *mut c_void, len: ctypes::size_t, flags:
 pub unsafe extern "C" fn madvise(addr: *mut c_void, len: ctypes::size_t, advice: c_int) -> c_int {
     sys_madvise(addr, len, advice)
 }
+
+/// Report whether the pages of a specific region of memory are resident.
+#[no_mangle]
+pub unsafe extern "C" fn mincore(
+    addr: *mut c_void,
+    len: ctypes::size_t,
+    vec: *mut c_uchar,
+) -> c_int {
+    e(sys_mincore(addr, len, vec))
+}
+
+/// Lock the pages of a specific region of memory into memory.
+#[no_mangle]
+pub unsafe extern "C" fn mlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    e(sys_mlock(addr, len))
+}
+
+/// Lock the pages of a specific region of memory into memory, with `flags`.
+#[no_mangle]
+pub unsafe extern "C" fn mlock2(addr: *const c_void, len: ctypes::size_t, flags: c_uint) -> c_int {
+    e(sys_mlock2(addr, len, flags as c_int))
+}
+
+/// Unlock the pages of a specific region of memory.
+#[no_mangle]
+pub unsafe extern "C" fn munlock(addr: *const c_void, len: ctypes::size_t) -> c_int {
+    e(sys_munlock(addr, len))
+}
+
+/// Lock all the mapped pages into memory.
+#[no_mangle]
+pub unsafe extern "C" fn mlockall(flags: c_int) -> c_int {
+    e(sys_mlockall(flags))
+}
+
+/// Unlock all the mapped pages.
+#[no_mangle]
+pub unsafe extern "C" fn munlockall() -> c_int {
+    e(sys_munlockall())
+}
+
+/// Enable swapping on the swap file `path`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn swapon(path: *const c_char, flags: c_int) -> c_int {
+    e(sys_swapon(path, flags))
+}
+
+/// Disable swapping on the swap file `path`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn swapoff(path: *const c_char) -> c_int {
+    e(sys_swapoff(path))
+}
+
+/// Create an anonymous file, and return its fd.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn memfd_create(name: *const c_char, flags: c_uint) -> c_int {
+    e(sys_memfd_create(name, flags))
+}
+
+/// Open the POSIX shared memory object `name`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shm_open(name: *const c_char, flag: c_int, mode: ctypes::mode_t) -> c_int {
+    e(sys_shm_open(name, flag, mode))
+}
+
+/// Remove the POSIX shared memory object `name`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shm_unlink(name: *const c_char) -> c_int {
+    e(sys_shm_unlink(name))
+}
+
+/// Get the System V shared memory segment of `key`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shmget(key: ctypes::key_t, size: ctypes::size_t, flag: c_int) -> c_int {
+    e(sys_shmget(key, size, flag))
+}
+
+/// Attach the System V shared memory segment `id`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shmat(id: c_int, addr: *const c_void, flag: c_int) -> *mut c_void {
+    let ret = sys_shmat(id, addr, flag);
+    if (ret as isize) < 0 {
+        e(ret as isize as c_int) as isize as *mut c_void
+    } else {
+        ret
+    }
+}
+
+/// Detach the System V shared memory segment attached at `addr`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shmdt(addr: *const c_void) -> c_int {
+    e(sys_shmdt(addr))
+}
+
+/// Control the System V shared memory segment `id`.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn shmctl(id: c_int, cmd: c_int, buf: *mut ctypes::shmid_ds) -> c_int {
+    e(sys_shmctl(id, cmd, buf))
+}
@@ -7,6 +7,7 @@
  *   See the Mulan PSL v2 for more details.
  */
 
+use axerrno::LinuxError;
 use core::ffi::{c_char, c_int, c_void};
 use ruxos_posix_api as api;
 
@@ -150,9 +151,9 @@ pub unsafe extern "C" fn getaddrinfo(
     hints: *const ctypes::addrinfo,
     res: *mut *mut ctypes::addrinfo,
 ) -> c_int {
-    let ret = e(api::sys_getaddrinfo(nodename, servname, hints, res));
+    let ret = api::sys_getaddrinfo(nodename, servname, hints, res);
     match ret {
-        r if r < 0 => ctypes::EAI_FAIL,
+        r if r < 0 => gai_error(r),
         0 => ctypes::EAI_NONAME,
         _ => 0,
     }
@@ -164,6 +165,42 @@ pub unsafe extern "C" fn freeaddrinfo(res: *mut ctypes::addrinfo) {
     api::sys_freeaddrinfo(res);
 }
 
+/// Convert a socket address to the host and service names.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn getnameinfo(
+    sa: *const ctypes::sockaddr,
+    salen: ctypes::socklen_t,
+    host: *mut c_char,
+    hostlen: ctypes::socklen_t,
+    serv: *mut c_char,
+    servlen: ctypes::socklen_t,
+    flags: c_int,
+) -> c_int {
+    let ret = api::sys_getnameinfo(sa, salen, host, hostlen, serv, servlen, flags);
+    match ret {
+        r if r < 0 => gai_error(r),
+        _ => 0,
+    }
+}
+
+/// Convert the negative errno returned by the resolver to an `EAI_*` code.
+///
+/// The errno is also set, so that it can be checked after `EAI_SYSTEM`, as
+/// with glibc.
+fn gai_error(ret: c_int) -> c_int {
+    crate::errno::set_errno(-ret);
+    match -ret {
+        e if e == LinuxError::ENOENT as c_int => ctypes::EAI_NONAME,
+        e if e == LinuxError::ENOSPC as c_int => ctypes::EAI_OVERFLOW,
+        e if e == LinuxError::EAGAIN as c_int => ctypes::EAI_AGAIN,
+        e if e == LinuxError::EAFNOSUPPORT as c_int => ctypes::EAI_FAMILY,
+        e if e == LinuxError::ENOMEM as c_int => ctypes::EAI_MEMORY,
+        _ => ctypes::EAI_SYSTEM,
+    }
+}
+
 /// Get current address to which the socket sockfd is bound.
 #[no_mangle]
 pub unsafe extern "C" fn getsockname(
@@ -7,11 +7,11 @@
  *   See the Mulan PSL v2 for more details.
  */
 
-use core::ffi::c_int;
+use core::ffi::{c_int, c_uint};
 
-use ruxos_posix_api::sys_pipe;
+use ruxos_posix_api::{sys_pipe, sys_splice};
 
-use crate::utils::e;
+use crate::{ctypes, utils::e};
 
 /// Create a pipe
 ///
@@ -21,3 +21,18 @@ pub unsafe extern "C" fn pipe(fd: *mut c_int) -> c_int {
     let fds = unsafe { core::slice::from_raw_parts_mut(fd, 2) };
     e(sys_pipe(fds))
 }
+
+/// Move data between a pipe and another file descriptor.
+///
+/// Return the number of bytes moved.
+#[no_mangle]
+pub unsafe extern "C" fn splice(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    e(sys_splice(fd_in, off_in, fd_out, off_out, len, flags) as _) as _
+}
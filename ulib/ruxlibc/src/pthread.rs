@@ -46,6 +46,101 @@ pub unsafe extern "C" fn pthread_join(
     e(api::sys_pthread_join(thread, retval))
 }
 
+/// Marks the given thread as detached, its resources are released when it
+/// exits, without being joined.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_detach(thread: ctypes::pthread_t) -> c_int {
+    e(api::sys_pthread_detach(thread))
+}
+
+/// Sends a cancellation request to the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    e(api::sys_pthread_cancel(thread))
+}
+
+/// Sets the cancelability state of the current thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_setcancelstate(state: c_int, oldstate: *mut c_int) -> c_int {
+    e(api::sys_pthread_setcancelstate(state, oldstate))
+}
+
+/// Sets the cancelability type of the current thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_setcanceltype(ty: c_int, oldtype: *mut c_int) -> c_int {
+    e(api::sys_pthread_setcanceltype(ty, oldtype))
+}
+
+/// Creates a cancellation point in the current thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_testcancel() {
+    api::sys_pthread_testcancel()
+}
+
+/// Pushes a cleanup handler of the current thread, called by the
+/// `pthread_cleanup_push` macro.
+#[no_mangle]
+pub unsafe extern "C" fn _pthread_cleanup_push(
+    cb: *mut ctypes::__ptcb,
+    routine: Option<unsafe extern "C" fn(arg: *mut c_void)>,
+    arg: *mut c_void,
+) {
+    api::sys_pthread_cleanup_push(cb, routine, arg)
+}
+
+/// Pops the last cleanup handler of the current thread and runs it if
+/// `execute` is not 0, called by the `pthread_cleanup_pop` macro.
+#[no_mangle]
+pub unsafe extern "C" fn _pthread_cleanup_pop(cb: *mut ctypes::__ptcb, execute: c_int) {
+    api::sys_pthread_cleanup_pop(cb, execute)
+}
+
+/// Set the CPU affinity of the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_setaffinity_np(
+    thread: ctypes::pthread_t,
+    cpusetsize: usize,
+    cpuset: *const ctypes::cpu_set_t,
+) -> c_int {
+    e(api::sys_pthread_setaffinity_np(thread, cpusetsize, cpuset))
+}
+
+/// Get the CPU affinity of the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_getaffinity_np(
+    thread: ctypes::pthread_t,
+    cpusetsize: usize,
+    cpuset: *mut ctypes::cpu_set_t,
+) -> c_int {
+    e(api::sys_pthread_getaffinity_np(thread, cpusetsize, cpuset))
+}
+
+/// Set the scheduling policy and priority of the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_setschedparam(
+    thread: ctypes::pthread_t,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    e(api::sys_pthread_setschedparam(thread, policy, param))
+}
+
+/// Get the scheduling policy and priority of the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_getschedparam(
+    thread: ctypes::pthread_t,
+    policy: *mut c_int,
+    param: *mut ctypes::sched_param,
+) -> c_int {
+    e(api::sys_pthread_getschedparam(thread, policy, param))
+}
+
+/// Set the scheduling priority of the given thread.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_setschedprio(thread: ctypes::pthread_t, prio: c_int) -> c_int {
+    e(api::sys_pthread_setschedprio(thread, prio))
+}
+
 /// Initialize a mutex.
 #[no_mangle]
 pub unsafe extern "C" fn pthread_mutex_init(
@@ -80,6 +175,39 @@ pub unsafe extern "C" fn pthread_mutex_unlock(mutex: *mut ctypes::pthread_mutex_
     e(api::sys_pthread_mutex_unlock(mutex))
 }
 
+/// Initialize a mutex attributes object.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_mutexattr_init(attr: *mut ctypes::pthread_mutexattr_t) -> c_int {
+    e(api::sys_pthread_mutexattr_init(attr))
+}
+
+/// Destroy a mutex attributes object.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_mutexattr_destroy(
+    attr: *mut ctypes::pthread_mutexattr_t,
+) -> c_int {
+    e(api::sys_pthread_mutexattr_destroy(attr))
+}
+
+/// Set the protocol of a mutex attributes object, `PTHREAD_PRIO_INHERIT`
+/// makes the owner of the mutex inherit the priority of the waiters.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_mutexattr_setprotocol(
+    attr: *mut ctypes::pthread_mutexattr_t,
+    protocol: c_int,
+) -> c_int {
+    e(api::sys_pthread_mutexattr_setprotocol(attr, protocol))
+}
+
+/// Get the protocol of a mutex attributes object.
+#[no_mangle]
+pub unsafe extern "C" fn pthread_mutexattr_getprotocol(
+    attr: *const ctypes::pthread_mutexattr_t,
+    protocol: *mut c_int,
+) -> c_int {
+    e(api::sys_pthread_mutexattr_getprotocol(attr, protocol))
+}
+
 /// Initialize a condition variable
 #[no_mangle]
 pub unsafe extern "C" fn pthread_cond_init(
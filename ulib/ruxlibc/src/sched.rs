@@ -0,0 +1,118 @@
+/* Copyright (c) [2023] [Syswonder Community]
+ *   [Ruxos] is licensed under Mulan PSL v2.
+ *   You can use this software according to the terms and conditions of the Mulan PSL v2.
+ *   You may obtain a copy of Mulan PSL v2 at:
+ *               http://license.coscl.org.cn/MulanPSL2
+ *   THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+ *   See the Mulan PSL v2 for more details.
+ */
+
+use crate::{ctypes, utils::e};
+use core::ffi::{c_int, c_uint};
+use ruxos_posix_api as api;
+
+/// Set the CPU affinity of the thread `pid`, or the current thread if `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_setaffinity(
+    pid: ctypes::pid_t,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    e(api::sys_sched_setaffinity(pid, cpusetsize, mask))
+}
+
+/// Get the CPU affinity of the thread `pid`, or the current thread if `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getaffinity(
+    pid: ctypes::pid_t,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    match e(api::sys_sched_getaffinity(pid, cpusetsize, mask)) {
+        ret if ret < 0 => ret,
+        _ => 0,
+    }
+}
+
+/// Get the CPU the current thread is running on.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getcpu() -> c_int {
+    let mut cpu: c_uint = 0;
+    match e(api::sys_getcpu(&mut cpu, core::ptr::null_mut())) {
+        ret if ret < 0 => ret,
+        _ => cpu as c_int,
+    }
+}
+
+/// Get the maximum priority of the scheduling policy.
+#[no_mangle]
+pub unsafe extern "C" fn sched_get_priority_max(policy: c_int) -> c_int {
+    e(api::sys_sched_get_priority_max(policy))
+}
+
+/// Get the minimum priority of the scheduling policy.
+#[no_mangle]
+pub unsafe extern "C" fn sched_get_priority_min(policy: c_int) -> c_int {
+    e(api::sys_sched_get_priority_min(policy))
+}
+
+/// Set the scheduling policy and priority of the thread `pid`, or the current
+/// thread if `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_setscheduler(
+    pid: ctypes::pid_t,
+    policy: c_int,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    e(api::sys_sched_setscheduler(pid, policy, param))
+}
+
+/// Get the scheduling policy of the thread `pid`, or the current thread if
+/// `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getscheduler(pid: ctypes::pid_t) -> c_int {
+    e(api::sys_sched_getscheduler(pid))
+}
+
+/// Set the scheduling priority of the thread `pid`, or the current thread if
+/// `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_setparam(
+    pid: ctypes::pid_t,
+    param: *const ctypes::sched_param,
+) -> c_int {
+    e(api::sys_sched_setparam(pid, param))
+}
+
+/// Get the scheduling priority of the thread `pid`, or the current thread if
+/// `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getparam(
+    pid: ctypes::pid_t,
+    param: *mut ctypes::sched_param,
+) -> c_int {
+    e(api::sys_sched_getparam(pid, param))
+}
+
+/// Set the scheduling policy and attributes of the thread `pid`, or the
+/// current thread if `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_setattr(
+    pid: ctypes::pid_t,
+    attr: *const ctypes::sched_attr,
+    flags: c_uint,
+) -> c_int {
+    e(api::sys_sched_setattr(pid, attr, flags))
+}
+
+/// Get the scheduling policy and attributes of the thread `pid`, or the
+/// current thread if `pid` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sched_getattr(
+    pid: ctypes::pid_t,
+    attr: *mut ctypes::sched_attr,
+    size: c_uint,
+    flags: c_uint,
+) -> c_int {
+    e(api::sys_sched_getattr(pid, attr, size, flags))
+}
@@ -44,6 +44,8 @@ pub unsafe extern "C" fn sysconf(name: c_int) -> c_long {
         }
         // Maximum number of keys per thread
         ctypes::_SC_THREAD_KEYS_MAX => config::PTHREAD_KEY_MAX as c_long,
+        // Maximum number of rounds of calling the destructors of keys
+        ctypes::_SC_THREAD_DESTRUCTOR_ITERATIONS => ctypes::PTHREAD_DESTRUCTOR_ITERATIONS as c_long,
         _ => 0,
     }
 }
@@ -30,6 +30,8 @@ pub enum SyscallId {
     #[cfg(feature = "fs")]
     RENAMEAT = 38,
     #[cfg(feature = "fs")]
+    FTRUNCATE = 46,
+    #[cfg(feature = "fs")]
     FACCESSAT = 48,
     #[cfg(feature = "fs")]
     CHDIR = 49,
@@ -57,10 +59,14 @@ pub enum SyscallId {
     PWRITE64 = 68,
     #[cfg(feature = "fs")]
     PREADV = 69,
+    #[cfg(feature = "fs")]
+    SENDFILE = 71,
     #[cfg(feature = "select")]
     PSELECT6 = 72,
     #[cfg(feature = "poll")]
     PPOLL = 73,
+    #[cfg(feature = "pipe")]
+    SPLICE = 76,
     #[cfg(feature = "fs")]
     READLINKAT = 78,
     #[cfg(feature = "fs")]
@@ -77,10 +83,30 @@ pub enum SyscallId {
     SET_TID_ADDRESS = 96,
     #[cfg(feature = "multitask")]
     FUTEX = 98,
+    #[cfg(feature = "multitask")]
+    SET_ROBUST_LIST = 99,
+    #[cfg(feature = "multitask")]
+    GET_ROBUST_LIST = 100,
     NANO_SLEEP = 101,
     CLOCK_SETTIME = 112,
     CLOCK_GETTIME = 113,
+    #[cfg(feature = "multitask")]
+    SCHED_SETPARAM = 118,
+    #[cfg(feature = "multitask")]
+    SCHED_SETSCHEDULER = 119,
+    #[cfg(feature = "multitask")]
+    SCHED_GETSCHEDULER = 120,
+    #[cfg(feature = "multitask")]
+    SCHED_GETPARAM = 121,
+    #[cfg(feature = "multitask")]
+    SCHED_SETAFFINITY = 122,
+    #[cfg(feature = "multitask")]
+    SCHED_GETAFFINITY = 123,
     SCHED_YIELD = 124,
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MAX = 125,
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MIN = 126,
     #[cfg(feature = "signal")]
     KILL = 129,
     #[cfg(feature = "signal")]
@@ -98,6 +124,7 @@ pub enum SyscallId {
     GETRLIMIT = 163,
     SETRLIMIT = 164,
     UMASK = 166,
+    GETCPU = 168,
     #[cfg(feature = "multitask")]
     GETPID = 172,
     GETPPID = 173,
@@ -107,6 +134,14 @@ pub enum SyscallId {
     GETEGID = 177,
     GETTID = 178,
     SYSINFO = 179,
+    #[cfg(feature = "fs")]
+    SHMGET = 194,
+    #[cfg(feature = "fs")]
+    SHMCTL = 195,
+    #[cfg(feature = "fs")]
+    SHMAT = 196,
+    #[cfg(feature = "fs")]
+    SHMDT = 197,
     #[cfg(feature = "net")]
     SOCKET = 198,
     #[cfg(feature = "net")]
@@ -141,12 +176,36 @@ pub enum SyscallId {
     EXECVE = 221,
     #[cfg(feature = "alloc")]
     MMAP = 222,
+    #[cfg(feature = "fs")]
+    SWAPON = 224,
+    #[cfg(feature = "fs")]
+    SWAPOFF = 225,
     #[cfg(feature = "alloc")]
     MPROTECT = 226,
     #[cfg(feature = "alloc")]
     MSYNC = 227,
     #[cfg(feature = "alloc")]
+    MLOCK = 228,
+    #[cfg(feature = "alloc")]
+    MUNLOCK = 229,
+    #[cfg(feature = "alloc")]
+    MLOCKALL = 230,
+    #[cfg(feature = "alloc")]
+    MUNLOCKALL = 231,
+    #[cfg(feature = "alloc")]
+    MINCORE = 232,
+    #[cfg(feature = "alloc")]
     MADVISE = 233,
     PRLIMIT64 = 261,
+    #[cfg(feature = "multitask")]
+    SCHED_SETATTR = 274,
+    #[cfg(feature = "multitask")]
+    SCHED_GETATTR = 275,
     GETRANDOM = 278,
+    #[cfg(feature = "fs")]
+    MEMFD_CREATE = 279,
+    #[cfg(feature = "alloc")]
+    MLOCK2 = 284,
+    #[cfg(feature = "fs")]
+    COPY_FILE_RANGE = 285,
 }
@@ -30,6 +30,8 @@ pub enum SyscallId {
     #[cfg(feature = "fs")]
     RENAMEAT = 38,
     #[cfg(feature = "fs")]
+    FTRUNCATE = 46,
+    #[cfg(feature = "fs")]
     FCHOWNAT = 54,
     #[cfg(feature = "fs")]
     OPENAT = 56,
@@ -47,10 +49,14 @@ pub enum SyscallId {
     READV = 65,
     #[cfg(feature = "fd")]
     WRITEV = 66,
+    #[cfg(feature = "fs")]
+    SENDFILE = 71,
     #[cfg(feature = "select")]
     PSELECT6 = 72,
     #[cfg(feature = "poll")]
     PPOLL = 73,
+    #[cfg(feature = "pipe")]
+    SPLICE = 76,
     #[cfg(feature = "fs")]
     READLINKAT = 78,
     #[cfg(feature = "fs")]
@@ -66,10 +72,30 @@ pub enum SyscallId {
     SET_TID_ADDRESS = 96,
     #[cfg(feature = "multitask")]
     FUTEX = 98,
+    #[cfg(feature = "multitask")]
+    SET_ROBUST_LIST = 99,
+    #[cfg(feature = "multitask")]
+    GET_ROBUST_LIST = 100,
     NANO_SLEEP = 101,
     CLOCK_SETTIME = 112,
     CLOCK_GETTIME = 113,
+    #[cfg(feature = "multitask")]
+    SCHED_SETPARAM = 118,
+    #[cfg(feature = "multitask")]
+    SCHED_SETSCHEDULER = 119,
+    #[cfg(feature = "multitask")]
+    SCHED_GETSCHEDULER = 120,
+    #[cfg(feature = "multitask")]
+    SCHED_GETPARAM = 121,
+    #[cfg(feature = "multitask")]
+    SCHED_SETAFFINITY = 122,
+    #[cfg(feature = "multitask")]
+    SCHED_GETAFFINITY = 123,
     SCHED_YIELD = 124,
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MAX = 125,
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MIN = 126,
     #[cfg(feature = "signal")]
     SIGALTSTACK = 132,
     #[cfg(feature = "signal")]
@@ -80,11 +106,20 @@ pub enum SyscallId {
     GETRLIMIT = 163,
     SETRLIMIT = 164,
     UMASK = 166,
+    GETCPU = 168,
     #[cfg(feature = "multitask")]
     GETPID = 172,
     GETEUID = 175,
     GETEGID = 177,
     SYSINFO = 179,
+    #[cfg(feature = "fs")]
+    SHMGET = 194,
+    #[cfg(feature = "fs")]
+    SHMCTL = 195,
+    #[cfg(feature = "fs")]
+    SHMAT = 196,
+    #[cfg(feature = "fs")]
+    SHMDT = 197,
     #[cfg(feature = "net")]
     SOCKET = 198,
     #[cfg(feature = "net")]
@@ -117,9 +152,33 @@ pub enum SyscallId {
     CLONE = 220,
     #[cfg(feature = "alloc")]
     MMAP = 222,
+    #[cfg(feature = "fs")]
+    SWAPON = 224,
+    #[cfg(feature = "fs")]
+    SWAPOFF = 225,
+    #[cfg(feature = "alloc")]
+    MLOCK = 228,
+    #[cfg(feature = "alloc")]
+    MUNLOCK = 229,
+    #[cfg(feature = "alloc")]
+    MLOCKALL = 230,
+    #[cfg(feature = "alloc")]
+    MUNLOCKALL = 231,
+    #[cfg(feature = "alloc")]
+    MINCORE = 232,
     #[cfg(feature = "alloc")]
     MADVISE = 233,
     #[cfg(feature = "alloc")]
     MPROTECT = 226,
     PRLIMIT64 = 261,
+    #[cfg(feature = "multitask")]
+    SCHED_SETATTR = 274,
+    #[cfg(feature = "multitask")]
+    SCHED_GETATTR = 275,
+    #[cfg(feature = "fs")]
+    MEMFD_CREATE = 279,
+    #[cfg(feature = "alloc")]
+    MLOCK2 = 284,
+    #[cfg(feature = "fs")]
+    COPY_FILE_RANGE = 285,
 }
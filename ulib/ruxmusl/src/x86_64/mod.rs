@@ -168,11 +168,39 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                 args[2] as c_int,
             ) as _,
 
+            #[cfg(feature = "alloc")]
+            SyscallId::MINCORE => ruxos_posix_api::sys_mincore(
+                args[0] as *mut core::ffi::c_void,
+                args[1] as ctypes::size_t,
+                args[2] as *mut core::ffi::c_uchar,
+            ) as _,
+
             #[cfg(feature = "alloc")]
             SyscallId::MADVISE => {
                 ruxos_posix_api::sys_madvise(args[0] as *mut c_void, args[1], args[2] as c_int) as _
             }
 
+            #[cfg(feature = "fs")]
+            SyscallId::SHMGET => ruxos_posix_api::sys_shmget(
+                args[0] as ctypes::key_t,
+                args[1] as ctypes::size_t,
+                args[2] as c_int,
+            ) as _,
+
+            #[cfg(feature = "fs")]
+            SyscallId::SHMAT => ruxos_posix_api::sys_shmat(
+                args[0] as c_int,
+                args[1] as *const core::ffi::c_void,
+                args[2] as c_int,
+            ) as _,
+
+            #[cfg(feature = "fs")]
+            SyscallId::SHMCTL => ruxos_posix_api::sys_shmctl(
+                args[0] as c_int,
+                args[1] as c_int,
+                args[2] as *mut ctypes::shmid_ds,
+            ) as _,
+
             #[cfg(feature = "fd")]
             SyscallId::DUP => ruxos_posix_api::sys_dup(args[0] as c_int) as _,
 
@@ -187,6 +215,14 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
             #[cfg(feature = "multitask")]
             SyscallId::GETPID => ruxos_posix_api::sys_getpid() as _,
 
+            #[cfg(feature = "fs")]
+            SyscallId::SENDFILE => ruxos_posix_api::sys_sendfile(
+                args[0] as c_int,
+                args[1] as c_int,
+                args[2] as *mut ctypes::off_t,
+                args[3] as ctypes::size_t,
+            ) as _,
+
             #[cfg(feature = "net")]
             SyscallId::SOCKET => {
                 ruxos_posix_api::sys_socket(args[0] as c_int, args[1] as c_int, args[2] as c_int)
@@ -303,6 +339,11 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
 
             SyscallId::UNAME => ruxos_posix_api::sys_uname(args[0] as *mut c_void) as _,
 
+            #[cfg(feature = "fs")]
+            SyscallId::SHMDT => {
+                ruxos_posix_api::sys_shmdt(args[0] as *const core::ffi::c_void) as _
+            }
+
             #[cfg(feature = "fd")]
             SyscallId::FCNTL => {
                 ruxos_posix_api::sys_fcntl(args[0] as c_int, args[1] as c_int, args[2]) as _
@@ -314,6 +355,11 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
             #[cfg(feature = "fs")]
             SyscallId::FDATASYNC => ruxos_posix_api::sys_fdatasync(args[0] as c_int) as _,
 
+            #[cfg(feature = "fs")]
+            SyscallId::FTRUNCATE => {
+                ruxos_posix_api::sys_ftruncate(args[0] as c_int, args[1] as ctypes::off_t) as _
+            }
+
             #[cfg(feature = "fs")]
             SyscallId::GETDENTS => ruxos_posix_api::sys_getdents64(
                 args[0] as core::ffi::c_int,
@@ -397,6 +443,58 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                     as _
             }
 
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_SETPARAM => ruxos_posix_api::sys_sched_setparam(
+                args[0] as ctypes::pid_t,
+                args[1] as *const ctypes::sched_param,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GETPARAM => ruxos_posix_api::sys_sched_getparam(
+                args[0] as ctypes::pid_t,
+                args[1] as *mut ctypes::sched_param,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_SETSCHEDULER => ruxos_posix_api::sys_sched_setscheduler(
+                args[0] as ctypes::pid_t,
+                args[1] as c_int,
+                args[2] as *const ctypes::sched_param,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GETSCHEDULER => {
+                ruxos_posix_api::sys_sched_getscheduler(args[0] as ctypes::pid_t) as _
+            }
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GET_PRIORITY_MAX => {
+                ruxos_posix_api::sys_sched_get_priority_max(args[0] as c_int) as _
+            }
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GET_PRIORITY_MIN => {
+                ruxos_posix_api::sys_sched_get_priority_min(args[0] as c_int) as _
+            }
+
+            #[cfg(feature = "alloc")]
+            SyscallId::MLOCK => ruxos_posix_api::sys_mlock(
+                args[0] as *const core::ffi::c_void,
+                args[1] as ctypes::size_t,
+            ) as _,
+
+            #[cfg(feature = "alloc")]
+            SyscallId::MUNLOCK => ruxos_posix_api::sys_munlock(
+                args[0] as *const core::ffi::c_void,
+                args[1] as ctypes::size_t,
+            ) as _,
+
+            #[cfg(feature = "alloc")]
+            SyscallId::MLOCKALL => ruxos_posix_api::sys_mlockall(args[0] as c_int) as _,
+
+            #[cfg(feature = "alloc")]
+            SyscallId::MUNLOCKALL => ruxos_posix_api::sys_munlockall() as _,
+
             SyscallId::PRCTL => ruxos_posix_api::sys_prctl(
                 args[0] as c_int,
                 args[1] as c_ulong,
@@ -409,6 +507,14 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                 ruxos_posix_api::sys_arch_prctl(args[0] as c_int, args[1] as c_ulong) as _
             }
 
+            #[cfg(feature = "fs")]
+            SyscallId::SWAPON => {
+                ruxos_posix_api::sys_swapon(args[0] as *const c_char, args[1] as c_int) as _
+            }
+
+            #[cfg(feature = "fs")]
+            SyscallId::SWAPOFF => ruxos_posix_api::sys_swapoff(args[0] as *const c_char) as _,
+
             #[cfg(feature = "multitask")]
             SyscallId::GETTID => ruxos_posix_api::sys_gettid() as _,
 
@@ -422,6 +528,20 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                 args[5] as _,
             ) as _,
 
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_SETAFFINITY => ruxos_posix_api::sys_sched_setaffinity(
+                args[0] as ctypes::pid_t,
+                args[1] as ctypes::size_t,
+                args[2] as *const ctypes::cpu_set_t,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GETAFFINITY => ruxos_posix_api::sys_sched_getaffinity(
+                args[0] as ctypes::pid_t,
+                args[1] as ctypes::size_t,
+                args[2] as *mut ctypes::cpu_set_t,
+            ) as _,
+
             #[cfg(feature = "epoll")]
             SyscallId::EPOLL_CREATE => ruxos_posix_api::sys_epoll_create(args[0] as c_int) as _,
 
@@ -534,6 +654,28 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                 args[4] as ctypes::size_t,
             ) as _,
 
+            #[cfg(feature = "multitask")]
+            SyscallId::SET_ROBUST_LIST => {
+                ruxos_posix_api::sys_set_robust_list(args[0], args[1]) as _
+            }
+
+            #[cfg(feature = "multitask")]
+            SyscallId::GET_ROBUST_LIST => ruxos_posix_api::sys_get_robust_list(
+                args[0] as ctypes::pid_t,
+                args[1] as *mut usize,
+                args[2] as *mut usize,
+            ) as _,
+
+            #[cfg(feature = "pipe")]
+            SyscallId::SPLICE => ruxos_posix_api::sys_splice(
+                args[0] as c_int,
+                args[1] as *mut ctypes::off_t,
+                args[2] as c_int,
+                args[3] as *mut ctypes::off_t,
+                args[4] as ctypes::size_t,
+                args[5] as core::ffi::c_uint,
+            ) as _,
+
             #[cfg(feature = "epoll")]
             SyscallId::EPOLL_PWAIT => ruxos_posix_api::sys_epoll_pwait(
                 args[0] as c_int,
@@ -573,11 +715,54 @@ pub fn syscall(syscall_id: SyscallId, args: [usize; 6]) -> isize {
                 args[3] as *mut ctypes::rlimit,
             ) as _,
 
+            SyscallId::GETCPU => ruxos_posix_api::sys_getcpu(
+                args[0] as *mut core::ffi::c_uint,
+                args[1] as *mut core::ffi::c_uint,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_SETATTR => ruxos_posix_api::sys_sched_setattr(
+                args[0] as ctypes::pid_t,
+                args[1] as *const ctypes::sched_attr,
+                args[2] as core::ffi::c_uint,
+            ) as _,
+
+            #[cfg(feature = "multitask")]
+            SyscallId::SCHED_GETATTR => ruxos_posix_api::sys_sched_getattr(
+                args[0] as ctypes::pid_t,
+                args[1] as *mut ctypes::sched_attr,
+                args[2] as core::ffi::c_uint,
+                args[3] as core::ffi::c_uint,
+            ) as _,
+
             SyscallId::GETRANDOM => ruxos_posix_api::sys_getrandom(
                 args[0] as *mut c_void,
                 args[1] as ctypes::size_t,
                 args[2] as c_int,
             ) as _,
+
+            #[cfg(feature = "fs")]
+            SyscallId::MEMFD_CREATE => ruxos_posix_api::sys_memfd_create(
+                args[0] as *const core::ffi::c_char,
+                args[1] as core::ffi::c_uint,
+            ) as _,
+
+            #[cfg(feature = "alloc")]
+            SyscallId::MLOCK2 => ruxos_posix_api::sys_mlock2(
+                args[0] as *const core::ffi::c_void,
+                args[1] as ctypes::size_t,
+                args[2] as c_int,
+            ) as _,
+
+            #[cfg(feature = "fs")]
+            SyscallId::COPY_FILE_RANGE => ruxos_posix_api::sys_copy_file_range(
+                args[0] as c_int,
+                args[1] as *mut ctypes::off_t,
+                args[2] as c_int,
+                args[3] as *mut ctypes::off_t,
+                args[4] as ctypes::size_t,
+                args[5] as core::ffi::c_uint,
+            ) as _,
         }
     }
 }
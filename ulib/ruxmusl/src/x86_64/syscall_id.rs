@@ -76,9 +76,21 @@ pub enum SyscallId {
     #[cfg(feature = "alloc")]
     MSYNC = 26,
 
+    #[cfg(feature = "alloc")]
+    MINCORE = 27,
+
     #[cfg(feature = "alloc")]
     MADVISE = 28,
 
+    #[cfg(feature = "fs")]
+    SHMGET = 29,
+
+    #[cfg(feature = "fs")]
+    SHMAT = 30,
+
+    #[cfg(feature = "fs")]
+    SHMCTL = 31,
+
     #[cfg(feature = "fd")]
     DUP = 32,
 
@@ -90,6 +102,9 @@ pub enum SyscallId {
     #[cfg(feature = "multitask")]
     GETPID = 39,
 
+    #[cfg(feature = "fs")]
+    SENDFILE = 40,
+
     #[cfg(feature = "net")]
     SOCKET = 41,
 
@@ -140,6 +155,9 @@ pub enum SyscallId {
 
     UNAME = 63,
 
+    #[cfg(feature = "fs")]
+    SHMDT = 67,
+
     #[cfg(feature = "fd")]
     FCNTL = 72,
 
@@ -149,6 +167,9 @@ pub enum SyscallId {
     #[cfg(feature = "fs")]
     FDATASYNC = 75,
 
+    #[cfg(feature = "fs")]
+    FTRUNCATE = 77,
+
     #[cfg(feature = "fs")]
     GETDENTS = 78,
 
@@ -200,16 +221,58 @@ pub enum SyscallId {
     #[cfg(feature = "signal")]
     SIGALTSTACK = 131,
 
+    #[cfg(feature = "multitask")]
+    SCHED_SETPARAM = 142,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GETPARAM = 143,
+
+    #[cfg(feature = "multitask")]
+    SCHED_SETSCHEDULER = 144,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GETSCHEDULER = 145,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MAX = 146,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GET_PRIORITY_MIN = 147,
+
+    #[cfg(feature = "alloc")]
+    MLOCK = 149,
+
+    #[cfg(feature = "alloc")]
+    MUNLOCK = 150,
+
+    #[cfg(feature = "alloc")]
+    MLOCKALL = 151,
+
+    #[cfg(feature = "alloc")]
+    MUNLOCKALL = 152,
+
     PRCTL = 157,
 
     ARCH_PRCTL = 158,
 
+    #[cfg(feature = "fs")]
+    SWAPON = 167,
+
+    #[cfg(feature = "fs")]
+    SWAPOFF = 168,
+
     #[cfg(feature = "multitask")]
     GETTID = 186,
 
     #[cfg(feature = "multitask")]
     FUTEX = 202,
 
+    #[cfg(feature = "multitask")]
+    SCHED_SETAFFINITY = 203,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GETAFFINITY = 204,
+
     #[cfg(feature = "epoll")]
     EPOLL_CREATE = 213,
 
@@ -256,6 +319,15 @@ pub enum SyscallId {
     #[cfg(feature = "poll")]
     PPOLL = 271,
 
+    #[cfg(feature = "multitask")]
+    SET_ROBUST_LIST = 273,
+
+    #[cfg(feature = "multitask")]
+    GET_ROBUST_LIST = 274,
+
+    #[cfg(feature = "pipe")]
+    SPLICE = 275,
+
     #[cfg(feature = "epoll")]
     EPOLL_PWAIT = 281,
 
@@ -273,5 +345,22 @@ pub enum SyscallId {
 
     PRLIMIT64 = 302,
 
+    GETCPU = 309,
+
+    #[cfg(feature = "multitask")]
+    SCHED_SETATTR = 314,
+
+    #[cfg(feature = "multitask")]
+    SCHED_GETATTR = 315,
+
     GETRANDOM = 318,
+
+    #[cfg(feature = "fs")]
+    MEMFD_CREATE = 319,
+
+    #[cfg(feature = "alloc")]
+    MLOCK2 = 325,
+
+    #[cfg(feature = "fs")]
+    COPY_FILE_RANGE = 326,
 }